@@ -0,0 +1,84 @@
+//! Run with `cargo bench`. Compare with a previous run to see whether a change to the parsers
+//! made them slower.
+
+use std::io::Cursor;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use mp4_parser::logger::NullLogger;
+use mp4_parser::model::Movie;
+use mp4_parser::probe::probe;
+use mp4_parser::reader::Reader;
+use mp4_parser::tree::{parse_mp4, BoxFilter};
+
+/// The test files in the repository: audio, video, both, and a fragmented file
+const FIXTURES: [(&str, &[u8]); 4] = [
+    ("aud_500ms", include_bytes!("../aud_500ms.mp4")),
+    ("vid_120ms", include_bytes!("../vid_120ms.mp4")),
+    ("av_120ms", include_bytes!("../av_120ms.mp4")),
+    ("frag_aud_120ms", include_bytes!("../frag_aud_120ms.mp4")),
+];
+
+/// Only ftyp and moov, as with `--probe`
+fn header_only(c: &mut Criterion) {
+    let mut group = c.benchmark_group("header_only");
+    for (name, buf) in FIXTURES {
+        group.bench_function(name, |b| {
+            b.iter(|| probe(&mut Cursor::new(black_box(buf))).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Every box, as when printing the tree
+fn full_tree(c: &mut Criterion) {
+    let mut group = c.benchmark_group("full_tree");
+    for (name, buf) in FIXTURES {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut logger = NullLogger::default();
+                parse_mp4(black_box(buf), &mut logger, &BoxFilter::default()).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Resolving the sample tables of parsed tracks into samples with offsets and times
+fn sample_tables(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sample_tables");
+    for (name, buf) in FIXTURES {
+        let movie = Movie::parse(buf).unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for track in &black_box(&movie).tracks {
+                    black_box(movie.track_samples(track));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// The reads that sample tables (e.g. stsz and stco) consist of, over 1 MiB. The test files are
+/// so small that the parsers' other work hides how fast `Reader` is.
+fn reader(c: &mut Criterion) {
+    let buf = vec![0x5a; 1 << 20];
+    let mut group = c.benchmark_group("reader");
+    group.bench_function("read_u32", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(black_box(&buf));
+            (0..buf.len() / 4).fold(0, |sum: u32, _| sum.wrapping_add(reader.read_u32()))
+        })
+    });
+    group.bench_function("read_u32_limited", |b| {
+        b.iter(|| {
+            let mut reader = Reader::new(black_box(&buf)).limited(buf.len() as u64);
+            (0..buf.len() / 4).fold(0, |sum: u32, _| sum.wrapping_add(reader.read_u32()))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, header_only, full_tree, sample_tables, reader);
+criterion_main!(benches);
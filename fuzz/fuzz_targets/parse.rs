@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mp4_parser::fuzz::{parse_all, ArbitraryFile};
+
+fuzz_target!(|file: ArbitraryFile| {
+    parse_all(&file.to_bytes());
+});
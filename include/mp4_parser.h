@@ -0,0 +1,143 @@
+#ifndef MP4_PARSER_H
+#define MP4_PARSER_H
+
+/* Generated by cbindgen from src/capi.rs. Don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ A parsed file. Opaque to C.
+ */
+typedef struct Mp4File Mp4File;
+
+/*
+ A box, as listed by `mp4_parser_get_box`
+ */
+typedef struct Mp4BoxInfo {
+  /*
+   The four-character code, NUL-terminated
+   */
+  char box_type[5];
+  uint64_t offset;
+  /*
+   Including the header
+   */
+  uint64_t size;
+  /*
+   1 for top-level boxes
+   */
+  uint32_t depth;
+  /*
+   E.g. "Movie Header Box"
+   */
+  const char *name;
+} Mp4BoxInfo;
+
+/*
+ A track, as listed by `mp4_parser_get_track`. Values that don't apply to the track are 0.
+ */
+typedef struct Mp4TrackInfo {
+  uint32_t track_id;
+  /*
+   E.g. "vide" or "soun", NUL-terminated
+   */
+  char handler_type[5];
+  /*
+   E.g. "H.264/avc1 (High profile, level 4.0)", or NULL if the codec isn't known
+   */
+  const char *codec;
+  /*
+   ISO 639-2/T code, e.g. "eng"
+   */
+  const char *language;
+  /*
+   In seconds, after edit lists
+   */
+  double duration;
+  uint64_t sample_count;
+  uint16_t width;
+  uint16_t height;
+  float sample_rate;
+  uint16_t channel_count;
+  /*
+   Average, in bits per second
+   */
+  double bitrate;
+} Mp4TrackInfo;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Parses the `len` bytes at `data`, which are only read during the call. Returns NULL if the
+ file can't be parsed, see `mp4_parser_last_error`.
+
+ # Safety
+
+ `data` must point to `len` readable bytes.
+ */
+struct Mp4File *mp4_parser_open(const uint8_t *data, size_t len);
+
+/*
+ Why the last `mp4_parser_open` on this thread returned NULL, or NULL if it didn't. Valid
+ until the next `mp4_parser_open` on this thread.
+ */
+const char *mp4_parser_last_error(void);
+
+/*
+ Frees a file from `mp4_parser_open`. NULL is ignored.
+
+ # Safety
+
+ `file` must come from `mp4_parser_open`, and not have been freed already.
+ */
+void mp4_parser_free(struct Mp4File *file);
+
+/*
+ The number of boxes, at all depths
+
+ # Safety
+
+ `file` must come from `mp4_parser_open`.
+ */
+size_t mp4_parser_box_count(const struct Mp4File *file);
+
+/*
+ Fills in `out` with the box at `index`, in file order with each box before its children.
+ Returns false if there's no such box.
+
+ # Safety
+
+ `file` must come from `mp4_parser_open`, and `out` must point to an `Mp4BoxInfo`.
+ */
+bool mp4_parser_get_box(const struct Mp4File *file, size_t index, struct Mp4BoxInfo *out);
+
+/*
+ The number of tracks in moov
+
+ # Safety
+
+ `file` must come from `mp4_parser_open`.
+ */
+size_t mp4_parser_track_count(const struct Mp4File *file);
+
+/*
+ Fills in `out` with the track at `index`, in the order of the trak boxes. Returns false if
+ there's no such track.
+
+ # Safety
+
+ `file` must come from `mp4_parser_open`, and `out` must point to an `Mp4TrackInfo`.
+ */
+bool mp4_parser_get_track(const struct Mp4File *file, size_t index, struct Mp4TrackInfo *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MP4_PARSER_H */
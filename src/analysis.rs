@@ -0,0 +1,441 @@
+use std::ops::Range;
+
+use crate::edit::TimeRange;
+use crate::media_time::MediaTime;
+use crate::model::{Movie, Sample, Track};
+use crate::rewrite::top_level_boxes;
+use crate::seek::seek;
+
+/// Distribution of a track's sample sizes, in bytes
+#[derive(Debug)]
+pub struct SampleSizeStats {
+    pub count: usize,
+    pub min: u32,
+    pub max: u32,
+    pub mean: f64,
+    pub p50: u32,
+    pub p90: u32,
+    pub p99: u32,
+}
+
+impl SampleSizeStats {
+    pub fn from_samples(samples: &[Sample]) -> Option<Self> {
+        let mut sizes: Vec<u32> = samples.iter().map(|s| s.size).collect();
+        if sizes.is_empty() {
+            return None;
+        }
+        sizes.sort_unstable();
+        let total: u64 = sizes.iter().map(|&s| s as u64).sum();
+        Some(Self {
+            count: sizes.len(),
+            min: sizes[0],
+            max: sizes[sizes.len() - 1],
+            mean: total as f64 / sizes.len() as f64,
+            p50: percentile(&sizes, 50.0),
+            p90: percentile(&sizes, 90.0),
+            p99: percentile(&sizes, 99.0),
+        })
+    }
+}
+
+/// The statistics of a single track, which don't depend on any other track
+#[derive(Debug)]
+pub struct TrackStats {
+    pub track_id: u32,
+    pub handler_type: String,
+    pub sizes: Option<SampleSizeStats>,
+    pub bitrate: Option<BitrateStats>,
+}
+
+impl TrackStats {
+    /// Only the samples in `range` are counted, see `sample_range`
+    pub fn from_track(movie: &Movie, track: &Track, range: &TimeRange) -> Self {
+        let samples = movie.track_samples(track);
+        let samples = &samples[sample_range(movie, track, &samples, range)];
+        Self {
+            track_id: track.id,
+            handler_type: track.handler_type.clone(),
+            sizes: SampleSizeStats::from_samples(samples),
+            bitrate: BitrateStats::from_samples(track, samples),
+        }
+    }
+}
+
+/// Computes the statistics of each track, see `map_tracks`
+pub fn track_stats(movie: &Movie, range: &TimeRange, parallel: bool) -> Vec<TrackStats> {
+    map_tracks(movie, parallel, |track| {
+        TrackStats::from_track(movie, track, range)
+    })
+}
+
+/// The indexes of the track's samples (from `Movie::track_samples`) that are decoded in
+/// `range`, in the track's media timeline. The start is moved back to the sync sample that
+/// `seek::seek` finds for it, so that the first sample can be decoded on its own.
+pub fn sample_range(
+    movie: &Movie,
+    track: &Track,
+    samples: &[Sample],
+    range: &TimeRange,
+) -> Range<usize> {
+    let end = match range.end {
+        Some(end) => {
+            let end = MediaTime::from_secs_f64(end, track.timescale).ticks();
+            samples.partition_point(|s| (s.decode_time as i64) < end)
+        }
+        None => samples.len(),
+    };
+    let start = match range.start {
+        // In a fragmented file, the seek point is the moof box before the sync sample
+        Some(start) => match seek(movie, track.id, start) {
+            Some(point) => samples.partition_point(|s| s.offset < point.offset),
+            None => {
+                let start = MediaTime::from_secs_f64(start, track.timescale).ticks();
+                samples.partition_point(|s| (s.decode_time as i64) < start)
+            }
+        },
+        None => 0,
+    };
+    start.min(end)..end
+}
+
+/// Runs `analyze` for each track and returns the results in track order. With `parallel`, and
+/// when built with the `parallel` feature, each track is analyzed by a separate rayon worker.
+/// The workers only share the parsed movie, which they can't change, so `analyze` must not
+/// depend on the results for the other tracks.
+pub fn map_tracks<T, F>(movie: &Movie, parallel: bool, analyze: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&Track) -> T + Sync,
+{
+    #[cfg(feature = "parallel")]
+    {
+        if parallel {
+            use rayon::prelude::*;
+            return movie.tracks.par_iter().map(&analyze).collect();
+        }
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        if parallel {
+            log::warn!("Built without the parallel feature. Analyzing one track at a time.");
+        }
+    }
+    movie.tracks.iter().map(analyze).collect()
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u32], p: f64) -> u32 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// The bitrate over the one-second window that starts at `start` (in seconds)
+#[derive(Debug, Clone, Copy)]
+pub struct BitrateWindow {
+    pub start: f64,
+    pub bits_per_second: f64,
+}
+
+#[derive(Debug)]
+pub struct BitrateStats {
+    pub average_bits_per_second: f64,
+    /// The window with the most data, out of the windows starting at every sample that end
+    /// within the track. None if the track is shorter than a window.
+    pub peak: Option<BitrateWindow>,
+    /// Windows starting at every whole second. The last one is scaled to the part of the
+    /// second that the track covers.
+    pub windows: Vec<BitrateWindow>,
+}
+
+impl BitrateStats {
+    /// Computes bitrates in decoding time, over a sliding window of one second
+    pub fn from_samples(track: &Track, samples: &[Sample]) -> Option<Self> {
+        if samples.is_empty() || track.timescale == 0 {
+            return None;
+        }
+        let timescale = track.timescale as u64;
+        let last = samples.last().unwrap();
+        let end = last.decode_time + last.duration as u64;
+        let window_bytes = |start_ticks: u64| -> u64 {
+            let first = samples.partition_point(|s| s.decode_time < start_ticks);
+            samples[first..]
+                .iter()
+                .take_while(|s| s.decode_time < start_ticks + timescale)
+                .map(|s| s.size as u64)
+                .sum()
+        };
+        // The bytes over the time that the window covers, which is less than a second at the
+        // end of the track
+        let to_window = |start_ticks: u64| {
+            let covered = (end.saturating_sub(start_ticks)).clamp(1, timescale);
+            BitrateWindow {
+                start: MediaTime::new(start_ticks, track.timescale).as_secs_f64(),
+                bits_per_second: window_bytes(start_ticks) as f64 * 8.0
+                    / MediaTime::new(covered, track.timescale).as_secs_f64(),
+            }
+        };
+
+        let peak = samples
+            .iter()
+            .filter(|s| s.decode_time + timescale <= end)
+            .map(|s| to_window(s.decode_time))
+            .fold(None, |peak: Option<BitrateWindow>, window| match peak {
+                Some(peak) if peak.bits_per_second >= window.bits_per_second => Some(peak),
+                _ => Some(window),
+            });
+
+        // The windows start at whole seconds, from the one the first sample is in
+        let first_window = samples[0].decode_time / timescale * timescale;
+        let windows = (first_window..end.max(first_window + 1))
+            .step_by(timescale as usize)
+            .map(to_window)
+            .collect();
+
+        let total_bytes: u64 = samples.iter().map(|s| s.size as u64).sum();
+        let media_duration = end.saturating_sub(samples[0].decode_time);
+        let average_bits_per_second = if media_duration > 0 {
+            total_bytes as f64 * 8.0 / MediaTime::new(media_duration, track.timescale).as_secs_f64()
+        } else {
+            0.0
+        };
+
+        Some(Self {
+            average_bits_per_second,
+            peak,
+            windows,
+        })
+    }
+}
+
+/// When the first audio and video are presented, after applying edit lists
+#[derive(Debug)]
+pub struct AvOffset {
+    pub video_track_id: u32,
+    pub audio_track_id: u32,
+    /// In seconds
+    pub video_start: f64,
+    /// In seconds
+    pub audio_start: f64,
+}
+
+impl AvOffset {
+    /// Compares the first video track with the first audio track
+    pub fn from_movie(movie: &Movie) -> Option<Self> {
+        let video = movie.tracks.iter().find(|t| t.is_video())?;
+        let audio = movie.tracks.iter().find(|t| t.is_audio())?;
+        Some(Self {
+            video_track_id: video.id,
+            audio_track_id: audio.id,
+            video_start: presentation_start(movie, video, &movie.track_samples(video))?,
+            audio_start: presentation_start(movie, audio, &movie.track_samples(audio))?,
+        })
+    }
+
+    /// Positive if the audio starts after the video, in seconds
+    pub fn offset(&self) -> f64 {
+        self.audio_start - self.video_start
+    }
+}
+
+/// The time (in seconds) at which the track's first sample is presented, after applying its
+/// edit list (see `Track::presentation_timeline`)
+pub fn presentation_start(movie: &Movie, track: &Track, samples: &[Sample]) -> Option<f64> {
+    let timeline = track.presentation_timeline(movie.timescale);
+    samples
+        .iter()
+        .filter_map(|sample| timeline.presentation_time(sample))
+        .min()
+        .map(|time| time.as_secs_f64())
+}
+
+/// How much of the data in the mdat boxes is used by the tracks' samples
+#[derive(Debug)]
+pub struct MdatUtilization {
+    pub mdat_count: usize,
+    /// The payload of the mdat boxes, without their headers
+    pub mdat_bytes: u64,
+    pub referenced_bytes: u64,
+    /// Ranges of file offsets (start, end) inside mdat that no sample refers to, e.g. left by
+    /// editors that removed samples without rewriting the mdat
+    pub orphaned: Vec<(u64, u64)>,
+}
+
+impl MdatUtilization {
+    pub fn from_movie(buf: &[u8], movie: &Movie) -> Self {
+        // Samples of track fragments for tracks that aren't in moov are counted too
+        let fragment_samples = movie
+            .fragments
+            .iter()
+            .flat_map(|fragment| fragment.tracks.iter())
+            .flat_map(|track_fragment| track_fragment.samples.iter());
+        let mut ranges: Vec<(u64, u64)> = movie
+            .tracks
+            .iter()
+            .flat_map(|track| track.sample_table.samples())
+            .map(|sample| (sample.offset, sample.size))
+            .chain(fragment_samples.map(|sample| (sample.offset, sample.size)))
+            .filter(|&(_, size)| size > 0)
+            .map(|(offset, size)| (offset, offset.saturating_add(size as u64)))
+            .collect();
+        ranges.sort_unstable();
+        let mut referenced: Vec<(u64, u64)> = Vec::new();
+        for (start, end) in ranges {
+            match referenced.last_mut() {
+                Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+                _ => referenced.push((start, end)),
+            }
+        }
+
+        let mdats: Vec<(u64, u64)> = top_level_boxes(buf)
+            .iter()
+            .filter(|header| header.box_type == "mdat")
+            .map(|header| {
+                let end = (header.start_offset + header.box_size).min(buf.len() as u64);
+                (end.saturating_sub(header.inner_size), end)
+            })
+            .collect();
+        let mut utilization = Self {
+            mdat_count: mdats.len(),
+            mdat_bytes: mdats.iter().map(|(start, end)| end - start).sum(),
+            referenced_bytes: 0,
+            orphaned: vec![],
+        };
+        for (mdat_start, mdat_end) in mdats {
+            let mut position = mdat_start;
+            for &(start, end) in &referenced {
+                if end <= position || start >= mdat_end {
+                    continue;
+                }
+                if start > position {
+                    utilization.orphaned.push((position, start));
+                }
+                position = end.min(mdat_end);
+            }
+            if position < mdat_end {
+                utilization.orphaned.push((position, mdat_end));
+            }
+        }
+        let orphaned_bytes: u64 = utilization.orphaned_bytes();
+        utilization.referenced_bytes = utilization.mdat_bytes - orphaned_bytes;
+        utilization
+    }
+
+    pub fn orphaned_bytes(&self) -> u64 {
+        self.orphaned.iter().map(|(start, end)| end - start).sum()
+    }
+}
+
+/// How far apart in time the audio and video data that are stored next to each other are. A
+/// player that reads the file from start to end must buffer the data of the track that is
+/// ahead until the other track catches up.
+#[derive(Debug)]
+pub struct Interleaving {
+    pub video_track_id: u32,
+    pub audio_track_id: u32,
+    pub video_chunks: usize,
+    pub audio_chunks: usize,
+    /// The largest difference between how far the video and the audio have come, in seconds,
+    /// while reading the file from start to end
+    pub depth: f64,
+    /// File offset of the chunk at which the depth is reached
+    pub depth_offset: u64,
+    /// The most data that has been read ahead of the other track at any point, in bytes
+    pub max_buffered_bytes: u64,
+}
+
+/// Consecutive samples of a track that are stored together
+struct Chunk {
+    offset: u64,
+    size: u64,
+    /// In seconds
+    start_time: f64,
+    end_time: f64,
+}
+
+fn chunks(track: &Track, samples: &[Sample]) -> Vec<Chunk> {
+    let secs = |ticks: u64| MediaTime::new(ticks, track.timescale).as_secs_f64();
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut previous: Option<&Sample> = None;
+    for sample in samples {
+        let end_time = secs(sample.decode_time + sample.duration as u64);
+        match (chunks.last_mut(), previous) {
+            (Some(chunk), Some(previous))
+                if previous.chunk_index == sample.chunk_index
+                    && previous.offset + previous.size as u64 == sample.offset =>
+            {
+                chunk.size += sample.size as u64;
+                chunk.end_time = end_time;
+            }
+            _ => chunks.push(Chunk {
+                offset: sample.offset,
+                size: sample.size as u64,
+                start_time: secs(sample.decode_time),
+                end_time,
+            }),
+        }
+        previous = Some(sample);
+    }
+    chunks
+}
+
+impl Interleaving {
+    /// Compares the first video track with the first audio track
+    pub fn from_movie(movie: &Movie) -> Option<Self> {
+        let video = movie.tracks.iter().find(|t| t.is_video())?;
+        let audio = movie.tracks.iter().find(|t| t.is_audio())?;
+        let video_chunks = chunks(video, &movie.track_samples(video));
+        let audio_chunks = chunks(audio, &movie.track_samples(audio));
+        if video_chunks.is_empty() || audio_chunks.is_empty() {
+            return None;
+        }
+
+        // Index 0 is video, 1 is audio
+        let mut in_file_order: Vec<(usize, &Chunk)> = video_chunks
+            .iter()
+            .map(|chunk| (0, chunk))
+            .chain(audio_chunks.iter().map(|chunk| (1, chunk)))
+            .collect();
+        in_file_order.sort_by_key(|(_, chunk)| chunk.offset);
+
+        let mut reached = [0.0_f64; 2];
+        // The start times of the chunks read so far, with the total bytes read up to them
+        let mut read: [Vec<(f64, u64)>; 2] = [vec![], vec![]];
+        let mut interleaving = Self {
+            video_track_id: video.id,
+            audio_track_id: audio.id,
+            video_chunks: video_chunks.len(),
+            audio_chunks: audio_chunks.len(),
+            depth: 0.0,
+            depth_offset: 0,
+            max_buffered_bytes: 0,
+        };
+        for (track, chunk) in in_file_order {
+            let total = read[track].last().map_or(0, |&(_, total)| total) + chunk.size;
+            read[track].push((chunk.start_time, total));
+            reached[track] = reached[track].max(chunk.end_time);
+
+            let depth = (reached[0] - reached[1]).abs();
+            if depth > interleaving.depth {
+                interleaving.depth = depth;
+                interleaving.depth_offset = chunk.offset;
+            }
+            // The chunks of the track that is ahead that start after where the other one has
+            // come are buffered
+            let (ahead, behind) = if reached[0] > reached[1] {
+                (0, 1)
+            } else {
+                (1, 0)
+            };
+            let needed = read[ahead].partition_point(|&(start, _)| start < reached[behind]);
+            let total_read = read[ahead].last().map_or(0, |&(_, total)| total);
+            let needed_bytes = match needed {
+                0 => 0,
+                n => read[ahead][n - 1].1,
+            };
+            interleaving.max_buffered_bytes = interleaving
+                .max_buffered_bytes
+                .max(total_read - needed_bytes);
+        }
+        Some(interleaving)
+    }
+}
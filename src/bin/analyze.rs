@@ -0,0 +1,279 @@
+use std::fs::File;
+use std::io::Read;
+
+use clap::{App, Arg};
+
+use mp4_parser::analysis::{sample_range, track_stats, AvOffset, Interleaving, MdatUtilization};
+use mp4_parser::edit::TimeRange;
+use mp4_parser::exit_code;
+use mp4_parser::model::{Movie, Track, TrackSelector};
+use mp4_parser::validate::ValidationOptions;
+
+fn main() {
+    let matches =
+        App::new("mp4-analyze")
+            .about("Print statistics about the tracks of an MP4 file")
+            .arg(
+                Arg::with_name("FILE")
+                    .help("The mp4 file that should be analyzed")
+                    .required(true)
+                    .index(1),
+            )
+            .arg(
+                Arg::with_name("bitrate-series")
+                    .long("bitrate-series")
+                    .help("Print the bitrate of every one-second window, not just the peak"),
+            )
+            .arg(Arg::with_name("frames").long("frames").help(
+                "Print when every sample is decoded and presented, after applying edit lists",
+            ))
+            .arg(
+                Arg::with_name("track")
+                    .long("track")
+                    .value_name("TRACK")
+                    .validator(|v| v.parse::<TrackSelector>().map(|_| ()))
+                    .help(
+                        "Only analyze one track: a track ID, index:N (counting from 0) or a kind \
+                         like video. A/V sync, interleaving and media data aren't printed then",
+                    ),
+            )
+            .arg(
+                Arg::with_name("from")
+                    .long("from")
+                    .value_name("SECONDS")
+                    .validator(validate_time)
+                    .help(
+                        "Only analyze the samples from this time on, starting at the sync sample \
+                         before it",
+                    ),
+            )
+            .arg(
+                Arg::with_name("to")
+                    .long("to")
+                    .value_name("SECONDS")
+                    .validator(validate_time)
+                    .help("Only analyze the samples before this time"),
+            )
+            .arg(
+                Arg::with_name("parallel")
+                    .long("parallel")
+                    .help("Analyze the tracks on separate threads (needs the parallel feature)"),
+            )
+            .get_matches();
+
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    exit_code::set_panic_hook();
+
+    let path = matches.value_of("FILE").unwrap();
+    let mut f = File::open(path).unwrap();
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf).unwrap();
+
+    let movie = exit_code::exit_on_error(Movie::parse(&buf));
+    let selected_track_id = matches.value_of("track").map(|selector| {
+        let selector: TrackSelector = selector.parse().unwrap();
+        match movie.select_track(&selector) {
+            Some(track) => track.id,
+            None => {
+                eprintln!("ERROR: No track matches '{}'", selector);
+                std::process::exit(exit_code::PARSE_ERROR);
+            }
+        }
+    });
+    let range = TimeRange {
+        start: matches.value_of("from").map(|t| t.parse().unwrap()),
+        end: matches.value_of("to").map(|t| t.parse().unwrap()),
+    };
+    let all_stats = track_stats(&movie, &range, matches.is_present("parallel"));
+    for stats in all_stats
+        .into_iter()
+        .filter(|stats| selected_track_id.is_none_or(|id| stats.track_id == id))
+    {
+        println!("Track {} ({})", stats.track_id, stats.handler_type);
+
+        match stats.sizes {
+            Some(sizes) => {
+                println!("  Samples:       {}", sizes.count);
+                println!(
+                    "  Sample size:   min {} / mean {:.1} / max {} bytes",
+                    sizes.min, sizes.mean, sizes.max
+                );
+                println!(
+                    "  Percentiles:   p50 {} / p90 {} / p99 {} bytes",
+                    sizes.p50, sizes.p90, sizes.p99
+                );
+            }
+            None => println!("  No samples"),
+        }
+
+        if let Some(bitrate) = stats.bitrate {
+            println!(
+                "  Bitrate:       {} average",
+                format_bitrate(bitrate.average_bits_per_second)
+            );
+            match &bitrate.peak {
+                Some(peak) => println!(
+                    "  Peak bitrate:  {} (1s window at {:.3}s)",
+                    format_bitrate(peak.bits_per_second),
+                    peak.start
+                ),
+                None => println!("  Peak bitrate:  - (the track is shorter than the 1s window)"),
+            }
+            if matches.is_present("bitrate-series") {
+                for window in &bitrate.windows {
+                    println!(
+                        "    {:>10.3}s  {}",
+                        window.start,
+                        format_bitrate(window.bits_per_second)
+                    );
+                }
+            }
+        }
+
+        if matches.is_present("frames") {
+            let track_id = stats.track_id;
+            if let Some(track) = movie.tracks.iter().find(|t| t.id == track_id) {
+                print_frames(&movie, track, &range);
+            }
+        }
+    }
+
+    // The rest is about the file as a whole
+    if selected_track_id.is_some() || range.start.is_some() || range.end.is_some() {
+        return;
+    }
+
+    if let Some(av) = AvOffset::from_movie(&movie) {
+        println!("A/V sync");
+        println!(
+            "  Video (track {}) starts at {:.3}s, audio (track {}) at {:.3}s",
+            av.video_track_id, av.video_start, av.audio_track_id, av.audio_start
+        );
+        let offset_ms = av.offset() * 1000.0;
+        if offset_ms.abs() < 0.5 {
+            println!("  Offset:        none");
+        } else if offset_ms > 0.0 {
+            println!(
+                "  Offset:        audio starts {:.1} ms after video",
+                offset_ms
+            );
+        } else {
+            println!(
+                "  Offset:        audio starts {:.1} ms before video",
+                -offset_ms
+            );
+        }
+    }
+
+    if let Some(interleaving) = Interleaving::from_movie(&movie) {
+        println!("Interleaving");
+        println!(
+            "  Chunks:        {} video (track {}), {} audio (track {})",
+            interleaving.video_chunks,
+            interleaving.video_track_id,
+            interleaving.audio_chunks,
+            interleaving.audio_track_id
+        );
+        println!(
+            "  Depth:         {:.3}s (at offset {})",
+            interleaving.depth, interleaving.depth_offset
+        );
+        println!(
+            "  Buffer:        {} bytes needed for progressive playback",
+            interleaving.max_buffered_bytes
+        );
+        if interleaving.depth > ValidationOptions::default().max_interleave_depth {
+            println!("  WARN: audio and video are poorly interleaved");
+        }
+    }
+
+    let utilization = MdatUtilization::from_movie(&buf, &movie);
+    if utilization.mdat_count > 0 {
+        println!("Media data");
+        println!(
+            "  mdat:          {} bytes in {} box(es)",
+            utilization.mdat_bytes, utilization.mdat_count
+        );
+        println!(
+            "  Referenced:    {} bytes ({:.1}%)",
+            utilization.referenced_bytes,
+            percentage(utilization.referenced_bytes, utilization.mdat_bytes)
+        );
+        if utilization.orphaned.is_empty() {
+            println!("  Orphaned:      none");
+        } else {
+            println!(
+                "  Orphaned:      {} bytes in {} range(s), not used by any sample",
+                utilization.orphaned_bytes(),
+                utilization.orphaned.len()
+            );
+            for (start, end) in utilization.orphaned.iter().take(MAX_ORPHANED_RANGES) {
+                println!("    {}-{} ({} bytes)", start, end, end - start);
+            }
+            if utilization.orphaned.len() > MAX_ORPHANED_RANGES {
+                println!(
+                    "    ... and {} more",
+                    utilization.orphaned.len() - MAX_ORPHANED_RANGES
+                );
+            }
+        }
+    }
+}
+
+/// One line per sample in decoding order. The decode and composition times are in the track's
+/// timescale, the presentation time in seconds, or "-" for samples that no edit shows.
+fn print_frames(movie: &Movie, track: &Track, range: &TimeRange) {
+    let timeline = track.presentation_timeline(movie.timescale);
+    println!(
+        "  {:>8}  {:>12}  {:>12}  {:>12}  {:>8}  sync",
+        "sample", "decode", "composition", "presentation", "size"
+    );
+    let samples = movie.track_samples(track);
+    let range = sample_range(movie, track, &samples, range);
+    for (i, sample) in samples
+        .iter()
+        .enumerate()
+        .skip(range.start)
+        .take(range.len())
+    {
+        let presentation = match timeline.presentation_time(sample) {
+            Some(time) => format!("{:.3}s", time.as_secs_f64()),
+            None => "-".to_string(),
+        };
+        println!(
+            "  {:>8}  {:>12}  {:>12}  {:>12}  {:>8}  {}",
+            i + 1,
+            sample.decode_time,
+            sample.decode_time as i64 + sample.composition_offset as i64,
+            presentation,
+            sample.size,
+            if sample.is_sync { "*" } else { "" }
+        );
+    }
+}
+
+fn validate_time(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs >= 0.0 => Ok(()),
+        _ => Err(format!("Expected a time in seconds, got '{}'", value)),
+    }
+}
+
+/// How many of the orphaned ranges in mdat are listed
+const MAX_ORPHANED_RANGES: usize = 10;
+
+fn percentage(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+fn format_bitrate(bits_per_second: f64) -> String {
+    if bits_per_second >= 1_000_000.0 {
+        format!("{:.2} Mbit/s", bits_per_second / 1_000_000.0)
+    } else {
+        format!("{:.1} kbit/s", bits_per_second / 1000.0)
+    }
+}
@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::{Read, Write};
+
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use clap::{App, Arg};
+
+use mp4_parser::edit::{
+    edit_timestamps, edit_track, parse_chapters, set_chapters, trim, TimeRange, TimestampEdit,
+    TrackEdit,
+};
+use mp4_parser::exit_code;
+
+fn main() {
+    exit_code::exit_on_panic(run)
+}
+
+fn run() {
+    let matches = App::new("mp4-edit")
+        .about("Make lossless edits to an MP4 file")
+        .arg(
+            Arg::with_name("FILE")
+                .help("The mp4 file that should be edited")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("OUTPUT_FILE")
+                .required(true)
+                .help("Where the edited file should be written"),
+        )
+        .arg(
+            Arg::with_name("trim")
+                .long("trim")
+                .value_name("START..END")
+                .validator(|v| v.parse::<TimeRange>().map(|_| ()))
+                .help(
+                    "Keep only the given range (in seconds), e.g. 5..10.5 or 60..\n\
+                     The start is moved back to the closest preceding sync sample",
+                ),
+        )
+        .arg(
+            Arg::with_name("set-time")
+                .long("set-time")
+                .value_name("UTC_TIME")
+                .conflicts_with("shift-time")
+                .validator(|v| parse_time(&v).map(|_| ()))
+                .help("Set all creation and modification times, e.g. 2021-10-21T12:00:00"),
+        )
+        .arg(
+            Arg::with_name("shift-time")
+                .long("shift-time")
+                .value_name("OFFSET")
+                .allow_hyphen_values(true)
+                .validator(|v| parse_offset(&v).map(|_| ()))
+                .help(
+                    "Shift all creation and modification times, e.g. -3h, +90m or 3600s.\n\
+                     Useful if the camera's clock was set wrong",
+                ),
+        )
+        .arg(
+            Arg::with_name("track")
+                .long("track")
+                .value_name("TRACK_ID")
+                .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("The track that --language, --enabled and --in-movie apply to"),
+        )
+        .arg(
+            Arg::with_name("language")
+                .long("language")
+                .value_name("CODE")
+                .requires("track")
+                .help("Set the track's language, e.g. eng"),
+        )
+        .arg(
+            Arg::with_name("enabled")
+                .long("enabled")
+                .value_name("true|false")
+                .possible_values(&["true", "false"])
+                .requires("track")
+                .help("Enable or disable the track"),
+        )
+        .arg(
+            Arg::with_name("in-movie")
+                .long("in-movie")
+                .value_name("true|false")
+                .possible_values(&["true", "false"])
+                .requires("track")
+                .help("Set whether the track is used in the presentation"),
+        )
+        .arg(
+            Arg::with_name("chapters")
+                .long("chapters")
+                .value_name("CHAPTERS_FILE")
+                .help(
+                    "Replace the chapter markers with those in the given file. Either JSON,\n\
+                     e.g. [{\"start\": 90.5, \"title\": \"Part 2\"}], or text with one\n\
+                     chapter per line, e.g. 00:01:30.5 Part 2",
+                ),
+        )
+        .get_matches();
+
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    exit_code::set_panic_hook();
+
+    let path = matches.value_of("FILE").unwrap();
+    let output_path = matches.value_of("output").unwrap();
+    let mut buf = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut buf))
+        .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", path, e)));
+
+    if let Some(range) = matches.value_of("trim") {
+        let range: TimeRange = range.parse().unwrap();
+        buf = trim(&buf, &range).unwrap_or_else(|e| exit_with_error(&e));
+    }
+
+    if let Some(time) = matches.value_of("set-time") {
+        let edit = TimestampEdit::Set(parse_time(time).unwrap());
+        buf = edit_timestamps(&buf, &edit);
+    }
+    if let Some(offset) = matches.value_of("shift-time") {
+        let edit = TimestampEdit::Shift(parse_offset(offset).unwrap());
+        buf = edit_timestamps(&buf, &edit);
+    }
+    if let Some(track_id) = matches.value_of("track") {
+        let edit = TrackEdit {
+            track_id: track_id.parse().unwrap(),
+            language: matches.value_of("language").map(|l| l.to_string()),
+            enabled: matches.value_of("enabled").map(|v| v == "true"),
+            in_movie: matches.value_of("in-movie").map(|v| v == "true"),
+            in_preview: None,
+        };
+        buf = edit_track(&buf, &edit).unwrap_or_else(|e| exit_with_error(&e));
+    }
+    if let Some(chapters_path) = matches.value_of("chapters") {
+        let text = std::fs::read_to_string(chapters_path)
+            .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", chapters_path, e)));
+        let chapters = parse_chapters(&text).unwrap_or_else(|e| exit_with_error(&e));
+        buf = set_chapters(&buf, &chapters);
+    }
+
+    File::create(output_path)
+        .and_then(|mut f| f.write_all(&buf))
+        .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", output_path, e)));
+    println!("Wrote {} bytes to {}", buf.len(), output_path);
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("ERROR: {}", message);
+    std::process::exit(exit_code::for_error(message));
+}
+
+fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    let s = s.trim_end_matches('Z');
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S"))
+        .map(|time| Utc.from_utc_datetime(&time))
+        .map_err(|e| format!("Invalid time '{}': {}", s, e))
+}
+
+/// Parses a signed offset with an optional unit (h, m or s), e.g. "-3h". Seconds by default.
+fn parse_offset(s: &str) -> Result<Duration, String> {
+    let (number, unit_secs) = match s.chars().last() {
+        Some('h') => (&s[..s.len() - 1], 3600),
+        Some('m') => (&s[..s.len() - 1], 60),
+        Some('s') => (&s[..s.len() - 1], 1),
+        _ => (s, 1),
+    };
+    let number: i64 = number
+        .trim_start_matches('+')
+        .parse()
+        .map_err(|e| format!("Invalid offset '{}': {}", s, e))?;
+    Ok(Duration::seconds(number * unit_secs))
+}
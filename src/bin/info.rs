@@ -1,11 +1,35 @@
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
 
-use clap::{App, Arg};
+use clap::{arg_enum, App, Arg};
+use serde::Serialize;
 
-use mp4_parser::boxes::{BoxHeader, Mp4Box, SampleEntry};
+use mp4_parser::boxes::{chroma_subsampling, BoxHeader, HdrMetadata, Mp4Box, SampleEntry};
+use mp4_parser::error::ParseError;
+use mp4_parser::exit_code;
+use mp4_parser::heif::MirrorAxis;
+use mp4_parser::media_time::MediaTime;
+use mp4_parser::model::{Movie, TrackKind, TrackSelector};
+use mp4_parser::probe::read_without_media_data;
 use mp4_parser::reader::Reader;
 
+arg_enum! {
+    #[derive(PartialEq, Debug)]
+    pub enum Format {
+        Text,
+        Json,
+    }
+}
+
+/// The sample entries that `SampleDescriptionBox::parse_entry` can handle
+const SUPPORTED_SAMPLE_ENTRIES: [&str; 21] = [
+    "mp4a", "ac-4", "mlpa", "twos", "sowt", "in24", "lpcm", "ulaw", "alaw", "avc1", "hvc1", "hev1",
+    "dvh1", "dvhe", "mp4v", "s263", "jpeg", "mjpa", "mjpb", "png ", "mebx",
+];
+
+/// Bumped whenever the JSON output changes in a way that could break scripts
+const JSON_SCHEMA_VERSION: u32 = 1;
+
 fn main() {
     let matches = App::new("mp4-info")
         .about("Show high-level info of an MP4 file")
@@ -15,77 +39,314 @@ fn main() {
                 .required(true)
                 .index(1),
         )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&Format::variants())
+                .case_insensitive(true)
+                .default_value("text")
+                .help("Text is meant for humans, JSON has a stable schema for scripts"),
+        )
+        .arg(
+            Arg::with_name("track")
+                .long("track")
+                .value_name("TRACK")
+                .validator(|v| v.parse::<TrackSelector>().map(|_| ()))
+                .help("Only show one track: a track ID, index:N (counting from 0) or a kind like video"),
+        )
         .get_matches();
 
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    exit_code::set_panic_hook();
+
     let path = matches.value_of("FILE").unwrap();
-    let mut f = File::open(&path).unwrap();
-    let mut buf = Vec::new();
-    f.read_to_end(&mut buf).unwrap();
+    let mut f = File::open(path).unwrap();
+    // The media data isn't needed, so it's skipped rather than read into memory
+    let buf = read_without_media_data(&mut f).unwrap();
 
     let parser = Parser::new();
-    let info = parser.parse_mp4(&mut buf);
-    println!("{:#?}", info);
+    let mut info = exit_code::exit_on_error(parser.parse_mp4(&buf));
+    if let Some(selector) = matches.value_of("track") {
+        let selector: TrackSelector = selector.parse().unwrap();
+        let track_id = selector
+            .resolve(
+                info.tracks
+                    .iter()
+                    .map(|track| (track.id, track.kind.clone())),
+            )
+            .unwrap_or_else(|| {
+                eprintln!("ERROR: No track matches '{}'", selector);
+                std::process::exit(exit_code::PARSE_ERROR);
+            });
+        info.tracks.retain(|track| track.id == track_id);
+        info.alternate_groups = alternate_groups(&info.tracks);
+    }
+    match matches.value_of("format").unwrap().parse().unwrap() {
+        Format::Text => {
+            println!("{:#?}", info);
+            for group in &info.alternate_groups {
+                let renditions: Vec<String> = group
+                    .renditions
+                    .iter()
+                    .map(|r| {
+                        let default = if r.default { " (default)" } else { "" };
+                        format!("{}{}", r.language, default)
+                    })
+                    .collect();
+                println!("{} renditions: {}", group.kind, renditions.join(", "));
+            }
+            if let Some(image) = &info.image {
+                println!("{}", image);
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&info).unwrap()),
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 struct Info {
+    schema_version: u32,
+    /// In seconds
+    duration: f64,
     tracks: Vec<Track>,
+    /// Tracks that are alternatives to each other, e.g. audio in different languages
+    alternate_groups: Vec<AlternateGroup>,
+    /// Only for HEIF and AVIF files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<ImageInfo>,
 }
 
-#[derive(Debug)]
+/// The primary image of a HEIF or AVIF file, from its item properties
+#[derive(Debug, Serialize)]
+struct ImageInfo {
+    /// The most specific of the image brands, e.g. "avif" for AVIF still images, "avis" for
+    /// AVIF image sequences, or "heic"
+    brand: String,
+    width: Option<u32>,
+    height: Option<u32>,
+    bit_depth: Option<u8>,
+    /// Whether there is an alpha plane (an auxiliary image)
+    has_alpha: bool,
+    /// All items, including alpha planes, thumbnails and metadata such as Exif
+    item_count: usize,
+    layer_count: usize,
+    /// Anti-clockwise, in degrees (irot)
+    rotation: u16,
+    /// imir
+    mirror: Option<MirrorAxis>,
+    /// The rotation and mirroring in the order they are applied
+    transformations: Vec<String>,
+}
+
+impl fmt::Display for ImageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.brand.as_str() {
+            "avif" => "AVIF image",
+            "avis" => "AVIF image sequence",
+            "heic" | "heix" => "HEIC image",
+            "msf1" => "HEIF image sequence",
+            _ => "HEIF image",
+        };
+        write!(f, "{}: ", kind)?;
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => write!(f, "{}x{}", width, height)?,
+            _ => write!(f, "unknown size")?,
+        }
+        if let Some(bit_depth) = self.bit_depth {
+            write!(f, ", {} bit", bit_depth)?;
+        }
+        if self.has_alpha {
+            write!(f, ", alpha")?;
+        }
+        if !self.transformations.is_empty() {
+            write!(f, ", {}", self.transformations.join(" then "))?;
+        }
+        write!(
+            f,
+            ", {} items, {} layers",
+            self.item_count, self.layer_count
+        )
+    }
+}
+
+#[derive(Debug, Serialize)]
 struct Track {
     id: u32,
+    /// The handler type, e.g. "soun"
+    handler: String,
+    /// Derived from the handler type, e.g. "audio". Tracks whose sample entries aren't supported
+    /// still have a kind.
+    kind: TrackKind,
+    /// The sample entry type, e.g. "avc1"
+    codec: Option<String>,
+    /// In seconds
+    duration: f64,
+    language: String,
+    /// Average, in bits per second
+    bitrate: u64,
+    /// Whether the track is played by default (tkhd flags)
+    enabled: bool,
+    /// 0 if the track has no alternatives
+    alternate_group: u16,
+    /// Tracks with lower layers are shown in front of those with higher ones
+    layer: i16,
+    #[serde(flatten)]
     info: TrackInfo,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+struct AlternateGroup {
+    id: u16,
+    kind: TrackKind,
+    /// Ordered by layer
+    renditions: Vec<Rendition>,
+}
+
+#[derive(Debug, Serialize)]
+struct Rendition {
+    track_id: u32,
+    language: String,
+    /// The enabled track is the one that's played unless the user picks another one
+    default: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 enum TrackInfo {
     Audio(AudioTrack),
     Video(VideoTrack),
+    /// Subtitles, metadata, or audio/video in a format that isn't supported yet
+    Other {
+        sample_entry_fourcc: Option<String>,
+    },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 struct AudioTrack {
     channel_count: u16,
     sample_rate: f32,
+    /// From esds, e.g. 2 for AAC LC
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio_object_type: Option<u8>,
+    /// E.g. "AAC LC" or "HE-AAC"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 struct VideoTrack {
     width: u16,
     height: u16,
+    /// From avcC or hvcC, e.g. "High"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+    /// E.g. "4.1"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    level: Option<String>,
+    /// E.g. "4:2:0"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chroma_subsampling: Option<String>,
+    /// Of the luma samples
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bit_depth: Option<u8>,
+    /// The codec string of the Dolby Vision configuration, e.g. "dvhe.08.06"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dolby_vision: Option<String>,
+    /// Only if the sample entry has HDR metadata
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hdr: Option<HdrInfo>,
+}
+
+/// Static HDR metadata, e.g. for HDR10
+#[derive(Debug, Serialize)]
+struct HdrInfo {
+    /// Of the mastering display, in cd/m²
+    max_luminance: Option<f64>,
+    /// Of the mastering display, in cd/m²
+    min_luminance: Option<f64>,
+    /// Maximum content light level, in cd/m²
+    max_cll: Option<u16>,
+    /// Maximum frame-average light level, in cd/m²
+    max_fall: Option<u16>,
+}
+
+impl HdrInfo {
+    fn new(hdr_metadata: &HdrMetadata) -> Option<Self> {
+        let mastering_display = hdr_metadata.mastering_display.as_ref();
+        let content_light_level = hdr_metadata.content_light_level.as_ref();
+        if mastering_display.is_none() && content_light_level.is_none() {
+            return None;
+        }
+        Some(Self {
+            max_luminance: mastering_display.map(|m| m.max_luminance),
+            min_luminance: mastering_display.map(|m| m.min_luminance),
+            max_cll: content_light_level.map(|c| c.max_cll),
+            max_fall: content_light_level.map(|c| c.max_fall),
+        })
+    }
 }
 
 struct Parser {
+    timescale: u32,
+    duration: u64,
     tracks: Vec<Track>,
     current_track: Option<TrackBuilder>,
 }
 
 struct TrackBuilder {
     id: Option<u32>,
+    enabled: bool,
+    alternate_group: u16,
+    layer: i16,
+    handler: String,
+    codec: Option<String>,
+    timescale: u32,
+    duration: u64,
+    language: String,
     info: Option<TrackInfo>,
 }
 
 impl Parser {
     fn new() -> Self {
         Self {
+            timescale: 0,
+            duration: 0,
             tracks: vec![],
             current_track: None,
         }
     }
 
-    fn parse_mp4(mut self, buf: &mut Vec<u8>) -> Info {
-        let mut reader = Reader::new(buf);
+    fn parse_mp4(mut self, buf: &[u8]) -> Result<Info, ParseError> {
+        let movie = Movie::parse(buf)?;
 
+        let mut reader = Reader::new(buf);
         self.parse(&mut reader, buf.len() as u64);
+        if let Some(error) = reader.take_error() {
+            return Err(error);
+        }
 
-        Info {
-            tracks: self.tracks,
+        for track in &mut self.tracks {
+            if let Some(bitrate) = movie
+                .tracks
+                .iter()
+                .find(|t| t.id == track.id)
+                .and_then(|t| movie.bitrate(t))
+            {
+                track.bitrate = bitrate.round() as u64;
+            }
         }
+        Ok(Info {
+            schema_version: JSON_SCHEMA_VERSION,
+            duration: MediaTime::new(self.duration, self.timescale).as_secs_f64(),
+            alternate_groups: alternate_groups(&self.tracks),
+            tracks: self.tracks,
+            image: image_info(&movie),
+        })
     }
 
     fn parse(&mut self, reader: &mut Reader, end_offset: u64) {
-        while reader.position() < end_offset {
+        while reader.position() < end_offset && !reader.failed() {
             let box_start_offset = reader.position();
             let header = BoxHeader::parse(reader);
 
@@ -93,59 +354,265 @@ impl Parser {
                 // We will build a Track from this box's children
                 self.current_track = Some(TrackBuilder {
                     id: None,
+                    enabled: false,
+                    alternate_group: 0,
+                    layer: 0,
+                    handler: String::new(),
+                    codec: None,
+                    timescale: 0,
+                    duration: 0,
+                    language: String::new(),
                     info: None,
                 });
             }
 
             let box_ = Mp4Box::parse_contents(reader, &header.box_type, header.inner_size);
 
-            let box_ = match box_ {
-                Some(b) => b,
-                None => {
-                    reader
-                        .skip_bytes(header.inner_size as u32)
-                        .unwrap_or_else(|e| panic!("Truncated '{}' box: {}", header.box_type, e));
-                    continue;
-                }
-            };
-
             let box_end_offset = box_start_offset + header.box_size;
             match box_ {
                 Mp4Box::Container(_) => {
                     self.parse(reader, box_end_offset);
                 }
+                Mp4Box::Mvhd(movie_header_box) => {
+                    self.timescale = movie_header_box.timescale;
+                    self.duration = movie_header_box.duration;
+                }
                 Mp4Box::Tkhd(track_header_box) => {
-                    self.current_track.as_mut().unwrap().id = Some(track_header_box.track_id);
+                    let track = self.current_track.as_mut().unwrap();
+                    track.id = Some(track_header_box.track_id);
+                    track.enabled = track_header_box.track_enabled;
+                    track.alternate_group = track_header_box.alternate_group;
+                    // Stored as a signed integer
+                    track.layer = track_header_box.layer as i16;
+                }
+                Mp4Box::Hdlr(handler_reference_box) => {
+                    if let Some(track) = self.current_track.as_mut() {
+                        track.handler = handler_reference_box.handler_type;
+                    }
+                }
+                Mp4Box::Mdhd(media_header_box) => {
+                    let track = self.current_track.as_mut().unwrap();
+                    track.timescale = media_header_box.timescale;
+                    track.duration = media_header_box.duration;
+                    track.language = media_header_box.language;
                 }
                 Mp4Box::Stsd(sample_description_box) => {
                     for _ in 0..sample_description_box.entry_count {
+                        let track = self.current_track.as_mut().unwrap();
+                        let entry_header = reader.peek_bytes(8);
+                        let fourcc =
+                            String::from_utf8_lossy(entry_header.get(4..).unwrap_or_default())
+                                .to_string();
+                        track.codec = Some(fourcc.clone());
+                        if !SUPPORTED_SAMPLE_ENTRIES.contains(&fourcc.as_str()) {
+                            // The rest of the box is skipped below
+                            break;
+                        }
                         let info = match sample_description_box.parse_entry(reader) {
                             SampleEntry::Mp4a(mp4a) => TrackInfo::Audio(AudioTrack {
                                 channel_count: mp4a.channel_count,
                                 sample_rate: mp4a.sample_rate,
+                                audio_object_type: mp4a
+                                    .es_descriptor
+                                    .as_ref()
+                                    .and_then(|esds| esds.audio_object_type),
+                                profile: mp4a
+                                    .es_descriptor
+                                    .as_ref()
+                                    .and_then(|esds| esds.audio_profile_name()),
+                            }),
+                            SampleEntry::Ac4(ac4) => TrackInfo::Audio(AudioTrack {
+                                channel_count: ac4.channel_count,
+                                sample_rate: ac4.sample_rate,
+                                audio_object_type: None,
+                                profile: None,
+                            }),
+                            SampleEntry::Mlpa(mlpa) => TrackInfo::Audio(AudioTrack {
+                                channel_count: mlpa.channels(),
+                                sample_rate: mlpa.sampling_rate() as f32,
+                                audio_object_type: None,
+                                profile: None,
+                            }),
+                            SampleEntry::Pcm(pcm) => TrackInfo::Audio(AudioTrack {
+                                channel_count: pcm.channel_count as u16,
+                                sample_rate: pcm.sample_rate as f32,
+                                audio_object_type: None,
+                                profile: None,
                             }),
                             SampleEntry::Avc1(avc1) => TrackInfo::Video(VideoTrack {
                                 width: avc1.width,
                                 height: avc1.height,
+                                profile: avc1.avc_config.as_ref().map(|c| c.profile_name()),
+                                level: avc1.avc_config.as_ref().map(|c| c.level()),
+                                chroma_subsampling: avc1
+                                    .avc_config
+                                    .as_ref()
+                                    .and_then(|c| c.chroma_format)
+                                    .map(|format| chroma_subsampling(format).to_string()),
+                                bit_depth: avc1.avc_config.as_ref().and_then(|c| c.bit_depth_luma),
+                                dolby_vision: avc1
+                                    .dolby_vision_config
+                                    .map(|config| config.codec_string()),
+                                hdr: HdrInfo::new(&avc1.hdr_metadata),
+                            }),
+                            SampleEntry::Hevc(hevc) => TrackInfo::Video(VideoTrack {
+                                width: hevc.width,
+                                height: hevc.height,
+                                profile: hevc.hevc_config.as_ref().map(|c| c.profile_name()),
+                                level: hevc.hevc_config.as_ref().map(|c| c.level()),
+                                chroma_subsampling: hevc
+                                    .hevc_config
+                                    .as_ref()
+                                    .map(|c| chroma_subsampling(c.chroma_format).to_string()),
+                                bit_depth: hevc.hevc_config.as_ref().map(|c| c.bit_depth_luma),
+                                dolby_vision: hevc
+                                    .dolby_vision_config
+                                    .map(|config| config.codec_string()),
+                                hdr: HdrInfo::new(&hevc.hdr_metadata),
                             }),
+                            SampleEntry::Mp4v(mp4v) => TrackInfo::Video(VideoTrack {
+                                width: mp4v.width,
+                                height: mp4v.height,
+                                profile: mp4v
+                                    .es_descriptor
+                                    .as_ref()
+                                    .and_then(|esds| esds.visual_profile_name()),
+                                level: None,
+                                chroma_subsampling: None,
+                                bit_depth: None,
+                                dolby_vision: None,
+                                hdr: None,
+                            }),
+                            SampleEntry::H263(h263) => TrackInfo::Video(VideoTrack {
+                                width: h263.width,
+                                height: h263.height,
+                                profile: h263
+                                    .h263_config
+                                    .as_ref()
+                                    .map(|config| config.profile.to_string()),
+                                level: h263
+                                    .h263_config
+                                    .as_ref()
+                                    .map(|config| config.level.to_string()),
+                                chroma_subsampling: None,
+                                bit_depth: None,
+                                dolby_vision: None,
+                                hdr: None,
+                            }),
+                            SampleEntry::Image(image) => TrackInfo::Video(VideoTrack {
+                                width: image.width,
+                                height: image.height,
+                                profile: None,
+                                level: None,
+                                chroma_subsampling: None,
+                                bit_depth: None,
+                                dolby_vision: None,
+                                hdr: None,
+                            }),
+                            // Listed as "other", with its fourcc
+                            SampleEntry::Mebx(_)
+                            | SampleEntry::TimedMetadata(_)
+                            | SampleEntry::Text(_)
+                            | SampleEntry::WebVtt(_)
+                            | SampleEntry::Encrypted(_)
+                            | SampleEntry::Unknown(_) => continue,
                         };
                         self.current_track.as_mut().unwrap().info = Some(info);
                     }
                 }
                 _ => {}
             }
+            if reader.failed() {
+                return;
+            }
 
-            let remaining = (box_end_offset - reader.position()) as u32;
+            let remaining = box_end_offset - reader.position();
             if remaining > 0 {
                 reader.skip_bytes(remaining).unwrap();
             }
 
             if &header.box_type == "trak" {
                 let track_builder = self.current_track.take().unwrap();
-                let id = track_builder.id.unwrap();
-                let info = track_builder.info.unwrap();
-                self.tracks.push(Track { id, info });
+                let id = match track_builder.id {
+                    Some(id) => id,
+                    None => {
+                        log::warn!(
+                            "Skipping the trak at {:#X}, as it has no tkhd",
+                            box_start_offset
+                        );
+                        continue;
+                    }
+                };
+                let duration =
+                    MediaTime::new(track_builder.duration, track_builder.timescale).as_secs_f64();
+                self.tracks.push(Track {
+                    id,
+                    handler: track_builder.handler.clone(),
+                    kind: TrackKind::from_handler_type(&track_builder.handler),
+                    enabled: track_builder.enabled,
+                    alternate_group: track_builder.alternate_group,
+                    layer: track_builder.layer,
+                    info: track_builder.info.unwrap_or(TrackInfo::Other {
+                        sample_entry_fourcc: track_builder.codec.clone(),
+                    }),
+                    codec: track_builder.codec,
+                    duration,
+                    language: track_builder.language,
+                    // Filled in from the model, like in the summary
+                    bitrate: 0,
+                });
             }
         }
     }
 }
+
+/// For HEIF and AVIF files, which are recognized by their brands
+fn image_info(movie: &Movie) -> Option<ImageInfo> {
+    let brand = ["avis", "avif", "heic", "heix", "msf1", "mif1"]
+        .iter()
+        .find(|&&brand| movie.has_brand(brand))?;
+    let primary_item = movie.primary_item();
+    let size = primary_item.and_then(|item| item.size());
+    Some(ImageInfo {
+        brand: brand.to_string(),
+        width: size.map(|(width, _)| width),
+        height: size.map(|(_, height)| height),
+        bit_depth: primary_item.and_then(|item| item.bit_depth()),
+        has_alpha: movie.items.iter().any(|item| item.is_alpha()),
+        item_count: movie.items.len(),
+        layer_count: primary_item.map_or(1, |item| item.layer_count()),
+        rotation: primary_item.map_or(0, |item| item.rotation()),
+        mirror: primary_item.and_then(|item| item.mirror()),
+        transformations: primary_item.map_or(vec![], |item| item.transformations()),
+    })
+}
+
+/// Groups the tracks that have the same, non-zero, alternate group
+fn alternate_groups(tracks: &[Track]) -> Vec<AlternateGroup> {
+    let mut ids: Vec<u16> = tracks
+        .iter()
+        .map(|t| t.alternate_group)
+        .filter(|&group| group != 0)
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    ids.into_iter()
+        .map(|id| {
+            let mut members: Vec<&Track> =
+                tracks.iter().filter(|t| t.alternate_group == id).collect();
+            members.sort_by_key(|t| (t.layer, t.id));
+            AlternateGroup {
+                id,
+                kind: members[0].kind.clone(),
+                renditions: members
+                    .iter()
+                    .map(|t| Rendition {
+                        track_id: t.id,
+                        language: t.language.clone(),
+                        default: t.enabled,
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
@@ -1,14 +1,32 @@
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufWriter, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
 
-use clap::{arg_enum, App, Arg};
+use clap::{arg_enum, App, Arg, ArgMatches};
+use serde_json::json;
 
-use mp4_parser::boxes::{BoxHeader, Mp4Box};
+use mp4_parser::box_stats::{box_stats, BoxTypeStats};
+use mp4_parser::diagnostics::{Diagnostics, Severity};
+use mp4_parser::edit::TimeRange;
+use mp4_parser::encryption::{EncryptionDump, EncryptionReport};
+use mp4_parser::error::ParseError;
+use mp4_parser::exit_code;
+use mp4_parser::extract;
+use mp4_parser::golden;
+use mp4_parser::hash::{hash_file_with_progress, FileHashes};
 use mp4_parser::logger::{
-    Logger, LOG_LEVEL_DEBUG, LOG_LEVEL_INFO, LOG_LEVEL_NONE, LOG_LEVEL_TRACE,
+    FacadeLogger, LogLevel, Logger, TextLogger, LOG_LEVEL_DEBUG, LOG_LEVEL_INFO, LOG_LEVEL_NONE,
+    LOG_LEVEL_TRACE,
 };
-use mp4_parser::quicktime::EncoderTag;
-use mp4_parser::reader::Reader;
+use mp4_parser::model::{Movie, Track, TrackSelector};
+use mp4_parser::probe::{probe, read_skipping_media_data};
+use mp4_parser::progress::ProgressBar;
+use mp4_parser::subtitles::{self, SubtitleFormat};
+use mp4_parser::summary::Summary;
+use mp4_parser::timed_metadata;
+use mp4_parser::tree::{parse_mp4, BoxFilter};
+use mp4_parser::xmp;
 
 arg_enum! {
     #[derive(PartialEq, Debug)]
@@ -20,15 +38,93 @@ arg_enum! {
     }
 }
 
+arg_enum! {
+    #[derive(PartialEq, Debug)]
+    pub enum LogOutputArg {
+        Console,
+        Log,
+    }
+}
+
+arg_enum! {
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum Format {
+        Text,
+        Jsonl,
+    }
+}
+
+/// Files with these extensions are picked up when searching directories
+const MP4_EXTENSIONS: [&str; 8] = ["mp4", "m4a", "m4v", "mov", "3gp", "m4s", "cmfv", "cmfa"];
+
+/// Flags that print something else than the box tree. Only one of them can be given.
+const MODES: [&str; 13] = [
+    "summary",
+    "probe",
+    "hash",
+    "stats",
+    "encryption",
+    "dump-encryption",
+    "timed-metadata",
+    "expect",
+    "xmp",
+    "xmp-pretty",
+    "extract-cover",
+    "extract-track",
+    "extract-keyframe",
+];
+
+/// The modes that can't be combined with `mode`. The XMP flags can be combined with each other.
+fn other_modes(mode: &str) -> Vec<&'static str> {
+    let is_xmp = mode.starts_with("xmp");
+    MODES
+        .iter()
+        .copied()
+        .filter(|&other| other != mode && !(is_xmp && other.starts_with("xmp")))
+        .collect()
+}
+
+/// The flags that the tree-only flags, e.g. --max-depth, can't be combined with
+fn modes_and_xmp_sidecar() -> Vec<&'static str> {
+    MODES.iter().copied().chain(["xmp-sidecar"]).collect()
+}
+
 fn main() {
     let matches = App::new("mp4-parser")
         .about("Parse an MP4 file")
         .arg(
             Arg::with_name("FILE")
-                .help("The mp4 file that should be parsed")
+                .help("The mp4 files that should be parsed, or directories with --recursive")
                 .required(true)
+                .multiple(true)
                 .index(1),
         )
+        .arg(
+            Arg::with_name("recursive")
+                .short("r")
+                .long("recursive")
+                .help("Parse all mp4 files in the given directories and their subdirectories"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("OUTPUT_FILE")
+                .help(
+                    "Write the report to this file instead of stdout. Warnings still go to stderr",
+                ),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&Format::variants())
+                .case_insensitive(true)
+                .default_value("text")
+                .help(
+                    "With jsonl, one JSON object is printed per file. Requires --summary, --probe, --hash, --stats, --encryption, --expect or --xmp",
+                ),
+        )
         .arg(
             Arg::with_name("loglevel")
                 .short("l")
@@ -38,122 +134,801 @@ fn main() {
                 .case_insensitive(true)
                 .help("Chooses the verbosity of the tool's output"),
         )
+        .arg(
+            Arg::with_name("log-output")
+                .long("log-output")
+                .value_name("OUTPUT")
+                .possible_values(&LogOutputArg::variants())
+                .case_insensitive(true)
+                .default_value("console")
+                .help("With log, the box tree is printed as log records on stderr (see RUST_LOG)"),
+        )
+        .arg(
+            Arg::with_name("no-color")
+                .long("no-color")
+                .help("Disable colors, which are otherwise used when printing to a terminal"),
+        )
+        .arg(
+            Arg::with_name("spec-refs")
+                .long("spec-refs")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .help("Print the spec section that defines each box, e.g. ISO/IEC 14496-12 §8.3.2"),
+        )
+        .arg(
+            Arg::with_name("hexdump-fields")
+                .long("hexdump-fields")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .help("Print the fields of each box next to their raw bytes"),
+        )
+        .arg(
+            Arg::with_name("hex-offsets")
+                .long("hex-offsets")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .help("Print box offsets in hex, e.g. [0x0000671C], as hex editors show them"),
+        )
+        .arg(
+            Arg::with_name("relative-offsets")
+                .long("relative-offsets")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .overrides_with("absolute-offsets")
+                .help("Print box offsets relative to the start of the parent box"),
+        )
+        .arg(
+            Arg::with_name("absolute-offsets")
+                .long("absolute-offsets")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .overrides_with("relative-offsets")
+                .help("Print box offsets from the start of the file (the default)"),
+        )
+        .arg(
+            Arg::with_name("local-time")
+                .long("local-time")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .help("Show creation and modification times in the local time zone instead of UTC"),
+        )
+        .arg(
+            Arg::with_name("only")
+                .long("only")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .value_name("BOX_TYPES")
+                .use_delimiter(true)
+                .help("Only print boxes of these types, e.g. ftyp,moov,trak,stsd"),
+        )
+        .arg(
+            Arg::with_name("skip")
+                .long("skip")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .value_name("BOX_TYPES")
+                .use_delimiter(true)
+                .help("Don't print boxes of these types or their children, e.g. mdat,free"),
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .value_name("DEPTH")
+                .validator(|v| match v.parse::<usize>() {
+                    Ok(depth) if depth > 0 => Ok(()),
+                    _ => Err(format!("Expected a positive number, got '{}'", v)),
+                })
+                .help(
+                    "Don't print boxes nested deeper than this, e.g. 2 for moov and its children",
+                ),
+        )
+        .arg(
+            Arg::with_name("top-level")
+                .long("top-level")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .conflicts_with("max-depth")
+                .help("Only print the top-level boxes. Same as --max-depth 1"),
+        )
+        .arg(
+            Arg::with_name("track")
+                .long("track")
+                .value_name("TRACK")
+                .validator(|v| v.parse::<TrackSelector>().map(|_| ()))
+                .help(
+                    "Only show one track: a track ID, index:N (counting from 0) or a kind like \
+                     video. Applies to the box tree, --summary, --probe, --hash and \
+                     --extract-keyframe",
+                ),
+        )
+        .arg(
+            Arg::with_name("summary")
+                .long("summary")
+                .conflicts_with_all(&other_modes("summary"))
+                .help("Print brand, duration and tracks instead of the box tree"),
+        )
+        .arg(
+            Arg::with_name("probe")
+                .long("probe")
+                .conflicts_with_all(&other_modes("probe"))
+                .help("Print the summary after reading only ftyp and moov, skipping the media data"),
+        )
+        .arg(
+            Arg::with_name("hash")
+                .long("hash")
+                .conflicts_with_all(&other_modes("hash"))
+                .help("Print SHA-256 hashes of top-level boxes and tracks instead of the tree"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .conflicts_with_all(&other_modes("stats"))
+                .help("Print how many boxes of each type there are and their total size instead of the tree"),
+        )
+        .arg(
+            Arg::with_name("encryption")
+                .long("encryption")
+                .conflicts_with_all(&other_modes("encryption"))
+                .help("Print how the tracks are encrypted (CENC) instead of the box tree"),
+        )
+        .arg(
+            Arg::with_name("timed-metadata")
+                .long("timed-metadata")
+                .conflicts_with_all(&other_modes("timed-metadata"))
+                .help(
+                    "Print emsg events and metadata track samples, with ID3 decoded, instead of \
+                     the box tree",
+                ),
+        )
+        .arg(
+            Arg::with_name("from")
+                .long("from")
+                .value_name("SECONDS")
+                .requires("timed-metadata")
+                .validator(validate_time)
+                .help("With --timed-metadata, only print what is presented from this time on"),
+        )
+        .arg(
+            Arg::with_name("to")
+                .long("to")
+                .value_name("SECONDS")
+                .requires("timed-metadata")
+                .validator(validate_time)
+                .help("With --timed-metadata, only print what is presented before this time"),
+        )
+        .arg(
+            Arg::with_name("dump-encryption")
+                .long("dump-encryption")
+                .conflicts_with_all(&other_modes("dump-encryption"))
+                .value_name("JSON_FILE")
+                .help("Write the IV and subsamples of each encrypted sample to a JSON file"),
+        )
+        .arg(
+            Arg::with_name("expect")
+                .long("expect")
+                .conflicts_with_all(&other_modes("expect"))
+                .value_name("GOLDEN_FILE")
+                .help("Compare the summary with a golden JSON file and report the differences"),
+        )
+        .arg(
+            Arg::with_name("update-expected")
+                .long("update-expected")
+                .requires("expect")
+                .help("Write the summary to the --expect file instead of comparing with it"),
+        )
+        .arg(
+            Arg::with_name("xmp")
+                .long("xmp")
+                .conflicts_with_all(&other_modes("xmp"))
+                .help("Print the XMP metadata packet instead of the box tree"),
+        )
+        .arg(
+            Arg::with_name("xmp-pretty")
+                .long("xmp-pretty")
+                .conflicts_with_all(&other_modes("xmp-pretty"))
+                .help("Like --xmp, but with one XML element per line, indented"),
+        )
+        .arg(
+            Arg::with_name("xmp-sidecar")
+                .long("xmp-sidecar")
+                .conflicts_with_all(&other_modes("xmp"))
+                .help("Write the XMP metadata packet to a .xmp file next to the mp4 file"),
+        )
+        .arg(
+            Arg::with_name("extract-cover")
+                .long("extract-cover")
+                .conflicts_with_all(&other_modes("extract-cover"))
+                .value_name("IMAGE_FILE")
+                .help("Write the cover art to a file. The extension is added if there is none"),
+        )
+        .arg(
+            Arg::with_name("extract-track")
+                .long("extract-track")
+                .conflicts_with_all(&other_modes("extract-track"))
+                .value_names(&["TRACK", "FILE"])
+                .conflicts_with("track")
+                .help("Write the sample data of a track (picked like with --track) to a file"),
+        )
+        .arg(
+            Arg::with_name("extract-keyframe")
+                .long("extract-keyframe")
+                .conflicts_with_all(&other_modes("extract-keyframe"))
+                .value_names(&["SECONDS", "FILE"])
+                .conflicts_with("extract-track")
+                .help(
+                    "Write the keyframe of the first video track (or --track) that is closest to \
+                     a time as an H.264/HEVC Annex B access unit. FILE can be - for stdout",
+                ),
+        )
+        .arg(
+            Arg::with_name("wav")
+                .long("wav")
+                .requires("extract-track")
+                .help("Write an uncompressed audio track as a WAV file, with --extract-track"),
+        )
+        .arg(
+            Arg::with_name("srt")
+                .long("srt")
+                .requires("extract-track")
+                .conflicts_with_all(&["wav", "vtt"])
+                .help("Write a tx3g or wvtt subtitle track as an .srt file, with --extract-track"),
+        )
+        .arg(
+            Arg::with_name("vtt")
+                .long("vtt")
+                .requires("extract-track")
+                .conflicts_with("wav")
+                .help("Write a tx3g or wvtt subtitle track as a .vtt file, with --extract-track"),
+        )
+        .arg(Arg::with_name("no-progress").long("no-progress").help(
+            "Don't show progress on stderr while parsing with --summary or hashing with --hash",
+        ))
+        .arg(
+            Arg::with_name("fail-on-warning")
+                .long("fail-on-warning")
+                .help("Exit with code 2 if any warnings were printed"),
+        )
+        .arg(
+            Arg::with_name("no-diagnostics")
+                .long("no-diagnostics")
+                .conflicts_with_all(&modes_and_xmp_sidecar())
+                .help("Don't list the problems found while parsing after the boxes"),
+        )
         .get_matches();
 
+    // Only the parser's own warnings go through the log crate, unless the box tree does too
+    let default_filter = match matches.value_of("log-output").unwrap().parse().unwrap() {
+        LogOutputArg::Console => "warn",
+        LogOutputArg::Log => match verbosity(&matches) {
+            LOG_LEVEL_NONE => "off",
+            LOG_LEVEL_INFO => "info",
+            LOG_LEVEL_DEBUG => "debug",
+            _ => "trace",
+        },
+    };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_filter))
+        .init();
+    exit_code::set_panic_hook();
+
+    let format: Format = matches.value_of("format").unwrap().parse().unwrap();
+    if format == Format::Jsonl
+        && !matches.is_present("summary")
+        && !matches.is_present("probe")
+        && !matches.is_present("hash")
+        && !matches.is_present("stats")
+        && !matches.is_present("encryption")
+        && !matches.is_present("timed-metadata")
+        && !matches.is_present("expect")
+        && !matches.is_present("xmp")
+    {
+        eprintln!(
+            "ERROR: --format jsonl requires --summary, --probe, --hash, --stats, --encryption, \
+             --timed-metadata, --expect or --xmp"
+        );
+        std::process::exit(exit_code::PARSE_ERROR);
+    }
+
+    let recursive = matches.is_present("recursive");
+    let mut paths = Vec::new();
+    for path in matches.values_of("FILE").unwrap() {
+        collect_paths(Path::new(path), recursive, &mut paths);
+    }
+
+    let mut out: Box<dyn Write> = match matches.value_of("output") {
+        Some(output) => match File::create(output) {
+            Ok(f) => Box::new(BufWriter::new(f)),
+            Err(e) => {
+                eprintln!("ERROR: {}: {}", output, e);
+                std::process::exit(exit_code::PARSE_ERROR);
+            }
+        },
+        None => Box::new(std::io::stdout()),
+    };
+
+    let batch = paths.len() > 1 || recursive;
+    if batch {
+        // Errors are reported per file instead
+        panic::set_hook(Box::new(|_| {}));
+    }
+    // In a batch, the highest exit code of any file is used
+    let mut code = exit_code::OK;
+    for path in &paths {
+        if batch && format == Format::Text {
+            writeln!(out, "==> {} <==", path.display()).unwrap();
+        }
+        let error = match exit_code::catch(|| process_file(path, &matches, format, &mut out)) {
+            Ok(Ok(file_code)) => {
+                code = code.max(file_code);
+                continue;
+            }
+            Ok(Err(error)) => {
+                if format == Format::Text && !batch {
+                    eprintln!("ERROR: {}", error);
+                }
+                error
+            }
+            // The output was closed early, e.g. by `| head`
+            Err(error) if exit_code::for_error(&error.message) == exit_code::OK => {
+                std::process::exit(exit_code::OK)
+            }
+            // Outside of a batch, the panic has already been printed
+            Err(error) => error,
+        };
+        match format {
+            Format::Text if batch => eprintln!("ERROR: {}: {}", path.display(), error),
+            Format::Text => {}
+            Format::Jsonl => {
+                let line = json!({
+                    "file": path.display().to_string(),
+                    "error": error.message,
+                    "box_path": error.box_path,
+                    "offset": error.offset,
+                });
+                writeln!(out, "{}", line).unwrap();
+            }
+        }
+        code = code.max(exit_code::for_error(&error.message));
+    }
+    out.flush().unwrap_or_else(|e| panic!("{}", e));
+    std::process::exit(code);
+}
+
+/// Adds `path` to `paths`, or if it's a directory and `recursive` is set, all mp4 files in it
+fn collect_paths(path: &Path, recursive: bool, paths: &mut Vec<PathBuf>) {
+    if !(recursive && path.is_dir()) {
+        paths.push(path.to_path_buf());
+        return;
+    }
+    let mut entries: Vec<PathBuf> = match std::fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(e) => {
+            eprintln!("ERROR: {}: {}", path.display(), e);
+            return;
+        }
+    };
+    entries.sort();
+    for entry in entries {
+        let is_mp4 = entry
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| MP4_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+        if entry.is_dir() || is_mp4 {
+            collect_paths(&entry, recursive, paths);
+        }
+    }
+}
+
+fn validate_time(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs >= 0.0 => Ok(()),
+        _ => Err(format!("Expected a time in seconds, got '{}'", value)),
+    }
+}
+
+fn verbosity(matches: &ArgMatches) -> LogLevel {
     let log_level = matches.value_of("loglevel").map(|v| v.to_lowercase());
-    let path = matches.value_of("FILE").unwrap();
-    let verbosity = match log_level.as_ref().map(|v| &v[..]) {
+    match log_level.as_ref().map(|v| &v[..]) {
         Some("none") => LOG_LEVEL_NONE,
         Some("info") => LOG_LEVEL_INFO,
         Some("debug") => LOG_LEVEL_DEBUG,
         Some("trace") => LOG_LEVEL_TRACE,
         None => LOG_LEVEL_DEBUG,
         _ => panic!("Unhandled log level: {:?}", log_level),
-    };
-    let mut f = File::open(&path).unwrap();
-    let mut buf = Vec::new();
-    f.read_to_end(&mut buf).unwrap();
-    let mut logger = Logger::new(verbosity);
-    logger.debug(format!("Read {} bytes", buf.len()));
-
-    parse_mp4(&mut buf, &mut logger);
-}
-
-#[derive(Copy, Clone)]
-enum HandleUnknown {
-    Skip,
-    Panic,
+    }
 }
 
-fn parse_mp4(buf: &mut Vec<u8>, mut logger: &mut Logger) {
-    let mut reader = Reader::new(buf);
-
-    _parse(
-        &mut reader,
-        &mut logger,
-        HandleUnknown::Panic,
-        buf.len() as u64,
-    );
-
-    logger.debug(format!("[{}]", reader.position()));
-    logger.debug("Reached end of file");
-}
+/// Writes the report for the file to `out` and returns the exit code for the file
+fn process_file(
+    path: &Path,
+    matches: &ArgMatches,
+    format: Format,
+    out: &mut dyn Write,
+) -> Result<i32, ParseError> {
+    let track_selector: Option<TrackSelector> =
+        matches.value_of("track").map(|t| t.parse().unwrap());
+    let mut f = File::open(path).unwrap_or_else(|e| panic!("{}", e));
+    if matches.is_present("probe") {
+        let probe = probe(&mut f).unwrap_or_else(|e| panic!("{}", e));
+        let mut summary =
+            Summary::from_layout(&probe.movie, &probe.top_level_boxes, probe.fragmented);
+        if let Some(selector) = &track_selector {
+            let track_id = select_track(&probe.movie, selector).id;
+            summary.tracks.retain(|track| track.id == track_id);
+        }
+        match format {
+            Format::Text => write!(out, "{}", summary),
+            Format::Jsonl => writeln!(
+                out,
+                "{}",
+                json!({"file": path.display().to_string(), "summary": summary})
+            ),
+        }
+        .unwrap();
+        return Ok(exit_code::OK);
+    }
+    let buf = read_file(&mut f, matches);
 
-fn _parse(
-    reader: &mut Reader,
-    logger: &mut Logger,
-    handle_unknown: HandleUnknown,
-    end_offset: u64,
-) {
-    while reader.position() < end_offset {
-        let box_start_offset = reader.position();
-
-        let header = BoxHeader::parse(reader);
-
-        logger.log_start_of_box(header.start_offset);
-        logger.debug_box(format!("{:?} ({} bytes)", header.box_type, header.box_size));
-
-        let box_ = Mp4Box::parse_contents(reader, &header.box_type, header.inner_size);
-        // println!("DEBUG: Parsed box: {:?}", box_);
-
-        let box_ = match box_ {
-            Some(b) => b,
-            None => match handle_unknown {
-                HandleUnknown::Skip => {
-                    logger.log_box_title(format!(
-                        "Skipping unknown: '{}' ({} bytes)",
-                        header.box_type, header.box_size
-                    ));
-                    reader
-                        .skip_bytes(header.inner_size as u32)
-                        .unwrap_or_else(|e| panic!("Truncated '{}' box: {}", header.box_type, e));
-                    continue;
+    let file = path.display().to_string();
+    let show_progress = !matches.is_present("no-progress");
+    if let Some(golden_path) = matches.value_of("expect") {
+        let movie = Movie::parse(&buf)?;
+        let actual = serde_json::to_value(Summary::new(&buf, &movie)).unwrap();
+        if matches.is_present("update-expected") {
+            let json = serde_json::to_string_pretty(&actual).unwrap();
+            std::fs::write(golden_path, json + "\n")
+                .unwrap_or_else(|e| panic!("{}: {}", golden_path, e));
+            return Ok(exit_code::OK);
+        }
+        let golden =
+            std::fs::read(golden_path).unwrap_or_else(|e| panic!("{}: {}", golden_path, e));
+        let expected: serde_json::Value = serde_json::from_slice(&golden)
+            .unwrap_or_else(|e| panic!("{}: invalid JSON: {}", golden_path, e));
+        let mismatches = golden::compare(&expected, &actual);
+        match format {
+            Format::Text if mismatches.is_empty() => {
+                writeln!(out, "OK: matches {}", golden_path).unwrap()
+            }
+            Format::Text => {
+                for mismatch in &mismatches {
+                    writeln!(out, "MISMATCH: {}", mismatch).unwrap();
                 }
-                HandleUnknown::Panic => {
-                    todo!(
-                        "Unhandled box: {:?} (inner size: {})",
-                        header.box_type,
-                        header.inner_size
-                    );
+            }
+            Format::Jsonl => {
+                let report: Vec<String> = mismatches.iter().map(|m| m.to_string()).collect();
+                let line = json!({"file": file, "expect": golden_path, "mismatches": report});
+                writeln!(out, "{}", line).unwrap();
+            }
+        }
+        return Ok(if mismatches.is_empty() {
+            exit_code::OK
+        } else {
+            exit_code::WARNINGS
+        });
+    }
+    if matches.is_present("xmp")
+        || matches.is_present("xmp-pretty")
+        || matches.is_present("xmp-sidecar")
+    {
+        let xmp = Movie::parse(&buf)?.xmp;
+        if matches.is_present("xmp-sidecar") {
+            if let Some(xmp) = &xmp {
+                let sidecar = xmp::sidecar_path(path);
+                std::fs::write(&sidecar, xmp)
+                    .unwrap_or_else(|e| panic!("{}: {}", sidecar.display(), e));
+                eprintln!("Wrote {}", sidecar.display());
+            }
+        }
+        let print = matches.is_present("xmp") || matches.is_present("xmp-pretty");
+        match (format, &xmp) {
+            (Format::Jsonl, _) => writeln!(out, "{}", json!({"file": file, "xmp": xmp})).unwrap(),
+            (Format::Text, Some(xmp)) if matches.is_present("xmp-pretty") => {
+                writeln!(out, "{}", xmp::pretty_print(xmp)).unwrap()
+            }
+            (Format::Text, Some(xmp)) if print => writeln!(out, "{}", xmp).unwrap(),
+            (Format::Text, Some(_)) => {}
+            (Format::Text, None) => eprintln!("WARN: {}: no XMP metadata found", file),
+        }
+        return Ok(exit_code::OK);
+    }
+    if let Some(cover_path) = matches.value_of("extract-cover") {
+        let movie = Movie::parse(&buf)?;
+        let cover_art = match movie.cover_art() {
+            Some(cover_art) => cover_art,
+            None => {
+                eprintln!("WARN: {}: no cover art found", file);
+                return Ok(exit_code::OK);
+            }
+        };
+        let mut cover_path = PathBuf::from(cover_path);
+        match cover_path.extension() {
+            None => {
+                cover_path.set_extension(cover_art.format.extension());
+            }
+            Some(extension)
+                if !cover_art
+                    .format
+                    .matches_extension(&extension.to_string_lossy()) =>
+            {
+                eprintln!(
+                    "WARN: the cover art is {}, but is written to {}",
+                    cover_art.format,
+                    cover_path.display()
+                )
+            }
+            Some(_) => {}
+        }
+        std::fs::write(&cover_path, &cover_art.data)
+            .unwrap_or_else(|e| panic!("{}: {}", cover_path.display(), e));
+        eprintln!(
+            "Wrote {} ({}, {} bytes)",
+            cover_path.display(),
+            cover_art.format,
+            cover_art.data.len()
+        );
+        return Ok(exit_code::OK);
+    }
+    if let Some(mut values) = matches.values_of("extract-keyframe") {
+        let time = values.next().unwrap();
+        let output_path = values.next().unwrap();
+        let time: f64 = time
+            .parse()
+            .ok()
+            .filter(|time: &f64| time.is_finite())
+            .unwrap_or_else(|| panic!("Invalid time: {}", time));
+        let movie = Movie::parse(&buf)?;
+        let track = match &track_selector {
+            Some(selector) => select_track(&movie, selector),
+            None => match movie.tracks.iter().find(|t| t.is_video()) {
+                Some(track) => track,
+                None => {
+                    eprintln!("WARN: {}: no video track", file);
+                    return Ok(exit_code::OK);
                 }
             },
         };
+        let keyframe =
+            extract::keyframe(&buf, &movie, track, time).unwrap_or_else(|e| panic!("{}", e));
+        if output_path == "-" {
+            std::io::stdout().write_all(&keyframe.data).unwrap();
+        } else {
+            std::fs::write(output_path, &keyframe.data)
+                .unwrap_or_else(|e| panic!("{}: {}", output_path, e));
+        }
+        eprintln!(
+            "Wrote {} (track {}, sample {} at {:.3}s, {} bytes)",
+            output_path,
+            track.id,
+            keyframe.sample_number,
+            keyframe.time,
+            keyframe.data.len()
+        );
+        return Ok(exit_code::OK);
+    }
+    if let Some(mut values) = matches.values_of("extract-track") {
+        let selector: TrackSelector = values
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap_or_else(|e| panic!("{}", e));
+        let output_path = values.next().unwrap();
+        let movie = Movie::parse(&buf)?;
+        let track = select_track(&movie, &selector);
+        let subtitle_format = if matches.is_present("srt") {
+            Some(SubtitleFormat::Srt)
+        } else if matches.is_present("vtt") {
+            Some(SubtitleFormat::WebVtt)
+        } else {
+            None
+        };
+        let data = if matches.is_present("wav") {
+            extract::to_wav(&buf, &movie, track).unwrap_or_else(|e| panic!("{}", e))
+        } else if let Some(subtitle_format) = subtitle_format {
+            subtitles::export(&buf, &movie, track, subtitle_format)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Subtitle output is not supported for track {}, which isn't tx3g or wvtt",
+                        track.id
+                    )
+                })
+                .into_bytes()
+        } else {
+            extract::track_data(&buf, &movie, track)
+        };
+        std::fs::write(output_path, &data).unwrap_or_else(|e| panic!("{}: {}", output_path, e));
+        eprintln!(
+            "Wrote {} (track {}, {} bytes)",
+            output_path,
+            track.id,
+            data.len()
+        );
+        return Ok(exit_code::OK);
+    }
+    if matches.is_present("summary") {
+        let mut progress = ProgressBar::new("Parsing").with_enabled(show_progress);
+        let movie =
+            Movie::parse_with_progress(&buf, &mut |done, total| progress.update(done, total))?;
+        progress.finish();
+        let mut summary = Summary::new(&buf, &movie);
+        if let Some(selector) = &track_selector {
+            let track_id = select_track(&movie, selector).id;
+            summary.tracks.retain(|track| track.id == track_id);
+        }
+        match format {
+            Format::Text => write!(out, "{}", summary),
+            Format::Jsonl => writeln!(out, "{}", json!({"file": file, "summary": summary})),
+        }
+        .unwrap();
+        return Ok(exit_code::OK);
+    }
+    if matches.is_present("hash") {
+        let mut progress = ProgressBar::new("Hashing").with_enabled(show_progress);
+        let mut hashes =
+            hash_file_with_progress(&buf, &mut |done, total| progress.update(done, total))?;
+        progress.finish();
+        if let Some(selector) = &track_selector {
+            let track_id = select_track(&Movie::parse(&buf)?, selector).id;
+            hashes.boxes.clear();
+            hashes.tracks.retain(|track| track.track_id == track_id);
+        }
+        match format {
+            Format::Text => print_hashes(&hashes, out),
+            Format::Jsonl => writeln!(out, "{}", json!({"file": file, "hashes": hashes})).unwrap(),
+        }
+        return Ok(exit_code::OK);
+    }
+    if matches.is_present("stats") {
+        let stats = box_stats(&buf);
+        match format {
+            Format::Text => print_box_stats(&stats, out),
+            Format::Jsonl => writeln!(out, "{}", json!({"file": file, "stats": stats})).unwrap(),
+        }
+        return Ok(exit_code::OK);
+    }
 
-        logger.log_box_title(box_.name());
-        box_.print_attributes(|k, v| logger.debug_box_attr(k, v));
-
-        let box_end_offset = box_start_offset + header.box_size;
-        match box_ {
-            Mp4Box::Container(_) => {
-                logger.increase_indent();
-                //println!("DEBUG: It's a container. Will jump into it");
-                _parse(reader, logger, HandleUnknown::Skip, box_end_offset);
-                logger.decrease_indent();
-            }
-            Mp4Box::QuickTimeMetadataItemList(metadata_item_list) => {
-                logger.increase_indent();
-                while reader.position() < box_end_offset {
-                    let tag: EncoderTag = metadata_item_list.parse_entry(reader);
-                    logger.debug_box(format!("{:?}", tag));
-                }
-                logger.decrease_indent();
-            }
-            Mp4Box::Stsd(sample_description_box) => {
-                logger.increase_indent();
-                for _ in 0..sample_description_box.entry_count {
-                    let entry = sample_description_box.parse_entry(reader);
-                    logger.debug_box(entry.name());
-                    entry.print_attributes(|k, v| logger.debug_box_attr(k, v));
+    if let Some(dump_path) = matches.value_of("dump-encryption") {
+        let movie = Movie::parse(&buf)?;
+        let dump = EncryptionDump::new(&buf, &movie);
+        let json = serde_json::to_string_pretty(&dump).unwrap();
+        std::fs::write(dump_path, json + "\n").unwrap_or_else(|e| panic!("{}: {}", dump_path, e));
+        let sample_count: usize = dump
+            .tracks
+            .iter()
+            .flat_map(|track| track.fragments.iter())
+            .map(|fragment| fragment.samples.len())
+            .sum();
+        eprintln!("Wrote {} ({} samples)", dump_path, sample_count);
+        return Ok(exit_code::OK);
+    }
+    if matches.is_present("encryption") {
+        let movie = Movie::parse(&buf)?;
+        let report = EncryptionReport::new(&movie);
+        match format {
+            Format::Text => write!(out, "{}", report),
+            Format::Jsonl => writeln!(out, "{}", json!({"file": file, "encryption": report})),
+        }
+        .unwrap();
+        return Ok(exit_code::OK);
+    }
+    if matches.is_present("timed-metadata") {
+        let movie = Movie::parse(&buf)?;
+        let range = TimeRange {
+            start: matches.value_of("from").map(|t| t.parse().unwrap()),
+            end: matches.value_of("to").map(|t| t.parse().unwrap()),
+        };
+        let entries = timed_metadata::collect(&buf, &movie);
+        for entry in entries.iter().filter(|entry| range.contains(entry.time)) {
+            match format {
+                Format::Text => writeln!(out, "{}", entry),
+                Format::Jsonl => {
+                    writeln!(out, "{}", json!({"file": file, "timed_metadata": entry}))
                 }
-                logger.decrease_indent();
             }
-            _ => {}
+            .unwrap();
         }
+        return Ok(exit_code::OK);
+    }
+
+    let to_file = matches.is_present("output");
+    let color = !matches.is_present("no-color")
+        && std::env::var_os("NO_COLOR").is_none()
+        && !to_file
+        && std::io::stdout().is_terminal();
+    let mut logger: Box<dyn Logger + '_> =
+        match matches.value_of("log-output").unwrap().parse().unwrap() {
+            LogOutputArg::Console => Box::new(
+                TextLogger::with_writer(&mut *out, verbosity(matches))
+                    .with_color(color)
+                    .with_warnings_on_stderr(to_file)
+                    .with_hex_offsets(matches.is_present("hex-offsets"))
+                    .with_relative_offsets(matches.is_present("relative-offsets")),
+            ),
+            LogOutputArg::Log => Box::new(FacadeLogger::default()),
+        };
+    logger.debug(&format!("Read {} bytes", buf.len()));
+
+    let filter = BoxFilter {
+        only: matches
+            .values_of("only")
+            .map(|types| types.map(|t| t.to_string()).collect()),
+        skip: matches
+            .values_of("skip")
+            .map(|types| types.map(|t| t.to_string()).collect())
+            .unwrap_or_default(),
+        max_depth: if matches.is_present("top-level") {
+            Some(1)
+        } else {
+            matches.value_of("max-depth").map(|d| d.parse().unwrap())
+        },
+        hexdump_fields: matches.is_present("hexdump-fields"),
+        spec_refs: matches.is_present("spec-refs"),
+        local_time: matches.is_present("local-time"),
+        track_id: match &track_selector {
+            Some(selector) => Some(select_track(&Movie::parse(&buf)?, selector).id),
+            None => None,
+        },
+    };
+
+    let (result, diagnostics) = Diagnostics::collect(|| parse_mp4(&buf, logger.as_mut(), &filter));
+    result?;
+    let warning_count = logger.warning_count() + diagnostics.count(Severity::Warning);
+    drop(logger);
+    if !diagnostics.is_empty() && !matches.is_present("no-diagnostics") {
+        write!(out, "\n{}", diagnostics).unwrap();
+    }
+    if matches.is_present("fail-on-warning") && warning_count > 0 {
+        Ok(exit_code::WARNINGS)
+    } else {
+        Ok(exit_code::OK)
+    }
+}
 
-        let remaining = (box_end_offset - reader.position()) as u32;
-        if remaining > 0 {
-            // println!("DEBUG: Skipping {} bytes of {}", remaining, header.box_type);
-            reader.skip_bytes(remaining).unwrap();
+/// The whole file for the modes that read sample data. Otherwise the media data in mdat is
+/// skipped, and left as zeros, so that large files are parsed without reading all of them.
+fn read_file(f: &mut File, matches: &ArgMatches) -> Vec<u8> {
+    let needs_media_data = [
+        "hash",
+        "timed-metadata",
+        "dump-encryption",
+        "extract-track",
+        "extract-keyframe",
+    ]
+    .iter()
+    .any(|mode| matches.is_present(mode));
+    if !needs_media_data {
+        // A file whose top-level boxes can't be read this way is read in full, for the parser
+        // to report where it's broken
+        if let Ok(buf) = read_skipping_media_data(f) {
+            return buf;
         }
+        f.seek(SeekFrom::Start(0))
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf).unwrap_or_else(|e| panic!("{}", e));
+    buf
+}
+
+/// The track picked with --track, which has to exist
+fn select_track<'a>(movie: &'a Movie, selector: &TrackSelector) -> &'a Track {
+    movie
+        .select_track(selector)
+        .unwrap_or_else(|| panic!("No track matches '{}'", selector))
+}
+
+fn print_box_stats(stats: &[BoxTypeStats], out: &mut dyn Write) {
+    writeln!(out, "{:<6} {:>8} {:>14}", "Box", "Count", "Total bytes").unwrap();
+    for s in stats {
+        writeln!(out, "{:<6} {:>8} {:>14}", s.box_type, s.count, s.total_size).unwrap();
+    }
+}
+
+fn print_hashes(hashes: &FileHashes, out: &mut dyn Write) {
+    for b in &hashes.boxes {
+        writeln!(
+            out,
+            "{}  box '{}' at {} ({} bytes)",
+            b.sha256, b.box_type, b.offset, b.size
+        )
+        .unwrap();
+    }
+    for t in &hashes.tracks {
+        writeln!(
+            out,
+            "{}  track {} ({}, {} samples)",
+            t.sha256, t.track_id, t.handler_type, t.sample_count
+        )
+        .unwrap();
     }
 }
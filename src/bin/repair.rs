@@ -0,0 +1,78 @@
+use std::fs::File;
+use std::io::{Read, Write};
+
+use clap::{App, Arg};
+
+use mp4_parser::exit_code;
+use mp4_parser::repair::repair;
+
+fn main() {
+    exit_code::exit_on_panic(run)
+}
+
+fn run() {
+    let matches = App::new("mp4-repair")
+        .about("Recover a playable file from one that has a missing or corrupt moov box")
+        .arg(
+            Arg::with_name("FILE")
+                .help("The broken mp4 file, e.g. from a camera that lost power while recording")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("reference")
+                .short("r")
+                .long("reference")
+                .value_name("REFERENCE_FILE")
+                .required(true)
+                .help("A healthy file recorded with the same device and settings"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("OUTPUT_FILE")
+                .required(true)
+                .help("Where the repaired file should be written"),
+        )
+        .get_matches();
+
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    exit_code::set_panic_hook();
+
+    let broken = read_file(matches.value_of("FILE").unwrap());
+    let reference = read_file(matches.value_of("reference").unwrap());
+    let output_path = matches.value_of("output").unwrap();
+
+    let (repaired, report) = repair(&broken, &reference).unwrap_or_else(|e| {
+        eprintln!("ERROR: {}", e);
+        std::process::exit(exit_code::PARSE_ERROR);
+    });
+
+    for warning in &report.warnings {
+        println!("WARN: {}", warning);
+    }
+    for track in &report.tracks {
+        println!(
+            "Track {} ({}): recovered {} samples ({} sync samples)",
+            track.track_id, track.handler_type, track.sample_count, track.sync_sample_count
+        );
+    }
+    if report.unreferenced_bytes > 0 {
+        println!(
+            "{} bytes of media data couldn't be attributed to any track",
+            report.unreferenced_bytes
+        );
+    }
+
+    let mut f = File::create(output_path).unwrap();
+    f.write_all(&repaired).unwrap();
+    println!("Wrote {} bytes to {}", repaired.len(), output_path);
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    let mut f = File::open(path).unwrap();
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf).unwrap();
+    buf
+}
@@ -0,0 +1,71 @@
+use std::fs::File;
+use std::io::Read;
+
+use clap::{App, Arg};
+
+use mp4_parser::exit_code;
+use mp4_parser::model::Movie;
+use mp4_parser::validate::{check_box_structure, validate, Severity, ValidationOptions};
+
+fn main() {
+    let matches = App::new("mp4-validate")
+        .about("Check an MP4 file for timing problems")
+        .arg(
+            Arg::with_name("FILE")
+                .help("The mp4 file that should be validated")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("max-av-mismatch")
+                .long("max-av-mismatch")
+                .value_name("SECONDS")
+                .validator(|v| v.parse::<f64>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("How much the audio and video durations may differ (default: 0.2)"),
+        )
+        .arg(
+            Arg::with_name("max-interleave-depth")
+                .long("max-interleave-depth")
+                .value_name("SECONDS")
+                .validator(|v| v.parse::<f64>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("How far apart in time the audio and video data may be (default: 1.0)"),
+        )
+        .arg(
+            Arg::with_name("fail-on-warning")
+                .long("fail-on-warning")
+                .help("Exit with code 2 on warnings too, not only on errors"),
+        )
+        .get_matches();
+
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    exit_code::set_panic_hook();
+
+    let path = matches.value_of("FILE").unwrap();
+    let mut f = File::open(path).unwrap();
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf).unwrap();
+
+    let mut options = ValidationOptions::default();
+    if let Some(max_mismatch) = matches.value_of("max-av-mismatch") {
+        options.max_av_duration_mismatch = max_mismatch.parse().unwrap();
+    }
+    if let Some(max_depth) = matches.value_of("max-interleave-depth") {
+        options.max_interleave_depth = max_depth.parse().unwrap();
+    }
+
+    let movie = exit_code::exit_on_error(Movie::parse(&buf));
+    let mut issues = check_box_structure(&buf);
+    issues.extend(validate(&movie, &options));
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    println!("Found {} issue(s)", issues.len());
+
+    let fail_on_warning = matches.is_present("fail-on-warning");
+    if issues
+        .iter()
+        .any(|issue| issue.severity == Severity::Error || fail_on_warning)
+    {
+        std::process::exit(exit_code::WARNINGS);
+    }
+}
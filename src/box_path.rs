@@ -0,0 +1,135 @@
+//! The boxes that are being parsed, so that a parse error can say where in the file it happened,
+//! e.g. "moov/trak[0]/mdia/minf/stbl/stsd/avc1/avcC @ 0x4F32". Every box header that is parsed
+//! replaces the boxes on the path that don't contain it, so the parsers don't need to say when
+//! they're done with a box. The path is kept per thread, and only with the `std` feature.
+
+/// Boxes that usually come more than once, whose index is always shown. For other boxes it's
+/// only shown after the first one, e.g. "stsd/avc1[1]".
+#[cfg(feature = "std")]
+const REPEATED: [&str; 3] = ["trak", "moof", "traf"];
+
+#[cfg(feature = "std")]
+mod state {
+    use std::cell::RefCell;
+
+    /// A box on the path
+    pub struct Entry {
+        pub box_type: String,
+        /// Among the boxes of the same type in the parent
+        pub index: usize,
+        pub start_offset: u64,
+        pub end_offset: u64,
+        pub children: Children,
+    }
+
+    /// The boxes of each type found so far in a parent
+    #[derive(Default)]
+    pub struct Children {
+        counts: Vec<(String, usize)>,
+        last_start_offset: Option<u64>,
+    }
+
+    impl Children {
+        /// The index of a new child among those of its type
+        pub fn add(&mut self, box_type: &str, start_offset: u64) -> usize {
+            // Parsing the same boxes again, e.g. in a second pass over the file
+            if self
+                .last_start_offset
+                .is_some_and(|last| start_offset <= last)
+            {
+                self.counts.clear();
+            }
+            self.last_start_offset = Some(start_offset);
+            match self.counts.iter_mut().find(|(t, _)| t == box_type) {
+                Some((_, count)) => {
+                    *count += 1;
+                    *count - 1
+                }
+                None => {
+                    self.counts.push((box_type.to_string(), 1));
+                    0
+                }
+            }
+        }
+    }
+
+    #[derive(Default)]
+    pub struct BoxPath {
+        pub top_level: Children,
+        pub entries: Vec<Entry>,
+    }
+
+    thread_local! {
+        pub static PATH: RefCell<BoxPath> = RefCell::new(BoxPath::default());
+    }
+}
+
+/// Called for every box header that is parsed
+pub(crate) fn enter(box_type: &str, start_offset: u64, end_offset: u64) {
+    #[cfg(feature = "std")]
+    state::PATH.with(|path| {
+        let mut path = path.borrow_mut();
+        leave_boxes_before(&mut path, start_offset);
+        let siblings = match path.entries.last_mut() {
+            Some(parent) => &mut parent.children,
+            None => &mut path.top_level,
+        };
+        let index = siblings.add(box_type, start_offset);
+        path.entries.push(state::Entry {
+            box_type: box_type.to_string(),
+            index,
+            start_offset,
+            end_offset,
+            children: state::Children::default(),
+        });
+    });
+    #[cfg(not(feature = "std"))]
+    let _ = (box_type, start_offset, end_offset);
+}
+
+/// Called for a box header that can't be read, so that the path ends with its parent
+pub(crate) fn leave_before(start_offset: u64) {
+    #[cfg(feature = "std")]
+    state::PATH.with(|path| leave_boxes_before(&mut path.borrow_mut(), start_offset));
+    #[cfg(not(feature = "std"))]
+    let _ = start_offset;
+}
+
+/// Pops the boxes that end before `start_offset`
+#[cfg(feature = "std")]
+fn leave_boxes_before(path: &mut state::BoxPath, start_offset: u64) {
+    while let Some(parent) = path.entries.last() {
+        if parent.start_offset < start_offset && start_offset < parent.end_offset {
+            break;
+        }
+        path.entries.pop();
+    }
+}
+
+/// Forgets the path, e.g. when the boxes have been parsed and what comes next isn't about any
+/// one box
+pub fn clear() {
+    #[cfg(feature = "std")]
+    state::PATH.with(|path| *path.borrow_mut() = state::BoxPath::default());
+}
+
+/// The path to the box that was parsed last, and its file offset
+#[cfg(feature = "std")]
+pub fn current() -> Option<(String, u64)> {
+    state::PATH.with(|path| {
+        let path = path.borrow();
+        let innermost = path.entries.last()?;
+        let names: Vec<String> = path
+            .entries
+            .iter()
+            .map(|entry| {
+                if entry.index > 0 || REPEATED.contains(&entry.box_type.as_str()) {
+                    format!("{}[{}]", entry.box_type, entry.index)
+                } else {
+                    entry.box_type.clone()
+                }
+            })
+            .collect();
+        Some((names.join("/"), innermost.start_offset))
+    })
+}
@@ -0,0 +1,111 @@
+//! How many boxes of each type a file has, and how many bytes they take up (see `--stats`).
+//! Makes it easy to spot e.g. thousands of tiny moof boxes, or a bloated udta. The walk over
+//! the box tree is also used to validate where the boxes are.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::box_path;
+use crate::boxes::BoxHeader;
+use crate::reader::Reader;
+
+/// Boxes whose payload is only child boxes, after the version and flags for meta
+const CONTAINERS: [&str; 15] = [
+    "moov", "trak", "edts", "mdia", "minf", "dinf", "stbl", "mvex", "moof", "traf", "mfra", "udta",
+    "meta", "iprp", "ipco",
+];
+
+/// Boxes whose payload is the version and flags, an entry count, and then the entries as boxes
+const ENTRY_CONTAINERS: [&str; 2] = ["stsd", "dref"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BoxTypeStats {
+    pub box_type: String,
+    pub count: u64,
+    /// The sizes of the boxes added up, including their headers and children
+    pub total_size: u64,
+}
+
+/// Every box type in the file, the largest total size first. The sample entries in stsd are
+/// counted, but not the boxes inside them. As a box's children are counted on their own too,
+/// the sizes add up to more than the size of the file.
+pub fn box_stats(buf: &[u8]) -> Vec<BoxTypeStats> {
+    let mut stats: HashMap<String, (u64, u64)> = HashMap::new();
+    walk_boxes(buf, &mut |_, header| {
+        let (count, total_size) = stats.entry(header.box_type.clone()).or_insert((0, 0));
+        *count += 1;
+        *total_size += header.box_size;
+    })
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    let mut stats: Vec<BoxTypeStats> = stats
+        .into_iter()
+        .map(|(box_type, (count, total_size))| BoxTypeStats {
+            box_type,
+            count,
+            total_size,
+        })
+        .collect();
+    stats.sort_by(|a, b| {
+        b.total_size
+            .cmp(&a.total_size)
+            .then_with(|| a.box_type.cmp(&b.box_type))
+    });
+    stats
+}
+
+/// Calls `visit` with the types of the parents and the header of each box in the file, in file
+/// order, descending into the containers and the entries of stsd and dref. The payloads of
+/// other boxes aren't parsed. While `visit` runs, `box_path::current` is the visited box.
+/// The walk stops at the first box header that is broken, which is returned as an error.
+pub fn walk_boxes(buf: &[u8], visit: &mut dyn FnMut(&[String], &BoxHeader)) -> Result<(), String> {
+    let mut reader = Reader::new(buf);
+    let result = walk(buf, &mut reader, buf.len() as u64, &mut Vec::new(), visit);
+    box_path::clear();
+    result
+}
+
+/// Boxes that are cut short by the end of their parent, or of the file, are visited with the
+/// size in their header
+fn walk(
+    buf: &[u8],
+    reader: &mut Reader,
+    end_offset: u64,
+    parents: &mut Vec<String>,
+    visit: &mut dyn FnMut(&[String], &BoxHeader),
+) -> Result<(), String> {
+    while reader.position() + 8 <= end_offset {
+        let header = BoxHeader::try_parse(reader)?;
+        visit(parents, &header);
+
+        let box_end_offset = (header.start_offset + header.box_size).min(end_offset);
+        let box_type = header.box_type.as_str();
+        let descends = if CONTAINERS.contains(&box_type) {
+            // QuickTime's meta box is a plain box, whose first child (hdlr) follows directly
+            let position = reader.position() as usize;
+            let is_plain_box = buf.get(position + 4..position + 8) == Some(b"hdlr");
+            if box_type == "meta" && !is_plain_box {
+                let _ = reader.skip_bytes(4.min(header.inner_size));
+            }
+            true
+        } else if ENTRY_CONTAINERS.contains(&box_type) {
+            let _ = reader.skip_bytes(8.min(header.inner_size));
+            true
+        } else {
+            false
+        };
+        if descends {
+            parents.push(header.box_type.clone());
+            let result = walk(buf, reader, box_end_offset, parents, visit);
+            parents.pop();
+            result?;
+        }
+
+        let remaining = box_end_offset.saturating_sub(reader.position());
+        if reader.skip_bytes(remaining).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
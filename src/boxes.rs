@@ -1,12 +1,40 @@
-use chrono::{Duration, NaiveDate, NaiveDateTime};
-
-use crate::quicktime::MetadataItemList;
-use crate::reader::Reader;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+
+use crate::box_path;
+use crate::brand::{self, Profile};
+use crate::diagnostics::{diagnostic, Severity};
+use crate::encryption::{
+    ProtectionSchemeInfo, ProtectionSystemSpecificHeaderBox, SampleAuxiliaryInfoOffsetsBox,
+    SampleAuxiliaryInfoSizesBox, SampleEncryptionBox,
+};
+use crate::heif::{
+    self, AuxiliaryTypeProperty, ImageMirrorProperty, ImageRotationProperty,
+    ImageSpatialExtentsProperty, ItemInfoEntry, ItemPropertyAssociationBox,
+    LayeredImageIndexingProperty, PixelInformationProperty, PrimaryItemBox,
+};
+use crate::media_time::MediaTime;
+use crate::piff::{self, TfrfBox, TfxdBox};
+use crate::quicktime::{MetadataItemList, MetadataSampleEntry, UserDataTextBox};
+use crate::reader::{BitReader, Reader};
+use crate::registry::{self, CustomBox};
+use crate::subtitles::{TextSampleEntry, WebVttSampleEntry};
+use crate::timed_metadata::{EventMessageBox, TimedMetadataSampleEntry};
+use crate::writer::Writer;
 
 #[derive(Debug)]
 pub enum Mp4Box {
     QuickTimeMetadataItemList(MetadataItemList),
+    /// A QuickTime annotation in udta, e.g. ©day
+    UserDataText(UserDataTextBox),
     Ftyp(FileTypeBox),
+    /// styp, which has the same fields as ftyp, but for a segment
+    Styp(FileTypeBox),
     Free,
     Mdat,
     Container(&'static str),
@@ -25,27 +53,173 @@ pub enum Mp4Box {
     Stsc(SampleToChunkBox),
     Stsz(SampleSizeBox),
     Stco(ChunkOffsetBox),
+    Co64(ChunkLargeOffsetBox),
     Sgpd(SampleGroupDescriptionBox),
     Sbgp(SampleToGroupBox),
     Sdtp(SampleDependencyTypeBox),
     Trex(TrackExtendsBox),
     Mfhd(MovieFragmentHeaderBox),
+    Tfhd(TrackFragmentHeaderBox),
+    Tfdt(TrackFragmentBaseMediaDecodeTimeBox),
+    Trun(TrackRunBox),
+    Chpl(ChapterListBox),
+    Mere(MetaboxRelationBox),
+    Uuid(UserExtensionBox),
+    Xml(XmlBox),
+    Cprt(CopyrightBox),
+    Stdp(DegradationPriorityBox),
+    Padb(PaddingBitsBox),
+    Tfra(TrackFragmentRandomAccessBox),
+    Mfro(MovieFragmentRandomAccessOffsetBox),
+    Mehd(MovieExtendsHeaderBox),
+    Sidx(SegmentIndexBox),
+    Emsg(EventMessageBox),
+    Pssh(ProtectionSystemSpecificHeaderBox),
+    Senc(SampleEncryptionBox),
+    Saiz(SampleAuxiliaryInfoSizesBox),
+    Saio(SampleAuxiliaryInfoOffsetsBox),
+    /// PIFF's uuid boxes, from Smooth Streaming
+    PiffSenc(SampleEncryptionBox),
+    PiffPssh(ProtectionSystemSpecificHeaderBox),
+    Tfxd(TfxdBox),
+    Tfrf(TfrfBox),
+    UnsupportedVersion(UnsupportedVersionBox),
+    Pitm(PrimaryItemBox),
+    Infe(ItemInfoEntry),
+    Ipma(ItemPropertyAssociationBox),
+    Ispe(ImageSpatialExtentsProperty),
+    Pixi(PixelInformationProperty),
+    AuxC(AuxiliaryTypeProperty),
+    A1lx(LayeredImageIndexingProperty),
+    Irot(ImageRotationProperty),
+    Imir(ImageMirrorProperty),
+    Unknown(UnknownBox),
+    Custom(Box<dyn CustomBox>),
+}
+
+/// The highest version of each full box that can be parsed. Boxes with a later version are
+/// skipped, as their fields may have changed.
+fn max_supported_version(box_type: &str) -> Option<u8> {
+    match box_type {
+        "mvhd" | "tkhd" | "mdhd" | "ctts" | "mehd" | "sidx" | "trun" | "tfdt" | "tfra" | "chpl"
+        | "emsg" => Some(1),
+        "elst" | "hdlr" | "vmhd" | "smhd" | "dref" | "stsd" | "stts" | "stss" | "stsc" | "stsz"
+        | "stco" | "co64" | "stdp" | "padb" | "sdtp" | "trex" | "mfhd" | "tfhd" | "mfro"
+        | "mere" | "cprt" | "ispe" | "pixi" | "auxC" => Some(0),
+        "pitm" | "iinf" | "ipma" | "pssh" => Some(1),
+        "senc" | "saiz" => Some(0),
+        "saio" => Some(1),
+        "infe" => Some(3),
+        _ => None,
+    }
 }
 
 impl Mp4Box {
-    pub fn parse_contents(reader: &mut Reader, box_type: &str, inner_size: u64) -> Option<Self> {
+    /// Parses the fields of a box, but not its children or entries. The box's parser can't read
+    /// past its end, and a warning is logged if it tries to, or if it leaves bytes unparsed.
+    pub fn parse_contents(reader: &mut Reader, box_type: &str, inner_size: u64) -> Self {
+        Self::parse_with_trailing_data(reader, box_type, inner_size).0
+    }
+
+    /// Like `parse_contents`, but also returns where the bytes at the end of the box are, if
+    /// its parser left some unparsed
+    pub fn parse_with_trailing_data(
+        reader: &mut Reader,
+        box_type: &str,
+        inner_size: u64,
+    ) -> (Self, Option<RawData>) {
+        let start_offset = reader.position();
+        let mut box_reader = reader.limited(inner_size);
+        let box_ = match Self::parse_fields(&mut box_reader, box_type, inner_size) {
+            Some(b) => b,
+            None => {
+                box_reader.skip(inner_size);
+                Mp4Box::Unknown(UnknownBox {
+                    box_type: box_type.to_string(),
+                    data: RawData {
+                        offset: start_offset,
+                        len: inner_size,
+                    },
+                })
+            }
+        };
+        if let Mp4Box::Ftyp(b) = &box_ {
+            reader.set_profile(b.profile());
+        }
+        let consumed = box_reader.position() - start_offset;
+        let mut trailing_data = None;
+        if box_reader.overrun() > 0 {
+            diagnostic!(
+                Severity::Error,
+                "The '{}' box is {} bytes, but its fields need {} more",
+                box_type,
+                inner_size,
+                box_reader.overrun()
+            );
+        } else if consumed < inner_size && box_.parses_all_fields() {
+            diagnostic!(
+                Severity::Warning,
+                "{} bytes at the end of the '{}' box weren't parsed",
+                inner_size - consumed,
+                box_type
+            );
+            trailing_data = Some(RawData {
+                offset: start_offset + consumed,
+                len: inner_size - consumed,
+            });
+        }
+        reader.skip(consumed);
+        (box_, trailing_data)
+    }
+
+    fn parse_fields(reader: &mut Reader, box_type: &str, inner_size: u64) -> Option<Self> {
+        if let Some(max_version) = max_supported_version(box_type) {
+            let version = reader
+                .peek_bytes(1.min(inner_size as usize))
+                .first()
+                .copied()
+                .unwrap_or(0);
+            if version > max_version {
+                diagnostic!(
+                    Severity::Warning,
+                    "Unsupported version {} of box '{}'",
+                    version,
+                    box_type
+                );
+                reader.skip(inner_size);
+                return Some(Mp4Box::UnsupportedVersion(UnsupportedVersionBox {
+                    box_type: box_type.to_string(),
+                    version,
+                }));
+            }
+        }
         match box_type {
             "ftyp" => {
                 let b = FileTypeBox::parse(reader, inner_size);
-                if b.major_brand == "qt  " {
-                    println!("WARN: Apple QuickTime is not supported.");
+                for (first, second) in
+                    brand::conflicting_brands(&b.major_brand, &b.compatible_brands)
+                {
+                    diagnostic!(
+                        Severity::Warning,
+                        "The brands '{}' and '{}' contradict each other",
+                        first,
+                        second
+                    );
                 }
                 Some(Mp4Box::Ftyp(b))
             }
+            "styp" => {
+                let b = FileTypeBox::parse(reader, inner_size);
+                Some(Mp4Box::Styp(b))
+            }
             "free" => {
                 FreeSpaceBox::parse(reader, inner_size);
                 Some(Mp4Box::Free)
             }
+            "wide" if reader.profile() == Profile::QuickTime => {
+                FreeSpaceBox::parse(reader, inner_size);
+                Some(Mp4Box::Free)
+            }
             "mdat" => {
                 MediaDataBox::parse(reader, inner_size);
                 Some(Mp4Box::Mdat)
@@ -117,6 +291,18 @@ impl Mp4Box {
                 let b = ChunkOffsetBox::parse_header(reader);
                 Some(Mp4Box::Stco(b))
             }
+            "co64" => {
+                let b = ChunkLargeOffsetBox::parse_header(reader);
+                Some(Mp4Box::Co64(b))
+            }
+            "stdp" => {
+                let b = DegradationPriorityBox::parse_header(reader, inner_size);
+                Some(Mp4Box::Stdp(b))
+            }
+            "padb" => {
+                let b = PaddingBitsBox::parse_header(reader);
+                Some(Mp4Box::Padb(b))
+            }
             "sgpd" => {
                 let b = SampleGroupDescriptionBox::parse_header(reader);
                 Some(Mp4Box::Sgpd(b))
@@ -130,6 +316,34 @@ impl Mp4Box {
                 Some(Mp4Box::Sdtp(b))
             }
             "mvex" => Some(Mp4Box::Container("Movie Extends Box (container)")),
+            "mehd" => {
+                let b = MovieExtendsHeaderBox::parse(reader, inner_size);
+                Some(Mp4Box::Mehd(b))
+            }
+            "sidx" => {
+                let b = SegmentIndexBox::parse(reader, inner_size);
+                Some(Mp4Box::Sidx(b))
+            }
+            "emsg" => {
+                let b = EventMessageBox::parse(reader, inner_size);
+                Some(Mp4Box::Emsg(b))
+            }
+            "pssh" => {
+                let b = ProtectionSystemSpecificHeaderBox::parse(reader, inner_size);
+                Some(Mp4Box::Pssh(b))
+            }
+            "senc" => {
+                let b = SampleEncryptionBox::parse_header(reader);
+                Some(Mp4Box::Senc(b))
+            }
+            "saiz" => {
+                let b = SampleAuxiliaryInfoSizesBox::parse(reader, inner_size);
+                Some(Mp4Box::Saiz(b))
+            }
+            "saio" => {
+                let b = SampleAuxiliaryInfoOffsetsBox::parse(reader, inner_size);
+                Some(Mp4Box::Saio(b))
+            }
             "trex" => {
                 let b = TrackExtendsBox::parse(reader, inner_size);
                 Some(Mp4Box::Trex(b))
@@ -140,26 +354,207 @@ impl Mp4Box {
                 Some(Mp4Box::Mfhd(b))
             }
             "traf" => Some(Mp4Box::Container("Track Fragment Box (container)")),
+            "tfhd" => {
+                let b = TrackFragmentHeaderBox::parse(reader, inner_size);
+                Some(Mp4Box::Tfhd(b))
+            }
+            "tfdt" => {
+                let b = TrackFragmentBaseMediaDecodeTimeBox::parse(reader, inner_size);
+                Some(Mp4Box::Tfdt(b))
+            }
+            "trun" => {
+                let b = TrackRunBox::parse_header(reader);
+                Some(Mp4Box::Trun(b))
+            }
             "mfra" => Some(Mp4Box::Container(
                 "Movie Fragment Random Access Box (container)",
             )),
+            "tfra" => {
+                let b = TrackFragmentRandomAccessBox::parse_header(reader);
+                Some(Mp4Box::Tfra(b))
+            }
+            "mfro" => {
+                let b = MovieFragmentRandomAccessOffsetBox::parse(reader, inner_size);
+                Some(Mp4Box::Mfro(b))
+            }
             "udta" => Some(Mp4Box::Container("User Data Box (container)")),
             "meta" => {
-                FullBoxHeader::parse(reader);
+                // QuickTime's meta box is a plain box, so its first child (hdlr) follows directly
+                let has_full_box_header = reader.profile() != Profile::QuickTime
+                    || inner_size < 8
+                    || reader.peek_bytes(8).get(4..) != Some(&b"hdlr"[..]);
+                if has_full_box_header {
+                    FullBoxHeader::parse(reader);
+                }
                 Some(Mp4Box::Container("The Meta Box (container)"))
             }
             "ilst" => Some(Mp4Box::QuickTimeMetadataItemList(MetadataItemList)),
+            "chpl" => {
+                let b = ChapterListBox::parse(reader, inner_size);
+                Some(Mp4Box::Chpl(b))
+            }
+            "meco" => Some(Mp4Box::Container(
+                "Additional Metadata Container Box (container)",
+            )),
+            "mere" => {
+                let b = MetaboxRelationBox::parse(reader, inner_size);
+                Some(Mp4Box::Mere(b))
+            }
+            "cprt" => {
+                let b = CopyrightBox::parse(reader, inner_size);
+                Some(Mp4Box::Cprt(b))
+            }
+            "uuid" => match registry::uuid_parser(&reader.peek_bytes(16)) {
+                Some(parse) => {
+                    reader.skip(16);
+                    Some(Mp4Box::Custom(parse(reader, inner_size.saturating_sub(16))))
+                }
+                None => {
+                    let extended_type = reader.peek_bytes(16);
+                    match extended_type.as_slice() {
+                        uuid if uuid == piff::SAMPLE_ENCRYPTION_UUID => {
+                            reader.skip(16);
+                            let b = SampleEncryptionBox::parse_header(reader);
+                            Some(Mp4Box::PiffSenc(b))
+                        }
+                        uuid if uuid == piff::PROTECTION_SYSTEM_HEADER_UUID => {
+                            reader.skip(16);
+                            let b = ProtectionSystemSpecificHeaderBox::parse(reader, inner_size);
+                            Some(Mp4Box::PiffPssh(b))
+                        }
+                        uuid if uuid == piff::TFXD_UUID => {
+                            reader.skip(16);
+                            let b = TfxdBox::parse(reader, inner_size.saturating_sub(16));
+                            Some(Mp4Box::Tfxd(b))
+                        }
+                        uuid if uuid == piff::TFRF_UUID => {
+                            reader.skip(16);
+                            let b = TfrfBox::parse(reader, inner_size.saturating_sub(16));
+                            Some(Mp4Box::Tfrf(b))
+                        }
+                        _ => {
+                            let b = UserExtensionBox::parse(reader, inner_size);
+                            Some(Mp4Box::Uuid(b))
+                        }
+                    }
+                }
+            },
+            "pitm" => {
+                let b = PrimaryItemBox::parse(reader, inner_size);
+                Some(Mp4Box::Pitm(b))
+            }
+            "iinf" => {
+                heif::parse_item_info_header(reader);
+                Some(Mp4Box::Container("Item Information Box (container)"))
+            }
+            "infe" => {
+                let b = ItemInfoEntry::parse(reader, inner_size);
+                Some(Mp4Box::Infe(b))
+            }
+            "iprp" => Some(Mp4Box::Container("Item Properties Box (container)")),
+            "ipco" => Some(Mp4Box::Container("Item Property Container Box (container)")),
+            "ipma" => {
+                let b = ItemPropertyAssociationBox::parse(reader, inner_size);
+                Some(Mp4Box::Ipma(b))
+            }
+            "ispe" => {
+                let b = ImageSpatialExtentsProperty::parse(reader, inner_size);
+                Some(Mp4Box::Ispe(b))
+            }
+            "pixi" => {
+                let b = PixelInformationProperty::parse(reader, inner_size);
+                Some(Mp4Box::Pixi(b))
+            }
+            "auxC" => {
+                let b = AuxiliaryTypeProperty::parse(reader, inner_size);
+                Some(Mp4Box::AuxC(b))
+            }
+            "a1lx" => {
+                let b = LayeredImageIndexingProperty::parse(reader, inner_size);
+                Some(Mp4Box::A1lx(b))
+            }
+            "irot" => {
+                let b = ImageRotationProperty::parse(reader, inner_size);
+                Some(Mp4Box::Irot(b))
+            }
+            "imir" => {
+                let b = ImageMirrorProperty::parse(reader, inner_size);
+                Some(Mp4Box::Imir(b))
+            }
+            "xml " => {
+                let b = XmlBox::parse(reader, inner_size);
+                Some(Mp4Box::Xml(b))
+            }
 
-            _ => None,
+            _ => match registry::parser(box_type) {
+                Some(parse) => Some(Mp4Box::Custom(parse(reader, inner_size))),
+                // In udta. The items of ilst have the same types, but they're read as entries.
+                None if box_type.starts_with('©') => {
+                    let b = UserDataTextBox::parse(reader, box_type, inner_size);
+                    Some(Mp4Box::UserDataText(b))
+                }
+                None => None,
+            },
         }
     }
 
+    /// Whether all of the box's contents are read when it's parsed. For other boxes, the
+    /// children or entries are read separately.
+    fn parses_all_fields(&self) -> bool {
+        use Mp4Box::*;
+        matches!(
+            self,
+            Ftyp(_)
+                | Styp(_)
+                | Free
+                | Mdat
+                | Mvhd(_)
+                | Tkhd(_)
+                | Mdhd(_)
+                | Hdlr(_)
+                | Vmhd(_)
+                | Smhd(_)
+                | Trex(_)
+                | Mfhd(_)
+                | Tfhd(_)
+                | Tfdt(_)
+                | Chpl(_)
+                | Mere(_)
+                | Xml(_)
+                | UserDataText(_)
+                | Cprt(_)
+                | Mfro(_)
+                | Mehd(_)
+                | Sidx(_)
+                | Emsg(_)
+                | Pssh(_)
+                | Saiz(_)
+                | Saio(_)
+                | PiffPssh(_)
+                | Tfxd(_)
+                | Tfrf(_)
+                | Pitm(_)
+                | Infe(_)
+                | Ipma(_)
+                | Ispe(_)
+                | Pixi(_)
+                | AuxC(_)
+                | A1lx(_)
+                | Irot(_)
+                | Imir(_)
+                | UnsupportedVersion(_)
+                | Custom(_)
+        ) || matches!(self, Uuid(b) if b.xmp.is_some())
+    }
+
     pub fn name(&self) -> &'static str {
         use Mp4Box::*;
         match self {
             QuickTimeMetadataItemList(_) => "QuickTime Metadata Item List",
+            UserDataText(_) => "QuickTime User Data Text",
             Container(title) => title,
             Ftyp(_) => "File Type Box",
+            Styp(_) => "Segment Type Box",
             Mdat => "Media Data Box",
             Free => "Free Space Box",
             Mvhd(_) => "Movie Header Box",
@@ -177,23 +572,62 @@ impl Mp4Box {
             Stsc(_) => "Sample To Chunk Box",
             Stsz(_) => "Sample Size Box",
             Stco(_) => "Chunk Offset Box",
+            Co64(_) => "Chunk Large Offset Box",
             Sgpd(_) => "Sample Group Description Box",
             Sbgp(_) => "Sample To Group Box",
             Sdtp(_) => "Sample Dependency Type Box",
             Trex(_) => "Track Extends Box",
             Mfhd(_) => "Movie Fragment Header Box",
+            Tfhd(_) => "Track Fragment Header Box",
+            Tfdt(_) => "Track Fragment Base Media Decode Time Box",
+            Trun(_) => "Track Fragment Run Box",
+            Chpl(_) => "Chapter List Box (Nero)",
+            Mere(_) => "Metabox Relation Box",
+            Uuid(b) if b.xmp.is_some() => "XMP Metadata Box (Adobe)",
+            Uuid(_) => "User Extension Box",
+            Xml(_) => "XML Box",
+            Cprt(_) => "Copyright Box",
+            Stdp(_) => "Degradation Priority Box",
+            Padb(_) => "Padding Bits Box",
+            Tfra(_) => "Track Fragment Random Access Box",
+            Mfro(_) => "Movie Fragment Random Access Offset Box",
+            Mehd(_) => "Movie Extends Header Box",
+            Sidx(_) => "Segment Index Box",
+            Emsg(_) => "Event Message Box",
+            Pssh(_) => "Protection System Specific Header Box",
+            Senc(_) => "Sample Encryption Box",
+            Saiz(_) => "Sample Auxiliary Information Sizes Box",
+            Saio(_) => "Sample Auxiliary Information Offsets Box",
+            PiffSenc(_) => "PIFF Sample Encryption Box",
+            PiffPssh(_) => "PIFF Protection System Specific Header Box",
+            Tfxd(_) => "Smooth Streaming Fragment Time Box (tfxd)",
+            Tfrf(_) => "Smooth Streaming Next Fragments Box (tfrf)",
+            UnsupportedVersion(_) => "Box with unsupported version (skipped)",
+            Pitm(_) => "Primary Item Box",
+            Infe(_) => "Item Info Entry",
+            Ipma(_) => "Item Property Association Box",
+            Ispe(_) => "Image Spatial Extents Property",
+            Pixi(_) => "Pixel Information Property",
+            AuxC(_) => "Auxiliary Type Property",
+            A1lx(_) => "AV1 Layered Image Indexing Property",
+            Irot(_) => "Image Rotation Property",
+            Imir(_) => "Image Mirror Property",
+            Unknown(_) => "Unknown Box",
+            Custom(b) => b.name(),
         }
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         use Mp4Box::*;
         match self {
             QuickTimeMetadataItemList(_) => {}
+            UserDataText(b) => b.print_attributes(print),
             Container(_) => {}
             Ftyp(b) => b.print_attributes(print),
+            Styp(b) => b.print_brands(print),
             Mdat => {}
             Free => {}
             Mvhd(b) => b.print_attributes(print),
@@ -211,17 +645,53 @@ impl Mp4Box {
             Stsc(b) => b.print_attributes(print),
             Stsz(b) => b.print_attributes(print),
             Stco(b) => b.print_attributes(print),
+            Co64(b) => b.print_attributes(print),
             Sgpd(b) => b.print_attributes(print),
             Sbgp(b) => b.print_attributes(print),
             Sdtp(b) => b.print_attributes(print),
             Trex(b) => b.print_attributes(print),
             Mfhd(b) => b.print_attributes(print),
+            Tfhd(b) => b.print_attributes(print),
+            Tfdt(b) => b.print_attributes(print),
+            Trun(b) => b.print_attributes(print),
+            Chpl(b) => b.print_attributes(print),
+            Mere(b) => b.print_attributes(print),
+            Uuid(b) => b.print_attributes(print),
+            Xml(b) => b.print_attributes(print),
+            Cprt(b) => b.print_attributes(print),
+            Stdp(b) => b.print_attributes(print),
+            Padb(b) => b.print_attributes(print),
+            Tfra(b) => b.print_attributes(print),
+            Mfro(b) => b.print_attributes(print),
+            Mehd(b) => b.print_attributes(print),
+            Sidx(b) => b.print_attributes(print),
+            Emsg(b) => b.print_attributes(print),
+            Pssh(b) => b.print_attributes(print),
+            Senc(b) => b.print_attributes(print),
+            Saiz(b) => b.print_attributes(print),
+            Saio(b) => b.print_attributes(print),
+            PiffSenc(b) => b.print_attributes(print),
+            PiffPssh(b) => b.print_attributes(print),
+            Tfxd(b) => b.print_attributes(print),
+            Tfrf(b) => b.print_attributes(print),
+            UnsupportedVersion(b) => b.print_attributes(print),
+            Pitm(b) => b.print_attributes(print),
+            Infe(b) => b.print_attributes(print),
+            Ipma(b) => b.print_attributes(print),
+            Ispe(b) => b.print_attributes(print),
+            Pixi(b) => b.print_attributes(print),
+            AuxC(b) => b.print_attributes(print),
+            A1lx(b) => b.print_attributes(print),
+            Irot(b) => b.print_attributes(print),
+            Imir(b) => b.print_attributes(print),
+            Unknown(b) => b.print_attributes(print),
+            Custom(b) => b.print_attributes(&print),
         }
     }
 }
 
 /// ftyp
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FileTypeBox {
     pub major_brand: String,
     pub minor_version: u32,
@@ -232,9 +702,16 @@ impl FileTypeBox {
     pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
         let major_brand = reader.read_string(4);
         let minor_version = reader.read_u32();
-        let remaining = inner_size - 8;
+        let remaining = inner_size.saturating_sub(8);
+        // Each brand is a String of 4 bytes
+        let brand_size = core::mem::size_of::<String>() + 4;
+        let brand_count = if reader.reserve(remaining / 4, brand_size, "ftyp compatible brands") {
+            remaining / 4
+        } else {
+            0
+        };
         let mut compatible_brands = Vec::new();
-        for _ in 0..remaining / 4 {
+        for _ in 0..brand_count {
             compatible_brands.push(reader.read_string(4));
         }
 
@@ -245,25 +722,54 @@ impl FileTypeBox {
         }
     }
 
+    pub fn profile(&self) -> Profile {
+        Profile::from_brands(&self.major_brand, &self.compatible_brands)
+    }
+
+    /// Whether the brand is the major brand or one of the compatible ones
+    pub fn has_brand(&self, brand: &str) -> bool {
+        self.major_brand == brand || self.compatible_brands.iter().any(|b| b == brand)
+    }
+
     fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
-        print("Major brand", &self.major_brand);
+        self.print_brands(&print);
+        print("Profile", &self.profile());
+    }
+
+    fn print_brands<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        let major_brand = &self.major_brand;
+        print(
+            "Major brand",
+            &format!("{}{}", major_brand, brand_description(major_brand)),
+        );
         print("Minor version", &self.minor_version);
-        print("Compatible", &format!("{:?}", self.compatible_brands));
+        let compatible: Vec<String> = self
+            .compatible_brands
+            .iter()
+            .map(|brand| format!("{:?}{}", brand, brand_description(brand)))
+            .collect();
+        print("Compatible", &format!("[{}]", compatible.join(", ")));
     }
 }
 
+/// E.g. " (ISO Base Media v1)" for isom, to print after the brand
+fn brand_description(brand: &str) -> String {
+    brand::describe(brand).map_or(String::new(), |description| format!(" ({})", description))
+}
+
 /// mdat
 #[derive(Debug)]
 pub struct MediaDataBox;
 
 impl MediaDataBox {
     pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
-        reader
-            .skip_bytes(inner_size as u32)
-            .expect("Truncated 'mdat' box");
+        reader.skip(inner_size);
 
         Self
     }
@@ -274,8 +780,9 @@ impl MediaDataBox {
 pub struct FreeSpaceBox;
 
 impl FreeSpaceBox {
-    pub fn parse(_reader: &mut Reader, inner_size: u64) -> Self {
-        assert_eq!(inner_size, 0);
+    /// The payload is padding, which may be anything
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        reader.skip(inner_size);
         Self
     }
 }
@@ -283,10 +790,11 @@ impl FreeSpaceBox {
 /// mvhd
 #[derive(Debug)]
 pub struct MovieHeaderBox {
-    pub creation_time: NaiveDateTime,
-    pub modification_time: NaiveDateTime,
+    pub version: u8,
+    pub creation_time: Timestamp,
+    pub modification_time: Timestamp,
     pub timescale: u32,
-    pub duration: u32,
+    pub duration: u64,
     pub rate: f32,
     pub volume: f32,
     pub matrix: Vec<u32>,
@@ -297,71 +805,111 @@ impl MovieHeaderBox {
     pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
         let full_box = FullBoxHeader::parse(reader);
 
-        if full_box.version == 1 {
-            todo!("mvhd version 1")
+        let (creation_time, modification_time, timescale, duration) = if full_box.version == 1 {
+            let creation_time = Timestamp::from_epoch_secs(reader.read_u64());
+            let modification_time = Timestamp::from_epoch_secs(reader.read_u64());
+            let timescale = reader.read_u32();
+            let duration = reader.read_u64();
+            (creation_time, modification_time, timescale, duration)
         } else {
-            let creation_time = as_timestamp(reader.read_u32());
-            let modification_time = as_timestamp(reader.read_u32());
+            let creation_time = Timestamp::from_epoch_secs(reader.read_u32() as u64);
+            let modification_time = Timestamp::from_epoch_secs(reader.read_u32() as u64);
             let timescale = reader.read_u32();
-            let duration = reader.read_u32();
-            let rate = reader.read_fixed_point_16_16();
-            let volume = reader.read_fixed_point_8_8();
-            let _reserved = reader.read_string(2);
-            let _reserved = reader.read_string(8);
-            let mut matrix = Vec::new();
-            for _ in 0..9 {
-                matrix.push(reader.read_u32());
-            }
-            let mut _pre_defined = Vec::new();
-            for _ in 0..6 {
-                _pre_defined.push(reader.read_u32());
-            }
-            let next_track_id = reader.read_u32();
+            let duration = reader.read_u32() as u64;
+            (creation_time, modification_time, timescale, duration)
+        };
+        let rate = reader.read_fixed_point_16_16();
+        let volume = reader.read_fixed_point_8_8();
+        let _reserved = reader.read_string(2);
+        let _reserved = reader.read_string(8);
+        let mut matrix = Vec::new();
+        for _ in 0..9 {
+            matrix.push(reader.read_u32());
+        }
+        let mut _pre_defined = Vec::new();
+        for _ in 0..6 {
+            _pre_defined.push(reader.read_u32());
+        }
+        let next_track_id = reader.read_u32();
 
-            Self {
-                creation_time,
-                modification_time,
-                timescale,
-                duration,
-                rate,
-                volume,
-                matrix,
-                next_track_id,
-            }
+        Self {
+            version: full_box.version,
+            creation_time,
+            modification_time,
+            timescale,
+            duration,
+            rate,
+            volume,
+            matrix,
+            next_track_id,
         }
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Created", &self.creation_time);
         print("Modified", &self.modification_time);
         print("Timescale", &self.timescale);
-        print("Duration", &self.duration);
+        print("Duration", &format_duration(self.duration, self.timescale));
         print("Rate", &self.rate);
         print("Volume", &self.volume);
-        print("Matrix", &format!("{:?}", self.matrix));
+        let matrix = TransformMatrix::from_raw(&self.matrix);
+        print("Matrix", &matrix);
+        print("Orientation", &matrix.orientation_text());
         print("Next track ID", &self.next_track_id);
     }
+
+    pub fn write(&self, writer: &mut Writer) {
+        let creation_time = self.creation_time.epoch_secs();
+        let modification_time = self.modification_time.epoch_secs();
+        let version = header_version(
+            self.version,
+            &[creation_time, modification_time, self.duration],
+        );
+        let start = writer.begin_full_box("mvhd", version, [0, 0, 0]);
+        if version == 1 {
+            writer.write_u64(creation_time);
+            writer.write_u64(modification_time);
+            writer.write_u32(self.timescale);
+            writer.write_u64(self.duration);
+        } else {
+            writer.write_u32(creation_time as u32);
+            writer.write_u32(modification_time as u32);
+            writer.write_u32(self.timescale);
+            writer.write_u32(self.duration as u32);
+        }
+        writer.write_fixed_point_16_16(self.rate);
+        writer.write_fixed_point_8_8(self.volume);
+        writer.write_bytes(&[0; 2 + 8]); // reserved
+        for &value in &self.matrix {
+            writer.write_u32(value);
+        }
+        writer.write_bytes(&[0; 4 * 6]); // pre_defined
+        writer.write_u32(self.next_track_id);
+        writer.end_box(start);
+    }
 }
 
 /// tkhd
 #[derive(Debug)]
 pub struct TrackHeaderBox {
+    pub version: u8,
     pub track_enabled: bool,
     pub track_in_movie: bool,
     pub track_in_preview: bool,
-    pub creation_time: NaiveDateTime,
-    pub modification_time: NaiveDateTime,
+    pub creation_time: Timestamp,
+    pub modification_time: Timestamp,
     pub track_id: u32,
-    pub duration: u32,
+    pub duration: u64,
     pub layer: u16,
     pub alternate_group: u16,
     pub volume: f32,
     pub matrix: Vec<u32>,
-    pub width: u32,
-    pub height: u32,
+    /// The presentation size, which the decoded pictures are scaled to
+    pub width: f32,
+    pub height: f32,
 }
 
 impl TrackHeaderBox {
@@ -371,47 +919,54 @@ impl TrackHeaderBox {
         let track_in_movie = (full_box.flags[2] & 2) != 0;
         let track_in_preview = (full_box.flags[2] & 4) != 0;
 
-        if full_box.version == 1 {
-            todo!("tkhd version 1")
+        let (creation_time, modification_time, track_id, duration) = if full_box.version == 1 {
+            let creation_time = Timestamp::from_epoch_secs(reader.read_u64());
+            let modification_time = Timestamp::from_epoch_secs(reader.read_u64());
+            let track_id = reader.read_u32();
+            let _reserved = reader.read_string(4);
+            let duration = reader.read_u64();
+            (creation_time, modification_time, track_id, duration)
         } else {
-            let creation_time = as_timestamp(reader.read_u32());
-            let modification_time = as_timestamp(reader.read_u32());
+            let creation_time = Timestamp::from_epoch_secs(reader.read_u32() as u64);
+            let modification_time = Timestamp::from_epoch_secs(reader.read_u32() as u64);
             let track_id = reader.read_u32();
             let _reserved = reader.read_string(4);
-            let duration = reader.read_u32();
-            let _reserved = reader.read_string(4 * 2);
-            let layer = reader.read_u16();
-            let alternate_group = reader.read_u16();
-            let volume = reader.read_fixed_point_8_8();
-            let _reserved = reader.read_string(2);
-            let mut matrix = Vec::new();
-            for _ in 0..9 {
-                matrix.push(reader.read_u32());
-            }
-            let width = reader.read_u32();
-            let height = reader.read_u32();
+            let duration = reader.read_u32() as u64;
+            (creation_time, modification_time, track_id, duration)
+        };
+        let _reserved = reader.read_string(4 * 2);
+        let layer = reader.read_u16();
+        let alternate_group = reader.read_u16();
+        let volume = reader.read_fixed_point_8_8();
+        let _reserved = reader.read_string(2);
+        let mut matrix = Vec::new();
+        for _ in 0..9 {
+            matrix.push(reader.read_u32());
+        }
+        let width = reader.read_fixed_point_16_16();
+        let height = reader.read_fixed_point_16_16();
 
-            Self {
-                track_enabled,
-                track_in_movie,
-                track_in_preview,
-                creation_time,
-                modification_time,
-                track_id,
-                duration,
-                layer,
-                alternate_group,
-                volume,
-                matrix,
-                width,
-                height,
-            }
+        Self {
+            version: full_box.version,
+            track_enabled,
+            track_in_movie,
+            track_in_preview,
+            creation_time,
+            modification_time,
+            track_id,
+            duration,
+            layer,
+            alternate_group,
+            volume,
+            matrix,
+            width,
+            height,
         }
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Enabled", &self.track_enabled);
         print("In movie", &self.track_in_movie);
@@ -423,61 +978,207 @@ impl TrackHeaderBox {
         print("Layer", &self.layer);
         print("Alternate group", &self.alternate_group);
         print("Volume", &self.volume);
-        print("Matrix", &format!("{:?}", self.matrix));
-        print("Dimension", &format!("{} x {}", self.width, self.height));
+        let matrix = TransformMatrix::from_raw(&self.matrix);
+        print("Matrix", &matrix);
+        print("Orientation", &matrix.orientation_text());
+        print(
+            "Presentation size",
+            &format!("{} x {}", self.width, self.height),
+        );
+        let (width, height) = matrix.transform_size(self.width as f64, self.height as f64);
+        print("Display size", &format!("{} x {}", width, height));
+    }
+
+    pub fn write(&self, writer: &mut Writer) {
+        let mut flags = 0;
+        if self.track_enabled {
+            flags |= 1;
+        }
+        if self.track_in_movie {
+            flags |= 2;
+        }
+        if self.track_in_preview {
+            flags |= 4;
+        }
+        let creation_time = self.creation_time.epoch_secs();
+        let modification_time = self.modification_time.epoch_secs();
+        let version = header_version(
+            self.version,
+            &[creation_time, modification_time, self.duration],
+        );
+        let start = writer.begin_full_box("tkhd", version, [0, 0, flags]);
+        if version == 1 {
+            writer.write_u64(creation_time);
+            writer.write_u64(modification_time);
+            writer.write_u32(self.track_id);
+            writer.write_bytes(&[0; 4]); // reserved
+            writer.write_u64(self.duration);
+        } else {
+            writer.write_u32(creation_time as u32);
+            writer.write_u32(modification_time as u32);
+            writer.write_u32(self.track_id);
+            writer.write_bytes(&[0; 4]); // reserved
+            writer.write_u32(self.duration as u32);
+        }
+        writer.write_bytes(&[0; 4 * 2]); // reserved
+        writer.write_u16(self.layer);
+        writer.write_u16(self.alternate_group);
+        writer.write_fixed_point_8_8(self.volume);
+        writer.write_bytes(&[0; 2]); // reserved
+        for &value in &self.matrix {
+            writer.write_u32(value);
+        }
+        writer.write_fixed_point_16_16(self.width);
+        writer.write_fixed_point_16_16(self.height);
+        writer.end_box(start);
     }
 }
 
 /// mdhd
 #[derive(Debug)]
 pub struct MediaHeaderBox {
-    pub creation_time: NaiveDateTime,
-    pub modification_time: NaiveDateTime,
+    pub version: u8,
+    pub creation_time: Timestamp,
+    pub modification_time: Timestamp,
     pub timescale: u32,
-    pub duration: u32,
+    pub duration: u64,
     pub language: String,
+    /// The language as stored, which is written back unless `language` is changed. Keeps e.g.
+    /// Macintosh language codes, which are read as "und".
+    pub packed_language: u16,
 }
 
 impl MediaHeaderBox {
     pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
         let full_box = FullBoxHeader::parse(reader);
 
-        if full_box.version == 1 {
-            todo!("mdhd version 1")
-        }
+        let (creation_time, modification_time, timescale, duration) = if full_box.version == 1 {
+            let creation_time = Timestamp::from_epoch_secs(reader.read_u64());
+            let modification_time = Timestamp::from_epoch_secs(reader.read_u64());
+            let timescale = reader.read_u32();
+            let duration = reader.read_u64();
+            (creation_time, modification_time, timescale, duration)
+        } else {
+            let creation_time = Timestamp::from_epoch_secs(reader.read_u32() as u64);
+            let modification_time = Timestamp::from_epoch_secs(reader.read_u32() as u64);
+            let timescale = reader.read_u32();
+            let duration = reader.read_u32() as u64;
+            (creation_time, modification_time, timescale, duration)
+        };
 
-        let creation_time = as_timestamp(reader.read_u32());
-        let modification_time = as_timestamp(reader.read_u32());
-        let timescale = reader.read_u32();
-        let duration = reader.read_u32();
-
-        let language = reader.read_bytes(2);
-        // Each char is stored as 5bit ascii - 0x60
-        let c1 = ((language[0] & 0b0111_1100) >> 2) + 0x60;
-        let c2 = ((language[0] & 0b0000_0011) << 3) + ((language[1] & 0b1110_0000) >> 5) + 0x60;
-        let c3 = (language[1] & 0b0001_1111) + 0x60;
-        let language = String::from_utf8(vec![c1, c2, c3]).unwrap();
+        let packed_language = reader.read_u16();
+        let language = decode_language(packed_language);
         let _pre_defined = reader.read_bytes(2);
 
         Self {
+            version: full_box.version,
             creation_time,
             modification_time,
             timescale,
             duration,
             language,
+            packed_language,
         }
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Created", &self.creation_time);
         print("Modified", &self.modification_time);
         print("Timescale", &self.timescale);
-        print("Duration", &self.duration);
+        print("Duration", &format_duration(self.duration, self.timescale));
         print("Language", &self.language);
     }
+
+    pub fn write(&self, writer: &mut Writer) {
+        let creation_time = self.creation_time.epoch_secs();
+        let modification_time = self.modification_time.epoch_secs();
+        let version = header_version(
+            self.version,
+            &[creation_time, modification_time, self.duration],
+        );
+        let start = writer.begin_full_box("mdhd", version, [0, 0, 0]);
+        if version == 1 {
+            writer.write_u64(creation_time);
+            writer.write_u64(modification_time);
+            writer.write_u32(self.timescale);
+            writer.write_u64(self.duration);
+        } else {
+            writer.write_u32(creation_time as u32);
+            writer.write_u32(modification_time as u32);
+            writer.write_u32(self.timescale);
+            writer.write_u32(self.duration as u32);
+        }
+        let unchanged = unpack_language(self.packed_language)
+            .map_or(self.language == "und", |language| language == self.language);
+        if unchanged {
+            writer.write_u16(self.packed_language);
+        } else {
+            writer.write_u16(pack_language(&self.language));
+        }
+        writer.write_bytes(&[0; 2]); // pre_defined
+        writer.end_box(start);
+    }
+}
+
+/// Reads an ISO-639-2/T language code, as stored in mdhd and cprt. Unset and invalid codes
+/// are read as "und" (undetermined).
+fn parse_language(reader: &mut Reader) -> String {
+    decode_language(reader.read_u16())
+}
+
+/// See `parse_language`
+pub(crate) fn decode_language(packed: u16) -> String {
+    let masked = packed & 0x7FFF;
+    // 0x7FFF is used by QuickTime for "unspecified"
+    if masked == 0 || masked == 0x7FFF {
+        return "und".to_string();
+    }
+    unpack_language(packed).unwrap_or_else(|| {
+        diagnostic!(Severity::Warning, "Invalid language code: {:#06x}", masked);
+        "und".to_string()
+    })
+}
+
+/// The three letters of a packed language code, if they are all lowercase
+fn unpack_language(packed: u16) -> Option<String> {
+    // Each char is stored as 5bit ascii - 0x60
+    let chars = [
+        ((packed >> 10) & 0b1_1111) as u8 + 0x60,
+        ((packed >> 5) & 0b1_1111) as u8 + 0x60,
+        (packed & 0b1_1111) as u8 + 0x60,
+    ];
+    if !chars.iter().all(|c| c.is_ascii_lowercase()) {
+        return None;
+    }
+    Some(String::from_utf8(chars.to_vec()).unwrap())
+}
+
+/// See `unpack_language`
+pub(crate) fn pack_language(language: &str) -> u16 {
+    let c: Vec<u16> = language
+        .bytes()
+        .map(|b| (b.wrapping_sub(0x60) & 0b0001_1111) as u16)
+        .collect();
+    (c[0] << 10) | (c[1] << 5) | c[2]
+}
+
+/// Reads a name that is either NUL-terminated (ISO) or a Pascal string, i.e. prefixed with its
+/// length (QuickTime). Any padding after the name is left out.
+fn parse_name(bytes: &[u8]) -> String {
+    let name = match bytes.split_first() {
+        Some((&len, rest)) if len > 0 && is_pascal_string(len, rest) => &rest[..len as usize],
+        _ => bytes.split(|&b| b == 0).next().unwrap_or_default(),
+    };
+    String::from_utf8_lossy(name).to_string()
+}
+
+/// Whether the length byte covers exactly the text that follows it, up to the first NUL
+fn is_pascal_string(len: u8, rest: &[u8]) -> bool {
+    let text_len = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+    len as usize == text_len
 }
 
 /// hdlr
@@ -489,20 +1190,28 @@ pub struct HandlerReferenceBox {
 
 impl HandlerReferenceBox {
     pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
-        FullBoxHeader::parse(reader);
-
-        let _predefined = reader.read_string(4);
-        let handler_type = reader.read_string(4);
-        let _reserved = reader.read_string(4 * 3);
-        let remaining = inner_size - 24;
-        let name = reader.read_string(remaining as usize);
+        // Version and flags (4), predefined (4), handler type (4) and reserved (12), followed
+        // by the name. Some writers leave out the name, or even the reserved fields.
+        let bytes = reader.read_bytes(inner_size as usize);
+        if bytes.len() < 24 {
+            diagnostic!(
+                Severity::Warning,
+                "hdlr box is only {} bytes, expected at least 24",
+                bytes.len()
+            );
+        }
+        let handler_type = bytes
+            .get(8..12)
+            .map(|t| String::from_utf8_lossy(t).to_string())
+            .unwrap_or_default();
+        let name = bytes.get(24..).map(parse_name).unwrap_or_default();
 
         Self { handler_type, name }
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Handler type", &self.handler_type);
         print("Name", &self.name);
@@ -529,7 +1238,7 @@ impl VideoMediaHandler {
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Graphics mode", &self.graphicsmode);
         print("Opcolor", &format!("{:?}", &self.opcolor));
@@ -552,7 +1261,7 @@ impl SoundMediaHandler {
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Balance", &self.balance);
     }
@@ -580,21 +1289,19 @@ impl DataReferenceBox {
     pub fn parse_entry(reader: &mut Reader) -> DataEntryUrlBox {
         let header = BoxHeader::parse(reader);
         if header.box_type != "url " {
-            todo!("Handle DataEntryUrnBox");
+            reader.fail("Data references other than 'url ' are not supported".to_string());
         }
         let full_box = FullBoxHeader::parse(reader);
-        if full_box.flags == [0, 0, 1] {
-            DataEntryUrlBox {
-                self_contained: true,
-            }
-        } else {
-            todo!("Handle external media URL")
+        let self_contained = full_box.flags == [0, 0, 1];
+        if !self_contained {
+            reader.fail("Media in other files is not supported".to_string());
         }
+        DataEntryUrlBox { self_contained }
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("# entries", &self.entry_count);
     }
@@ -606,7 +1313,7 @@ pub struct EditListBox {
     pub entry_count: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct EditListEntry {
     pub segment_duration: u32,
     pub media_time: i32,
@@ -628,22 +1335,38 @@ impl EditListEntry {
         }
     }
 
+    /// An empty edit (media time -1) shows nothing for its duration, e.g. to delay a track
+    pub fn is_empty(&self) -> bool {
+        self.media_time == -1
+    }
+
+    pub fn media_rate(&self) -> f32 {
+        self.media_rate_integer as f32 + self.media_rate_fraction as f32 / 65536.0
+    }
+
+    /// Players only handle normal speed, and 0 for a "dwell" that holds a single frame
+    pub fn has_unusual_media_rate(&self) -> bool {
+        let rate = self.media_rate();
+        rate != 1.0 && rate != 0.0
+    }
+
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Segment duration", &self.segment_duration);
-        print("Media time", &self.media_time);
-        print("Media rate", &self.media_rate_integer);
+        if self.is_empty() {
+            print("Media time", &"empty edit (-1)");
+        } else {
+            print("Media time", &self.media_time);
+        }
+        print("Media rate", &self.media_rate());
     }
 }
 
 impl EditListBox {
     pub fn parse_header(reader: &mut Reader) -> Self {
-        let full_box = FullBoxHeader::parse(reader);
-        if full_box.version == 1 {
-            todo!("elst version 1")
-        }
+        FullBoxHeader::parse(reader);
         let entry_count = reader.read_u32();
         Self { entry_count }
     }
@@ -652,9 +1375,33 @@ impl EditListBox {
         EditListEntry::parse(reader)
     }
 
+    /// How long the empty edits at the start delay the track, in the movie's timescale
+    pub fn start_delay(entries: &[EditListEntry]) -> u64 {
+        entries
+            .iter()
+            .take_while(|e| e.is_empty())
+            .map(|e| e.segment_duration as u64)
+            .sum()
+    }
+
+    /// Writes a complete 'edts' box containing the edit list
+    pub fn write(writer: &mut Writer, entries: &[EditListEntry]) {
+        let edts_start = writer.begin_box("edts");
+        let start = writer.begin_full_box("elst", 0, [0, 0, 0]);
+        writer.write_u32(entries.len() as u32);
+        for entry in entries {
+            writer.write_u32(entry.segment_duration);
+            writer.write_i32(entry.media_time);
+            writer.write_i16(entry.media_rate_integer);
+            writer.write_i16(entry.media_rate_fraction);
+        }
+        writer.end_box(start);
+        writer.end_box(edts_start);
+    }
+
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("# entries", &self.entry_count);
     }
@@ -684,7 +1431,7 @@ impl DecodingTimeToSampleEntry {
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Sample count", &self.sample_count);
         print("Sample delta", &self.sample_delta);
@@ -693,10 +1440,7 @@ impl DecodingTimeToSampleEntry {
 
 impl DecodingTimeToSampleBox {
     pub fn parse_header(reader: &mut Reader) -> Self {
-        let full_box = FullBoxHeader::parse(reader);
-        if full_box.version == 1 {
-            todo!("elst version 1")
-        }
+        FullBoxHeader::parse(reader);
         let entry_count = reader.read_u32();
         Self { entry_count }
     }
@@ -705,9 +1449,19 @@ impl DecodingTimeToSampleBox {
         DecodingTimeToSampleEntry::parse(reader)
     }
 
+    pub fn write(writer: &mut Writer, entries: &[DecodingTimeToSampleEntry]) {
+        let start = writer.begin_full_box("stts", 0, [0, 0, 0]);
+        writer.write_u32(entries.len() as u32);
+        for entry in entries {
+            writer.write_u32(entry.sample_count);
+            writer.write_u32(entry.sample_delta);
+        }
+        writer.end_box(start);
+    }
+
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("# entries", &self.entry_count);
     }
@@ -727,150 +1481,350 @@ impl SyncSampleBox {
     }
 
     pub fn skip_entries(&self, reader: &mut Reader) {
-        reader.skip_bytes(4 * self.entry_count).unwrap();
+        reader.skip(4 * self.entry_count as u64);
+    }
+
+    pub fn parse_entry(reader: &mut Reader) -> u32 {
+        reader.read_u32()
+    }
+
+    pub fn write(writer: &mut Writer, sync_samples: &[u32]) {
+        let start = writer.begin_full_box("stss", 0, [0, 0, 0]);
+        writer.write_u32(sync_samples.len() as u32);
+        for &sample_number in sync_samples {
+            writer.write_u32(sample_number);
+        }
+        writer.end_box(start);
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("# entries", &self.entry_count);
     }
 }
 
-/// ctts
+/// stdp
+///
+/// Has one entry per sample, but no entry count of its own. The sample count comes from stsz.
 #[derive(Debug)]
-pub struct CompositionTimeToSampleBox {
-    pub version: u8,
-    pub entry_count: u32,
+pub struct DegradationPriorityBox {
+    /// How many entries fit in the box
+    pub max_entry_count: u32,
 }
 
-impl CompositionTimeToSampleBox {
-    pub fn parse_header(reader: &mut Reader) -> Self {
-        let full_box = FullBoxHeader::parse(reader);
-        let entry_count = reader.read_u32();
-        Self {
-            version: full_box.version,
-            entry_count,
-        }
+impl DegradationPriorityBox {
+    pub fn parse_header(reader: &mut Reader, inner_size: u64) -> Self {
+        FullBoxHeader::parse(reader);
+        let max_entry_count = (inner_size.saturating_sub(4) / 2) as u32;
+        Self { max_entry_count }
+    }
 
-        // TODO: handle entries
-        // for i in 0..entry_count {
-        //     let sample_count = reader.read_u32();
-        //     let sample_offset = reader.read_u32();
-        //     logger.trace_box(format!(
-        //         "({}) Sample count: {}, sample offset: {}",
-        //         i, sample_count, sample_offset,
-        //     ));
-        // }
+    pub fn parse_entry(reader: &mut Reader) -> u16 {
+        reader.read_u16()
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
-        print("# entries", &self.entry_count);
+        print("# entries", &self.max_entry_count);
     }
 }
 
-/// stsc
+/// padb
 #[derive(Debug)]
-pub struct SampleToChunkBox {
-    pub entry_count: u32,
+pub struct PaddingBitsBox {
+    pub sample_count: u32,
 }
 
-impl SampleToChunkBox {
+impl PaddingBitsBox {
     pub fn parse_header(reader: &mut Reader) -> Self {
         FullBoxHeader::parse(reader);
-        let entry_count = reader.read_u32();
-        Self { entry_count }
+        let sample_count = reader.read_u32();
+        Self { sample_count }
+    }
 
-        // TODO: handle entries
-        // for i in 0..entry_count {
-        //     let first_chunk = reader.read_u32();
-        //     let samples_per_chunk = reader.read_u32();
-        //     let sample_description_index = reader.read_u32();
-        //     logger.trace_box(format!(
-        //         "({}) First chunk: {}, smpls/chunk: {}, smpl dscr idx: {}",
-        //         i, first_chunk, samples_per_chunk, sample_description_index,
-        //     ));
-        // }
+    /// Each entry holds the number of padding bits of two samples
+    pub fn parse_entry(reader: &mut Reader) -> (u8, u8) {
+        let b = reader.read_u8();
+        ((b >> 4) & 0b0111, b & 0b0111)
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
-        print("# entries", &self.entry_count);
+        print("Sample count", &self.sample_count);
     }
 }
 
-/// stsz
+/// ctts
 #[derive(Debug)]
-pub struct SampleSizeBox {
-    pub sample_size: u32,
-    pub sample_count: u32,
+pub struct CompositionTimeToSampleBox {
+    pub version: u8,
+    pub entry_count: u32,
 }
 
-impl SampleSizeBox {
-    pub fn parse_header(reader: &mut Reader) -> Self {
-        FullBoxHeader::parse(reader);
+#[derive(Debug)]
+pub struct CompositionTimeToSampleEntry {
+    pub sample_count: u32,
+    pub sample_offset: i32,
+}
 
-        let sample_size = reader.read_u32();
+impl CompositionTimeToSampleEntry {
+    fn parse(reader: &mut Reader) -> Self {
         let sample_count = reader.read_u32();
+        // Version 0 stores the offset unsigned, but writers in practice never exceed i32::MAX,
+        // so both versions can be read the same way.
+        let sample_offset = reader.read_i32();
         Self {
-            sample_size,
             sample_count,
+            sample_offset,
         }
-
-        // TODO: handle entries
-        // if sample_size == 0 {
-        //     for i in 0..sample_count {
-        //         let sample_size = reader.read_u32();
-        //         logger.trace_box(format!("({}) Sample size: {}", i, sample_size));
-        //     }
-        // }
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
-        print("Sample size", &self.sample_size);
-        print("# samples", &self.sample_count);
+        print("Sample count", &self.sample_count);
+        print("Sample offset", &self.sample_offset);
     }
 }
 
-/// stco
-#[derive(Debug)]
-pub struct ChunkOffsetBox {
-    pub entry_count: u32,
-}
-
-impl ChunkOffsetBox {
+impl CompositionTimeToSampleBox {
     pub fn parse_header(reader: &mut Reader) -> Self {
-        FullBoxHeader::parse(reader);
+        let full_box = FullBoxHeader::parse(reader);
         let entry_count = reader.read_u32();
-        Self { entry_count }
+        Self {
+            version: full_box.version,
+            entry_count,
+        }
+    }
+
+    pub fn parse_entry(reader: &mut Reader) -> CompositionTimeToSampleEntry {
+        CompositionTimeToSampleEntry::parse(reader)
+    }
 
-        // TODO: handle entries
-        // for i in 0..entry_count {
-        //     let chunk_offset = reader.read_u32();
-        //     logger.trace_box(format!("({}) Chunk offset: {}", i, chunk_offset))
-        // }
+    pub fn write(writer: &mut Writer, entries: &[CompositionTimeToSampleEntry]) {
+        let version = if entries.iter().any(|e| e.sample_offset < 0) {
+            1
+        } else {
+            0
+        };
+        let start = writer.begin_full_box("ctts", version, [0, 0, 0]);
+        writer.write_u32(entries.len() as u32);
+        for entry in entries {
+            writer.write_u32(entry.sample_count);
+            writer.write_i32(entry.sample_offset);
+        }
+        writer.end_box(start);
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("# entries", &self.entry_count);
     }
 }
 
-/// sgpd
+/// stsc
 #[derive(Debug)]
-pub struct SampleGroupDescriptionBox {}
+pub struct SampleToChunkBox {
+    pub entry_count: u32,
+}
+
+#[derive(Debug)]
+pub struct SampleToChunkEntry {
+    pub first_chunk: u32,
+    pub samples_per_chunk: u32,
+    pub sample_description_index: u32,
+}
+
+impl SampleToChunkEntry {
+    fn parse(reader: &mut Reader) -> Self {
+        let first_chunk = reader.read_u32();
+        let samples_per_chunk = reader.read_u32();
+        let sample_description_index = reader.read_u32();
+        Self {
+            first_chunk,
+            samples_per_chunk,
+            sample_description_index,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("First chunk", &self.first_chunk);
+        print("Samples per chunk", &self.samples_per_chunk);
+        print("Sample descr. index", &self.sample_description_index);
+    }
+}
+
+impl SampleToChunkBox {
+    pub fn parse_header(reader: &mut Reader) -> Self {
+        FullBoxHeader::parse(reader);
+        let entry_count = reader.read_u32();
+        Self { entry_count }
+    }
+
+    pub fn parse_entry(reader: &mut Reader) -> SampleToChunkEntry {
+        SampleToChunkEntry::parse(reader)
+    }
+
+    pub fn write(writer: &mut Writer, entries: &[SampleToChunkEntry]) {
+        let start = writer.begin_full_box("stsc", 0, [0, 0, 0]);
+        writer.write_u32(entries.len() as u32);
+        for entry in entries {
+            writer.write_u32(entry.first_chunk);
+            writer.write_u32(entry.samples_per_chunk);
+            writer.write_u32(entry.sample_description_index);
+        }
+        writer.end_box(start);
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("# entries", &self.entry_count);
+    }
+}
+
+/// stsz
+#[derive(Debug)]
+pub struct SampleSizeBox {
+    pub sample_size: u32,
+    pub sample_count: u32,
+}
+
+impl SampleSizeBox {
+    pub fn parse_header(reader: &mut Reader) -> Self {
+        FullBoxHeader::parse(reader);
+
+        let sample_size = reader.read_u32();
+        let sample_count = reader.read_u32();
+        Self {
+            sample_size,
+            sample_count,
+        }
+    }
+
+    /// Only present if `sample_size` is 0
+    pub fn parse_entry(reader: &mut Reader) -> u32 {
+        reader.read_u32()
+    }
+
+    pub fn write(writer: &mut Writer, sample_sizes: &[u32]) {
+        let start = writer.begin_full_box("stsz", 0, [0, 0, 0]);
+        let constant_size = match sample_sizes.first() {
+            Some(&first) if sample_sizes.iter().all(|&size| size == first) => first,
+            _ => 0,
+        };
+        writer.write_u32(constant_size);
+        writer.write_u32(sample_sizes.len() as u32);
+        if constant_size == 0 {
+            for &size in sample_sizes {
+                writer.write_u32(size);
+            }
+        }
+        writer.end_box(start);
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Sample size", &self.sample_size);
+        print("# samples", &self.sample_count);
+    }
+}
+
+/// stco
+#[derive(Debug)]
+pub struct ChunkOffsetBox {
+    pub entry_count: u32,
+}
+
+impl ChunkOffsetBox {
+    pub fn parse_header(reader: &mut Reader) -> Self {
+        FullBoxHeader::parse(reader);
+        let entry_count = reader.read_u32();
+        Self { entry_count }
+    }
+
+    pub fn parse_entry(reader: &mut Reader) -> u32 {
+        reader.read_u32()
+    }
+
+    /// Writes 'stco', or 'co64' if any of the offsets don't fit in 32 bits
+    pub fn write(writer: &mut Writer, chunk_offsets: &[u64]) {
+        if chunk_offsets.iter().any(|&offset| offset > u32::MAX as u64) {
+            log::warn!(
+                "Chunk offsets are past 4 GiB, so co64 is written instead of stco ({} chunks)",
+                chunk_offsets.len()
+            );
+            ChunkLargeOffsetBox::write(writer, chunk_offsets);
+            return;
+        }
+        let start = writer.begin_full_box("stco", 0, [0, 0, 0]);
+        writer.write_u32(chunk_offsets.len() as u32);
+        for &offset in chunk_offsets {
+            writer.write_u32(offset as u32);
+        }
+        writer.end_box(start);
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("# entries", &self.entry_count);
+    }
+}
+
+/// co64
+#[derive(Debug)]
+pub struct ChunkLargeOffsetBox {
+    pub entry_count: u32,
+}
+
+impl ChunkLargeOffsetBox {
+    pub fn parse_header(reader: &mut Reader) -> Self {
+        FullBoxHeader::parse(reader);
+        let entry_count = reader.read_u32();
+        Self { entry_count }
+    }
+
+    pub fn parse_entry(reader: &mut Reader) -> u64 {
+        reader.read_u64()
+    }
+
+    pub fn write(writer: &mut Writer, chunk_offsets: &[u64]) {
+        let start = writer.begin_full_box("co64", 0, [0, 0, 0]);
+        writer.write_u32(chunk_offsets.len() as u32);
+        for &offset in chunk_offsets {
+            writer.write_u64(offset);
+        }
+        writer.end_box(start);
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("# entries", &self.entry_count);
+    }
+}
+
+/// sgpd
+#[derive(Debug)]
+pub struct SampleGroupDescriptionBox {}
 
 impl SampleGroupDescriptionBox {
     pub fn parse_header(_reader: &mut Reader) -> Self {
@@ -880,7 +1834,7 @@ impl SampleGroupDescriptionBox {
 
     pub fn print_attributes<F>(&self, _print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         // TODO
     }
@@ -898,7 +1852,7 @@ impl SampleToGroupBox {
 
     pub fn print_attributes<F>(&self, _print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         // TODO
     }
@@ -916,12 +1870,116 @@ impl SampleDependencyTypeBox {
 
     pub fn print_attributes<F>(&self, _print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         // TODO
     }
 }
 
+/// mehd
+#[derive(Debug)]
+pub struct MovieExtendsHeaderBox {
+    /// The duration of the whole movie, including all fragments, in the movie's timescale
+    pub fragment_duration: u64,
+}
+
+impl MovieExtendsHeaderBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let fragment_duration = if full_box.version == 1 {
+            reader.read_u64()
+        } else {
+            reader.read_u32() as u64
+        };
+        Self { fragment_duration }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Fragment duration", &self.fragment_duration);
+    }
+}
+
+/// sidx
+#[derive(Debug)]
+pub struct SegmentIndexBox {
+    pub reference_id: u32,
+    pub timescale: u32,
+    pub earliest_presentation_time: u64,
+    pub first_offset: u64,
+    pub references: Vec<SegmentReference>,
+}
+
+#[derive(Debug)]
+pub struct SegmentReference {
+    /// Whether the reference is to another sidx box, rather than to media
+    pub references_index: bool,
+    pub referenced_size: u32,
+    pub subsegment_duration: u32,
+    pub starts_with_sap: bool,
+    pub sap_type: u8,
+    pub sap_delta_time: u32,
+}
+
+impl SegmentIndexBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let reference_id = reader.read_u32();
+        let timescale = reader.read_u32();
+        let (earliest_presentation_time, first_offset) = if full_box.version == 1 {
+            (reader.read_u64(), reader.read_u64())
+        } else {
+            (reader.read_u32() as u64, reader.read_u32() as u64)
+        };
+        let _reserved = reader.read_u16();
+        let reference_count = reader.read_u16();
+        let references = (0..reference_count)
+            .map(|_| {
+                let size = reader.read_u32();
+                let subsegment_duration = reader.read_u32();
+                let sap = reader.read_u32();
+                SegmentReference {
+                    references_index: size >> 31 != 0,
+                    referenced_size: size & 0x7FFF_FFFF,
+                    subsegment_duration,
+                    starts_with_sap: sap >> 31 != 0,
+                    sap_type: ((sap >> 28) & 0b0111) as u8,
+                    sap_delta_time: sap & 0x0FFF_FFFF,
+                }
+            })
+            .collect();
+        Self {
+            reference_id,
+            timescale,
+            earliest_presentation_time,
+            first_offset,
+            references,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Reference ID", &self.reference_id);
+        print("Timescale", &self.timescale);
+        print(
+            "Earliest presentation time",
+            &format_duration(self.earliest_presentation_time, self.timescale),
+        );
+        print("First offset", &self.first_offset);
+        print("# references", &self.references.len());
+        let total: u64 = self
+            .references
+            .iter()
+            .map(|r| r.subsegment_duration as u64)
+            .sum();
+        print("Duration", &format_duration(total, self.timescale));
+    }
+}
+
 /// trex
 #[derive(Debug)]
 pub struct TrackExtendsBox {
@@ -951,7 +2009,7 @@ impl TrackExtendsBox {
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Track ID", &self.track_id);
         print(
@@ -979,120 +2037,1401 @@ impl MovieFragmentHeaderBox {
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Sequence number", &self.sequence_number);
     }
 }
 
-/// stsd
+/// tfra
 #[derive(Debug)]
-pub struct SampleDescriptionBox {
+pub struct TrackFragmentRandomAccessBox {
+    pub version: u8,
+    pub track_id: u32,
+    /// In bytes, for the traf, trun and sample numbers of each entry
+    pub length_size_of_traf_num: u8,
+    pub length_size_of_trun_num: u8,
+    pub length_size_of_sample_num: u8,
     pub entry_count: u32,
 }
 
-impl SampleDescriptionBox {
-    pub fn parse_header(reader: &mut Reader, _inner_size: u64) -> Self {
-        FullBoxHeader::parse(reader);
+/// A sync sample that playback can start from
+#[derive(Debug, Clone)]
+pub struct TrackFragmentRandomAccessEntry {
+    /// Presentation time, in the track's timescale
+    pub time: u64,
+    /// File offset of the moof box that has the sample
+    pub moof_offset: u64,
+    /// The 1-based numbers of the traf in the moof, the trun in the traf and the sample in
+    /// the trun
+    pub traf_number: u32,
+    pub trun_number: u32,
+    pub sample_number: u32,
+}
 
+impl TrackFragmentRandomAccessBox {
+    pub fn parse_header(reader: &mut Reader) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let track_id = reader.read_u32();
+        let lengths = reader.read_u32();
         let entry_count = reader.read_u32();
-        Self { entry_count }
+        Self {
+            version: full_box.version,
+            track_id,
+            length_size_of_traf_num: ((lengths >> 4) & 0b11) as u8 + 1,
+            length_size_of_trun_num: ((lengths >> 2) & 0b11) as u8 + 1,
+            length_size_of_sample_num: (lengths & 0b11) as u8 + 1,
+            entry_count,
+        }
     }
 
-    pub fn parse_entry(&self, reader: &mut Reader) -> SampleEntry {
-        let header = BoxHeader::parse(reader);
-        match header.box_type.as_ref() {
-            "mp4a" => SampleEntry::Mp4a(Mp4aAudioSampleEntry::parse(reader)),
-            "avc1" => SampleEntry::Avc1(Avc1VisualSampleEntry::parse(reader)),
-            _ => panic!("Unhandled sample description entry: {}", header.box_type),
+    pub fn parse_entry(&self, reader: &mut Reader) -> TrackFragmentRandomAccessEntry {
+        let (time, moof_offset) = if self.version == 1 {
+            (reader.read_u64(), reader.read_u64())
+        } else {
+            (reader.read_u32() as u64, reader.read_u32() as u64)
+        };
+        let mut read_number = |length_size: u8| match length_size {
+            1 => reader.read_u8() as u32,
+            2 => reader.read_u16() as u32,
+            3 => reader.read_u24(),
+            _ => reader.read_u32(),
+        };
+        TrackFragmentRandomAccessEntry {
+            time,
+            moof_offset,
+            traf_number: read_number(self.length_size_of_traf_num),
+            trun_number: read_number(self.length_size_of_trun_num),
+            sample_number: read_number(self.length_size_of_sample_num),
         }
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
+        print("Track ID", &self.track_id);
         print("# entries", &self.entry_count);
     }
 }
 
+/// mfro
 #[derive(Debug)]
-pub enum SampleEntry {
-    Mp4a(Mp4aAudioSampleEntry),
-    Avc1(Avc1VisualSampleEntry),
+pub struct MovieFragmentRandomAccessOffsetBox {
+    /// Size of the enclosing mfra box, so that it can be found from the end of the file
+    pub mfra_size: u32,
 }
 
-impl SampleEntry {
-    pub fn name(&self) -> &'static str {
-        match self {
-            SampleEntry::Mp4a(_) => "AudioSampleEntry(mp4a)",
-            SampleEntry::Avc1(_) => "VisualSampleEntry(avc1)",
-        }
+impl MovieFragmentRandomAccessOffsetBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        FullBoxHeader::parse(reader);
+        let mfra_size = reader.read_u32();
+        Self { mfra_size }
     }
 
     pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
-        match self {
-            SampleEntry::Mp4a(mp4a) => mp4a.print_attributes(print),
-            SampleEntry::Avc1(avc1) => avc1.print_attributes(print),
-        }
+        print("mfra size", &self.mfra_size);
     }
 }
 
-/// mp4a
+/// tfhd
 #[derive(Debug)]
-pub struct Mp4aAudioSampleEntry {
-    pub data_reference_index: u16,
-    pub channel_count: u16,
-    pub sample_size: u16,
-    pub sample_rate: f32,
+pub struct TrackFragmentHeaderBox {
+    pub track_id: u32,
+    pub base_data_offset: Option<u64>,
+    pub sample_description_index: Option<u32>,
+    pub default_sample_duration: Option<u32>,
+    pub default_sample_size: Option<u32>,
+    pub default_sample_flags: Option<u32>,
+    pub duration_is_empty: bool,
+    pub default_base_is_moof: bool,
 }
 
-impl Mp4aAudioSampleEntry {
-    fn parse(reader: &mut Reader) -> Self {
-        let _reserved = reader.read_string(6);
-        let data_reference_index = reader.read_u16();
-
-        //let mut remaining = inner_size - 8;
-
-        // https://www.fatalerrors.org/a/analysis-of-mp4-file-format.html
-
-        let _reserved = reader.read_bytes(4 * 2);
-        let channel_count = reader.read_u16();
-        let sample_size = reader.read_u16();
-        let _predefined = reader.read_bytes(2);
-        let _reserved = reader.read_bytes(2);
-        let sample_rate = reader.read_fixed_point_16_16();
+impl TrackFragmentHeaderBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let flags = full_box.flags_u32();
+        let track_id = reader.read_u32();
+        let base_data_offset = (flags & 0x000001 != 0).then(|| reader.read_u64());
+        let sample_description_index = (flags & 0x000002 != 0).then(|| reader.read_u32());
+        let default_sample_duration = (flags & 0x000008 != 0).then(|| reader.read_u32());
+        let default_sample_size = (flags & 0x000010 != 0).then(|| reader.read_u32());
+        let default_sample_flags = (flags & 0x000020 != 0).then(|| reader.read_u32());
+        Self {
+            track_id,
+            base_data_offset,
+            sample_description_index,
+            default_sample_duration,
+            default_sample_size,
+            default_sample_flags,
+            duration_is_empty: flags & 0x010000 != 0,
+            default_base_is_moof: flags & 0x020000 != 0,
+        }
+    }
 
-        //remaining -= 20;
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Track ID", &self.track_id);
+        if let Some(base_data_offset) = self.base_data_offset {
+            print("Base data offset", &base_data_offset);
+        }
+        if let Some(index) = self.sample_description_index {
+            print("Smpl. descr. index", &index);
+        }
+        if let Some(duration) = self.default_sample_duration {
+            print("Default sample duration", &duration);
+        }
+        if let Some(size) = self.default_sample_size {
+            print("Default sample size", &size);
+        }
+        if let Some(flags) = self.default_sample_flags {
+            print("Default sample flags", &flags);
+        }
+        if self.duration_is_empty {
+            print("Duration is empty", &true);
+        }
+        print("Default base is moof", &self.default_base_is_moof);
+    }
+}
 
-        // TODO ?
-        // parse_container_sub_boxes(reader, remaining, logger, HandleUnknown::Skip);
+/// tfdt
+#[derive(Debug)]
+pub struct TrackFragmentBaseMediaDecodeTimeBox {
+    pub base_media_decode_time: u64,
+}
 
+impl TrackFragmentBaseMediaDecodeTimeBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let base_media_decode_time = if full_box.version == 1 {
+            reader.read_u64()
+        } else {
+            reader.read_u32() as u64
+        };
         Self {
-            data_reference_index,
-            channel_count,
-            sample_size,
-            sample_rate,
+            base_media_decode_time,
         }
     }
 
-    fn print_attributes<F>(&self, print: F)
+    pub fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
-        print("Data reference index", &self.data_reference_index);
-        print("Channel count", &self.channel_count);
-        print("Sample size", &self.sample_size);
-        print("Sample rate", &self.sample_rate);
+        print("Base media decode time", &self.base_media_decode_time);
     }
 }
 
-/// avc1
+/// trun
 #[derive(Debug)]
-pub struct Avc1VisualSampleEntry {
+pub struct TrackRunBox {
+    pub version: u8,
+    pub flags: u32,
+    pub sample_count: u32,
+    pub data_offset: Option<i32>,
+    pub first_sample_flags: Option<u32>,
+}
+
+/// A trun entry. Fields that are None take their value from tfhd or trex.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackRunEntry {
+    pub sample_duration: Option<u32>,
+    pub sample_size: Option<u32>,
+    pub sample_flags: Option<u32>,
+    pub sample_composition_time_offset: Option<i32>,
+}
+
+impl TrackRunBox {
+    pub fn parse_header(reader: &mut Reader) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let flags = full_box.flags_u32();
+        let sample_count = reader.read_u32();
+        let data_offset = (flags & 0x000001 != 0).then(|| reader.read_i32());
+        let first_sample_flags = (flags & 0x000004 != 0).then(|| reader.read_u32());
+        Self {
+            version: full_box.version,
+            flags,
+            sample_count,
+            data_offset,
+            first_sample_flags,
+        }
+    }
+
+    /// Whether each entry has its own duration, rather than the default from tfhd or trex
+    pub fn has_sample_durations(&self) -> bool {
+        self.flags & 0x000100 != 0
+    }
+
+    pub fn parse_entry(&self, reader: &mut Reader) -> TrackRunEntry {
+        let sample_duration = self.has_sample_durations().then(|| reader.read_u32());
+        let sample_size = (self.flags & 0x000200 != 0).then(|| reader.read_u32());
+        let sample_flags = (self.flags & 0x000400 != 0).then(|| reader.read_u32());
+        // Version 0 offsets are unsigned, but offsets above i32::MAX don't occur in practice
+        let sample_composition_time_offset =
+            (self.flags & 0x000800 != 0).then(|| reader.read_i32());
+        TrackRunEntry {
+            sample_duration,
+            sample_size,
+            sample_flags,
+            sample_composition_time_offset,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Sample count", &self.sample_count);
+        if let Some(data_offset) = self.data_offset {
+            print("Data offset", &data_offset);
+        }
+        if let Some(flags) = self.first_sample_flags {
+            print("First sample flags", &flags);
+        }
+    }
+}
+
+/// chpl (not part of ISO-BMFF, but written by Nero and ffmpeg, and read by most players)
+#[derive(Debug)]
+pub struct ChapterListBox {
+    pub chapters: Vec<Chapter>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    /// In units of 100 nanoseconds
+    pub start_time: u64,
+    pub title: String,
+}
+
+impl Chapter {
+    pub fn start_secs(&self) -> f64 {
+        self.start_time as f64 / 10_000_000.0
+    }
+}
+
+impl ChapterListBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        if full_box.version == 1 {
+            let _reserved = reader.read_u32();
+        }
+        let chapter_count = reader.read_u8();
+        let mut chapters = Vec::new();
+        for _ in 0..chapter_count {
+            let start_time = reader.read_u64();
+            let title_len = reader.read_u8();
+            let title = String::from_utf8_lossy(&reader.read_bytes(title_len as usize)).to_string();
+            chapters.push(Chapter { start_time, title });
+        }
+        Self { chapters }
+    }
+
+    /// Writes a version 1 'chpl'. The format has room for at most 255 chapters, with titles
+    /// of at most 255 bytes.
+    pub fn write(writer: &mut Writer, chapters: &[Chapter]) {
+        let start = writer.begin_full_box("chpl", 1, [0, 0, 0]);
+        writer.write_u32(0); // reserved
+        writer.write_u8(chapters.len().min(255) as u8);
+        for chapter in chapters.iter().take(255) {
+            let mut title_len = chapter.title.len().min(255);
+            while !chapter.title.is_char_boundary(title_len) {
+                title_len -= 1;
+            }
+            writer.write_u64(chapter.start_time);
+            writer.write_u8(title_len as u8);
+            writer.write_string(&chapter.title[..title_len]);
+        }
+        writer.end_box(start);
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("# chapters", &self.chapters.len());
+        for chapter in &self.chapters {
+            print(
+                "Chapter",
+                &format!("{:.3}s {}", chapter.start_secs(), chapter.title),
+            );
+        }
+    }
+}
+
+/// mere
+///
+/// Found in a meco box, next to the meta boxes of the different metadata systems that it relates
+#[derive(Debug)]
+pub struct MetaboxRelationBox {
+    pub first_metabox_handler_type: String,
+    pub second_metabox_handler_type: String,
+    pub metabox_relation: u8,
+}
+
+impl MetaboxRelationBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        FullBoxHeader::parse(reader);
+        let first_metabox_handler_type = reader.read_string(4);
+        let second_metabox_handler_type = reader.read_string(4);
+        let metabox_relation = reader.read_u8();
+        Self {
+            first_metabox_handler_type,
+            second_metabox_handler_type,
+            metabox_relation,
+        }
+    }
+
+    pub fn relation_name(&self) -> &'static str {
+        match self.metabox_relation {
+            1 => "unknown",
+            2 => "unrelated",
+            3 => "complementary",
+            4 => "overlapping",
+            5 => "second is a subset of the first, which is preferred",
+            6 => "equivalent",
+            _ => "reserved",
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("First metabox handler", &self.first_metabox_handler_type);
+        print("Second metabox handler", &self.second_metabox_handler_type);
+        print(
+            "Relation",
+            &format!("{} ({})", self.metabox_relation, self.relation_name()),
+        );
+    }
+}
+
+/// cprt
+#[derive(Debug)]
+pub struct CopyrightBox {
+    pub language: String,
+    pub notice: String,
+}
+
+impl CopyrightBox {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        FullBoxHeader::parse(reader);
+        let language = parse_language(reader);
+        let notice = reader.read_bytes((inner_size.saturating_sub(6)) as usize);
+        // The notice is UTF-8, unless it starts with a byte order mark, in which case it's UTF-16
+        let notice = match notice.as_slice() {
+            [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+            [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+            _ => String::from_utf8_lossy(&notice).to_string(),
+        };
+        let notice = notice.trim_end_matches('\0').to_string();
+        Self { language, notice }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Language", &self.language);
+        print("Notice", &self.notice);
+    }
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// The extended type of the uuid box in which Adobe stores XMP metadata
+pub const XMP_UUID: [u8; 16] = [
+    0xBE, 0x7A, 0xCF, 0xCB, 0x97, 0xA9, 0x42, 0xE8, 0x9C, 0x71, 0x99, 0x94, 0x91, 0xE3, 0xAF, 0xAC,
+];
+
+/// uuid
+#[derive(Debug)]
+pub struct UserExtensionBox {
+    pub extended_type: [u8; 16],
+    /// The XMP packet, if this is Adobe's XMP box. The contents of other uuid boxes are skipped.
+    pub xmp: Option<String>,
+}
+
+impl UserExtensionBox {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let mut extended_type = [0; 16];
+        reader.read_exact(&mut extended_type);
+        let xmp = (extended_type == XMP_UUID).then(|| {
+            let packet = reader.read_bytes((inner_size.saturating_sub(16)) as usize);
+            String::from_utf8_lossy(&packet)
+                .trim_end_matches('\0')
+                .to_string()
+        });
+        Self { extended_type, xmp }
+    }
+
+    /// The extended type in the usual UUID notation
+    pub fn uuid(&self) -> String {
+        format_uuid(&self.extended_type)
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("UUID", &self.uuid());
+        if let Some(xmp) = &self.xmp {
+            print("XMP packet", &format!("{} bytes", xmp.len()));
+        }
+    }
+}
+
+/// xml
+#[derive(Debug)]
+pub struct XmlBox {
+    pub xml: String,
+}
+
+impl XmlBox {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        FullBoxHeader::parse(reader);
+        let xml = reader.read_bytes((inner_size.saturating_sub(4)) as usize);
+        let xml = String::from_utf8_lossy(&xml)
+            .trim_end_matches('\0')
+            .to_string();
+        Self { xml }
+    }
+
+    /// Whether the XML is an XMP packet, rather than e.g. MPEG-7 metadata
+    pub fn is_xmp(&self) -> bool {
+        self.xml.contains("<x:xmpmeta") || self.xml.contains("<?xpacket")
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("XML", &format!("{} bytes", self.xml.len()));
+        print("XMP", &self.is_xmp());
+    }
+}
+
+/// stsd
+#[derive(Debug)]
+pub struct SampleDescriptionBox {
+    pub entry_count: u32,
+}
+
+impl SampleDescriptionBox {
+    pub fn parse_header(reader: &mut Reader, _inner_size: u64) -> Self {
+        FullBoxHeader::parse(reader);
+
+        let entry_count = reader.read_u32();
+        Self { entry_count }
+    }
+
+    pub fn parse_entry(&self, reader: &mut Reader) -> SampleEntry {
+        let header = BoxHeader::parse(reader);
+        match header.box_type.as_ref() {
+            "mp4a" => SampleEntry::Mp4a(Mp4aAudioSampleEntry::parse(reader, header.inner_size)),
+            "avc1" => SampleEntry::Avc1(Avc1VisualSampleEntry::parse(reader, header.inner_size)),
+            "mp4v" => SampleEntry::Mp4v(Mp4vVisualSampleEntry::parse(reader, header.inner_size)),
+            "s263" => SampleEntry::H263(H263VisualSampleEntry::parse(reader, header.inner_size)),
+            "jpeg" | "mjpa" | "mjpb" | "png " => SampleEntry::Image(ImageVisualSampleEntry::parse(
+                reader,
+                &header.box_type,
+                header.inner_size,
+            )),
+            "hvc1" | "hev1" | "dvh1" | "dvhe" => SampleEntry::Hevc(HevcVisualSampleEntry::parse(
+                reader,
+                &header.box_type,
+                header.inner_size,
+            )),
+            "ac-4" => SampleEntry::Ac4(Ac4AudioSampleEntry::parse(reader, header.inner_size)),
+            "mlpa" => SampleEntry::Mlpa(MlpaAudioSampleEntry::parse(reader, header.inner_size)),
+            "twos" | "sowt" | "in24" | "lpcm" | "ulaw" | "alaw" => SampleEntry::Pcm(
+                PcmAudioSampleEntry::parse(reader, &header.box_type, header.inner_size),
+            ),
+            "encv" | "enca" => SampleEntry::Encrypted(EncryptedSampleEntry::parse(
+                reader,
+                &header.box_type,
+                header.inner_size,
+            )),
+            "mebx" => SampleEntry::Mebx(MetadataSampleEntry::parse(reader, header.inner_size)),
+            "mett" | "metx" => SampleEntry::TimedMetadata(TimedMetadataSampleEntry::parse(
+                reader,
+                &header.box_type,
+                header.inner_size,
+            )),
+            "tx3g" => SampleEntry::Text(TextSampleEntry::parse(reader, header.inner_size)),
+            "wvtt" => SampleEntry::WebVtt(WebVttSampleEntry::parse(reader, header.inner_size)),
+            _ => {
+                diagnostic!(
+                    Severity::Warning,
+                    "Unknown sample entry '{}', its {} bytes are skipped",
+                    header.box_type,
+                    header.inner_size
+                );
+                SampleEntry::Unknown(UnknownSampleEntry::parse(
+                    reader,
+                    &header.box_type,
+                    header.inner_size,
+                ))
+            }
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("# entries", &self.entry_count);
+    }
+}
+
+#[derive(Debug)]
+pub enum SampleEntry {
+    Mp4a(Mp4aAudioSampleEntry),
+    Ac4(Ac4AudioSampleEntry),
+    Mlpa(MlpaAudioSampleEntry),
+    /// Uncompressed audio
+    Pcm(PcmAudioSampleEntry),
+    Avc1(Avc1VisualSampleEntry),
+    Hevc(HevcVisualSampleEntry),
+    /// MPEG-4 Visual
+    Mp4v(Mp4vVisualSampleEntry),
+    /// H.263 (s263)
+    H263(H263VisualSampleEntry),
+    /// Motion JPEG or PNG
+    Image(ImageVisualSampleEntry),
+    /// QuickTime timed metadata
+    Mebx(MetadataSampleEntry),
+    /// mett or metx
+    TimedMetadata(TimedMetadataSampleEntry),
+    /// 3GPP timed text (tx3g)
+    Text(TextSampleEntry),
+    /// WebVTT (wvtt)
+    WebVtt(WebVttSampleEntry),
+    /// encv or enca
+    Encrypted(EncryptedSampleEntry),
+    /// Any other type, which is skipped
+    Unknown(UnknownSampleEntry),
+}
+
+impl SampleEntry {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SampleEntry::Mp4a(_) => "AudioSampleEntry(mp4a)",
+            SampleEntry::Ac4(_) => "AudioSampleEntry(ac-4)",
+            SampleEntry::Mlpa(_) => "AudioSampleEntry(mlpa)",
+            SampleEntry::Pcm(pcm) => match pcm.fourcc.as_ref() {
+                "twos" => "AudioSampleEntry(twos)",
+                "sowt" => "AudioSampleEntry(sowt)",
+                "in24" => "AudioSampleEntry(in24)",
+                "lpcm" => "AudioSampleEntry(lpcm)",
+                "ulaw" => "AudioSampleEntry(ulaw)",
+                _ => "AudioSampleEntry(alaw)",
+            },
+            SampleEntry::Avc1(_) => "VisualSampleEntry(avc1)",
+            SampleEntry::Hevc(hevc) => match hevc.fourcc.as_ref() {
+                "hvc1" => "VisualSampleEntry(hvc1)",
+                "hev1" => "VisualSampleEntry(hev1)",
+                "dvh1" => "VisualSampleEntry(dvh1)",
+                _ => "VisualSampleEntry(dvhe)",
+            },
+            SampleEntry::Mp4v(_) => "VisualSampleEntry(mp4v)",
+            SampleEntry::H263(_) => "VisualSampleEntry(s263)",
+            SampleEntry::Image(image) => match image.fourcc.as_ref() {
+                "mjpa" => "VisualSampleEntry(mjpa)",
+                "mjpb" => "VisualSampleEntry(mjpb)",
+                "png " => "VisualSampleEntry(png )",
+                _ => "VisualSampleEntry(jpeg)",
+            },
+            SampleEntry::Mebx(_) => "MetadataSampleEntry(mebx)",
+            SampleEntry::TimedMetadata(entry) if entry.fourcc == "metx" => {
+                "XMLMetaDataSampleEntry(metx)"
+            }
+            SampleEntry::TimedMetadata(_) => "TextMetaDataSampleEntry(mett)",
+            SampleEntry::Text(_) => "TextSampleEntry(tx3g)",
+            SampleEntry::WebVtt(_) => "WVTTSampleEntry(wvtt)",
+            SampleEntry::Encrypted(entry) if entry.fourcc == "encv" => "VisualSampleEntry(encv)",
+            SampleEntry::Encrypted(_) => "AudioSampleEntry(enca)",
+            SampleEntry::Unknown(_) => "SampleEntry(unknown)",
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        match self {
+            SampleEntry::Mp4a(mp4a) => mp4a.print_attributes(print),
+            SampleEntry::Ac4(ac4) => ac4.print_attributes(print),
+            SampleEntry::Mlpa(mlpa) => mlpa.print_attributes(print),
+            SampleEntry::Pcm(pcm) => pcm.print_attributes(print),
+            SampleEntry::Avc1(avc1) => avc1.print_attributes(print),
+            SampleEntry::Hevc(hevc) => hevc.print_attributes(print),
+            SampleEntry::Mp4v(mp4v) => mp4v.print_attributes(print),
+            SampleEntry::H263(h263) => h263.print_attributes(print),
+            SampleEntry::Image(image) => image.print_attributes(print),
+            SampleEntry::Mebx(mebx) => mebx.print_attributes(print),
+            SampleEntry::TimedMetadata(entry) => entry.print_attributes(print),
+            SampleEntry::Text(text) => text.print_attributes(print),
+            SampleEntry::WebVtt(webvtt) => webvtt.print_attributes(print),
+            SampleEntry::Encrypted(entry) => entry.print_attributes(print),
+            SampleEntry::Unknown(entry) => entry.print_attributes(print),
+        }
+    }
+}
+
+/// mp4a
+#[derive(Debug)]
+pub struct Mp4aAudioSampleEntry {
+    pub data_reference_index: u16,
+    pub channel_count: u16,
+    pub sample_size: u16,
+    pub sample_rate: f32,
+    pub es_descriptor: Option<ElementaryStreamDescriptorBox>,
+}
+
+impl Mp4aAudioSampleEntry {
+    fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let start_offset = reader.position();
+        let fields = AudioSampleEntryFields::parse(reader);
+
+        let end_offset = start_offset + inner_size;
+        let mut es_descriptor = None;
+        let mut sampling_rate = None;
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            if header.box_type == "wave" {
+                // QuickTime puts esds in here. Its children are read as if they were the sample
+                // entry's.
+                continue;
+            }
+            let box_end_offset = header.start_offset + header.box_size;
+            match header.box_type.as_str() {
+                "esds" => {
+                    es_descriptor = Some(ElementaryStreamDescriptorBox::parse(
+                        reader,
+                        header.inner_size,
+                    ))
+                }
+                "srat" => sampling_rate = Some(parse_sampling_rate(reader)),
+                _ => {}
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+
+        Self {
+            data_reference_index: fields.data_reference_index,
+            channel_count: fields.channels().min(u16::MAX as u32) as u16,
+            sample_size: fields.sample_size,
+            sample_rate: sampling_rate.map_or(fields.sample_rate_hz(), f64::from) as f32,
+            es_descriptor,
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        print("Channel count", &self.channel_count);
+        print("Sample size", &self.sample_size);
+        print("Sample rate", &self.sample_rate);
+        if let Some(es_descriptor) = &self.es_descriptor {
+            es_descriptor.print_attributes(print);
+        }
+    }
+}
+
+/// esds, which wraps an MPEG-4 ES descriptor. Only its decoder config is kept.
+#[derive(Debug, Clone)]
+pub struct ElementaryStreamDescriptorBox {
+    /// E.g. 0x40 for MPEG-4 audio
+    pub object_type_indication: u8,
+    /// In bits per second
+    pub max_bitrate: u32,
+    /// In bits per second
+    pub avg_bitrate: u32,
+    /// From the AudioSpecificConfig, e.g. 2 for AAC LC
+    pub audio_object_type: Option<u8>,
+    pub channel_configuration: Option<u8>,
+    /// From the visual object sequence header of MPEG-4 Visual, e.g. 0xF5 for Advanced Simple
+    /// Profile level 5
+    pub profile_level_indication: Option<u8>,
+}
+
+impl ElementaryStreamDescriptorBox {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        FullBoxHeader::parse(reader);
+        let descriptors_size = inner_size.saturating_sub(4);
+        // The descriptor sizes can't be trusted to stay within the box
+        let mut descriptors = reader.limited(descriptors_size);
+        reader.skip(descriptors_size);
+        let mut result = Self {
+            object_type_indication: 0,
+            max_bitrate: 0,
+            avg_bitrate: 0,
+            audio_object_type: None,
+            channel_configuration: None,
+            profile_level_indication: None,
+        };
+
+        // ES_Descriptor
+        match read_descriptor_header(&mut descriptors, 0x03) {
+            Some(size) if size >= 3 => {}
+            _ => return result,
+        }
+        let _es_id = descriptors.read_u16();
+        let flags = descriptors.read_u8();
+        if flags & 0x80 != 0 {
+            let _depends_on_es_id = descriptors.read_u16();
+        }
+        if flags & 0x40 != 0 {
+            let url_len = descriptors.read_u8();
+            descriptors.skip(url_len as u64);
+        }
+        if flags & 0x20 != 0 {
+            let _ocr_es_id = descriptors.read_u16();
+        }
+
+        // DecoderConfigDescriptor
+        let config_size = match read_descriptor_header(&mut descriptors, 0x04) {
+            Some(size) if size >= 13 => size,
+            _ => return result,
+        };
+        result.object_type_indication = descriptors.read_u8();
+        let _stream_type = descriptors.read_u8();
+        let _buffer_size = descriptors.read_u24();
+        result.max_bitrate = descriptors.read_u32();
+        result.avg_bitrate = descriptors.read_u32();
+
+        // DecoderSpecificInfo, i.e. the AudioSpecificConfig for MPEG-4 audio, and the visual
+        // object sequence and object layer headers for MPEG-4 Visual
+        if config_size <= 13 {
+            return result;
+        }
+        let info_size = match read_descriptor_header(&mut descriptors, 0x05) {
+            Some(info_size) => info_size,
+            None => return result,
+        };
+        match result.object_type_indication {
+            0x40 => {
+                let info = descriptors.read_bytes((info_size as usize).min(8));
+                let mut bits = BitReader::new(&info);
+                let mut audio_object_type = bits.read_bits(5) as u8;
+                if audio_object_type == 31 {
+                    audio_object_type = 32 + bits.read_bits(6) as u8;
+                }
+                if bits.read_bits(4) == 0xF {
+                    // Explicit sampling frequency
+                    bits.skip_bits(24);
+                }
+                result.audio_object_type = Some(audio_object_type);
+                result.channel_configuration = Some(bits.read_bits(4) as u8);
+            }
+            0x20 => {
+                let info = descriptors.read_bytes(info_size as usize);
+                // The byte after the visual_object_sequence_start_code
+                result.profile_level_indication = info
+                    .windows(5)
+                    .find(|window| window[..4] == [0, 0, 1, 0xB0])
+                    .map(|window| window[4]);
+            }
+            _ => {}
+        }
+        result
+    }
+
+    /// The codecs parameter of RFC 6381, e.g. "mp4a.40.2" for AAC LC or "mp4v.20.9" for
+    /// MPEG-4 Visual Simple Profile level 0. Only the object type if the decoder config doesn't
+    /// tell more.
+    pub fn codec_string(&self, fourcc: &str) -> String {
+        let detail = match self.object_type_indication {
+            0x40 => self.audio_object_type,
+            0x20 => self.profile_level_indication,
+            _ => None,
+        };
+        match detail {
+            Some(detail) => format!("{}.{:02X}.{}", fourcc, self.object_type_indication, detail),
+            None => format!("{}.{:02X}", fourcc, self.object_type_indication),
+        }
+    }
+
+    /// The profile of MPEG-4 Visual, with its level, e.g. "Advanced Simple, level 5"
+    pub fn visual_profile_name(&self) -> Option<String> {
+        let name = match self.profile_level_indication? {
+            0x08 => "Simple, level 0".to_string(),
+            0x09 => "Simple, level 0b".to_string(),
+            level @ 0x01..=0x03 => format!("Simple, level {}", level),
+            0x04 => "Simple, level 4a".to_string(),
+            0x05 => "Simple, level 5".to_string(),
+            0x06 => "Simple, level 6".to_string(),
+            0xF7 => "Advanced Simple, level 3b".to_string(),
+            level @ 0xF0..=0xF5 => format!("Advanced Simple, level {}", level - 0xF0),
+            other => format!("profile and level {:#04x}", other),
+        };
+        Some(name)
+    }
+
+    /// The name of the AAC profile, e.g. "AAC LC" or "HE-AAC v2"
+    pub fn audio_profile_name(&self) -> Option<String> {
+        let name = match self.audio_object_type? {
+            1 => "AAC Main".to_string(),
+            2 => "AAC LC".to_string(),
+            3 => "AAC SSR".to_string(),
+            4 => "AAC LTP".to_string(),
+            5 => "HE-AAC".to_string(),
+            29 => "HE-AAC v2".to_string(),
+            42 => "xHE-AAC".to_string(),
+            other => format!("audio object type {}", other),
+        };
+        Some(name)
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print(
+            "Object type",
+            &format!("{:#04x}", self.object_type_indication),
+        );
+        print("Max bitrate", &self.max_bitrate);
+        print("Avg bitrate", &self.avg_bitrate);
+        if let Some(profile) = self.audio_profile_name() {
+            print("Audio profile", &profile);
+        }
+        if let Some(channel_configuration) = self.channel_configuration {
+            print("Channel configuration", &channel_configuration);
+        }
+        if let Some(profile) = self.visual_profile_name() {
+            print("Visual profile", &profile);
+        }
+    }
+}
+
+/// Reads the tag and size of the descriptor that comes next, and returns the size if it has
+/// the given tag
+fn read_descriptor_header(reader: &mut Reader, tag: u8) -> Option<u32> {
+    if reader.read_u8() != tag {
+        return None;
+    }
+    Some(reader.read_descriptor_size())
+}
+
+/// The fields that all audio sample entries start with, and the ones that QuickTime's sound
+/// sample descriptions add after them
+struct AudioSampleEntryFields {
+    data_reference_index: u16,
+    channel_count: u16,
+    sample_size: u16,
+    /// Normally 16.16 fixed point, but a plain integer in some sample entries
+    sample_rate: u32,
+    v2: Option<SoundDescriptionV2>,
+}
+
+/// The fields of a version 2 QuickTime sound sample description, which replace the channel
+/// count and sample rate before them. Those are then always 3 and 1 Hz.
+struct SoundDescriptionV2 {
+    sample_rate: f64,
+    channel_count: u32,
+    bits_per_channel: u32,
+    /// For lpcm, see `LPCM_FLAG_IS_FLOAT` etc.
+    format_flags: u32,
+}
+
+impl AudioSampleEntryFields {
+    fn parse(reader: &mut Reader) -> Self {
+        let _reserved = reader.read_bytes(6);
+        let data_reference_index = reader.read_u16();
+
+        // https://www.fatalerrors.org/a/analysis-of-mp4-file-format.html
+
+        // 0 in ISOBMFF. QuickTime's sound sample descriptions have more fields in versions 1
+        // and 2.
+        let version = reader.read_u16();
+        let _reserved = reader.read_bytes(6);
+        let channel_count = reader.read_u16();
+        let sample_size = reader.read_u16();
+        let _predefined = reader.read_bytes(2);
+        let _reserved = reader.read_bytes(2);
+        let sample_rate = reader.read_u32();
+
+        let mut v2 = None;
+        match version {
+            1 if reader.profile() == Profile::QuickTime || !next_is_box(reader) => {
+                // Samples per packet, bytes per packet, bytes per frame and bytes per sample,
+                // which describe how compressed audio is packed. ISOBMFF's version 1 has its
+                // child boxes right away instead, and since QuickTime movies don't always have
+                // an ftyp box to tell, anything but a box is taken to be these.
+                reader.skip(16);
+            }
+            2 => {
+                let _size_of_struct_only = reader.read_u32();
+                let sample_rate = f64::from_bits(reader.read_u64());
+                let channel_count = reader.read_u32();
+                let _always_7f000000 = reader.read_u32();
+                let bits_per_channel = reader.read_u32();
+                let format_flags = reader.read_u32();
+                let _bytes_per_packet = reader.read_u32();
+                let _frames_per_packet = reader.read_u32();
+                v2 = Some(SoundDescriptionV2 {
+                    sample_rate,
+                    channel_count,
+                    bits_per_channel,
+                    format_flags,
+                });
+            }
+            _ => {}
+        }
+
+        Self {
+            data_reference_index,
+            channel_count,
+            sample_size,
+            sample_rate,
+            v2,
+        }
+    }
+
+    /// In Hz, from the 16.16 fixed point sample rate unless it's a version 2 description
+    fn sample_rate_hz(&self) -> f64 {
+        match &self.v2 {
+            Some(v2) => v2.sample_rate,
+            None => self.sample_rate as f64 / 65536.0,
+        }
+    }
+
+    fn channels(&self) -> u32 {
+        match &self.v2 {
+            Some(v2) => v2.channel_count,
+            None => self.channel_count as u32,
+        }
+    }
+}
+
+/// srat, in ISOBMFF's version 1 audio sample entries: the sample rate in Hz, for rates that
+/// don't fit in the 16.16 fixed point field, e.g. 192 kHz
+fn parse_sampling_rate(reader: &mut Reader) -> u32 {
+    FullBoxHeader::parse(reader);
+    reader.read_u32()
+}
+
+/// Whether the next 8 bytes look like a box header: a size of at least 8, and a type of
+/// printable characters
+fn next_is_box(reader: &mut Reader) -> bool {
+    let header = reader.peek_bytes(8);
+    if header.len() < 8 {
+        return false;
+    }
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    (size >= 8 || size == 1)
+        && header[4..]
+            .iter()
+            .all(|&b| b.is_ascii_graphic() || b == b' ' || b == 0xA9)
+}
+
+/// ac-4, Dolby AC-4
+#[derive(Debug)]
+pub struct Ac4AudioSampleEntry {
+    pub data_reference_index: u16,
+    pub channel_count: u16,
+    pub sample_rate: f32,
+    pub ac4_config: Option<Ac4SpecificBox>,
+}
+
+impl Ac4AudioSampleEntry {
+    fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let start_offset = reader.position();
+        let fields = AudioSampleEntryFields::parse(reader);
+
+        let end_offset = start_offset + inner_size;
+        let mut ac4_config = None;
+        let mut sampling_rate = None;
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            match header.box_type.as_str() {
+                "dac4" => ac4_config = Some(Ac4SpecificBox::parse(reader)),
+                "srat" => sampling_rate = Some(parse_sampling_rate(reader)),
+                _ => {}
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+
+        Self {
+            data_reference_index: fields.data_reference_index,
+            channel_count: fields.channels().min(u16::MAX as u32) as u16,
+            sample_rate: sampling_rate.map_or(fields.sample_rate_hz(), f64::from) as f32,
+            ac4_config,
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        print("Channel count", &self.channel_count);
+        print("Sample rate", &self.sample_rate);
+        if let Some(ac4_config) = &self.ac4_config {
+            print("AC-4 DSI version", &ac4_config.dsi_version);
+            print("AC-4 bitstream version", &ac4_config.bitstream_version);
+            print("AC-4 sample rate", &ac4_config.sample_rate());
+            print("AC-4 frame rate index", &ac4_config.frame_rate_index);
+            print("# presentations", &ac4_config.presentation_count);
+        }
+    }
+}
+
+/// dac4. Only the fields before the presentations are parsed.
+#[derive(Debug, Clone)]
+pub struct Ac4SpecificBox {
+    pub dsi_version: u8,
+    pub bitstream_version: u8,
+    /// 0 for 44.1 kHz, 1 for 48 kHz (or a multiple of it)
+    pub fs_index: u8,
+    pub frame_rate_index: u8,
+    pub presentation_count: u16,
+}
+
+impl Ac4SpecificBox {
+    pub fn parse(reader: &mut Reader) -> Self {
+        let bits = reader.read_u24();
+        Self {
+            dsi_version: (bits >> 21) as u8,
+            bitstream_version: ((bits >> 14) & 0x7F) as u8,
+            fs_index: ((bits >> 13) & 1) as u8,
+            frame_rate_index: ((bits >> 9) & 0xF) as u8,
+            presentation_count: (bits & 0x1FF) as u16,
+        }
+    }
+
+    /// In Hz
+    pub fn sample_rate(&self) -> u32 {
+        if self.fs_index == 0 {
+            44100
+        } else {
+            48000
+        }
+    }
+}
+
+/// mlpa, Dolby TrueHD
+#[derive(Debug)]
+pub struct MlpaAudioSampleEntry {
+    pub data_reference_index: u16,
+    /// Not meaningful for TrueHD, whose channels are given by dmlp
+    pub channel_count: u16,
+    /// In Hz, a plain integer rather than fixed point
+    pub sample_rate: u32,
+    pub mlp_config: Option<MlpSpecificBox>,
+}
+
+impl MlpaAudioSampleEntry {
+    fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let start_offset = reader.position();
+        let fields = AudioSampleEntryFields::parse(reader);
+
+        let end_offset = start_offset + inner_size;
+        let mut mlp_config = None;
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            if header.box_type == "dmlp" {
+                mlp_config = Some(MlpSpecificBox::parse(reader));
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+
+        Self {
+            data_reference_index: fields.data_reference_index,
+            channel_count: fields.channel_count,
+            sample_rate: fields.sample_rate,
+            mlp_config,
+        }
+    }
+
+    /// From dmlp if it's there, otherwise from the sample entry
+    pub fn channels(&self) -> u16 {
+        self.mlp_config
+            .as_ref()
+            .and_then(|config| config.channel_count())
+            .unwrap_or(self.channel_count)
+    }
+
+    /// In Hz. From dmlp if it's there, otherwise from the sample entry.
+    pub fn sampling_rate(&self) -> u32 {
+        self.mlp_config
+            .as_ref()
+            .and_then(|config| config.sample_rate())
+            .unwrap_or(self.sample_rate)
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        print("Channel count", &self.channels());
+        print("Sample rate", &self.sampling_rate());
+        if let Some(mlp_config) = &self.mlp_config {
+            print("Format info", &format!("{:#010x}", mlp_config.format_info));
+            print("Peak data rate", &mlp_config.peak_data_rate);
+        }
+    }
+}
+
+/// twos, sowt, in24, lpcm, ulaw or alaw: uncompressed audio, as QuickTime stores it
+#[derive(Debug)]
+pub struct PcmAudioSampleEntry {
+    pub fourcc: String,
+    pub data_reference_index: u16,
+    pub channel_count: u32,
+    /// In Hz
+    pub sample_rate: f64,
+    /// Of each channel. 8 for ulaw and alaw, whose samples are companded.
+    pub bits_per_sample: u32,
+    pub format: PcmFormat,
+    pub big_endian: bool,
+}
+
+/// How PCM samples are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmFormat {
+    SignedInteger,
+    UnsignedInteger,
+    Float,
+    /// G.711 µ-law
+    MuLaw,
+    /// G.711 A-law
+    ALaw,
+}
+
+impl core::fmt::Display for PcmFormat {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PcmFormat::SignedInteger => write!(f, "signed integer"),
+            PcmFormat::UnsignedInteger => write!(f, "unsigned integer"),
+            PcmFormat::Float => write!(f, "float"),
+            PcmFormat::MuLaw => write!(f, "µ-law"),
+            PcmFormat::ALaw => write!(f, "A-law"),
+        }
+    }
+}
+
+/// In the format specific flags of an lpcm sample entry, as in Core Audio's
+/// AudioStreamBasicDescription
+const LPCM_FLAG_IS_FLOAT: u32 = 0x1;
+const LPCM_FLAG_IS_BIG_ENDIAN: u32 = 0x2;
+const LPCM_FLAG_IS_SIGNED_INTEGER: u32 = 0x4;
+
+impl PcmAudioSampleEntry {
+    fn parse(reader: &mut Reader, fourcc: &str, inner_size: u64) -> Self {
+        let start_offset = reader.position();
+        let fields = AudioSampleEntryFields::parse(reader);
+        let mut entry = Self {
+            fourcc: fourcc.to_string(),
+            data_reference_index: fields.data_reference_index,
+            channel_count: fields.channels(),
+            sample_rate: fields.sample_rate_hz(),
+            bits_per_sample: fields.sample_size as u32,
+            format: PcmFormat::SignedInteger,
+            big_endian: fourcc != "sowt",
+        };
+        match fourcc {
+            "in24" => entry.bits_per_sample = 24,
+            "ulaw" | "alaw" => {
+                entry.bits_per_sample = 8;
+                entry.format = if fourcc == "ulaw" {
+                    PcmFormat::MuLaw
+                } else {
+                    PcmFormat::ALaw
+                };
+            }
+            _ => {}
+        }
+
+        if let (Some(v2), "lpcm") = (&fields.v2, fourcc) {
+            let flags = v2.format_flags;
+            entry.bits_per_sample = v2.bits_per_channel;
+            entry.big_endian = flags & LPCM_FLAG_IS_BIG_ENDIAN != 0;
+            entry.format = if flags & LPCM_FLAG_IS_FLOAT != 0 {
+                PcmFormat::Float
+            } else if flags & LPCM_FLAG_IS_SIGNED_INTEGER != 0 {
+                PcmFormat::SignedInteger
+            } else {
+                PcmFormat::UnsignedInteger
+            };
+        }
+
+        // The byte order of in24 is given by an enda box, usually inside a wave box. High
+        // sample rates can be given by srat.
+        let end_offset = start_offset + inner_size;
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            if header.box_type == "wave" {
+                // Its children are read as if they were the sample entry's
+                continue;
+            }
+            let box_end_offset = header.start_offset + header.box_size;
+            match header.box_type.as_str() {
+                "enda" if header.inner_size >= 2 => entry.big_endian = reader.read_u16() == 0,
+                "srat" => entry.sample_rate = parse_sampling_rate(reader) as f64,
+                _ => {}
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+        entry
+    }
+
+    /// The size of one sample of every channel, in bytes
+    pub fn bytes_per_frame(&self) -> u32 {
+        self.channel_count
+            .saturating_mul(self.bits_per_sample.div_ceil(8))
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        print("Channel count", &self.channel_count);
+        print("Sample rate", &self.sample_rate);
+        print("Bits per sample", &self.bits_per_sample);
+        print("Format", &self.format);
+        if self.bits_per_sample > 8 {
+            print(
+                "Byte order",
+                &if self.big_endian {
+                    "big-endian"
+                } else {
+                    "little-endian"
+                },
+            );
+        }
+    }
+}
+
+/// dmlp
+#[derive(Debug, Clone)]
+pub struct MlpSpecificBox {
+    /// The format info of the major sync of the TrueHD stream, which has the sample rate and
+    /// channel assignments
+    pub format_info: u32,
+    pub peak_data_rate: u16,
+}
+
+impl MlpSpecificBox {
+    pub fn parse(reader: &mut Reader) -> Self {
+        let format_info = reader.read_u32();
+        let peak_data_rate = reader.read_u16() >> 1;
+        Self {
+            format_info,
+            peak_data_rate,
+        }
+    }
+
+    /// In Hz
+    pub fn sample_rate(&self) -> Option<u32> {
+        match self.format_info >> 28 {
+            0 => Some(48000),
+            1 => Some(96000),
+            2 => Some(192000),
+            8 => Some(44100),
+            9 => Some(88200),
+            10 => Some(176400),
+            _ => None,
+        }
+    }
+
+    /// From the 8 channel presentation's channel assignment, or the 6 channel one's if it's
+    /// not set
+    pub fn channel_count(&self) -> Option<u16> {
+        // The number of channels that each bit of the assignment stands for, e.g. L/R, C, LFE
+        const CHANNELS_PER_BIT: [u16; 13] = [2, 1, 1, 2, 2, 2, 2, 1, 1, 2, 2, 1, 1];
+        let mut assignment = self.format_info & 0x1FFF;
+        if assignment == 0 {
+            assignment = (self.format_info >> 15) & 0x1F;
+        }
+        let count = CHANNELS_PER_BIT
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| assignment & (1 << bit) != 0)
+            .map(|(_, channels)| channels)
+            .sum();
+        if count > 0 {
+            Some(count)
+        } else {
+            None
+        }
+    }
+}
+
+/// encv or enca, a sample entry whose samples are encrypted. The original sample entry type is
+/// in its protection scheme info (sinf), and its other child boxes aren't parsed.
+#[derive(Debug)]
+pub struct EncryptedSampleEntry {
+    pub fourcc: String,
+    pub data_reference_index: u16,
+    pub protection: Option<ProtectionSchemeInfo>,
+}
+
+impl EncryptedSampleEntry {
+    fn parse(reader: &mut Reader, fourcc: &str, inner_size: u64) -> Self {
+        let start_offset = reader.position();
+        let data_reference_index = if fourcc == "encv" {
+            VisualSampleEntryFields::parse(reader).data_reference_index
+        } else {
+            AudioSampleEntryFields::parse(reader).data_reference_index
+        };
+
+        let end_offset = start_offset + inner_size;
+        let mut protection = None;
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            if header.box_type == "sinf" {
+                protection = Some(ProtectionSchemeInfo::parse(reader, header.inner_size));
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+
+        Self {
+            fourcc: fourcc.to_string(),
+            data_reference_index,
+            protection,
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        if let Some(protection) = &self.protection {
+            protection.print_attributes(print);
+        }
+    }
+}
+
+/// avc1
+#[derive(Debug)]
+pub struct Avc1VisualSampleEntry {
     pub data_reference_index: u16,
     pub width: u16,
     pub height: u16,
@@ -1101,30 +3440,345 @@ pub struct Avc1VisualSampleEntry {
     pub frame_count: u16,
     pub compressor_name: String,
     pub depth: u16,
+    pub avc_config: Option<AvcConfigurationBox>,
+    /// dvcC, dvvC or dvwC
+    pub dolby_vision_config: Option<DolbyVisionConfigurationBox>,
+    pub hdr_metadata: HdrMetadata,
 }
 
 impl Avc1VisualSampleEntry {
+    fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let start_offset = reader.position();
+        let fields = VisualSampleEntryFields::parse(reader);
+
+        let end_offset = start_offset + inner_size;
+        let mut avc_config = None;
+        let mut dolby_vision_config = None;
+        let mut hdr_metadata = HdrMetadata::default();
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            match header.box_type.as_ref() {
+                "avcC" => avc_config = Some(AvcConfigurationBox::parse(reader, header.inner_size)),
+                "dvcC" | "dvvC" | "dvwC" => {
+                    dolby_vision_config = Some(DolbyVisionConfigurationBox::parse(reader))
+                }
+                _ => hdr_metadata.parse_box(reader, &header.box_type),
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+
+        Self {
+            data_reference_index: fields.data_reference_index,
+            width: fields.width,
+            height: fields.height,
+            hor_resolution: fields.hor_resolution,
+            ver_resolution: fields.ver_resolution,
+            frame_count: fields.frame_count,
+            compressor_name: fields.compressor_name,
+            depth: fields.depth,
+            avc_config,
+            dolby_vision_config,
+            hdr_metadata,
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        print("Width", &self.width);
+        print("Height", &self.height);
+        print("Hor. resolution", &self.hor_resolution);
+        print("Ver. resolution", &self.ver_resolution);
+        print("Frame count", &self.frame_count);
+        print("Compressor name", &self.compressor_name);
+        print("Depth", &self.depth);
+        if let Some(avc_config) = &self.avc_config {
+            avc_config.print_attributes(&print);
+        }
+        if let Some(dolby_vision_config) = &self.dolby_vision_config {
+            dolby_vision_config.print_attributes(&print);
+        }
+        self.hdr_metadata.print_attributes(&print);
+    }
+}
+
+/// mp4v, MPEG-4 Visual (Part 2), as in older MP4 and 3GP files
+#[derive(Debug)]
+pub struct Mp4vVisualSampleEntry {
+    pub data_reference_index: u16,
+    pub width: u16,
+    pub height: u16,
+    pub hor_resolution: f32,
+    pub ver_resolution: f32,
+    pub frame_count: u16,
+    pub compressor_name: String,
+    pub depth: u16,
+    pub es_descriptor: Option<ElementaryStreamDescriptorBox>,
+}
+
+impl Mp4vVisualSampleEntry {
+    fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let start_offset = reader.position();
+        let fields = VisualSampleEntryFields::parse(reader);
+
+        let end_offset = start_offset + inner_size;
+        let mut es_descriptor = None;
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            if header.box_type == "esds" {
+                es_descriptor = Some(ElementaryStreamDescriptorBox::parse(
+                    reader,
+                    header.inner_size,
+                ));
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+
+        Self {
+            data_reference_index: fields.data_reference_index,
+            width: fields.width,
+            height: fields.height,
+            hor_resolution: fields.hor_resolution,
+            ver_resolution: fields.ver_resolution,
+            frame_count: fields.frame_count,
+            compressor_name: fields.compressor_name,
+            depth: fields.depth,
+            es_descriptor,
+        }
+    }
+
+    /// E.g. "mp4v.20.9"
+    pub fn codec_string(&self) -> String {
+        match &self.es_descriptor {
+            Some(es_descriptor) => es_descriptor.codec_string("mp4v"),
+            None => "mp4v".to_string(),
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        print("Width", &self.width);
+        print("Height", &self.height);
+        print("Hor. resolution", &self.hor_resolution);
+        print("Ver. resolution", &self.ver_resolution);
+        print("Frame count", &self.frame_count);
+        print("Compressor name", &self.compressor_name);
+        print("Depth", &self.depth);
+        print("Codec string", &self.codec_string());
+        if let Some(es_descriptor) = &self.es_descriptor {
+            es_descriptor.print_attributes(print);
+        }
+    }
+}
+
+/// s263, H.263 in 3GPP files
+#[derive(Debug)]
+pub struct H263VisualSampleEntry {
+    pub data_reference_index: u16,
+    pub width: u16,
+    pub height: u16,
+    pub hor_resolution: f32,
+    pub ver_resolution: f32,
+    pub frame_count: u16,
+    pub compressor_name: String,
+    pub depth: u16,
+    pub h263_config: Option<H263SpecificBox>,
+}
+
+impl H263VisualSampleEntry {
+    fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let start_offset = reader.position();
+        let fields = VisualSampleEntryFields::parse(reader);
+
+        let end_offset = start_offset + inner_size;
+        let mut h263_config = None;
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            if header.box_type == "d263" {
+                h263_config = Some(H263SpecificBox::parse(reader));
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+
+        Self {
+            data_reference_index: fields.data_reference_index,
+            width: fields.width,
+            height: fields.height,
+            hor_resolution: fields.hor_resolution,
+            ver_resolution: fields.ver_resolution,
+            frame_count: fields.frame_count,
+            compressor_name: fields.compressor_name,
+            depth: fields.depth,
+            h263_config,
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        print("Width", &self.width);
+        print("Height", &self.height);
+        print("Hor. resolution", &self.hor_resolution);
+        print("Ver. resolution", &self.ver_resolution);
+        print("Frame count", &self.frame_count);
+        print("Compressor name", &self.compressor_name);
+        print("Depth", &self.depth);
+        if let Some(h263_config) = &self.h263_config {
+            h263_config.print_attributes(print);
+        }
+    }
+}
+
+/// jpeg, mjpa, mjpb or png, whose samples are whole images, e.g. Motion JPEG from a camera.
+/// Their child boxes aren't parsed.
+#[derive(Debug)]
+pub struct ImageVisualSampleEntry {
+    pub fourcc: String,
+    pub data_reference_index: u16,
+    pub width: u16,
+    pub height: u16,
+    pub hor_resolution: f32,
+    pub ver_resolution: f32,
+    pub frame_count: u16,
+    pub compressor_name: String,
+    pub depth: u16,
+}
+
+impl ImageVisualSampleEntry {
+    fn parse(reader: &mut Reader, fourcc: &str, inner_size: u64) -> Self {
+        let start_offset = reader.position();
+        let fields = VisualSampleEntryFields::parse(reader);
+        let remaining = (start_offset + inner_size).saturating_sub(reader.position());
+        reader.skip(remaining);
+
+        Self {
+            fourcc: fourcc.to_string(),
+            data_reference_index: fields.data_reference_index,
+            width: fields.width,
+            height: fields.height,
+            hor_resolution: fields.hor_resolution,
+            ver_resolution: fields.ver_resolution,
+            frame_count: fields.frame_count,
+            compressor_name: fields.compressor_name,
+            depth: fields.depth,
+        }
+    }
+
+    /// E.g. "Motion JPEG" or "PNG"
+    pub fn format_name(&self) -> &'static str {
+        match self.fourcc.as_str() {
+            "png " => "PNG",
+            "mjpa" => "Motion JPEG A",
+            "mjpb" => "Motion JPEG B",
+            _ => "Motion JPEG",
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        print("Width", &self.width);
+        print("Height", &self.height);
+        print("Hor. resolution", &self.hor_resolution);
+        print("Ver. resolution", &self.ver_resolution);
+        print("Frame count", &self.frame_count);
+        print("Compressor name", &self.compressor_name);
+        print("Depth", &self.depth);
+        print("Format", &self.format_name());
+    }
+}
+
+/// d263, from 3GPP TS 26.244
+#[derive(Debug, Clone)]
+pub struct H263SpecificBox {
+    /// The encoder's vendor, e.g. "appl"
+    pub vendor: String,
+    pub decoder_version: u8,
+    /// E.g. 10 for level 1.0
+    pub level: u8,
+    /// E.g. 0 for the baseline profile
+    pub profile: u8,
+}
+
+impl H263SpecificBox {
+    fn parse(reader: &mut Reader) -> Self {
+        let vendor = reader.read_string_inexact(4);
+        let decoder_version = reader.read_u8();
+        let level = reader.read_u8();
+        let profile = reader.read_u8();
+        Self {
+            vendor,
+            decoder_version,
+            level,
+            profile,
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Vendor", &self.vendor);
+        print("Decoder version", &self.decoder_version);
+        print("H.263 profile", &self.profile);
+        print("H.263 level", &self.level);
+    }
+}
+
+/// The fields that all visual sample entries start with
+struct VisualSampleEntryFields {
+    data_reference_index: u16,
+    width: u16,
+    height: u16,
+    hor_resolution: f32,
+    ver_resolution: f32,
+    frame_count: u16,
+    compressor_name: String,
+    depth: u16,
+}
+
+impl VisualSampleEntryFields {
     fn parse(reader: &mut Reader) -> Self {
         let _reserved = reader.read_string(6);
         let data_reference_index = reader.read_u16();
-        //let mut remaining = inner_size - 8;
 
         // https://www.fatalerrors.org/a/analysis-of-mp4-file-format.html
 
-        reader.skip_bytes(2).unwrap(); // predefined
-        reader.skip_bytes(2).unwrap(); // reserved
-        reader.skip_bytes(4 * 3).unwrap(); // predefined
+        reader.skip(2); // predefined
+        reader.skip(2); // reserved
+        reader.skip(4 * 3); // predefined
         let width = reader.read_u16();
         let height = reader.read_u16();
         let hor_resolution = reader.read_fixed_point_16_16();
         let ver_resolution = reader.read_fixed_point_16_16();
-        reader.skip_bytes(4).unwrap(); // reserved
+        reader.skip(4); // reserved
         let frame_count = reader.read_u16();
-        let compressor_name = reader.read_string(32);
+        // Always a Pascal string in a 32 byte field, but some encoders write a plain string
+        // instead, or leave garbage after the name
+        let compressor_name_bytes = reader.read_bytes(32);
+        let compressor_name = match compressor_name_bytes[0] as usize {
+            len if len > 0 && len < 32 && !compressor_name_bytes[1..=len].contains(&0) => {
+                String::from_utf8_lossy(&compressor_name_bytes[1..=len]).to_string()
+            }
+            _ => parse_name(&compressor_name_bytes),
+        };
         let depth = reader.read_u16();
-        reader.skip_bytes(2).unwrap(); // predefined
-
-        //remaining -= 70;
+        reader.skip(2); // predefined
 
         Self {
             data_reference_index,
@@ -1136,29 +3790,689 @@ impl Avc1VisualSampleEntry {
             compressor_name,
             depth,
         }
+    }
+}
+
+/// hvc1, hev1, and Dolby Vision's dvh1 and dvhe, which carry HEVC
+#[derive(Debug)]
+pub struct HevcVisualSampleEntry {
+    pub fourcc: String,
+    pub data_reference_index: u16,
+    pub width: u16,
+    pub height: u16,
+    pub compressor_name: String,
+    pub depth: u16,
+    pub hevc_config: Option<HevcConfigurationBox>,
+    /// dvcC, dvvC or dvwC
+    pub dolby_vision_config: Option<DolbyVisionConfigurationBox>,
+    pub hdr_metadata: HdrMetadata,
+}
+
+impl HevcVisualSampleEntry {
+    fn parse(reader: &mut Reader, fourcc: &str, inner_size: u64) -> Self {
+        let start_offset = reader.position();
+        let fields = VisualSampleEntryFields::parse(reader);
+
+        let end_offset = start_offset + inner_size;
+        let mut hevc_config = None;
+        let mut dolby_vision_config = None;
+        let mut hdr_metadata = HdrMetadata::default();
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            match header.box_type.as_ref() {
+                "hvcC" => {
+                    hevc_config = Some(HevcConfigurationBox::parse(reader, header.inner_size))
+                }
+                "dvcC" | "dvvC" | "dvwC" => {
+                    dolby_vision_config = Some(DolbyVisionConfigurationBox::parse(reader))
+                }
+                _ => hdr_metadata.parse_box(reader, &header.box_type),
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
 
-        // TODO ?
-        // parse_container_sub_boxes(reader, remaining, logger, HandleUnknown::Skip);
+        Self {
+            fourcc: fourcc.to_string(),
+            data_reference_index: fields.data_reference_index,
+            width: fields.width,
+            height: fields.height,
+            compressor_name: fields.compressor_name,
+            depth: fields.depth,
+            hevc_config,
+            dolby_vision_config,
+            hdr_metadata,
+        }
     }
 
     fn print_attributes<F>(&self, print: F)
     where
-        F: Fn(&str, &dyn std::fmt::Display),
+        F: Fn(&str, &dyn core::fmt::Display),
     {
         print("Data reference index", &self.data_reference_index);
         print("Width", &self.width);
         print("Height", &self.height);
-        print("Hor. resolution", &self.hor_resolution);
-        print("Ver. resolution", &self.ver_resolution);
-        print("Frame count", &self.frame_count);
         print("Compressor name", &self.compressor_name);
         print("Depth", &self.depth);
+        if let Some(hevc_config) = &self.hevc_config {
+            hevc_config.print_attributes(&print);
+        }
+        if let Some(dolby_vision_config) = &self.dolby_vision_config {
+            dolby_vision_config.print_attributes(&print);
+        }
+        self.hdr_metadata.print_attributes(&print);
+    }
+}
+
+/// avcC
+#[derive(Debug)]
+pub struct AvcConfigurationBox {
+    pub configuration_version: u8,
+    pub profile_indication: u8,
+    pub profile_compatibility: u8,
+    pub level_indication: u8,
+    pub nal_length_size: u8,
+    pub sequence_parameter_sets: Vec<Vec<u8>>,
+    pub picture_parameter_sets: Vec<Vec<u8>>,
+    /// 0 for monochrome, 1 for 4:2:0, 2 for 4:2:2, 3 for 4:4:4. Only known for the High
+    /// profiles if the box has the extension with it, and always 4:2:0 for the others.
+    pub chroma_format: Option<u8>,
+    /// Known in the same cases as the chroma format
+    pub bit_depth_luma: Option<u8>,
+    pub bit_depth_chroma: Option<u8>,
+}
+
+impl AvcConfigurationBox {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let end_offset = reader.position() + inner_size;
+        let configuration_version = reader.read_u8();
+        let profile_indication = reader.read_u8();
+        let profile_compatibility = reader.read_u8();
+        let level_indication = reader.read_u8();
+        let nal_length_size = (reader.read_u8() & 0b0000_0011) + 1;
+
+        let num_sps = reader.read_u8() & 0b0001_1111;
+        let mut sequence_parameter_sets = Vec::new();
+        for _ in 0..num_sps {
+            let len = reader.read_u16();
+            sequence_parameter_sets.push(reader.read_bytes(len as usize));
+        }
+        let num_pps = reader.read_u8();
+        let mut picture_parameter_sets = Vec::new();
+        for _ in 0..num_pps {
+            let len = reader.read_u16();
+            picture_parameter_sets.push(reader.read_bytes(len as usize));
+        }
+
+        let (mut chroma_format, mut bit_depth_luma, mut bit_depth_chroma) = (None, None, None);
+        match profile_indication {
+            100 | 110 | 122 | 144 | 244 if reader.position() + 4 <= end_offset => {
+                chroma_format = Some(reader.read_u8() & 0b11);
+                bit_depth_luma = Some((reader.read_u8() & 0b111) + 8);
+                bit_depth_chroma = Some((reader.read_u8() & 0b111) + 8);
+            }
+            100 | 110 | 122 | 144 | 244 => {}
+            _ => {
+                chroma_format = Some(1);
+                bit_depth_luma = Some(8);
+                bit_depth_chroma = Some(8);
+            }
+        }
+
+        Self {
+            configuration_version,
+            profile_indication,
+            profile_compatibility,
+            level_indication,
+            nal_length_size,
+            sequence_parameter_sets,
+            picture_parameter_sets,
+            chroma_format,
+            bit_depth_luma,
+            bit_depth_chroma,
+        }
+    }
+
+    pub fn profile_name(&self) -> String {
+        match self.profile_indication {
+            66 => "Baseline".to_string(),
+            77 => "Main".to_string(),
+            88 => "Extended".to_string(),
+            100 => "High".to_string(),
+            110 => "High 10".to_string(),
+            122 => "High 4:2:2".to_string(),
+            244 => "High 4:4:4 Predictive".to_string(),
+            other => format!("unknown ({})", other),
+        }
+    }
+
+    /// E.g. "3.1"
+    pub fn level(&self) -> String {
+        format!("{:.1}", self.level_indication as f32 / 10.0)
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("AVC profile", &self.profile_indication);
+        print("AVC level", &self.level_indication);
+        print("NAL length size", &self.nal_length_size);
+        print("# SPS", &self.sequence_parameter_sets.len());
+        print("# PPS", &self.picture_parameter_sets.len());
+        if let Some(chroma_format) = self.chroma_format {
+            print("Chroma format", &chroma_subsampling(chroma_format));
+        }
+        if let (Some(luma), Some(chroma)) = (self.bit_depth_luma, self.bit_depth_chroma) {
+            print("Bit depth", &format!("{} (chroma: {})", luma, chroma));
+        }
+    }
+}
+
+/// hvcC
+#[derive(Debug, Clone)]
+pub struct HevcConfigurationBox {
+    pub configuration_version: u8,
+    pub profile_space: u8,
+    /// Main (false) or High (true) tier
+    pub tier_flag: bool,
+    pub profile_idc: u8,
+    /// 30 times the level, e.g. 93 for level 3.1
+    pub level_idc: u8,
+    /// 0 for monochrome, 1 for 4:2:0, 2 for 4:2:2, 3 for 4:4:4
+    pub chroma_format: u8,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+    pub nal_length_size: u8,
+    /// The NAL units of the parameter set arrays, usually a VPS, an SPS and a PPS, in order
+    pub parameter_sets: Vec<Vec<u8>>,
+}
+
+impl HevcConfigurationBox {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let end_offset = reader.position() + inner_size;
+        let configuration_version = reader.read_u8();
+        let profile = reader.read_u8();
+        let _profile_compatibility_flags = reader.read_u32();
+        let _constraint_indicator_flags = reader.read_bytes(6);
+        let level_idc = reader.read_u8();
+        let _min_spatial_segmentation = reader.read_u16();
+        let _parallelism_type = reader.read_u8();
+        let chroma_format = reader.read_u8() & 0b11;
+        let bit_depth_luma = (reader.read_u8() & 0b111) + 8;
+        let bit_depth_chroma = (reader.read_u8() & 0b111) + 8;
+        let _avg_frame_rate = reader.read_u16();
+        let nal_length_size = (reader.read_u8() & 0b11) + 1;
+
+        let mut parameter_sets = Vec::new();
+        if reader.position() < end_offset {
+            let num_arrays = reader.read_u8();
+            for _ in 0..num_arrays {
+                let _nal_unit_type = reader.read_u8() & 0b11_1111;
+                let num_nalus = reader.read_u16();
+                for _ in 0..num_nalus {
+                    let len = reader.read_u16();
+                    parameter_sets.push(reader.read_bytes(len as usize));
+                }
+            }
+        }
+
+        Self {
+            configuration_version,
+            profile_space: profile >> 6,
+            tier_flag: profile & 0b10_0000 != 0,
+            profile_idc: profile & 0b1_1111,
+            level_idc,
+            chroma_format,
+            bit_depth_luma,
+            bit_depth_chroma,
+            nal_length_size,
+            parameter_sets,
+        }
+    }
+
+    pub fn profile_name(&self) -> String {
+        match self.profile_idc {
+            1 => "Main".to_string(),
+            2 => "Main 10".to_string(),
+            3 => "Main Still Picture".to_string(),
+            4 => "Range Extensions".to_string(),
+            5 => "High Throughput".to_string(),
+            9 => "Screen Content Coding".to_string(),
+            other => format!("unknown ({})", other),
+        }
+    }
+
+    /// E.g. "4.1"
+    pub fn level(&self) -> String {
+        format!("{:.1}", self.level_idc as f32 / 30.0)
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("HEVC profile", &self.profile_name());
+        print(
+            "HEVC level",
+            &format!(
+                "{} ({} tier)",
+                self.level(),
+                if self.tier_flag { "High" } else { "Main" }
+            ),
+        );
+        print("Chroma format", &chroma_subsampling(self.chroma_format));
+        print(
+            "Bit depth",
+            &format!(
+                "{} (chroma: {})",
+                self.bit_depth_luma, self.bit_depth_chroma
+            ),
+        );
+        print("NAL length size", &self.nal_length_size);
+        print("# parameter sets", &self.parameter_sets.len());
+    }
+}
+
+/// The name of a chroma_format_idc, e.g. "4:2:0" for 1
+pub fn chroma_subsampling(chroma_format: u8) -> &'static str {
+    match chroma_format {
+        0 => "4:0:0",
+        1 => "4:2:0",
+        2 => "4:2:2",
+        _ => "4:4:4",
+    }
+}
+
+/// dvcC (Dolby Vision profiles up to 7), dvvC (profiles 8 to 10) and dvwC, which all have the
+/// same layout
+#[derive(Debug, Clone)]
+pub struct DolbyVisionConfigurationBox {
+    pub version_major: u8,
+    pub version_minor: u8,
+    pub profile: u8,
+    pub level: u8,
+    /// Whether there's a reference processing unit, i.e. the dynamic metadata
+    pub rpu_present: bool,
+    /// Whether there's an enhancement layer
+    pub el_present: bool,
+    /// Whether there's a base layer
+    pub bl_present: bool,
+    /// What the base layer can be played as without Dolby Vision, e.g. 1 for HDR10 or 4 for HLG
+    pub bl_signal_compatibility_id: u8,
+}
+
+impl DolbyVisionConfigurationBox {
+    pub fn parse(reader: &mut Reader) -> Self {
+        let version_major = reader.read_u8();
+        let version_minor = reader.read_u8();
+        let bits = reader.read_u16();
+        let bl_signal_compatibility_id = reader.read_u8() >> 4;
+        Self {
+            version_major,
+            version_minor,
+            profile: (bits >> 9) as u8,
+            level: ((bits >> 3) & 0b11_1111) as u8,
+            rpu_present: bits & 0b100 != 0,
+            el_present: bits & 0b10 != 0,
+            bl_present: bits & 0b1 != 0,
+            bl_signal_compatibility_id,
+        }
+    }
+
+    /// The codec string of the Dolby Vision layer, e.g. "dvhe.08.06"
+    pub fn codec_string(&self) -> String {
+        let prefix = match self.profile {
+            0 | 1 | 9 => "dvav",
+            10 => "dav1",
+            _ => "dvhe",
+        };
+        format!("{}.{:02}.{:02}", prefix, self.profile, self.level)
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print(
+            "Dolby Vision",
+            &format!("{}.{}", self.version_major, self.version_minor),
+        );
+        print("DV profile", &self.codec_string());
+        print(
+            "DV layers",
+            &format!(
+                "RPU: {}, EL: {}, BL: {}",
+                self.rpu_present, self.el_present, self.bl_present
+            ),
+        );
+        print("DV BL compatibility", &self.bl_signal_compatibility_id);
+    }
+}
+
+/// The static HDR metadata of a visual sample entry, from the boxes of ISO/IEC 23001-8 (mdcv,
+/// clli) or of the VP codec mapping (SmDm, CoLL)
+#[derive(Debug, Clone, Default)]
+pub struct HdrMetadata {
+    pub mastering_display: Option<MasteringDisplayColourVolume>,
+    pub content_light_level: Option<ContentLightLevel>,
+}
+
+impl HdrMetadata {
+    /// Parses the box if it's one of the HDR metadata boxes
+    fn parse_box(&mut self, reader: &mut Reader, box_type: &str) {
+        match box_type {
+            "mdcv" => self.mastering_display = Some(MasteringDisplayColourVolume::parse(reader)),
+            "SmDm" => {
+                FullBoxHeader::parse(reader);
+                self.mastering_display = Some(MasteringDisplayColourVolume::parse_smdm(reader))
+            }
+            "clli" => self.content_light_level = Some(ContentLightLevel::parse(reader)),
+            "CoLL" => {
+                FullBoxHeader::parse(reader);
+                self.content_light_level = Some(ContentLightLevel::parse(reader))
+            }
+            _ => {}
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        if let Some(mastering_display) = &self.mastering_display {
+            mastering_display.print_attributes(&print);
+        }
+        if let Some(content_light_level) = &self.content_light_level {
+            print("MaxCLL", &format!("{} cd/m²", content_light_level.max_cll));
+            print(
+                "MaxFALL",
+                &format!("{} cd/m²", content_light_level.max_fall),
+            );
+        }
+    }
+}
+
+/// mdcv or SmDm, the colour volume of the display that the content was mastered on
+#[derive(Debug, Clone, PartialEq)]
+pub struct MasteringDisplayColourVolume {
+    /// CIE 1931 (x, y) of the red, green and blue primaries
+    pub primaries: [(f64, f64); 3],
+    pub white_point: (f64, f64),
+    /// In cd/m²
+    pub max_luminance: f64,
+    /// In cd/m²
+    pub min_luminance: f64,
+}
+
+impl MasteringDisplayColourVolume {
+    /// mdcv, which has the same layout as the HEVC SEI message: green, blue and red primaries in
+    /// units of 0.00002, and luminance in units of 0.0001 cd/m²
+    pub fn parse(reader: &mut Reader) -> Self {
+        let mut read_point = || {
+            let x = reader.read_u16() as f64 * 0.00002;
+            let y = reader.read_u16() as f64 * 0.00002;
+            (x, y)
+        };
+        let green = read_point();
+        let blue = read_point();
+        let red = read_point();
+        let white_point = read_point();
+        let max_luminance = reader.read_u32() as f64 * 0.0001;
+        let min_luminance = reader.read_u32() as f64 * 0.0001;
+        Self {
+            primaries: [red, green, blue],
+            white_point,
+            max_luminance,
+            min_luminance,
+        }
+    }
+
+    /// SmDm, with red, green and blue primaries in 0.16 fixed point, and luminance in 24.8 and
+    /// 18.14 fixed point
+    pub fn parse_smdm(reader: &mut Reader) -> Self {
+        let mut read_point = || {
+            let x = reader.read_u16() as f64 / 65536.0;
+            let y = reader.read_u16() as f64 / 65536.0;
+            (x, y)
+        };
+        let red = read_point();
+        let green = read_point();
+        let blue = read_point();
+        let white_point = read_point();
+        let max_luminance = reader.read_u32() as f64 / 256.0;
+        let min_luminance = reader.read_u32() as f64 / 16384.0;
+        Self {
+            primaries: [red, green, blue],
+            white_point,
+            max_luminance,
+            min_luminance,
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        let point = |(x, y): (f64, f64)| format!("({:.4}, {:.4})", x, y);
+        print(
+            "Mastering primaries",
+            &format!(
+                "R {}, G {}, B {}",
+                point(self.primaries[0]),
+                point(self.primaries[1]),
+                point(self.primaries[2])
+            ),
+        );
+        print("Mastering white point", &point(self.white_point));
+        print(
+            "Mastering luminance",
+            &format!("{:.4} - {} cd/m²", self.min_luminance, self.max_luminance),
+        );
+    }
+}
+
+/// clli or CoLL
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentLightLevel {
+    /// The brightest pixel of the content, in cd/m²
+    pub max_cll: u16,
+    /// The highest average brightness of a frame, in cd/m²
+    pub max_fall: u16,
+}
+
+impl ContentLightLevel {
+    pub fn parse(reader: &mut Reader) -> Self {
+        let max_cll = reader.read_u16();
+        let max_fall = reader.read_u16();
+        Self { max_cll, max_fall }
+    }
+}
+
+/// Shows 16 bytes in the usual UUID notation, e.g. "BE7ACFCB-97A9-42E8-9C71-999491E3AFAC"
+pub fn format_uuid(bytes: &[u8; 16]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Shows a duration in ticks along with the time it corresponds to, e.g. "3003 (00:00:03.003)"
+pub fn format_duration(ticks: u64, timescale: u32) -> String {
+    MediaTime::new(ticks, timescale).to_string()
+}
+
+/// The transformation matrix of mvhd and tkhd, { a, b, u, c, d, v, x, y, w }, decoded from
+/// fixed point. A point (p, q) is displayed at (a*p + c*q + x, b*p + d*q + y).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransformMatrix {
+    pub values: [f64; 9],
+}
+
+/// A transform that only rotates by a multiple of 90 degrees, and possibly mirrors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Orientation {
+    /// Clockwise, in degrees
+    pub rotation: u16,
+    /// Flipped horizontally, before rotating
+    pub mirrored: bool,
+}
+
+impl TransformMatrix {
+    /// u, v and w are 2.30 fixed point, the other values 16.16. All of them are signed.
+    pub fn from_raw(matrix: &[u32]) -> Self {
+        let mut values = [0.0; 9];
+        for (i, (value, &raw)) in values.iter_mut().zip(matrix).enumerate() {
+            let fraction_bits = if i % 3 == 2 { 30 } else { 16 };
+            *value = raw as i32 as f64 / (1_u64 << fraction_bits) as f64;
+        }
+        Self { values }
+    }
+
+    /// None if the matrix scales, skews or rotates by some other angle
+    pub fn orientation(&self) -> Option<Orientation> {
+        let [a, b, _, c, d, _, _, _, _] = self.values;
+        let mirrored = a * d - b * c < 0.0;
+        // Undo the mirroring, which negates the x axis, to be left with the rotation
+        let (a, b) = if mirrored { (-a, -b) } else { (a, b) };
+        let rotations = [
+            (0, [1.0, 0.0, 0.0, 1.0]),
+            (90, [0.0, 1.0, -1.0, 0.0]),
+            (180, [-1.0, 0.0, 0.0, -1.0]),
+            (270, [0.0, -1.0, 1.0, 0.0]),
+        ];
+        rotations
+            .iter()
+            .find(|(_, expected)| {
+                expected
+                    .iter()
+                    .zip(&[a, b, c, d])
+                    .all(|(e, v)| (e - v).abs() < 0.001)
+            })
+            .map(|&(rotation, _)| Orientation { rotation, mirrored })
+    }
+
+    pub fn orientation_text(&self) -> String {
+        match self.orientation() {
+            Some(orientation) => format!(
+                "rotation: {}°, mirrored: {}",
+                orientation.rotation,
+                if orientation.mirrored { "yes" } else { "no" }
+            ),
+            None => "custom transform".to_string(),
+        }
+    }
+
+    /// The size of a width x height picture once transformed, e.g. swapped for 90° rotation
+    pub fn transform_size(&self, width: f64, height: f64) -> (f64, f64) {
+        let [a, b, _, c, d, _, _, _, _] = self.values;
+        (
+            (a * width + c * height).abs(),
+            (b * width + d * height).abs(),
+        )
+    }
+}
+
+impl core::fmt::Display for TransformMatrix {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let values: Vec<String> = self.values.iter().map(|v| v.to_string()).collect();
+        write!(f, "[{}]", values.join(", "))
+    }
+}
+
+/// A creation or modification time, stored as seconds since 1904-01-01 00:00:00 UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamp {
+    /// Many muxers write 0 instead of a time
+    Unset,
+    Time(DateTime<Utc>),
+    /// Too far in the future to be represented, so it's kept as it was stored
+    OutOfRange(u64),
+}
+
+impl Timestamp {
+    pub fn from_epoch_secs(epoch_secs: u64) -> Self {
+        if epoch_secs == 0 {
+            return Timestamp::Unset;
+        }
+        // Duration::seconds panics above this
+        if epoch_secs > (i64::MAX / 1000) as u64 {
+            return Timestamp::OutOfRange(epoch_secs);
+        }
+        epoch_1904()
+            .checked_add_signed(Duration::seconds(epoch_secs as i64))
+            .map_or(Timestamp::OutOfRange(epoch_secs), Timestamp::Time)
+    }
+
+    pub fn epoch_secs(&self) -> u64 {
+        match *self {
+            Timestamp::Unset => 0,
+            Timestamp::Time(time) => (time - epoch_1904()).num_seconds().max(0) as u64,
+            Timestamp::OutOfRange(epoch_secs) => epoch_secs,
+        }
     }
+
+    /// The time it would be if the muxer mistakenly counted from 1970 rather than 1904. Such
+    /// times end up before 1970 when counted from 1904.
+    pub fn as_unix_time(&self) -> Option<DateTime<Utc>> {
+        let unix_epoch = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+        match *self {
+            Timestamp::Time(time) if time < unix_epoch => {
+                unix_epoch.checked_add_signed(time - epoch_1904())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.format_with(|time| time.to_string()))
+    }
+}
+
+impl Timestamp {
+    /// Like `to_string`, but in the local time zone with its offset from UTC, e.g.
+    /// "2021-10-21 14:00:00 +02:00" (see `--local-time`). The timestamp is in UTC in the file.
+    #[cfg(feature = "std")]
+    pub fn to_local_string(&self) -> String {
+        self.format_with(|time| time.with_timezone(&chrono::Local).to_string())
+    }
+
+    fn format_with(&self, display_time: fn(DateTime<Utc>) -> String) -> String {
+        match self {
+            Timestamp::Unset => "not set (0)".to_string(),
+            Timestamp::Time(time) => match self.as_unix_time() {
+                // Files from before 1970 are rare, so it's likely a Unix time if it's plausible
+                Some(unix_time) if unix_time.year() >= 1990 => format!(
+                    "{} (looks like a Unix time, which would be {})",
+                    display_time(*time),
+                    display_time(unix_time)
+                ),
+                _ => display_time(*time),
+            },
+            Timestamp::OutOfRange(epoch_secs) => format!("out of range ({})", epoch_secs),
+        }
+    }
+}
+
+fn epoch_1904() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(1904, 1, 1, 0, 0, 0).unwrap()
 }
 
-fn as_timestamp(epoch_secs: u32) -> NaiveDateTime {
-    let epoch_1904: NaiveDateTime = NaiveDate::from_ymd(1904, 1, 1).and_hms(0, 0, 0);
-    epoch_1904 + Duration::seconds(epoch_secs as i64)
+/// Headers keep their version when written, unless a value doesn't fit in version 0
+fn header_version(version: u8, values: &[u64]) -> u8 {
+    if version == 1 || values.iter().any(|&v| v > u32::MAX as u64) {
+        1
+    } else {
+        0
+    }
 }
 
 /// Box (abstract)
@@ -1171,11 +4485,41 @@ pub struct BoxHeader {
 }
 
 impl BoxHeader {
+    /// A broken header fails the reader (see `Reader::fail`), and is read as a box without a
+    /// type that takes up the rest of what the reader may read, so that it's skipped
     pub fn parse(reader: &mut Reader) -> Self {
         let start_offset = reader.position();
+        Self::try_parse(reader).unwrap_or_else(|e| {
+            reader.fail(e);
+            let end_offset = reader.end_offset().max(reader.position());
+            Self {
+                start_offset,
+                box_size: end_offset - start_offset,
+                box_type: String::new(),
+                inner_size: end_offset - reader.position(),
+            }
+        })
+    }
+
+    /// Like `parse`, but a header that is cut short by the end of the file, or that has an
+    /// invalid size, is returned as an error
+    pub fn try_parse(reader: &mut Reader) -> Result<Self, String> {
+        let start_offset = reader.position();
+        let truncated = |header_size: u64| {
+            format!(
+                "The box header at {} ({} bytes) is cut short by the end of the file",
+                start_offset, header_size
+            )
+        };
+        if start_offset + 8 > reader.file_len() {
+            box_path::leave_before(start_offset);
+            return Err(truncated(8));
+        }
 
         let mut size = reader.read_u32() as u64;
+        reader.name_field("size");
         let box_type = reader.read_bytes(4);
+        reader.name_field("type");
         let box_type = String::from_utf8(box_type).unwrap_or_else(|e| {
             // QuickTime has boxes that begin with the copyright symbol ©, but it's
             // encoded as a single byte 0xA9. For these boxes the box_type is not valid UTF-8.
@@ -1185,30 +4529,154 @@ impl BoxHeader {
         });
 
         if size == 1 {
-            // largesize
+            if start_offset + 16 > reader.file_len() {
+                box_path::leave_before(start_offset);
+                return Err(truncated(16));
+            }
             size = reader.read_u64();
+            reader.name_field("largesize");
         } else if size == 0 {
-            println!("DEBUG: {:?}", reader.read_string_inexact(256));
-            todo!("Handle box with size=0 (box '{}' extends to EOF)", box_type)
+            // The box extends to the end of the file, which only the last box may do
+            size = reader.file_len().saturating_sub(start_offset);
         }
 
-        assert!(
-            size >= 8,
-            "Box {} (at {}) has invalid size: {}",
-            box_type,
-            start_offset,
-            size
-        );
+        let header_size = reader.position() - start_offset;
+        if size < header_size || start_offset.checked_add(size).is_none() {
+            box_path::enter(&box_type, start_offset, start_offset + header_size);
+            return Err(format!(
+                "Box {} (at {}) has invalid size: {}",
+                box_type, start_offset, size
+            ));
+        }
 
-        let inner_size = size - 8;
+        let inner_size = size - header_size;
+        box_path::enter(&box_type, start_offset, start_offset + size);
 
-        Self {
+        Ok(Self {
             start_offset,
             box_size: size,
             box_type,
             inner_size,
+        })
+    }
+
+    /// Like `try_parse`, for a box in a parent (or file) that ends at `end_offset`. If the
+    /// header is broken, or the box doesn't fit in what's left of the parent, the reader fails
+    /// and None is returned.
+    pub fn parse_within(reader: &mut Reader, end_offset: u64) -> Option<Self> {
+        let header = match Self::try_parse(reader) {
+            Ok(header) => header,
+            Err(e) => {
+                reader.fail(e);
+                return None;
+            }
+        };
+        let available = end_offset.saturating_sub(header.start_offset);
+        if header.box_size > available {
+            let container = if end_offset >= reader.file_len() {
+                "the file"
+            } else {
+                "its parent"
+            };
+            reader.fail(format!(
+                "The '{}' box is {} bytes, but only {} bytes are left of {}",
+                header.box_type, header.box_size, available, container
+            ));
+            return None;
+        }
+        Some(header)
+    }
+}
+
+/// Where some of the bytes of a box are in the file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawData {
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl RawData {
+    /// The bytes themselves, from the buffer that was parsed. Those of a truncated box are cut
+    /// off at the end of the buffer.
+    pub fn bytes<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        let end = (self.offset.saturating_add(self.len) as usize).min(buf.len());
+        &buf[(self.offset as usize).min(end)..end]
+    }
+}
+
+/// A box that isn't supported, e.g. a vendor's private box. Its payload is kept so that it can
+/// be decoded elsewhere.
+#[derive(Debug)]
+pub struct UnknownBox {
+    pub box_type: String,
+    pub data: RawData,
+}
+
+impl UnknownBox {
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Payload", &format!("{} bytes", self.data.len));
+    }
+}
+
+/// A sample entry of a type that isn't supported. Only the fields that all sample entries start
+/// with are parsed.
+#[derive(Debug)]
+pub struct UnknownSampleEntry {
+    pub fourcc: String,
+    pub data_reference_index: u16,
+    /// Everything after the box header
+    pub raw: RawData,
+}
+
+impl UnknownSampleEntry {
+    fn parse(reader: &mut Reader, fourcc: &str, inner_size: u64) -> Self {
+        let raw = RawData {
+            offset: reader.position(),
+            len: inner_size,
+        };
+        let data_reference_index = if inner_size >= 8 {
+            let _reserved = reader.read_bytes(6);
+            reader.read_u16()
+        } else {
+            0
+        };
+        let remaining = (raw.offset + inner_size).saturating_sub(reader.position());
+        reader.skip(remaining);
+        Self {
+            fourcc: fourcc.to_string(),
+            data_reference_index,
+            raw,
         }
     }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Type", &self.fourcc);
+        print("Data reference index", &self.data_reference_index);
+        print("Payload", &format!("{} bytes", self.raw.len));
+    }
+}
+
+/// A full box with a version that can't be parsed yet
+#[derive(Debug)]
+pub struct UnsupportedVersionBox {
+    pub box_type: String,
+    pub version: u8,
+}
+
+impl UnsupportedVersionBox {
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Box type", &self.box_type);
+        print("Version", &self.version);
+    }
 }
 
 /// FullBox (abstract)
@@ -1221,9 +4689,17 @@ pub struct FullBoxHeader {
 impl FullBoxHeader {
     pub fn parse(reader: &mut Reader) -> Self {
         let version = reader.read_u8();
-        let mut flags = [0; 3];
-        reader.read_exact(&mut flags);
+        reader.name_field("version");
+        let [_, a, b, c] = reader.read_u24().to_be_bytes();
+        reader.name_field("flags");
+
+        Self {
+            version,
+            flags: [a, b, c],
+        }
+    }
 
-        Self { version, flags }
+    pub fn flags_u32(&self) -> u32 {
+        u32::from_be_bytes([0, self.flags[0], self.flags[1], self.flags[2]])
     }
 }
@@ -0,0 +1,173 @@
+//! Which parsing and validation rules apply to a file, from the brands in its ftyp box, and
+//! what the brands mean
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+/// A family of brands with rules of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Profile {
+    /// Plain ISO-BMFF and MP4 (isom, iso2, mp41, mp42 etc.), and files without ftyp
+    #[default]
+    Iso,
+    /// Apple QuickTime (qt): meta boxes may lack the version and flags, and 'wide' boxes are
+    /// used as padding
+    QuickTime,
+    /// HEIF and AVIF images (heic, mif1, avif etc.), which have items in a meta box
+    Heif,
+    /// Segments for adaptive streaming (dash, cmfc etc.), which are made of movie fragments
+    Segmented,
+    /// Microsoft Smooth Streaming (isml, piff): fragments that carry their absolute time in tfxd,
+    /// and may come without a moov box, as it's described by the manifest
+    SmoothStreaming,
+}
+
+impl Profile {
+    /// The major brand decides, unless it's a plain ISO brand. Then the most specific of the
+    /// compatible brands is used.
+    pub fn from_brands(major_brand: &str, compatible_brands: &[String]) -> Self {
+        if let Some(profile) = Self::from_brand(major_brand) {
+            return profile;
+        }
+        let compatible: Vec<Profile> = compatible_brands
+            .iter()
+            .filter_map(|brand| Self::from_brand(brand))
+            .collect();
+        [
+            Profile::Heif,
+            Profile::Segmented,
+            Profile::SmoothStreaming,
+            Profile::QuickTime,
+        ]
+        .iter()
+        .copied()
+        .find(|profile| compatible.contains(profile))
+        .unwrap_or(Profile::Iso)
+    }
+
+    /// Whether a file can't follow the rules of both, e.g. a QuickTime movie that claims to be
+    /// a HEIF image. DASH and Smooth Streaming segments can be both, as with PIFF in DASH.
+    fn conflicts_with(self, other: Self) -> bool {
+        use Profile::*;
+        match (self, other) {
+            (Iso, _) | (_, Iso) => false,
+            (Segmented, SmoothStreaming) | (SmoothStreaming, Segmented) => false,
+            (a, b) => a != b,
+        }
+    }
+
+    fn from_brand(brand: &str) -> Option<Self> {
+        match brand {
+            "qt  " => Some(Profile::QuickTime),
+            "heic" | "heix" | "heim" | "heis" | "mif1" | "msf1" | "avif" | "avis" => {
+                Some(Profile::Heif)
+            }
+            "dash" | "msdh" | "msix" | "cmfc" | "cmf2" | "cmfs" | "cmff" | "cmfl" => {
+                Some(Profile::Segmented)
+            }
+            "isml" | "piff" => Some(Profile::SmoothStreaming),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Profile::Iso => write!(f, "ISO-BMFF"),
+            Profile::QuickTime => write!(f, "QuickTime"),
+            Profile::Heif => write!(f, "HEIF"),
+            Profile::Segmented => write!(f, "DASH/CMAF segments"),
+            Profile::SmoothStreaming => write!(f, "Smooth Streaming"),
+        }
+    }
+}
+
+/// What a brand stands for, e.g. "ISO Base Media v1" for isom. None for unknown brands.
+pub fn describe(brand: &str) -> Option<&'static str> {
+    let description = match brand {
+        "isom" => "ISO Base Media v1",
+        "iso2" => "ISO Base Media v2",
+        "iso3" => "ISO Base Media v3",
+        "iso4" => "ISO Base Media v4",
+        "iso5" => "ISO Base Media v5",
+        "iso6" => "ISO Base Media v6",
+        "iso7" => "ISO Base Media v7",
+        "iso8" => "ISO Base Media v8",
+        "iso9" => "ISO Base Media v9",
+        "mp41" => "MP4 v1",
+        "mp42" => "MP4 v2",
+        "avc1" => "AVC/H.264 tracks",
+        "hvc1" => "HEVC tracks",
+        "av01" => "AV1 tracks",
+        "dby1" => "Dolby audio/video",
+        "opus" => "Opus audio",
+        "M4A " => "iTunes audio",
+        "M4B " => "iTunes audiobook",
+        "M4P " => "iTunes protected audio",
+        "M4V " => "iTunes video",
+        "M4VH" => "iTunes HD video",
+        "M4VP" => "iTunes video for iPhone",
+        "F4V " => "Adobe Flash video",
+        "qt  " => "QuickTime movie",
+        "3gp4" => "3GPP Release 4",
+        "3gp5" => "3GPP Release 5",
+        "3gp6" => "3GPP Release 6",
+        "3gp7" => "3GPP Release 7",
+        "3gp8" => "3GPP Release 8",
+        "3gp9" => "3GPP Release 9",
+        "3gg6" => "3GPP general file",
+        "3g2a" => "3GPP2",
+        "dash" => "DASH segments",
+        "msdh" => "DASH media segment",
+        "msix" => "DASH indexed media segment",
+        "cmfc" => "CMAF track",
+        "cmf2" => "CMAF track, constrained",
+        "cmfs" => "CMAF segment",
+        "cmff" => "CMAF fragment",
+        "cmfl" => "CMAF chunk",
+        "isml" => "Smooth Streaming",
+        "piff" => "Protected Interoperable File Format",
+        "heic" => "HEIF image/HEVC",
+        "heix" => "HEIF image/HEVC range extensions",
+        "heim" => "HEIF image/multi-layer HEVC",
+        "heis" => "HEIF image/scalable HEVC",
+        "hevc" => "HEIF image sequence/HEVC",
+        "hevx" => "HEIF image sequence/HEVC range extensions",
+        "mif1" => "HEIF image",
+        "msf1" => "HEIF image sequence",
+        "miaf" => "MIAF image",
+        "avif" => "AVIF image",
+        "avis" => "AVIF image sequence",
+        "MSNV" => "Sony PSP video",
+        "XAVC" => "Sony XAVC",
+        _ => return None,
+    };
+    Some(description)
+}
+
+/// Pairs of brands that can't both hold, see `Profile::conflicts_with`. Only the first brand of
+/// each profile is paired, with the major brand first.
+pub fn conflicting_brands<'a>(
+    major_brand: &'a str,
+    compatible_brands: &'a [String],
+) -> Vec<(&'a str, &'a str)> {
+    let mut firsts: Vec<(&str, Profile)> = Vec::new();
+    let brands = core::iter::once(major_brand).chain(compatible_brands.iter().map(String::as_str));
+    for brand in brands {
+        if let Some(profile) = Profile::from_brand(brand) {
+            if firsts.iter().all(|&(_, p)| p != profile) {
+                firsts.push((brand, profile));
+            }
+        }
+    }
+    let mut conflicts = Vec::new();
+    for (i, &(a, profile_a)) in firsts.iter().enumerate() {
+        for &(b, profile_b) in &firsts[i + 1..] {
+            if profile_a.conflicts_with(profile_b) {
+                conflicts.push((a, b));
+            }
+        }
+    }
+    conflicts
+}
@@ -0,0 +1,268 @@
+//! A C API, for media tools written in C or C++. The header is generated with
+//! `cbindgen --config cbindgen.toml --output include/mp4_parser.h`.
+//!
+//! A file is parsed once by `mp4_parser_open`, after which its boxes and tracks can be read by
+//! index. Strings and the file itself stay valid until `mp4_parser_free`.
+
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::ptr;
+
+use crate::error::ParseError;
+use crate::exit_code;
+use crate::logger::{JsonBox, JsonLogger};
+use crate::model::Movie;
+use crate::summary::Summary;
+use crate::tree::{parse_mp4, BoxFilter};
+
+thread_local! {
+    /// The error of the last `mp4_parser_open`, see `mp4_parser_last_error`
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// A parsed file. Opaque to C.
+pub struct Mp4File {
+    boxes: Vec<BoxEntry>,
+    tracks: Vec<TrackEntry>,
+}
+
+struct BoxEntry {
+    box_type: [c_char; 5],
+    offset: u64,
+    size: u64,
+    depth: u32,
+    name: CString,
+}
+
+struct TrackEntry {
+    info: Mp4TrackInfo,
+    // Owned here, as `info` points to them
+    _codec: Option<CString>,
+    _language: CString,
+}
+
+/// A box, as listed by `mp4_parser_get_box`
+#[repr(C)]
+pub struct Mp4BoxInfo {
+    /// The four-character code, NUL-terminated
+    pub box_type: [c_char; 5],
+    pub offset: u64,
+    /// Including the header
+    pub size: u64,
+    /// 1 for top-level boxes
+    pub depth: u32,
+    /// E.g. "Movie Header Box"
+    pub name: *const c_char,
+}
+
+/// A track, as listed by `mp4_parser_get_track`. Values that don't apply to the track are 0.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Mp4TrackInfo {
+    pub track_id: u32,
+    /// E.g. "vide" or "soun", NUL-terminated
+    pub handler_type: [c_char; 5],
+    /// E.g. "H.264/avc1 (High profile, level 4.0)", or NULL if the codec isn't known
+    pub codec: *const c_char,
+    /// ISO 639-2/T code, e.g. "eng"
+    pub language: *const c_char,
+    /// In seconds, after edit lists
+    pub duration: f64,
+    pub sample_count: u64,
+    pub width: u16,
+    pub height: u16,
+    pub sample_rate: f32,
+    pub channel_count: u16,
+    /// Average, in bits per second
+    pub bitrate: f64,
+}
+
+/// Parses the `len` bytes at `data`, which are only read during the call. Returns NULL if the
+/// file can't be parsed, see `mp4_parser_last_error`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mp4_parser_open(data: *const u8, len: usize) -> *mut Mp4File {
+    if data.is_null() {
+        return ptr::null_mut();
+    }
+    let buf = std::slice::from_raw_parts(data, len);
+    // Panics must not unwind into C
+    let result = exit_code::catch(|| Mp4File::parse(buf)).and_then(|result| result);
+    LAST_ERROR.with(|last_error| {
+        *last_error.borrow_mut() = result.as_ref().err().map(|e| c_string(e.to_string()))
+    });
+    match result {
+        Ok(file) => Box::into_raw(Box::new(file)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Why the last `mp4_parser_open` on this thread returned NULL, or NULL if it didn't. Valid
+/// until the next `mp4_parser_open` on this thread.
+#[no_mangle]
+pub extern "C" fn mp4_parser_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
+
+/// Frees a file from `mp4_parser_open`. NULL is ignored.
+///
+/// # Safety
+///
+/// `file` must come from `mp4_parser_open`, and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn mp4_parser_free(file: *mut Mp4File) {
+    if !file.is_null() {
+        drop(Box::from_raw(file));
+    }
+}
+
+/// The number of boxes, at all depths
+///
+/// # Safety
+///
+/// `file` must come from `mp4_parser_open`.
+#[no_mangle]
+pub unsafe extern "C" fn mp4_parser_box_count(file: *const Mp4File) -> usize {
+    file.as_ref().map_or(0, |file| file.boxes.len())
+}
+
+/// Fills in `out` with the box at `index`, in file order with each box before its children.
+/// Returns false if there's no such box.
+///
+/// # Safety
+///
+/// `file` must come from `mp4_parser_open`, and `out` must point to an `Mp4BoxInfo`.
+#[no_mangle]
+pub unsafe extern "C" fn mp4_parser_get_box(
+    file: *const Mp4File,
+    index: usize,
+    out: *mut Mp4BoxInfo,
+) -> bool {
+    let entry = match file.as_ref().and_then(|file| file.boxes.get(index)) {
+        Some(entry) => entry,
+        None => return false,
+    };
+    if out.is_null() {
+        return false;
+    }
+    *out = Mp4BoxInfo {
+        box_type: entry.box_type,
+        offset: entry.offset,
+        size: entry.size,
+        depth: entry.depth,
+        name: entry.name.as_ptr(),
+    };
+    true
+}
+
+/// The number of tracks in moov
+///
+/// # Safety
+///
+/// `file` must come from `mp4_parser_open`.
+#[no_mangle]
+pub unsafe extern "C" fn mp4_parser_track_count(file: *const Mp4File) -> usize {
+    file.as_ref().map_or(0, |file| file.tracks.len())
+}
+
+/// Fills in `out` with the track at `index`, in the order of the trak boxes. Returns false if
+/// there's no such track.
+///
+/// # Safety
+///
+/// `file` must come from `mp4_parser_open`, and `out` must point to an `Mp4TrackInfo`.
+#[no_mangle]
+pub unsafe extern "C" fn mp4_parser_get_track(
+    file: *const Mp4File,
+    index: usize,
+    out: *mut Mp4TrackInfo,
+) -> bool {
+    let entry = match file.as_ref().and_then(|file| file.tracks.get(index)) {
+        Some(entry) => entry,
+        None => return false,
+    };
+    if out.is_null() {
+        return false;
+    }
+    *out = entry.info;
+    true
+}
+
+impl Mp4File {
+    fn parse(buf: &[u8]) -> Result<Self, ParseError> {
+        let summary = Summary::new(buf, &Movie::parse(buf)?);
+        let mut logger = JsonLogger::new();
+        // The boxes before a box that can't be parsed are still listed
+        let _ = parse_mp4(buf, &mut logger, &BoxFilter::default());
+        let mut boxes = Vec::new();
+        flatten(logger.into_boxes().0, 1, &mut boxes);
+
+        let tracks = summary
+            .tracks
+            .into_iter()
+            .map(|track| {
+                let codec = track.codec.map(c_string);
+                let language = c_string(track.language);
+                let info = Mp4TrackInfo {
+                    track_id: track.id,
+                    handler_type: fourcc(&track.handler_type),
+                    codec: codec.as_ref().map_or(ptr::null(), |codec| codec.as_ptr()),
+                    language: language.as_ptr(),
+                    duration: track.duration,
+                    sample_count: track.sample_count as u64,
+                    width: track.width.unwrap_or(0),
+                    height: track.height.unwrap_or(0),
+                    sample_rate: track.sample_rate.unwrap_or(0.0),
+                    channel_count: track.channel_count.unwrap_or(0),
+                    bitrate: track.bitrate.unwrap_or(0.0),
+                };
+                TrackEntry {
+                    info,
+                    _codec: codec,
+                    _language: language,
+                }
+            })
+            .collect();
+        Ok(Self { boxes, tracks })
+    }
+}
+
+/// Lists the boxes depth-first, leaving out entries such as sample descriptions
+fn flatten(json_boxes: Vec<JsonBox>, depth: u32, boxes: &mut Vec<BoxEntry>) {
+    for json_box in json_boxes {
+        if let (Some(box_type), Some(offset), Some(size)) =
+            (&json_box.box_type, json_box.offset, json_box.size)
+        {
+            boxes.push(BoxEntry {
+                box_type: fourcc(box_type),
+                offset,
+                size,
+                depth,
+                name: c_string(json_box.name),
+            });
+            flatten(json_box.children, depth + 1, boxes);
+        }
+    }
+}
+
+fn fourcc(text: &str) -> [c_char; 5] {
+    let mut code = [0; 5];
+    for (c, b) in code.iter_mut().zip(text.bytes().take(4)) {
+        *c = b as c_char;
+    }
+    code
+}
+
+/// Leaves out any NUL bytes, which C strings can't contain
+fn c_string(text: String) -> CString {
+    CString::new(text.replace('\0', "")).unwrap()
+}
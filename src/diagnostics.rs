@@ -0,0 +1,140 @@
+//! Problems found while parsing, kept apart from the parsed result. Each is logged when it's
+//! found, and with the `std` feature also collected by `Diagnostics::collect`, together with
+//! the box that was being parsed (see `box_path`).
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Worth knowing, but not a problem with the file, e.g. a box that isn't known
+    Info,
+    /// Something that was parsed, but that players may handle differently
+    Warning,
+    /// The file is broken, e.g. a box is too small for its fields
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // pad, so that the width in "{:<5}" is used
+        f.pad(match self {
+            Severity::Info => "INFO",
+            Severity::Warning => "WARN",
+            Severity::Error => "ERROR",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The box that was being parsed, e.g. "moov/trak[0]/mdia/mdhd"
+    pub box_path: Option<String>,
+    /// The file offset of that box
+    pub offset: Option<u64>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<5} ", self.severity)?;
+        if let (Some(box_path), Some(offset)) = (&self.box_path, self.offset) {
+            write!(f, "{} @ {:#X}: ", box_path, offset)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The diagnostics from parsing a file, in the order they were found
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    /// Runs `f`, and returns what it returned with the diagnostics reported on this thread in
+    /// the meantime
+    #[cfg(feature = "std")]
+    pub fn collect<T, F>(f: F) -> (T, Self)
+    where
+        F: FnOnce() -> T,
+    {
+        /// Puts back the outer collection, also if `f` panics
+        struct Restore(Option<Vec<Diagnostic>>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                COLLECTED.with(|collected| collected.replace(self.0.take()));
+            }
+        }
+
+        let restore = Restore(COLLECTED.with(|collected| collected.replace(Some(Vec::new()))));
+        let result = f();
+        let diagnostics = COLLECTED.with(|collected| collected.borrow_mut().take());
+        drop(restore);
+        (result, Self(diagnostics.unwrap_or_default()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter()
+    }
+
+    /// The number of diagnostics of at least the given severity
+    pub fn count(&self, min_severity: Severity) -> usize {
+        self.iter().filter(|d| d.severity >= min_severity).count()
+    }
+}
+
+/// A section for the end of a report, e.g. by `parse`
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Diagnostics ({}):", self.0.len())?;
+        for diagnostic in self.iter() {
+            writeln!(f, "  {}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// The diagnostics of the innermost `Diagnostics::collect`, if any
+    static COLLECTED: std::cell::RefCell<Option<Vec<Diagnostic>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Adds a diagnostic about the box that is being parsed, if they're being collected. Use
+/// `diagnostic!`, which also logs it.
+pub fn report(severity: Severity, message: String) {
+    #[cfg(feature = "std")]
+    COLLECTED.with(|collected| {
+        if let Some(collected) = collected.borrow_mut().as_mut() {
+            let (box_path, offset) = crate::box_path::current().unzip();
+            collected.push(Diagnostic {
+                severity,
+                box_path,
+                offset,
+                message,
+            });
+        }
+    });
+    #[cfg(not(feature = "std"))]
+    let _ = (severity, message);
+}
+
+/// Logs a warning, and reports it with the given severity. The log target is the module it's
+/// called from, as with `log::warn!`.
+macro_rules! diagnostic {
+    ($severity:expr, $($arg:tt)+) => {{
+        let message = alloc::format!($($arg)+);
+        log::warn!("{}", message);
+        $crate::diagnostics::report($severity, message);
+    }};
+}
+
+pub(crate) use diagnostic;
@@ -0,0 +1,502 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+
+use crate::boxes::{
+    BoxHeader, Chapter, ChapterListBox, EditListEntry, MediaHeaderBox, MovieHeaderBox, Timestamp,
+    TrackHeaderBox,
+};
+use crate::media_time::MediaTime;
+use crate::model::{Movie, Sample, SampleTable, Track};
+use crate::reader::Reader;
+use crate::rewrite::{
+    check_rewritable, rewrite_file, rewrite_moov, top_level_boxes, Rewrite, TrackRewrite,
+};
+use crate::writer::Writer;
+
+/// A range in seconds, as given on the command line: "START..END", where either end may be
+/// left out, e.g. "10.5.." or "..30". The default is the whole recording.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeRange {
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+impl FromStr for TimeRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once("..")
+            .ok_or_else(|| format!("Expected START..END, got '{}'", s))?;
+        let parse = |t: &str| -> Result<Option<f64>, String> {
+            if t.is_empty() {
+                return Ok(None);
+            }
+            let secs = t
+                .parse::<f64>()
+                .map_err(|e| format!("Invalid time '{}': {}", t, e))?;
+            check_secs(secs).map(Some)
+        };
+        let range = Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if let (Some(start), Some(end)) = (range.start, range.end) {
+            if end <= start {
+                return Err(format!("Empty time range: '{}'", s));
+            }
+        }
+        Ok(range)
+    }
+}
+
+/// A time from the user, which must be finite and not negative to be converted to ticks
+fn check_secs(secs: f64) -> Result<f64, String> {
+    if !secs.is_finite() {
+        Err(format!("Invalid time {}: not a finite number", secs))
+    } else if secs < 0.0 {
+        Err(format!("Invalid time {}: negative", secs))
+    } else {
+        Ok(secs)
+    }
+}
+
+impl TimeRange {
+    /// Whether `time` (in seconds) is from the start up to, but not including, the end
+    pub fn contains(&self, time: f64) -> bool {
+        self.start.is_none_or(|start| time >= start) && self.end.is_none_or(|end| time < end)
+    }
+}
+
+/// Cuts out the given time range without re-encoding anything. The start is moved back to
+/// the closest preceding sync sample, so that the result starts with a decodable frame.
+/// The output has its moov box before the media data.
+pub fn trim(buf: &[u8], range: &TimeRange) -> Result<Vec<u8>, String> {
+    for &secs in range.start.iter().chain(&range.end) {
+        check_secs(secs)?;
+    }
+    let top_level_boxes = top_level_boxes(buf);
+    if top_level_boxes.iter().any(|b| b.box_type == "moof") {
+        return Err("Trimming fragmented files is not supported".to_string());
+    }
+    let ftyp = top_level_boxes.iter().find(|b| b.box_type == "ftyp");
+    let moov = top_level_boxes
+        .iter()
+        .find(|b| b.box_type == "moov")
+        .ok_or("Found no moov box")?;
+
+    let movie = Movie::parse(buf).map_err(|e| e.to_string())?;
+    check_rewritable(&movie)?;
+    let all_samples: Vec<Vec<Sample>> = movie
+        .tracks
+        .iter()
+        .map(|track| track.sample_table.samples())
+        .collect();
+
+    let start = snap_to_sync_sample(&movie.tracks, &all_samples, range.start.unwrap_or(0.0));
+    let end = range.end.unwrap_or(f64::INFINITY);
+    if start >= end {
+        return Err(format!(
+            "Nothing left after trimming (start snapped to {:.3}s)",
+            start
+        ));
+    }
+
+    let mut selected: Vec<Vec<Sample>> = movie
+        .tracks
+        .iter()
+        .zip(&all_samples)
+        .map(|(track, samples)| {
+            let start_ticks = MediaTime::from_secs_f64(start, track.timescale).ticks() as u64;
+            samples
+                .iter()
+                .filter(|s| {
+                    s.decode_time + (s.duration as u64) > start_ticks
+                        && MediaTime::new(s.decode_time, track.timescale).as_secs_f64() < end
+                })
+                .cloned()
+                .collect()
+        })
+        .collect();
+
+    // Samples are written in their original order, so that the interleaving is preserved.
+    // A new chunk begins whenever the track or the original chunk changes.
+    let mut order: Vec<(usize, usize)> = selected
+        .iter()
+        .enumerate()
+        .flat_map(|(t, samples)| (0..samples.len()).map(move |i| (t, i)))
+        .collect();
+    order.sort_by_key(|&(t, i)| selected[t][i].offset);
+    let mut mdat_payload = Vec::new();
+    let mut previous: Option<(usize, u32)> = None;
+    let mut chunk_index = 0;
+    for &(t, i) in &order {
+        let sample = &mut selected[t][i];
+        if previous.is_some() && previous != Some((t, sample.chunk_index)) {
+            chunk_index += 1;
+        }
+        previous = Some((t, sample.chunk_index));
+        let start = sample.offset as usize;
+        let data = buf
+            .get(start..start + sample.size as usize)
+            .ok_or_else(|| {
+                format!(
+                    "Sample data of track {} at {:#X} is outside the file",
+                    movie.tracks[t].id, start
+                )
+            })?;
+        mdat_payload.extend_from_slice(data);
+        sample.offset = (mdat_payload.len() - sample.size as usize) as u64;
+        sample.chunk_index = chunk_index;
+    }
+
+    let mut track_rewrites: Vec<Option<TrackRewrite>> = movie
+        .tracks
+        .iter()
+        .zip(&selected)
+        .map(|(track, samples)| {
+            let first = samples.first()?;
+            let starts_at_beginning = first.decode_time == 0;
+            let first_decode_time = first.decode_time;
+            let mut samples = samples.clone();
+            for sample in samples.iter_mut() {
+                sample.decode_time -= first_decode_time;
+            }
+            let max_duration = range.end.map(|end| end - start);
+            let edits = trimmed_edits(track, &movie, &samples, starts_at_beginning, max_duration);
+            Some(TrackRewrite {
+                sample_table: SampleTable {
+                    has_large_offsets: track.sample_table.has_large_offsets,
+                    ..SampleTable::from_samples(&samples)
+                },
+                edits,
+            })
+        })
+        .collect();
+    if track_rewrites.iter().all(|t| t.is_none()) {
+        return Err("No samples in the given time range".to_string());
+    }
+
+    let mut head = Writer::new();
+    if let Some(ftyp) = ftyp {
+        let end = ftyp.start_offset + ftyp.box_size;
+        head.write_bytes(&buf[ftyp.start_offset as usize..end as usize]);
+    }
+    let head = head.into_inner();
+
+    // The chunk offsets depend on the size of moov, which in turn depends on whether the
+    // offsets fit in stco, so iterate until the layout is stable.
+    let mut mdat_header = Writer::new();
+    mdat_header.write_box_header("mdat", mdat_payload.len() as u64);
+    let mdat_header = mdat_header.into_inner();
+    let mut payload_offset = 0;
+    loop {
+        let mut moov_writer = Writer::new();
+        rewrite_moov(buf, moov, &movie, &track_rewrites, &mut moov_writer);
+        let new_payload_offset =
+            (head.len() + moov_writer.position() as usize + mdat_header.len()) as u64;
+        if new_payload_offset == payload_offset {
+            let mut writer = Writer::new();
+            writer.write_bytes(&head);
+            writer.write_bytes(&moov_writer.into_inner());
+            writer.write_bytes(&mdat_header);
+            writer.write_bytes(&mdat_payload);
+            return Ok(writer.into_inner());
+        }
+        for rewrite in track_rewrites.iter_mut().flatten() {
+            for offset in rewrite.sample_table.chunk_offsets.iter_mut() {
+                *offset = *offset - payload_offset + new_payload_offset;
+            }
+        }
+        payload_offset = new_payload_offset;
+    }
+}
+
+/// Finds the latest sync sample at or before `time` (in seconds) in the first track that
+/// has sync sample information, preferring video.
+fn snap_to_sync_sample(tracks: &[Track], all_samples: &[Vec<Sample>], time: f64) -> f64 {
+    let reference = tracks
+        .iter()
+        .position(|t| t.is_video() && t.sample_table.sync_samples.is_some())
+        .or_else(|| {
+            tracks
+                .iter()
+                .position(|t| t.sample_table.sync_samples.is_some())
+        });
+    let reference = match reference {
+        Some(i) => i,
+        None => return time,
+    };
+    let timescale = tracks[reference].timescale;
+    all_samples[reference]
+        .iter()
+        .map(|s| (s, MediaTime::new(s.decode_time, timescale).as_secs_f64()))
+        .rev()
+        .find(|(s, secs)| s.is_sync && *secs <= time)
+        .map(|(_, secs)| secs)
+        .unwrap_or(0.0)
+}
+
+/// An edit list for a trimmed track, which only needs to shift the presentation so that
+/// it starts at the first composition time. If the track wasn't cut at the beginning, the
+/// original media time is kept, as it may skip things like audio priming samples. Frames
+/// that are decoded before the end of the range but presented after it are cut off.
+fn trimmed_edits(
+    track: &Track,
+    movie: &Movie,
+    samples: &[Sample],
+    starts_at_beginning: bool,
+    max_duration: Option<f64>,
+) -> Option<Vec<EditListEntry>> {
+    let original_edit = track.edits.iter().find(|e| e.media_time >= 0)?;
+    let media_time = if starts_at_beginning {
+        original_edit.media_time as i64
+    } else {
+        samples
+            .iter()
+            .map(|s| s.decode_time as i64 + s.composition_offset as i64)
+            .min()
+            .unwrap_or(0)
+    };
+    let presentation_end = samples
+        .iter()
+        .map(|s| s.decode_time as i64 + s.composition_offset as i64 + s.duration as i64)
+        .max()
+        .unwrap_or(0);
+    let mut presented = (presentation_end - media_time).max(0) as u64;
+    if let Some(max_duration) = max_duration {
+        let max_duration = MediaTime::from_secs_f64(max_duration, track.timescale);
+        presented = presented.min(max_duration.ticks() as u64);
+    }
+    let segment_duration = MediaTime::new(presented, track.timescale)
+        .rescale(movie.timescale)
+        .ticks();
+    Some(vec![EditListEntry {
+        segment_duration: segment_duration as u32,
+        media_time: media_time as i32,
+        media_rate_integer: original_edit.media_rate_integer,
+        media_rate_fraction: original_edit.media_rate_fraction,
+    }])
+}
+
+/// A change to the creation and modification times of the movie and its tracks
+#[derive(Debug, Clone, Copy)]
+pub enum TimestampEdit {
+    /// Set all timestamps to the given time
+    Set(DateTime<Utc>),
+    /// Move all timestamps by the given amount, e.g. to correct a camera's clock
+    Shift(Duration),
+}
+
+impl TimestampEdit {
+    /// Times that are unset or out of range are left as they are when shifting
+    fn apply(&self, timestamp: Timestamp) -> Timestamp {
+        match (*self, timestamp) {
+            (TimestampEdit::Set(time), _) => Timestamp::Time(time),
+            (TimestampEdit::Shift(offset), Timestamp::Time(time)) => Timestamp::Time(time + offset),
+            (TimestampEdit::Shift(_), timestamp) => timestamp,
+        }
+    }
+}
+
+/// Rewrites the creation and modification times in mvhd, and in tkhd and mdhd of every
+/// track. Headers are upgraded to version 1 if a time no longer fits in 32 bits.
+pub fn edit_timestamps(buf: &[u8], edit: &TimestampEdit) -> Vec<u8> {
+    rewrite_file(buf, &mut |path: &[String],
+                            header: &BoxHeader,
+                            raw: &[u8]| {
+        let parent = path.last().map(|p| p.as_str());
+        let mut reader = Reader::new(raw);
+        reader
+            .skip_bytes(header.box_size - header.inner_size)
+            .unwrap();
+        let mut box_writer = Writer::new();
+        match (parent, header.box_type.as_ref()) {
+            (None, "moov") | (Some("moov"), "trak") | (Some("trak"), "mdia") => Rewrite::Descend,
+            (Some("moov"), "mvhd") => {
+                let mut mvhd = MovieHeaderBox::parse(&mut reader, header.inner_size);
+                mvhd.creation_time = edit.apply(mvhd.creation_time);
+                mvhd.modification_time = edit.apply(mvhd.modification_time);
+                mvhd.write(&mut box_writer);
+                Rewrite::Replace(box_writer.into_inner())
+            }
+            (Some("trak"), "tkhd") => {
+                let mut tkhd = TrackHeaderBox::parse(&mut reader, header.inner_size);
+                tkhd.creation_time = edit.apply(tkhd.creation_time);
+                tkhd.modification_time = edit.apply(tkhd.modification_time);
+                tkhd.write(&mut box_writer);
+                Rewrite::Replace(box_writer.into_inner())
+            }
+            (Some("mdia"), "mdhd") => {
+                let mut mdhd = MediaHeaderBox::parse(&mut reader, header.inner_size);
+                mdhd.creation_time = edit.apply(mdhd.creation_time);
+                mdhd.modification_time = edit.apply(mdhd.modification_time);
+                mdhd.write(&mut box_writer);
+                Rewrite::Replace(box_writer.into_inner())
+            }
+            _ => Rewrite::Keep,
+        }
+    })
+}
+
+/// Changes to the header fields of a single track
+#[derive(Debug, Clone, Default)]
+pub struct TrackEdit {
+    pub track_id: u32,
+    /// ISO-639-2/T language code, e.g. "eng"
+    pub language: Option<String>,
+    pub enabled: Option<bool>,
+    pub in_movie: Option<bool>,
+    pub in_preview: Option<bool>,
+}
+
+/// Rewrites the mdhd language and the tkhd flags of the track given in `edit`
+pub fn edit_track(buf: &[u8], edit: &TrackEdit) -> Result<Vec<u8>, String> {
+    if let Some(language) = &edit.language {
+        if language.len() != 3 || !language.bytes().all(|b| b.is_ascii_lowercase()) {
+            return Err(format!(
+                "Invalid language '{}': expected three lowercase letters, e.g. 'eng'",
+                language
+            ));
+        }
+    }
+    let movie = Movie::parse(buf).map_err(|e| e.to_string())?;
+    if !movie.tracks.iter().any(|t| t.id == edit.track_id) {
+        return Err(format!("Found no track with id {}", edit.track_id));
+    }
+
+    let mut in_edited_track = false;
+    Ok(rewrite_file(
+        buf,
+        &mut |path: &[String], header: &BoxHeader, raw: &[u8]| {
+            let parent = path.last().map(|p| p.as_str());
+            let mut reader = Reader::new(raw);
+            reader
+                .skip_bytes(header.box_size - header.inner_size)
+                .unwrap();
+            let mut box_writer = Writer::new();
+            match (parent, header.box_type.as_ref()) {
+                (None, "moov") | (Some("moov"), "trak") | (Some("trak"), "mdia") => {
+                    Rewrite::Descend
+                }
+                (Some("trak"), "tkhd") => {
+                    let mut tkhd = TrackHeaderBox::parse(&mut reader, header.inner_size);
+                    in_edited_track = tkhd.track_id == edit.track_id;
+                    if !in_edited_track {
+                        return Rewrite::Keep;
+                    }
+                    tkhd.track_enabled = edit.enabled.unwrap_or(tkhd.track_enabled);
+                    tkhd.track_in_movie = edit.in_movie.unwrap_or(tkhd.track_in_movie);
+                    tkhd.track_in_preview = edit.in_preview.unwrap_or(tkhd.track_in_preview);
+                    tkhd.write(&mut box_writer);
+                    Rewrite::Replace(box_writer.into_inner())
+                }
+                (Some("mdia"), "mdhd") if in_edited_track => match &edit.language {
+                    Some(language) => {
+                        let mut mdhd = MediaHeaderBox::parse(&mut reader, header.inner_size);
+                        mdhd.language = language.clone();
+                        mdhd.write(&mut box_writer);
+                        Rewrite::Replace(box_writer.into_inner())
+                    }
+                    None => Rewrite::Keep,
+                },
+                _ => Rewrite::Keep,
+            }
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+struct ChapterEntry {
+    /// In seconds
+    start: f64,
+    title: String,
+}
+
+/// Parses a chapter list, either as JSON (`[{"start": 0, "title": "Intro"}, ...]`) or as
+/// text with one chapter per line, e.g. "00:01:30.5 Second chapter" or "90.5 Second chapter".
+/// Empty lines and lines starting with '#' are ignored.
+pub fn parse_chapters(text: &str) -> Result<Vec<Chapter>, String> {
+    let entries: Vec<ChapterEntry> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(text).map_err(|e| format!("Invalid chapter list: {}", e))?
+    } else {
+        text.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (time, title) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+                let start = parse_chapter_time(time)
+                    .ok_or_else(|| format!("Invalid chapter time '{}' in line '{}'", time, line))?;
+                Ok(ChapterEntry {
+                    start,
+                    title: title.trim().to_string(),
+                })
+            })
+            .collect::<Result<_, String>>()?
+    };
+
+    if entries.len() > 255 {
+        return Err(format!(
+            "Too many chapters: {} (at most 255 are supported)",
+            entries.len()
+        ));
+    }
+    let mut chapters: Vec<Chapter> = entries
+        .into_iter()
+        .map(|e| {
+            if e.start < 0.0 || !e.start.is_finite() {
+                return Err(format!("Invalid start time for chapter '{}'", e.title));
+            }
+            Ok(Chapter {
+                start_time: (e.start * 10_000_000.0).round() as u64,
+                title: e.title,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    chapters.sort_by_key(|c| c.start_time);
+    Ok(chapters)
+}
+
+/// Parses "SECONDS", "MM:SS" or "HH:MM:SS", where the seconds may have decimals
+fn parse_chapter_time(time: &str) -> Option<f64> {
+    let mut secs = 0.0;
+    for part in time.split(':') {
+        secs = secs * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(secs)
+}
+
+/// Writes the chapters to a 'chpl' box in moov/udta, replacing any existing chapter list
+pub fn set_chapters(buf: &[u8], chapters: &[Chapter]) -> Vec<u8> {
+    let mut chpl = Writer::new();
+    ChapterListBox::write(&mut chpl, chapters);
+    let chpl = chpl.into_inner();
+
+    rewrite_file(buf, &mut |path: &[String],
+                            header: &BoxHeader,
+                            raw: &[u8]| {
+        let parent = path.last().map(|p| p.as_str());
+        let header_size = (header.box_size - header.inner_size) as usize;
+        match (parent, header.box_type.as_ref()) {
+            (None, "moov") => {
+                let has_udta = top_level_boxes(&raw[header_size..])
+                    .iter()
+                    .any(|b| b.box_type == "udta");
+                if has_udta {
+                    Rewrite::Descend
+                } else {
+                    let mut udta = Writer::new();
+                    let start = udta.begin_box("udta");
+                    udta.write_bytes(&chpl);
+                    udta.end_box(start);
+                    Rewrite::DescendAppending(udta.into_inner())
+                }
+            }
+            (Some("moov"), "udta") => Rewrite::DescendAppending(chpl.clone()),
+            (Some("udta"), "chpl") => Rewrite::Drop,
+            _ => Rewrite::Keep,
+        }
+    })
+}
@@ -0,0 +1,819 @@
+//! Common Encryption (CENC): how tracks are protected, and a report of it that doesn't need
+//! the keys
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+use serde::Serialize;
+
+use crate::boxes::{format_uuid, BoxHeader, FullBoxHeader};
+use crate::diagnostics::{diagnostic, Severity};
+use crate::model::Movie;
+use crate::piff;
+use crate::reader::Reader;
+
+/// Names of well-known DRM systems, by the system ID of their pssh boxes
+const PROTECTION_SYSTEMS: [([u8; 16], &str); 5] = [
+    (
+        [
+            0xED, 0xEF, 0x8B, 0xA9, 0x79, 0xD6, 0x4A, 0xCE, 0xA3, 0xC8, 0x27, 0xDC, 0xD5, 0x1D,
+            0x21, 0xED,
+        ],
+        "Widevine",
+    ),
+    (
+        [
+            0x9A, 0x04, 0xF0, 0x79, 0x98, 0x40, 0x42, 0x86, 0xAB, 0x92, 0xE6, 0x5B, 0xE0, 0x88,
+            0x5F, 0x95,
+        ],
+        "PlayReady",
+    ),
+    (
+        [
+            0x94, 0xCE, 0x86, 0xFB, 0x07, 0xFF, 0x4F, 0x43, 0xAD, 0xB8, 0x93, 0xD2, 0xFA, 0x96,
+            0x8C, 0xA2,
+        ],
+        "FairPlay",
+    ),
+    (
+        [
+            0x10, 0x77, 0xEF, 0xEC, 0xC0, 0xB2, 0x4D, 0x02, 0xAC, 0xE3, 0x3C, 0x1E, 0x52, 0xE2,
+            0xFB, 0x4B,
+        ],
+        "ClearKey",
+    ),
+    (
+        [
+            0xE2, 0x71, 0x9D, 0x58, 0xA9, 0x85, 0xB3, 0xC9, 0x78, 0x1A, 0xB0, 0x30, 0xAF, 0x78,
+            0xD3, 0x0E,
+        ],
+        "ClearKey (DASH-IF)",
+    ),
+];
+
+/// sinf, from an encv or enca sample entry
+#[derive(Debug, Clone)]
+pub struct ProtectionSchemeInfo {
+    /// From frma, the sample entry type before encryption, e.g. "avc1"
+    pub original_format: String,
+    /// From schm, e.g. "cenc" or "cbcs"
+    pub scheme_type: String,
+    pub scheme_version: u32,
+    pub track_encryption: Option<TrackEncryptionBox>,
+}
+
+impl ProtectionSchemeInfo {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let end_offset = reader.position() + inner_size;
+        let mut info = Self {
+            original_format: String::new(),
+            scheme_type: String::new(),
+            scheme_version: 0,
+            track_encryption: None,
+        };
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            match header.box_type.as_ref() {
+                "frma" => {
+                    info.original_format = String::from_utf8_lossy(&reader.read_bytes(4)).into()
+                }
+                "schm" => {
+                    FullBoxHeader::parse(reader);
+                    info.scheme_type = String::from_utf8_lossy(&reader.read_bytes(4)).into();
+                    info.scheme_version = reader.read_u32();
+                }
+                "schi" => {
+                    while reader.position() + 8 <= box_end_offset && !reader.failed() {
+                        let child = BoxHeader::parse(reader);
+                        let child_end_offset = child.start_offset + child.box_size;
+                        if child.box_type == "tenc" {
+                            info.track_encryption = Some(TrackEncryptionBox::parse(reader));
+                        } else if child.box_type == "uuid"
+                            && reader.peek_bytes(16) == piff::TRACK_ENCRYPTION_UUID
+                        {
+                            reader.skip(16);
+                            info.track_encryption = Some(TrackEncryptionBox::parse(reader));
+                        }
+                        let remaining = child_end_offset.saturating_sub(reader.position());
+                        reader.skip(remaining);
+                    }
+                }
+                _ => {}
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+        info
+    }
+
+    /// The scheme version as major.minor, e.g. "1.0"
+    pub fn version(&self) -> String {
+        format!(
+            "{}.{}",
+            self.scheme_version >> 16,
+            self.scheme_version & 0xFFFF
+        )
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Original format", &self.original_format);
+        print(
+            "Scheme",
+            &format!("{} (version {})", self.scheme_type, self.version()),
+        );
+        if let Some(tenc) = &self.track_encryption {
+            tenc.print_attributes(print);
+        }
+    }
+}
+
+/// tenc, the defaults for the track's samples
+#[derive(Debug, Clone)]
+pub struct TrackEncryptionBox {
+    /// Pattern encryption (cbcs, cens): the number of encrypted 16-byte blocks in each pattern.
+    /// Always 0 in version 0 of the box.
+    pub default_crypt_byte_block: u8,
+    /// Pattern encryption: the number of unencrypted 16-byte blocks that follow
+    pub default_skip_byte_block: u8,
+    pub default_is_protected: bool,
+    /// 0 if a constant IV is used
+    pub default_per_sample_iv_size: u8,
+    pub default_kid: [u8; 16],
+    pub default_constant_iv: Option<Vec<u8>>,
+}
+
+impl TrackEncryptionBox {
+    pub fn parse(reader: &mut Reader) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let _reserved = reader.read_u8();
+        let pattern = reader.read_u8();
+        let (default_crypt_byte_block, default_skip_byte_block) = if full_box.version == 0 {
+            (0, 0)
+        } else {
+            (pattern >> 4, pattern & 0xF)
+        };
+        let default_is_protected = reader.read_u8() == 1;
+        let default_per_sample_iv_size = reader.read_u8();
+        let mut default_kid = [0; 16];
+        reader.read_exact(&mut default_kid);
+        let default_constant_iv = if default_is_protected && default_per_sample_iv_size == 0 {
+            let size = reader.read_u8();
+            Some(reader.read_bytes(size as usize))
+        } else {
+            None
+        };
+        Self {
+            default_crypt_byte_block,
+            default_skip_byte_block,
+            default_is_protected,
+            default_per_sample_iv_size,
+            default_kid,
+            default_constant_iv,
+        }
+    }
+
+    /// E.g. "1:9" for one encrypted block followed by 9 unencrypted ones, if pattern encryption
+    /// is used
+    pub fn pattern(&self) -> Option<String> {
+        if self.default_crypt_byte_block == 0 && self.default_skip_byte_block == 0 {
+            None
+        } else {
+            Some(format!(
+                "{}:{}",
+                self.default_crypt_byte_block, self.default_skip_byte_block
+            ))
+        }
+    }
+
+    fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Protected", &self.default_is_protected);
+        print("Default KID", &format_uuid(&self.default_kid));
+        match &self.default_constant_iv {
+            Some(iv) => print("Constant IV", &hex(iv)),
+            None => print("Per-sample IV size", &self.default_per_sample_iv_size),
+        }
+        if let Some(pattern) = self.pattern() {
+            print("Pattern", &pattern);
+        }
+    }
+}
+
+/// pssh, the data that a DRM system needs to get the keys
+#[derive(Debug, Clone)]
+pub struct ProtectionSystemSpecificHeaderBox {
+    pub system_id: [u8; 16],
+    /// Only listed in version 1 of the box
+    pub kids: Vec<[u8; 16]>,
+    pub data_size: u32,
+}
+
+impl ProtectionSystemSpecificHeaderBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let mut system_id = [0; 16];
+        reader.read_exact(&mut system_id);
+        let mut kids = Vec::new();
+        if full_box.version > 0 {
+            let kid_count = reader.read_u32();
+            let kid_count = if reader.reserve(kid_count as u64, 16, "pssh KIDs") {
+                kid_count
+            } else {
+                0
+            };
+            for _ in 0..kid_count {
+                if reader.failed() {
+                    break;
+                }
+                let mut kid = [0; 16];
+                reader.read_exact(&mut kid);
+                kids.push(kid);
+            }
+        }
+        let data_size = reader.read_u32();
+        reader.skip(data_size as u64);
+        Self {
+            system_id,
+            kids,
+            data_size,
+        }
+    }
+
+    /// E.g. "Widevine", if it's a well-known system
+    pub fn system_name(&self) -> Option<&'static str> {
+        PROTECTION_SYSTEMS
+            .iter()
+            .find(|(id, _)| *id == self.system_id)
+            .map(|(_, name)| *name)
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print(
+            "System",
+            &format!(
+                "{} ({})",
+                self.system_name().unwrap_or("unknown"),
+                format_uuid(&self.system_id)
+            ),
+        );
+        for kid in &self.kids {
+            print("KID", &format_uuid(kid));
+        }
+        print("Data size", &self.data_size);
+    }
+}
+
+/// senc. The entries are read separately, since their IV size comes from the track's tenc box.
+#[derive(Debug)]
+pub struct SampleEncryptionBox {
+    pub sample_count: u32,
+    pub has_subsamples: bool,
+    /// Replaces the IV size of tenc, if set
+    pub per_sample_iv_size: Option<u8>,
+}
+
+/// The IV and the clear and protected ranges of one sample
+#[derive(Debug, Clone)]
+pub struct SampleEncryptionEntry {
+    pub iv: Vec<u8>,
+    /// Pairs of bytes of clear data and bytes of protected data. Empty if the whole sample is
+    /// protected.
+    pub subsamples: Vec<(u16, u32)>,
+}
+
+impl SampleEncryptionBox {
+    pub fn parse_header(reader: &mut Reader) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let flags = full_box.flags_u32();
+        // Flag 1: the box overrides the algorithm, IV size and KID of tenc
+        let per_sample_iv_size = if flags & 1 != 0 {
+            let _algorithm_id = reader.read_u24();
+            let iv_size = reader.read_u8();
+            let _kid = reader.read_bytes(16);
+            Some(iv_size)
+        } else {
+            None
+        };
+        let sample_count = reader.read_u32();
+        Self {
+            sample_count,
+            has_subsamples: flags & 2 != 0,
+            per_sample_iv_size,
+        }
+    }
+
+    pub fn parse_entry(&self, reader: &mut Reader, iv_size: u8) -> SampleEncryptionEntry {
+        let iv = reader.read_bytes(self.per_sample_iv_size.unwrap_or(iv_size) as usize);
+        let mut subsamples = Vec::new();
+        if self.has_subsamples {
+            let subsample_count = reader.read_u16();
+            let subsample_size = core::mem::size_of::<(u16, u32)>();
+            if !reader.reserve(subsample_count as u64, subsample_size, "senc subsamples") {
+                return SampleEncryptionEntry { iv, subsamples };
+            }
+            for _ in 0..subsample_count {
+                let clear_bytes = reader.read_u16();
+                let protected_bytes = reader.read_u32();
+                subsamples.push((clear_bytes, protected_bytes));
+            }
+        }
+        SampleEncryptionEntry { iv, subsamples }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Sample count", &self.sample_count);
+        print("Subsamples", &self.has_subsamples);
+        if let Some(iv_size) = self.per_sample_iv_size {
+            print("IV size", &iv_size);
+        }
+    }
+}
+
+impl SampleEncryptionEntry {
+    /// Sample auxiliary info, i.e. the same data as in senc but pointed to by saiz and saio. It
+    /// has subsamples if it's bigger than the IV.
+    pub fn parse_auxiliary_info(reader: &mut Reader, iv_size: u8, info_size: u8) -> Self {
+        let senc = SampleEncryptionBox {
+            sample_count: 1,
+            has_subsamples: info_size > iv_size,
+            per_sample_iv_size: None,
+        };
+        senc.parse_entry(reader, iv_size)
+    }
+}
+
+/// The scheme types of Common Encryption, which its sample auxiliary info is tagged with
+const CENC_SCHEME_TYPES: [&str; 4] = ["cenc", "cbc1", "cens", "cbcs"];
+
+/// Sample auxiliary info without a type is encryption info, given by the track's scheme type
+fn is_cenc_aux_info_type(aux_info_type: Option<&str>) -> bool {
+    aux_info_type.is_none_or(|t| CENC_SCHEME_TYPES.contains(&t))
+}
+
+/// saiz, the size of each sample's auxiliary info
+#[derive(Debug)]
+pub struct SampleAuxiliaryInfoSizesBox {
+    /// If not set, it's given by the sample entry, e.g. the scheme type of an encrypted track
+    pub aux_info_type: Option<String>,
+    pub default_sample_info_size: u8,
+    pub sample_count: u32,
+    /// Empty if all samples have the default size
+    pub sample_info_sizes: Vec<u8>,
+}
+
+impl SampleAuxiliaryInfoSizesBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let aux_info_type = if full_box.flags_u32() & 1 != 0 {
+            let aux_info_type = String::from_utf8_lossy(&reader.read_bytes(4)).into();
+            let _aux_info_type_parameter = reader.read_u32();
+            Some(aux_info_type)
+        } else {
+            None
+        };
+        let default_sample_info_size = reader.read_u8();
+        let sample_count = reader.read_u32();
+        // With a default size, `sizes` still makes one per sample
+        let reserved = reader.reserve(sample_count as u64, 1, "saiz");
+        let sample_info_sizes = if default_sample_info_size == 0 && reserved {
+            reader.read_bytes(sample_count as usize)
+        } else {
+            vec![]
+        };
+        Self {
+            aux_info_type,
+            default_sample_info_size,
+            sample_count,
+            sample_info_sizes,
+        }
+    }
+
+    /// Whether it's for the IVs and subsamples of encrypted samples
+    pub fn is_cenc(&self) -> bool {
+        is_cenc_aux_info_type(self.aux_info_type.as_deref())
+    }
+
+    /// The size of each sample's info
+    pub fn sizes(&self) -> Vec<u8> {
+        if self.default_sample_info_size == 0 {
+            self.sample_info_sizes.clone()
+        } else {
+            vec![self.default_sample_info_size; self.sample_count as usize]
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        if let Some(aux_info_type) = &self.aux_info_type {
+            print("Info type", aux_info_type);
+        }
+        print("Default size", &self.default_sample_info_size);
+        print("Sample count", &self.sample_count);
+    }
+}
+
+/// saio, where the sample auxiliary info is in the file
+#[derive(Debug)]
+pub struct SampleAuxiliaryInfoOffsetsBox {
+    pub aux_info_type: Option<String>,
+    /// One per chunk, or per trun in a movie fragment. In a traf, they're relative to the same
+    /// base offset as the sample data.
+    pub offsets: Vec<u64>,
+}
+
+impl SampleAuxiliaryInfoOffsetsBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let aux_info_type = if full_box.flags_u32() & 1 != 0 {
+            let aux_info_type = String::from_utf8_lossy(&reader.read_bytes(4)).into();
+            let _aux_info_type_parameter = reader.read_u32();
+            Some(aux_info_type)
+        } else {
+            None
+        };
+        let entry_count = reader.read_u32();
+        let entry_count = if reader.reserve(entry_count as u64, 8, "saio") {
+            entry_count
+        } else {
+            0
+        };
+        let offsets = (0..entry_count)
+            .map(|_| {
+                if full_box.version == 0 {
+                    reader.read_u32() as u64
+                } else {
+                    reader.read_u64()
+                }
+            })
+            .collect();
+        Self {
+            aux_info_type,
+            offsets,
+        }
+    }
+
+    pub fn is_cenc(&self) -> bool {
+        is_cenc_aux_info_type(self.aux_info_type.as_deref())
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        if let Some(aux_info_type) = &self.aux_info_type {
+            print("Info type", aux_info_type);
+        }
+        for offset in &self.offsets {
+            print("Offset", offset);
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// How a file is encrypted, without needing its keys
+#[derive(Debug, Serialize)]
+pub struct EncryptionReport {
+    pub tracks: Vec<TrackEncryption>,
+    pub protection_systems: Vec<ProtectionSystem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackEncryption {
+    pub track_id: u32,
+    /// E.g. "cenc" or "cbcs"
+    pub scheme: String,
+    pub original_format: String,
+    pub default_kid: Option<String>,
+    /// In hex, if all samples use the same IV
+    pub constant_iv: Option<String>,
+    pub per_sample_iv_size: Option<u8>,
+    /// Encrypted and unencrypted blocks, e.g. "1:9", for pattern encryption
+    pub pattern: Option<String>,
+    /// Samples described in senc boxes
+    pub sample_count: usize,
+    pub subsample_encrypted_count: usize,
+    /// The samples with subsample encryption, as 1-based ranges, e.g. "1-118, 120"
+    pub subsample_encrypted_samples: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProtectionSystem {
+    pub system_id: String,
+    pub name: Option<&'static str>,
+    pub kids: Vec<String>,
+    pub data_size: u32,
+}
+
+impl EncryptionReport {
+    pub fn new(movie: &Movie) -> Self {
+        let tracks = movie
+            .tracks
+            .iter()
+            .filter_map(|track| {
+                let protection = track.protection()?;
+                let tenc = protection.track_encryption.as_ref();
+                let entries: Vec<&SampleEncryptionEntry> = movie
+                    .fragments
+                    .iter()
+                    .flat_map(|fragment| fragment.tracks.iter())
+                    .filter(|t| t.track_id == track.id)
+                    .flat_map(|t| t.sample_encryption.iter())
+                    .collect();
+                let subsample_encrypted: Vec<usize> = entries
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| !entry.subsamples.is_empty())
+                    .map(|(i, _)| i + 1)
+                    .collect();
+                Some(TrackEncryption {
+                    track_id: track.id,
+                    scheme: protection.scheme_type.clone(),
+                    original_format: protection.original_format.clone(),
+                    default_kid: tenc.map(|tenc| format_uuid(&tenc.default_kid)),
+                    constant_iv: tenc.and_then(|tenc| tenc.default_constant_iv.as_deref().map(hex)),
+                    per_sample_iv_size: tenc
+                        .filter(|tenc| tenc.default_constant_iv.is_none())
+                        .map(|tenc| tenc.default_per_sample_iv_size),
+                    pattern: tenc.and_then(|tenc| tenc.pattern()),
+                    sample_count: entries.len(),
+                    subsample_encrypted_count: subsample_encrypted.len(),
+                    subsample_encrypted_samples: ranges(&subsample_encrypted),
+                })
+            })
+            .collect();
+        let protection_systems = movie
+            .protection_systems
+            .iter()
+            .map(|pssh| ProtectionSystem {
+                system_id: format_uuid(&pssh.system_id),
+                name: pssh.system_name(),
+                kids: pssh.kids.iter().map(format_uuid).collect(),
+                data_size: pssh.data_size,
+            })
+            .collect();
+        Self {
+            tracks,
+            protection_systems,
+        }
+    }
+}
+
+/// E.g. "1-3, 5" for [1, 2, 3, 5]
+fn ranges(numbers: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &n in numbers {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == n => *end = n,
+            _ => ranges.push((n, n)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for EncryptionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.tracks.is_empty() {
+            writeln!(f, "No encrypted tracks")?;
+        }
+        for track in &self.tracks {
+            let mut details = vec![format!("original format: {}", track.original_format)];
+            if let Some(kid) = &track.default_kid {
+                details.push(format!("default KID: {}", kid));
+            }
+            if let Some(iv) = &track.constant_iv {
+                details.push(format!("constant IV: {}", iv));
+            }
+            if let Some(iv_size) = track.per_sample_iv_size {
+                details.push(format!("per-sample IVs: {} bytes", iv_size));
+            }
+            if let Some(pattern) = &track.pattern {
+                details.push(format!("pattern: {}", pattern));
+            }
+            writeln!(
+                f,
+                "Track {}:    {} ({})",
+                track.track_id,
+                track.scheme,
+                details.join(", ")
+            )?;
+            if track.sample_count > 0 {
+                write!(
+                    f,
+                    "             {} samples in senc, {} with subsample encryption",
+                    track.sample_count, track.subsample_encrypted_count
+                )?;
+                if track.subsample_encrypted_count > 0 {
+                    write!(f, " (samples {})", track.subsample_encrypted_samples)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        for system in &self.protection_systems {
+            write!(
+                f,
+                "PSSH:       {} ({})",
+                system.name.unwrap_or("unknown system"),
+                system.system_id
+            )?;
+            if !system.kids.is_empty() {
+                write!(f, ", KIDs: {}", system.kids.join(", "))?;
+            }
+            writeln!(f, ", {} bytes of data", system.data_size)?;
+        }
+        Ok(())
+    }
+}
+
+/// The IV and subsamples of each encrypted sample, e.g. to compare the output of a packager
+/// between runs
+#[derive(Debug, Serialize)]
+pub struct EncryptionDump {
+    pub tracks: Vec<TrackEncryptionDump>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackEncryptionDump {
+    pub track_id: u32,
+    pub scheme: String,
+    pub default_kid: Option<String>,
+    pub constant_iv: Option<String>,
+    pub fragments: Vec<FragmentEncryptionDump>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FragmentEncryptionDump {
+    pub sequence_number: u32,
+    /// File offset of the moof box
+    pub moof_offset: u64,
+    /// "senc", or "saio" if the info was read from where saio points
+    pub source: Option<&'static str>,
+    /// From saio
+    pub aux_info_offset: Option<u64>,
+    pub samples: Vec<SampleEncryptionDump>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SampleEncryptionDump {
+    /// 1-based, counted over all fragments of the track
+    pub sample: usize,
+    /// In hex. Not set if the track has a constant IV.
+    pub iv: Option<String>,
+    /// From saiz
+    pub aux_info_size: Option<u8>,
+    pub subsamples: Vec<Subsample>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Subsample {
+    pub clear_bytes: u16,
+    pub protected_bytes: u32,
+}
+
+impl EncryptionDump {
+    /// The file is needed for track fragments that have no senc box, whose info is read from
+    /// where saio points instead
+    pub fn new(buf: &[u8], movie: &Movie) -> Self {
+        let tracks = movie
+            .tracks
+            .iter()
+            .filter_map(|track| {
+                let protection = track.protection()?;
+                let tenc = protection.track_encryption.as_ref();
+                let iv_size = tenc.map_or(0, |tenc| tenc.default_per_sample_iv_size);
+                let mut sample_number = 0;
+                let fragments = movie
+                    .fragments
+                    .iter()
+                    .flat_map(|fragment| {
+                        fragment
+                            .tracks
+                            .iter()
+                            .filter(|t| t.track_id == track.id)
+                            .map(move |t| (fragment, t))
+                    })
+                    .map(|(fragment, track_fragment)| {
+                        let (source, entries) = if !track_fragment.sample_encryption.is_empty() {
+                            (Some("senc"), track_fragment.sample_encryption.clone())
+                        } else if let Some(offset) = track_fragment.auxiliary_info_offset {
+                            (
+                                Some("saio"),
+                                read_auxiliary_info(
+                                    buf,
+                                    offset,
+                                    iv_size,
+                                    &track_fragment.auxiliary_info_sizes,
+                                ),
+                            )
+                        } else {
+                            (None, vec![])
+                        };
+                        let samples = entries
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, entry)| {
+                                sample_number += 1;
+                                SampleEncryptionDump {
+                                    sample: sample_number,
+                                    iv: if entry.iv.is_empty() {
+                                        None
+                                    } else {
+                                        Some(hex(&entry.iv))
+                                    },
+                                    aux_info_size: track_fragment
+                                        .auxiliary_info_sizes
+                                        .get(i)
+                                        .copied(),
+                                    subsamples: entry
+                                        .subsamples
+                                        .iter()
+                                        .map(|&(clear_bytes, protected_bytes)| Subsample {
+                                            clear_bytes,
+                                            protected_bytes,
+                                        })
+                                        .collect(),
+                                }
+                            })
+                            .collect();
+                        FragmentEncryptionDump {
+                            sequence_number: fragment.sequence_number,
+                            moof_offset: fragment.offset,
+                            source,
+                            aux_info_offset: track_fragment.auxiliary_info_offset,
+                            samples,
+                        }
+                    })
+                    .collect();
+                Some(TrackEncryptionDump {
+                    track_id: track.id,
+                    scheme: protection.scheme_type.clone(),
+                    default_kid: tenc.map(|tenc| format_uuid(&tenc.default_kid)),
+                    constant_iv: tenc.and_then(|tenc| tenc.default_constant_iv.as_deref().map(hex)),
+                    fragments,
+                })
+            })
+            .collect();
+        Self { tracks }
+    }
+}
+
+fn read_auxiliary_info(
+    buf: &[u8],
+    offset: u64,
+    iv_size: u8,
+    sizes: &[u8],
+) -> Vec<SampleEncryptionEntry> {
+    let total_size: u64 = sizes.iter().map(|&size| size as u64).sum();
+    let end = offset.checked_add(total_size);
+    if end.is_none_or(|end| end > buf.len() as u64) {
+        diagnostic!(
+            Severity::Error,
+            "Sample auxiliary info at {} ({} bytes) is past the end of the file",
+            offset,
+            total_size
+        );
+        return vec![];
+    }
+    let mut reader = Reader::new(&buf[offset as usize..]).limited(total_size);
+    sizes
+        .iter()
+        .map(|&size| {
+            let start = reader.position();
+            let entry = SampleEncryptionEntry::parse_auxiliary_info(&mut reader, iv_size, size);
+            let remaining = (start + size as u64).saturating_sub(reader.position());
+            reader.skip(remaining);
+            entry
+        })
+        .collect()
+}
@@ -0,0 +1,58 @@
+//! The error that parsing a file gives, with where in the file it happened
+
+use alloc::string::String;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use crate::box_path;
+
+/// A file that couldn't be parsed, e.g. because a box is larger than its parent. See
+/// `Reader::fail`.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub message: String,
+    /// The boxes that were being parsed, outermost first, e.g. "moov/trak[0]/mdia/mdhd"
+    pub box_path: Option<String>,
+    /// The file offset of the innermost of those boxes
+    pub offset: Option<u64>,
+}
+
+/// Why a file couldn't be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The file is corrupt, or uses something that isn't supported
+    Malformed,
+    /// The file needs more memory than `ParserOptions::max_alloc_bytes` allows, see
+    /// `Reader::reserve`
+    QuotaExceeded,
+}
+
+impl ParseError {
+    /// An error in the box that is being parsed, see `box_path::current`
+    pub fn in_current_box(message: String) -> Self {
+        #[cfg(feature = "std")]
+        let (box_path, offset) = box_path::current().unzip();
+        #[cfg(not(feature = "std"))]
+        let (box_path, offset) = (None, None);
+        Self {
+            kind: ParseErrorKind::Malformed,
+            message,
+            box_path,
+            offset,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let (Some(box_path), Some(offset)) = (&self.box_path, self.offset) {
+            write!(f, " (in {} @ {:#X})", box_path, offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
@@ -0,0 +1,97 @@
+//! Exit codes shared by the binaries, so that scripts can tell different failures apart
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+
+pub use crate::error::ParseError;
+use crate::error::ParseErrorKind;
+
+pub const OK: i32 = 0;
+/// The file is broken, or couldn't be read
+pub const PARSE_ERROR: i32 = 1;
+/// The file was parsed, but problems were found (see `--fail-on-warning`)
+pub const WARNINGS: i32 = 2;
+/// The file uses a feature that isn't supported yet
+pub const UNSUPPORTED: i32 = 3;
+
+/// Prints the error of a failed parse (see `Reader::fail`) and exits with `PARSE_ERROR` or
+/// `UNSUPPORTED` depending on the message
+pub fn exit_on_error<T>(result: Result<T, ParseError>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("ERROR: {}", error);
+        std::process::exit(for_error(&error.message))
+    })
+}
+
+/// Prints panics as errors instead of the default panic message. The backtrace is only printed
+/// if RUST_BACKTRACE is set. Nothing is printed if the output was closed early, e.g. by
+/// `| head`.
+pub fn set_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let message = panic_message(info.payload());
+        if is_broken_pipe(&message) {
+            return;
+        }
+        eprintln!("ERROR: {}", message);
+        if std::env::var_os("RUST_BACKTRACE").is_some() {
+            if let Some(location) = info.location() {
+                eprintln!("Panicked at {}", location);
+            }
+            eprintln!("{}", Backtrace::force_capture());
+        }
+    }));
+}
+
+/// Runs `f`. If it panics, the process exits with `PARSE_ERROR` or `UNSUPPORTED` depending on
+/// the panic message, which has already been printed by the panic hook. If the output was
+/// closed early, it exits with `OK`.
+pub fn exit_on_panic<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    catch(f).unwrap_or_else(|error| std::process::exit(for_error(&error.message)))
+}
+
+/// Runs `f`, returning the panic message if it panics. Parse errors aren't panics, so this is
+/// only a last resort for bugs, e.g. in code that is given a file to rewrite.
+pub fn catch<T, F>(f: F) -> Result<T, ParseError>
+where
+    F: FnOnce() -> T,
+{
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).map_err(|payload| ParseError {
+        kind: ParseErrorKind::Malformed,
+        message: panic_message(&*payload),
+        box_path: None,
+        offset: None,
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown error".to_string())
+}
+
+/// Whether printing failed because the reader of the output went away
+fn is_broken_pipe(message: &str) -> bool {
+    message.contains("Broken pipe")
+}
+
+/// The exit code for an error with the given message
+pub fn for_error(message: &str) -> i32 {
+    if is_broken_pipe(message) {
+        return OK;
+    }
+    // todo!() panics and the explicit "Unhandled ..." errors mark things that haven't been
+    // implemented yet
+    if message.starts_with("not yet implemented")
+        || message.starts_with("Unhandled")
+        || message.contains("not supported")
+    {
+        UNSUPPORTED
+    } else {
+        PARSE_ERROR
+    }
+}
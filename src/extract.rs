@@ -0,0 +1,255 @@
+//! Writes the media of a track to a file of its own: the sample data as it is, or for
+//! uncompressed audio, as a WAV file that can be played right away. A single keyframe can also
+//! be written as an access unit that a decoder takes on its own, e.g. for a thumbnail.
+
+use std::convert::TryFrom;
+
+use crate::boxes::{PcmAudioSampleEntry, PcmFormat, SampleEntry};
+use crate::model::{Movie, Sample, Track};
+
+/// The sample data of the track in decoding order, including that in movie fragments.
+/// Samples beyond the end of a truncated file are left out.
+pub fn track_data(buf: &[u8], movie: &Movie, track: &Track) -> Vec<u8> {
+    let table = &track.sample_table;
+    // QuickTime stores uncompressed audio with a sample size of 1, where each sample is one
+    // frame of all channels
+    let frame_size = match pcm_entry(track) {
+        Some(pcm)
+            if !table.sample_sizes.is_empty() && table.sample_sizes.iter().all(|&s| s == 1) =>
+        {
+            pcm.bytes_per_frame() as u64
+        }
+        _ => 1,
+    };
+
+    let mut data = Vec::new();
+    for chunk in table.chunks() {
+        let size = if frame_size > 1 {
+            chunk.samples.len() as u64 * frame_size
+        } else {
+            chunk.size
+        };
+        data.extend_from_slice(slice(buf, chunk.offset, size));
+    }
+    for fragment in &movie.fragments {
+        for track_fragment in fragment.tracks.iter().filter(|t| t.track_id == track.id) {
+            for sample in &track_fragment.samples {
+                data.extend_from_slice(slice(buf, sample.offset, sample.size as u64));
+            }
+        }
+    }
+    data
+}
+
+/// The track's audio as a WAV file. Fails if the track isn't uncompressed audio, or if it's in a
+/// format that WAV can't hold.
+pub fn to_wav(buf: &[u8], movie: &Movie, track: &Track) -> Result<Vec<u8>, String> {
+    let pcm = pcm_entry(track).ok_or_else(|| {
+        format!(
+            "WAV output is not supported for track {}, which isn't uncompressed audio",
+            track.id
+        )
+    })?;
+    let format_tag: u16 = match (pcm.format, pcm.bits_per_sample) {
+        (PcmFormat::SignedInteger | PcmFormat::UnsignedInteger, 8 | 16 | 24 | 32) => 1,
+        (PcmFormat::Float, 32 | 64) => 3,
+        (PcmFormat::ALaw, _) => 6,
+        (PcmFormat::MuLaw, _) => 7,
+        (format, bits) => {
+            return Err(format!(
+                "WAV output is not supported for {}-bit {} samples",
+                bits, format
+            ))
+        }
+    };
+    let channel_count = u16::try_from(pcm.channel_count)
+        .ok()
+        .filter(|&count| count > 0)
+        .ok_or_else(|| {
+            format!(
+                "WAV output is not supported for {} channels",
+                pcm.channel_count
+            )
+        })?;
+
+    let mut data = track_data(buf, movie, track);
+    let frame_size = pcm.bytes_per_frame() as usize;
+    data.truncate(data.len() - data.len() % frame_size);
+    to_wav_byte_order(&mut data, pcm);
+
+    let sample_rate = pcm.sample_rate.round() as u32;
+    let riff_size = u32::try_from(data.len() as u64 + 36)
+        .map_err(|_| "The audio is too large for a WAV file".to_string())?;
+    let mut wav = Vec::with_capacity(data.len() + 44);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&riff_size.to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&format_tag.to_le_bytes());
+    wav.extend_from_slice(&channel_count.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate.saturating_mul(frame_size as u32)).to_le_bytes());
+    wav.extend_from_slice(&(frame_size as u16).to_le_bytes());
+    wav.extend_from_slice(&(pcm.bits_per_sample as u16).to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&data);
+    Ok(wav)
+}
+
+/// A sync sample of an H.264 or HEVC track as an Annex B access unit: the NAL units with start
+/// codes instead of length prefixes, after the parameter sets from the sample entry
+#[derive(Debug)]
+pub struct Keyframe {
+    /// 1-based, in decoding order, counting the samples in movie fragments after the others
+    pub sample_number: usize,
+    /// When the sample is presented, in seconds, with the edit list applied
+    pub time: f64,
+    /// Where the sample is in the file
+    pub offset: u64,
+    pub size: u32,
+    pub data: Vec<u8>,
+}
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// The sync sample of the track that is presented closest to `time`, in seconds. Fails if the
+/// track isn't H.264 or HEVC, or if the sample is broken or beyond the end of the file.
+pub fn keyframe(buf: &[u8], movie: &Movie, track: &Track, time: f64) -> Result<Keyframe, String> {
+    let timeline = track.presentation_timeline(movie.timescale);
+    let samples = movie.track_samples(track);
+    let distance = |t: f64| (t - time).abs();
+    let (index, sample, presented) = samples
+        .iter()
+        .enumerate()
+        .filter(|(_, sample)| sample.is_sync)
+        .filter_map(|(i, sample)| {
+            let presented = timeline.presentation_time(sample)?.as_secs_f64();
+            Some((i, sample, presented))
+        })
+        .min_by(|a, b| distance(a.2).total_cmp(&distance(b.2)))
+        .ok_or_else(|| format!("Track {} has no presented sync samples", track.id))?;
+
+    let (parameter_sets, nal_length_size) = parameter_sets(track, sample)?;
+    let end = sample.offset.saturating_add(sample.size as u64);
+    if end > buf.len() as u64 {
+        return Err(format!(
+            "Sample {} at offset {} goes past the end of the file",
+            index + 1,
+            sample.offset
+        ));
+    }
+    let mut data = Vec::with_capacity(sample.size as usize + 256);
+    for parameter_set in parameter_sets {
+        data.extend_from_slice(&START_CODE);
+        data.extend_from_slice(parameter_set);
+    }
+    let mut nal_units = &buf[sample.offset as usize..end as usize];
+    while !nal_units.is_empty() {
+        let (len, rest) = match nal_units.get(..nal_length_size) {
+            Some(len) => (len, &nal_units[nal_length_size..]),
+            None => {
+                return Err(format!(
+                    "Sample {} ends inside a NAL unit length",
+                    index + 1
+                ))
+            }
+        };
+        let len = len.iter().fold(0, |len, &byte| len << 8 | byte as usize);
+        let nal_unit = rest.get(..len).ok_or_else(|| {
+            format!(
+                "Sample {} has a NAL unit of {} bytes, which is more than is left of it",
+                index + 1,
+                len
+            )
+        })?;
+        if !nal_unit.is_empty() {
+            data.extend_from_slice(&START_CODE);
+            data.extend_from_slice(nal_unit);
+        }
+        nal_units = &rest[len..];
+    }
+
+    Ok(Keyframe {
+        sample_number: index + 1,
+        time: presented,
+        offset: sample.offset,
+        size: sample.size,
+        data,
+    })
+}
+
+/// The parameter sets of the sample's sample entry, and the size of its NAL unit lengths
+fn parameter_sets<'a>(track: &'a Track, sample: &Sample) -> Result<(Vec<&'a [u8]>, usize), String> {
+    let entry = track
+        .sample_entries
+        .get(sample.description_index.saturating_sub(1) as usize)
+        .ok_or_else(|| {
+            format!(
+                "Track {} has no sample entry {}",
+                track.id, sample.description_index
+            )
+        })?;
+    match entry {
+        SampleEntry::Avc1(avc1) => {
+            let config = avc1
+                .avc_config
+                .as_ref()
+                .ok_or_else(|| format!("Track {} has no avcC", track.id))?;
+            let parameter_sets = config
+                .sequence_parameter_sets
+                .iter()
+                .chain(&config.picture_parameter_sets)
+                .map(Vec::as_slice)
+                .collect();
+            Ok((parameter_sets, config.nal_length_size as usize))
+        }
+        SampleEntry::Hevc(hevc) => {
+            let config = hevc
+                .hevc_config
+                .as_ref()
+                .ok_or_else(|| format!("Track {} has no hvcC", track.id))?;
+            let parameter_sets = config.parameter_sets.iter().map(Vec::as_slice).collect();
+            Ok((parameter_sets, config.nal_length_size as usize))
+        }
+        entry => Err(format!(
+            "Keyframe output is not supported for {}, only for H.264 and HEVC",
+            entry.name()
+        )),
+    }
+}
+
+fn pcm_entry(track: &Track) -> Option<&PcmAudioSampleEntry> {
+    match track.sample_entries.first()? {
+        SampleEntry::Pcm(pcm) => Some(pcm),
+        _ => None,
+    }
+}
+
+/// WAV has little-endian samples, which are unsigned if they're 8 bits and signed otherwise
+fn to_wav_byte_order(data: &mut [u8], pcm: &PcmAudioSampleEntry) {
+    let sample_size = pcm.bits_per_sample as usize / 8;
+    match pcm.format {
+        PcmFormat::MuLaw | PcmFormat::ALaw => return,
+        PcmFormat::SignedInteger if sample_size == 1 => {
+            data.iter_mut().for_each(|byte| *byte ^= 0x80);
+            return;
+        }
+        _ => {}
+    }
+    for sample in data.chunks_exact_mut(sample_size) {
+        if pcm.big_endian {
+            sample.reverse();
+        }
+        if pcm.format == PcmFormat::UnsignedInteger && sample_size > 1 {
+            sample[sample_size - 1] ^= 0x80;
+        }
+    }
+}
+
+fn slice(buf: &[u8], offset: u64, size: u64) -> &[u8] {
+    let start = (offset as usize).min(buf.len());
+    let end = (offset.saturating_add(size) as usize).min(buf.len());
+    &buf[start..end]
+}
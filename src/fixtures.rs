@@ -0,0 +1,605 @@
+//! Small files built in code, so that tests of the parsers don't need binary fixtures checked
+//! in. Each is the least that makes a valid file: a progressive or fragmented movie with the
+//! given tracks, or a HEIF image.
+//!
+//! ```
+//! use mp4_parser::fixtures::{movie, TrackFixture};
+//! use mp4_parser::model::Movie;
+//!
+//! let buf = movie(&[TrackFixture::video(), TrackFixture::audio()]);
+//! let parsed = Movie::parse(&buf).unwrap();
+//! assert_eq!(parsed.tracks.len(), 2);
+//! ```
+
+use alloc::{vec, vec::Vec};
+
+use crate::boxes::{pack_language, MediaHeaderBox, MovieHeaderBox, Timestamp, TrackHeaderBox};
+use crate::media_time::MediaTime;
+use crate::model::{Sample, SampleTable};
+use crate::writer::Writer;
+
+const IDENTITY_MATRIX: [u32; 9] = [0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x4000_0000];
+
+/// The timescale of mvhd
+const MOVIE_TIMESCALE: u32 = 1000;
+
+/// In trun and trex sample flags
+const SAMPLE_IS_NON_SYNC_SAMPLE: u32 = 0x0001_0000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum TrackKind {
+    /// H.264, Baseline profile
+    Video { width: u16, height: u16 },
+    /// AAC LC
+    Audio {
+        sample_rate: u32,
+        channel_count: u16,
+    },
+    /// 16-bit big-endian PCM (twos), one frame per sample
+    Pcm {
+        sample_rate: u32,
+        channel_count: u16,
+    },
+}
+
+/// A track whose samples all have the same size and duration. The sample data is zeros.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackFixture {
+    pub kind: TrackKind,
+    pub timescale: u32,
+    pub sample_count: u32,
+    pub sample_duration: u32,
+    pub sample_size: u32,
+    /// Every n:th sample is a sync sample, starting with the first one
+    pub sync_interval: u32,
+}
+
+impl TrackFixture {
+    /// One second of 320x240 video at 25 fps, with a sync sample every 5 frames
+    pub fn video() -> Self {
+        Self {
+            kind: TrackKind::Video {
+                width: 320,
+                height: 240,
+            },
+            timescale: 12800,
+            sample_count: 25,
+            sample_duration: 512,
+            sample_size: 100,
+            sync_interval: 5,
+        }
+    }
+
+    /// About a second of stereo audio at 48 kHz
+    pub fn audio() -> Self {
+        Self {
+            kind: TrackKind::Audio {
+                sample_rate: 48000,
+                channel_count: 2,
+            },
+            timescale: 48000,
+            sample_count: 47,
+            sample_duration: 1024,
+            sample_size: 20,
+            sync_interval: 1,
+        }
+    }
+
+    /// A tenth of a second of uncompressed stereo audio at 48 kHz
+    pub fn pcm_audio() -> Self {
+        Self {
+            kind: TrackKind::Pcm {
+                sample_rate: 48000,
+                channel_count: 2,
+            },
+            timescale: 48000,
+            sample_count: 4800,
+            sample_duration: 1,
+            sample_size: 4,
+            sync_interval: 1,
+        }
+    }
+
+    /// In the track's timescale
+    fn duration(&self) -> u64 {
+        self.sample_count as u64 * self.sample_duration as u64
+    }
+
+    fn in_movie_timescale(&self, ticks: u64) -> u64 {
+        MediaTime::new(ticks, self.timescale)
+            .rescale(MOVIE_TIMESCALE)
+            .ticks() as u64
+    }
+
+    fn is_sync(&self, sample_index: u32) -> bool {
+        sample_index.is_multiple_of(self.sync_interval.max(1))
+    }
+
+    /// The samples of the track, in one chunk at `offset`
+    fn samples(&self, offset: u64) -> Vec<Sample> {
+        (0..self.sample_count)
+            .map(|i| Sample {
+                offset: offset + i as u64 * self.sample_size as u64,
+                size: self.sample_size,
+                decode_time: i as u64 * self.sample_duration as u64,
+                composition_offset: 0,
+                duration: self.sample_duration,
+                is_sync: self.is_sync(i),
+                chunk_index: 0,
+                description_index: 1,
+            })
+            .collect()
+    }
+}
+
+/// ftyp, moov and an mdat with the samples of each track, one track after the other
+pub fn movie(tracks: &[TrackFixture]) -> Vec<u8> {
+    progressive_movie(tracks, false)
+}
+
+/// Like `movie`, but mdat's size is in the 64-bit largesize field, as it must be for media
+/// data of 4 GiB or more
+pub fn movie_with_largesize_mdat(tracks: &[TrackFixture]) -> Vec<u8> {
+    progressive_movie(tracks, true)
+}
+
+fn progressive_movie(tracks: &[TrackFixture], largesize: bool) -> Vec<u8> {
+    let ftyp = file_type("isom", &["isom", "iso2", "avc1", "mp41"]);
+    // The chunk offsets don't change the size of moov, so it's written twice: once to know
+    // where mdat starts, and once with the right offsets
+    let moov_len = movie_box(tracks, 0, false).len() as u64;
+    let mdat_header_len = if largesize { 16 } else { 8 };
+    let data_offset = (ftyp.len() as u64) + moov_len + mdat_header_len;
+    let moov = movie_box(tracks, data_offset, false);
+
+    let mut writer = Writer::new();
+    writer.write_bytes(&ftyp);
+    writer.write_bytes(&moov);
+    if largesize {
+        let len = media_data_len(tracks);
+        writer.write_u32(1);
+        writer.write_string("mdat");
+        writer.write_u64(len + 16);
+        writer.write_bytes(&vec![0; len as usize]);
+    } else {
+        write_media_data(&mut writer, tracks);
+    }
+    writer.into_inner()
+}
+
+/// ftyp and a moov with mvex, followed by `fragment_count` pairs of moof and mdat, each with the
+/// samples of every track
+pub fn fragmented_movie(tracks: &[TrackFixture], fragment_count: u32) -> Vec<u8> {
+    let mut writer = Writer::new();
+    writer.write_bytes(&file_type("iso6", &["iso6", "mp41"]));
+    writer.write_bytes(&movie_box(tracks, 0, true));
+    for sequence_number in 1..=fragment_count {
+        let decode_times: Vec<u64> = tracks
+            .iter()
+            .map(|track| (sequence_number - 1) as u64 * track.duration())
+            .collect();
+        // The data offsets are relative to the moof, and its size doesn't depend on them
+        let moof_len = movie_fragment(tracks, sequence_number, &decode_times, 0).len() as u32;
+        writer.write_bytes(&movie_fragment(
+            tracks,
+            sequence_number,
+            &decode_times,
+            moof_len + 8,
+        ));
+        write_media_data(&mut writer, tracks);
+    }
+    writer.into_inner()
+}
+
+/// One file of each kind, named, for checks that should hold for any valid file
+pub fn corpus() -> Vec<(&'static str, Vec<u8>)> {
+    let video = TrackFixture::video();
+    let audio = TrackFixture::audio();
+    vec![
+        ("video", movie(&[video])),
+        ("audio", movie(&[audio])),
+        ("video and audio", movie(&[video, audio])),
+        ("pcm audio", movie(&[TrackFixture::pcm_audio()])),
+        ("largesize mdat", movie_with_largesize_mdat(&[video, audio])),
+        ("fragmented", fragmented_movie(&[video, audio], 3)),
+        ("heif", heif_image(640, 480)),
+    ]
+}
+
+/// ftyp and a meta box with one HEVC image item, whose data is in mdat
+pub fn heif_image(width: u32, height: u32) -> Vec<u8> {
+    const ITEM_ID: u16 = 1;
+    const DATA_LEN: u32 = 16;
+    let ftyp = file_type("heic", &["mif1", "heic"]);
+    let meta_len = image_metadata(ITEM_ID, width, height, 0, DATA_LEN).len() as u32;
+    let data_offset = ftyp.len() as u32 + meta_len + 8;
+
+    let mut writer = Writer::new();
+    writer.write_bytes(&ftyp);
+    writer.write_bytes(&image_metadata(
+        ITEM_ID,
+        width,
+        height,
+        data_offset,
+        DATA_LEN,
+    ));
+    let start = writer.begin_box("mdat");
+    writer.write_bytes(&vec![0; DATA_LEN as usize]);
+    writer.end_box(start);
+    writer.into_inner()
+}
+
+fn file_type(major_brand: &str, compatible_brands: &[&str]) -> Vec<u8> {
+    let mut writer = Writer::new();
+    let start = writer.begin_box("ftyp");
+    writer.write_string(major_brand);
+    writer.write_u32(0);
+    for brand in compatible_brands {
+        writer.write_string(brand);
+    }
+    writer.end_box(start);
+    writer.into_inner()
+}
+
+/// mdat with the samples of each track, one track after the other
+fn write_media_data(writer: &mut Writer, tracks: &[TrackFixture]) {
+    let start = writer.begin_box("mdat");
+    writer.write_bytes(&vec![0; media_data_len(tracks) as usize]);
+    writer.end_box(start);
+}
+
+fn media_data_len(tracks: &[TrackFixture]) -> u64 {
+    tracks
+        .iter()
+        .map(|track| track.sample_count as u64 * track.sample_size as u64)
+        .sum()
+}
+
+fn movie_box(tracks: &[TrackFixture], data_offset: u64, fragmented: bool) -> Vec<u8> {
+    let mut writer = Writer::new();
+    let start = writer.begin_box("moov");
+    let duration = tracks
+        .iter()
+        .map(|track| track.in_movie_timescale(track.duration()))
+        .max()
+        .unwrap_or(0);
+    MovieHeaderBox {
+        version: 0,
+        creation_time: Timestamp::Unset,
+        modification_time: Timestamp::Unset,
+        timescale: MOVIE_TIMESCALE,
+        duration: if fragmented { 0 } else { duration },
+        rate: 1.0,
+        volume: 1.0,
+        matrix: IDENTITY_MATRIX.to_vec(),
+        next_track_id: tracks.len() as u32 + 1,
+    }
+    .write(&mut writer);
+
+    let mut offset = data_offset;
+    for (i, track) in tracks.iter().enumerate() {
+        let samples = if fragmented {
+            Vec::new()
+        } else {
+            track.samples(offset)
+        };
+        offset += track.sample_count as u64 * track.sample_size as u64;
+        write_track(&mut writer, track, i as u32 + 1, &samples);
+    }
+
+    if fragmented {
+        let mvex = writer.begin_box("mvex");
+        for track_id in 1..=tracks.len() as u32 {
+            let trex = writer.begin_full_box("trex", 0, [0, 0, 0]);
+            writer.write_u32(track_id);
+            writer.write_u32(1); // default_sample_description_index
+            writer.write_u32(0); // default_sample_duration
+            writer.write_u32(0); // default_sample_size
+            writer.write_u32(0); // default_sample_flags
+            writer.end_box(trex);
+        }
+        writer.end_box(mvex);
+    }
+    writer.end_box(start);
+    writer.into_inner()
+}
+
+fn write_track(writer: &mut Writer, track: &TrackFixture, track_id: u32, samples: &[Sample]) {
+    let trak = writer.begin_box("trak");
+    let media_duration: u64 = samples.iter().map(|s| s.duration as u64).sum();
+    let (width, height, volume) = match track.kind {
+        TrackKind::Video { width, height } => (width as f32, height as f32, 0.0),
+        TrackKind::Audio { .. } | TrackKind::Pcm { .. } => (0.0, 0.0, 1.0),
+    };
+    TrackHeaderBox {
+        version: 0,
+        track_enabled: true,
+        track_in_movie: true,
+        track_in_preview: false,
+        creation_time: Timestamp::Unset,
+        modification_time: Timestamp::Unset,
+        track_id,
+        duration: track.in_movie_timescale(media_duration),
+        layer: 0,
+        alternate_group: 0,
+        volume,
+        matrix: IDENTITY_MATRIX.to_vec(),
+        width,
+        height,
+    }
+    .write(writer);
+
+    let mdia = writer.begin_box("mdia");
+    MediaHeaderBox {
+        version: 0,
+        creation_time: Timestamp::Unset,
+        modification_time: Timestamp::Unset,
+        timescale: track.timescale,
+        duration: media_duration,
+        language: "und".into(),
+        packed_language: pack_language("und"),
+    }
+    .write(writer);
+    let handler_type = match track.kind {
+        TrackKind::Video { .. } => "vide",
+        TrackKind::Audio { .. } | TrackKind::Pcm { .. } => "soun",
+    };
+    write_handler(writer, handler_type);
+
+    let minf = writer.begin_box("minf");
+    match track.kind {
+        TrackKind::Video { .. } => {
+            let vmhd = writer.begin_full_box("vmhd", 0, [0, 0, 1]);
+            writer.write_bytes(&[0; 8]); // graphicsmode and opcolor
+            writer.end_box(vmhd);
+        }
+        TrackKind::Audio { .. } | TrackKind::Pcm { .. } => {
+            let smhd = writer.begin_full_box("smhd", 0, [0, 0, 0]);
+            writer.write_u32(0); // balance and reserved
+            writer.end_box(smhd);
+        }
+    }
+    let dinf = writer.begin_box("dinf");
+    let dref = writer.begin_full_box("dref", 0, [0, 0, 0]);
+    writer.write_u32(1);
+    // The data is in this file
+    let url = writer.begin_full_box("url ", 0, [0, 0, 1]);
+    writer.end_box(url);
+    writer.end_box(dref);
+    writer.end_box(dinf);
+
+    let stbl = writer.begin_box("stbl");
+    let stsd = writer.begin_full_box("stsd", 0, [0, 0, 0]);
+    writer.write_u32(1);
+    write_sample_entry(writer, track);
+    writer.end_box(stsd);
+    SampleTable::from_samples(samples).write(writer);
+    writer.end_box(stbl);
+    writer.end_box(minf);
+    writer.end_box(mdia);
+    writer.end_box(trak);
+}
+
+fn write_handler(writer: &mut Writer, handler_type: &str) {
+    let hdlr = writer.begin_full_box("hdlr", 0, [0, 0, 0]);
+    writer.write_u32(0); // pre_defined
+    writer.write_string(handler_type);
+    writer.write_bytes(&[0; 4 * 3]); // reserved
+    writer.write_u8(0); // An empty name
+    writer.end_box(hdlr);
+}
+
+fn write_sample_entry(writer: &mut Writer, track: &TrackFixture) {
+    match track.kind {
+        TrackKind::Video { width, height } => {
+            let avc1 = writer.begin_box("avc1");
+            writer.write_bytes(&[0; 6]); // reserved
+            writer.write_u16(1); // data_reference_index
+            writer.write_bytes(&[0; 2 + 2 + 4 * 3]); // pre_defined and reserved
+            writer.write_u16(width);
+            writer.write_u16(height);
+            writer.write_fixed_point_16_16(72.0);
+            writer.write_fixed_point_16_16(72.0);
+            writer.write_u32(0); // reserved
+            writer.write_u16(1); // frame_count
+            writer.write_bytes(&[0; 32]); // An empty compressorname
+            writer.write_u16(0x0018); // depth
+            writer.write_i16(-1); // pre_defined
+
+            let avcc = writer.begin_box("avcC");
+            writer.write_u8(1); // configurationVersion
+            writer.write_u8(66); // Baseline profile
+            writer.write_u8(0xC0); // profile_compatibility
+            writer.write_u8(30); // Level 3.0
+            writer.write_u8(0xFF); // 4-byte NAL unit lengths
+            writer.write_u8(0xE0); // No SPS
+            writer.write_u8(0); // No PPS
+            writer.end_box(avcc);
+            writer.end_box(avc1);
+        }
+        TrackKind::Audio {
+            sample_rate,
+            channel_count,
+        } => {
+            let mp4a = writer.begin_box("mp4a");
+            writer.write_bytes(&[0; 6]); // reserved
+            writer.write_u16(1); // data_reference_index
+            writer.write_bytes(&[0; 4 * 2]); // reserved
+            writer.write_u16(channel_count);
+            writer.write_u16(16); // samplesize
+            writer.write_u32(0); // pre_defined and reserved
+            writer.write_u32(sample_rate << 16);
+
+            let esds = writer.begin_full_box("esds", 0, [0, 0, 0]);
+            let audio_specific_config = audio_specific_config(sample_rate, channel_count);
+            let decoder_specific_info_len = 2 + audio_specific_config.len() as u8;
+            let decoder_config_len = 13 + decoder_specific_info_len;
+            // ES_Descriptor, with ES_ID and flags, then SLConfigDescriptor at the end
+            writer.write_bytes(&[0x03, 3 + 2 + decoder_config_len + 3, 0, 1, 0]);
+            writer.write_bytes(&[0x04, decoder_config_len]);
+            writer.write_u8(0x40); // MPEG-4 audio
+            writer.write_u8(0x15); // Audio stream
+            writer.write_bytes(&[0; 3]); // bufferSizeDB
+            let bitrate = track.sample_size * 8 * track.timescale / track.sample_duration.max(1);
+            writer.write_u32(bitrate); // maxBitrate
+            writer.write_u32(bitrate); // avgBitrate
+            writer.write_bytes(&[0x05, audio_specific_config.len() as u8]);
+            writer.write_bytes(&audio_specific_config);
+            writer.write_bytes(&[0x06, 1, 0x02]);
+            writer.end_box(esds);
+            writer.end_box(mp4a);
+        }
+        TrackKind::Pcm {
+            sample_rate,
+            channel_count,
+        } => {
+            let twos = writer.begin_box("twos");
+            writer.write_bytes(&[0; 6]); // reserved
+            writer.write_u16(1); // data_reference_index
+            writer.write_bytes(&[0; 4 * 2]); // version 0, revision and vendor
+            writer.write_u16(channel_count);
+            writer.write_u16(16); // samplesize
+            writer.write_u32(0); // compression_id and packet_size
+            writer.write_u32(sample_rate << 16);
+            writer.end_box(twos);
+        }
+    }
+}
+
+/// AAC LC, with the sampling frequency index if the rate has one
+fn audio_specific_config(sample_rate: u32, channel_count: u16) -> Vec<u8> {
+    const RATES: [u32; 13] = [
+        96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+    ];
+    let audio_object_type = 2_u64;
+    let channel_configuration = channel_count.min(7) as u64;
+    let (bits, len) = match RATES.iter().position(|&rate| rate == sample_rate) {
+        Some(index) => (
+            audio_object_type << 11 | (index as u64) << 7 | channel_configuration << 3,
+            2,
+        ),
+        None => (
+            audio_object_type << 35
+                | 0xF << 31
+                | (sample_rate as u64) << 7
+                | channel_configuration << 3,
+            5,
+        ),
+    };
+    bits.to_be_bytes()[8 - len..].to_vec()
+}
+
+fn movie_fragment(
+    tracks: &[TrackFixture],
+    sequence_number: u32,
+    decode_times: &[u64],
+    data_offset: u32,
+) -> Vec<u8> {
+    let mut writer = Writer::new();
+    let moof = writer.begin_box("moof");
+    let mfhd = writer.begin_full_box("mfhd", 0, [0, 0, 0]);
+    writer.write_u32(sequence_number);
+    writer.end_box(mfhd);
+
+    let mut data_offset = data_offset;
+    for (i, track) in tracks.iter().enumerate() {
+        let traf = writer.begin_box("traf");
+        // default-base-is-moof
+        let tfhd = writer.begin_full_box("tfhd", 0, [0x02, 0, 0]);
+        writer.write_u32(i as u32 + 1);
+        writer.end_box(tfhd);
+        let tfdt = writer.begin_full_box("tfdt", 1, [0, 0, 0]);
+        writer.write_u64(decode_times[i]);
+        writer.end_box(tfdt);
+        // data-offset, and the duration, size and flags of each sample
+        let trun = writer.begin_full_box("trun", 0, [0, 0x07, 0x01]);
+        writer.write_u32(track.sample_count);
+        writer.write_i32(data_offset as i32);
+        for sample_index in 0..track.sample_count {
+            writer.write_u32(track.sample_duration);
+            writer.write_u32(track.sample_size);
+            let flags = if track.is_sync(sample_index) {
+                0
+            } else {
+                SAMPLE_IS_NON_SYNC_SAMPLE
+            };
+            writer.write_u32(flags);
+        }
+        writer.end_box(trun);
+        writer.end_box(traf);
+        data_offset += track.sample_count * track.sample_size;
+    }
+    writer.end_box(moof);
+    writer.into_inner()
+}
+
+fn image_metadata(
+    item_id: u16,
+    width: u32,
+    height: u32,
+    data_offset: u32,
+    data_len: u32,
+) -> Vec<u8> {
+    let mut writer = Writer::new();
+    let meta = writer.begin_full_box("meta", 0, [0, 0, 0]);
+    write_handler(&mut writer, "pict");
+
+    let pitm = writer.begin_full_box("pitm", 0, [0, 0, 0]);
+    writer.write_u16(item_id);
+    writer.end_box(pitm);
+
+    let iinf = writer.begin_full_box("iinf", 0, [0, 0, 0]);
+    writer.write_u16(1);
+    let infe = writer.begin_full_box("infe", 2, [0, 0, 0]);
+    writer.write_u16(item_id);
+    writer.write_u16(0); // item_protection_index
+    writer.write_string("hvc1");
+    writer.write_u8(0); // An empty name
+    writer.end_box(infe);
+    writer.end_box(iinf);
+
+    let iloc = writer.begin_full_box("iloc", 0, [0, 0, 0]);
+    writer.write_u8(0x44); // 4-byte offsets and lengths
+    writer.write_u8(0); // No base offset
+    writer.write_u16(1); // item_count
+    writer.write_u16(item_id);
+    writer.write_u16(0); // data_reference_index
+    writer.write_u16(1); // extent_count
+    writer.write_u32(data_offset);
+    writer.write_u32(data_len);
+    writer.end_box(iloc);
+
+    let iprp = writer.begin_box("iprp");
+    let ipco = writer.begin_box("ipco");
+    let hvcc = writer.begin_box("hvcC");
+    writer.write_u8(1); // configurationVersion
+    writer.write_u8(3); // Main Still Picture profile
+    writer.write_u32(0); // profile_compatibility_flags
+    writer.write_bytes(&[0; 6]); // constraint_indicator_flags
+    writer.write_u8(90); // Level 3.0
+    writer.write_u16(0xF000); // min_spatial_segmentation_idc
+    writer.write_u8(0xFC); // parallelismType
+    writer.write_u8(0xFD); // 4:2:0
+    writer.write_u8(0xF8); // 8-bit luma
+    writer.write_u8(0xF8); // 8-bit chroma
+    writer.write_u16(0); // avgFrameRate
+    writer.write_u8(0x0F); // 4-byte NAL unit lengths
+    writer.write_u8(0); // No parameter sets
+    writer.end_box(hvcc);
+    let ispe = writer.begin_full_box("ispe", 0, [0, 0, 0]);
+    writer.write_u32(width);
+    writer.write_u32(height);
+    writer.end_box(ispe);
+    writer.end_box(ipco);
+    let ipma = writer.begin_full_box("ipma", 0, [0, 0, 0]);
+    writer.write_u32(1); // entry_count
+    writer.write_u16(item_id);
+    writer.write_u8(2); // association_count
+    writer.write_u8(0x81); // hvcC, essential
+    writer.write_u8(0x02); // ispe
+    writer.end_box(ipma);
+    writer.end_box(iprp);
+
+    writer.end_box(meta);
+    writer.into_inner()
+}
@@ -0,0 +1,382 @@
+//! Box trees for fuzzing, see `fuzz/`. The trees are built from known box types, with the
+//! right nesting and mostly small field values, so that the fuzzer gets past the box headers and
+//! into the parsers. Sizes and counts are still wrong now and then.
+
+use std::backtrace::Backtrace;
+use std::sync::Once;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::box_stats::box_stats;
+use crate::edit::{trim, TimeRange};
+use crate::exit_code;
+use crate::logger::NullLogger;
+use crate::model::{Movie, SampleTable};
+use crate::reader::ParserOptions;
+use crate::rewrite::{rewrite_moov, top_level_boxes, TrackRewrite};
+use crate::summary::Summary;
+use crate::tree::{parse_mp4_with_options, BoxFilter};
+use crate::validate::check_box_structure;
+use crate::writer::Writer;
+
+const CONTAINERS: [&str; 14] = [
+    "moov", "trak", "edts", "mdia", "minf", "dinf", "stbl", "mvex", "moof", "traf", "mfra", "udta",
+    "iprp", "ipco",
+];
+
+/// Boxes that start with a version and flags, followed by an entry count and the entries
+const ENTRY_CONTAINERS: [&str; 3] = ["stsd", "dref", "iinf"];
+
+/// Full boxes whose children come right after the version and flags
+const FULL_BOX_CONTAINERS: [&str; 1] = ["meta"];
+
+const LEAVES: [&str; 49] = [
+    "ftyp", "styp", "free", "mdat", "mvhd", "tkhd", "elst", "mdhd", "hdlr", "vmhd", "smhd", "stts",
+    "stss", "ctts", "stsc", "stsz", "stco", "co64", "stdp", "padb", "sgpd", "sbgp", "sdtp", "mehd",
+    "sidx", "pssh", "senc", "saiz", "saio", "trex", "mfhd", "tfhd", "tfdt", "trun", "tfra", "mfro",
+    "ilst", "chpl", "cprt", "uuid", "pitm", "infe", "ispe", "ipma", "pixi", "irot", "auxC", "emsg",
+    "©day",
+];
+
+/// Sample entries, with the size of the fields before their child boxes
+const SAMPLE_ENTRIES: [(&str, usize); 19] = [
+    ("avc1", 78),
+    ("jpeg", 78),
+    ("mp4v", 78),
+    ("s263", 78),
+    ("hvc1", 78),
+    ("hev1", 78),
+    ("dvh1", 78),
+    ("encv", 78),
+    ("mp4a", 28),
+    ("enca", 28),
+    ("ac-4", 28),
+    ("twos", 28),
+    ("lpcm", 64),
+    ("tx3g", 38),
+    ("wvtt", 8),
+    ("mebx", 8),
+    ("mett", 8),
+    ("tmcd", 26),
+    ("url ", 4),
+];
+
+/// The boxes found in sample entries
+const SAMPLE_ENTRY_CHILDREN: [&str; 15] = [
+    "avcC", "hvcC", "dvcC", "dvvC", "esds", "sinf", "frma", "schm", "schi", "tenc", "mdcv", "clli",
+    "ftab", "vttC", "d263",
+];
+
+/// The memory budget while fuzzing. A tiny file can declare tables that fit in the default
+/// budget but would run the fuzzer out of memory.
+const MAX_ALLOC_BYTES: u64 = 16 << 20;
+
+/// Nested boxes are left out below this depth, to keep the trees small
+const MAX_DEPTH: usize = 8;
+
+/// A file for the fuzzer: a list of top-level boxes
+#[derive(Debug)]
+pub struct ArbitraryFile {
+    pub boxes: Vec<ArbitraryBox>,
+}
+
+#[derive(Debug)]
+pub struct ArbitraryBox {
+    pub box_type: String,
+    pub contents: Contents,
+    /// Added to the correct size when the header is written
+    pub size_error: i8,
+}
+
+#[derive(Debug)]
+pub enum Contents {
+    Children(Vec<ArbitraryBox>),
+    FullBox {
+        version: u8,
+        flags: [u8; 3],
+        fields: Vec<u32>,
+        tail: Vec<u8>,
+    },
+    FullBoxChildren {
+        version: u8,
+        flags: [u8; 3],
+        children: Vec<ArbitraryBox>,
+    },
+    Entries {
+        version: u8,
+        flags: [u8; 3],
+        entry_count: u32,
+        entries: Vec<ArbitraryBox>,
+    },
+    SampleEntry {
+        fields: Vec<u8>,
+        children: Vec<ArbitraryBox>,
+    },
+}
+
+impl<'a> Arbitrary<'a> for ArbitraryFile {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            boxes: arbitrary_boxes(u, 0, |u| {
+                // Top-level boxes are mostly the ones found there
+                Ok(*u.choose(&[
+                    "ftyp", "moov", "mdat", "free", "moof", "mfra", "meta", "styp",
+                ])?)
+            })?,
+        })
+    }
+}
+
+impl ArbitraryFile {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for b in &self.boxes {
+            b.write(&mut writer);
+        }
+        writer.into_inner()
+    }
+}
+
+impl ArbitraryBox {
+    fn arbitrary_with_type(u: &mut Unstructured, box_type: &str, depth: usize) -> Result<Self> {
+        let contents = if CONTAINERS.contains(&box_type) {
+            Contents::Children(arbitrary_boxes(u, depth + 1, |u| {
+                let types = if u.ratio(1, 3)? {
+                    &CONTAINERS[..]
+                } else {
+                    &LEAVES[..]
+                };
+                Ok(*u.choose(types)?)
+            })?)
+        } else if FULL_BOX_CONTAINERS.contains(&box_type) {
+            Contents::FullBoxChildren {
+                version: arbitrary_version(u)?,
+                flags: u.arbitrary()?,
+                children: arbitrary_boxes(u, depth + 1, |u| Ok(*u.choose(&LEAVES)?))?,
+            }
+        } else if ENTRY_CONTAINERS.contains(&box_type) {
+            let entries = arbitrary_boxes(u, depth + 1, |u| Ok(u.choose(&SAMPLE_ENTRIES)?.0))?;
+            Contents::Entries {
+                version: arbitrary_version(u)?,
+                flags: u.arbitrary()?,
+                entry_count: if u.ratio(1, 8)? {
+                    u.arbitrary()?
+                } else {
+                    entries.len() as u32
+                },
+                entries,
+            }
+        } else if let Some((_, fields_len)) = SAMPLE_ENTRIES.iter().find(|(t, _)| *t == box_type) {
+            let mut fields = vec![0; *fields_len];
+            u.fill_buffer(&mut fields)?;
+            Contents::SampleEntry {
+                fields,
+                children: arbitrary_boxes(
+                    u,
+                    depth + 1,
+                    |u| Ok(*u.choose(&SAMPLE_ENTRY_CHILDREN)?),
+                )?,
+            }
+        } else {
+            let fields_len = u.int_in_range(0..=16)?;
+            Contents::FullBox {
+                version: arbitrary_version(u)?,
+                flags: u.arbitrary()?,
+                fields: (0..fields_len)
+                    .map(|_| arbitrary_field(u))
+                    .collect::<Result<_>>()?,
+                tail: u.arbitrary()?,
+            }
+        };
+        Ok(Self {
+            box_type: box_type.to_string(),
+            contents,
+            size_error: if u.ratio(1, 16)? { u.arbitrary()? } else { 0 },
+        })
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        let start_offset = writer.begin_box(&self.box_type);
+        match &self.contents {
+            Contents::Children(children) => {
+                for child in children {
+                    child.write(writer);
+                }
+            }
+            Contents::FullBox {
+                version,
+                flags,
+                fields,
+                tail,
+            } => {
+                writer.write_u8(*version);
+                writer.write_bytes(flags);
+                for field in fields {
+                    writer.write_u32(*field);
+                }
+                writer.write_bytes(tail);
+            }
+            Contents::FullBoxChildren {
+                version,
+                flags,
+                children,
+            } => {
+                writer.write_u8(*version);
+                writer.write_bytes(flags);
+                for child in children {
+                    child.write(writer);
+                }
+            }
+            Contents::Entries {
+                version,
+                flags,
+                entry_count,
+                entries,
+            } => {
+                writer.write_u8(*version);
+                writer.write_bytes(flags);
+                writer.write_u32(*entry_count);
+                for entry in entries {
+                    entry.write(writer);
+                }
+            }
+            Contents::SampleEntry { fields, children } => {
+                writer.write_bytes(fields);
+                for child in children {
+                    child.write(writer);
+                }
+            }
+        }
+        writer.end_box(start_offset);
+        if self.size_error != 0 {
+            let size = (writer.position() - start_offset) as i64;
+            let wrong_size = (size + self.size_error as i64).max(0) as u32;
+            writer.patch_u32(start_offset, wrong_size);
+        }
+    }
+}
+
+fn arbitrary_boxes<'a>(
+    u: &mut Unstructured<'a>,
+    depth: usize,
+    choose_type: impl Fn(&mut Unstructured<'a>) -> Result<&'static str>,
+) -> Result<Vec<ArbitraryBox>> {
+    if depth >= MAX_DEPTH {
+        return Ok(Vec::new());
+    }
+    let mut boxes = Vec::new();
+    u.arbitrary_loop(None, Some(8), |u| {
+        // Now and then a box type that no parser knows
+        let box_type = if u.ratio(1, 32)? {
+            let bytes: [u8; 4] = u.arbitrary()?;
+            bytes.iter().map(|&b| b as char).collect()
+        } else {
+            choose_type(u)?.to_string()
+        };
+        boxes.push(ArbitraryBox::arbitrary_with_type(u, &box_type, depth)?);
+        Ok(std::ops::ControlFlow::Continue(()))
+    })?;
+    Ok(boxes)
+}
+
+/// Mostly 0 and 1, which are the versions that are defined
+fn arbitrary_version(u: &mut Unstructured) -> Result<u8> {
+    if u.ratio(1, 8)? {
+        u.arbitrary()
+    } else {
+        u.int_in_range(0..=1)
+    }
+}
+
+/// Mostly small values, which keeps entry counts within the box
+fn arbitrary_field(u: &mut Unstructured) -> Result<u32> {
+    if u.ratio(1, 4)? {
+        u.arbitrary()
+    } else {
+        u.int_in_range(0..=16)
+    }
+}
+
+/// Parses the file like `parse` and `info` do, and checks, counts and rewrites its boxes like
+/// `validate`, `parse --stats` and `edit` do. A parse error is expected for most inputs, but
+/// other panics, such as an index out of bounds or an arithmetic overflow, are bugs and abort
+/// the process so that the fuzzer reports them.
+pub fn parse_all(buf: &[u8]) {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        // Replaces the fuzzer's hook, which aborts on every panic
+        std::panic::set_hook(Box::new(|info| {
+            if is_bug(info.payload_as_str().unwrap_or_default()) {
+                eprintln!("{}\n{}", info, Backtrace::force_capture());
+                std::process::abort();
+            }
+        }))
+    });
+
+    let options = ParserOptions {
+        max_alloc_bytes: MAX_ALLOC_BYTES,
+    };
+    let _ = exit_code::catch(|| {
+        parse_mp4_with_options(
+            buf,
+            &mut NullLogger::default(),
+            &BoxFilter::default(),
+            &options,
+        )
+    });
+    let _ = exit_code::catch(|| check_box_structure(buf));
+    let _ = exit_code::catch(|| box_stats(buf));
+    // The writers parse the file without the smaller memory budget, so they only get the files
+    // that fit in it
+    let movie = match exit_code::catch(|| Movie::parse_with_options(buf, &options)) {
+        Ok(Ok(movie)) => movie,
+        _ => return,
+    };
+    let _ = exit_code::catch(|| Summary::new(buf, &movie));
+    let _ = exit_code::catch(|| rewrite_sample_tables(buf, &movie));
+    let range = TimeRange {
+        start: Some(0.1),
+        end: None,
+    };
+    let _ = exit_code::catch(|| trim(buf, &range));
+}
+
+/// Rebuilds the sample table of every track from its samples and writes the moov box again
+fn rewrite_sample_tables(buf: &[u8], movie: &Movie) {
+    let moov = match top_level_boxes(buf)
+        .into_iter()
+        .find(|b| b.box_type == "moov")
+    {
+        Some(moov) => moov,
+        None => return,
+    };
+    let tracks: Vec<Option<TrackRewrite>> = movie
+        .tracks
+        .iter()
+        .map(|track| {
+            Some(TrackRewrite {
+                sample_table: SampleTable::from_samples(&track.sample_table.samples()),
+                edits: None,
+            })
+        })
+        .collect();
+    rewrite_moov(buf, &moov, movie, &tracks, &mut Writer::new());
+}
+
+/// Whether the panic message comes from Rust itself rather than from a parser that found
+/// something wrong with the file
+pub fn is_bug(message: &str) -> bool {
+    [
+        "attempt to ",
+        "index out of bounds",
+        "range start index",
+        "range end index",
+        "slice index starts",
+        "byte index",
+        "called `Option::unwrap()`",
+        "called `Result::unwrap()`",
+        "capacity overflow",
+    ]
+    .iter()
+    .any(|prefix| message.starts_with(prefix))
+}
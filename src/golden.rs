@@ -0,0 +1,97 @@
+//! Comparison of a file's summary against a stored "golden" JSON file, so that changes in how an
+//! encoder or muxer packages its output are noticed
+
+use std::fmt;
+
+use serde_json::Value;
+
+/// A value that differs between the golden file and the parsed file
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// Where in the JSON the values differ, e.g. "tracks[1].sample_count"
+    pub path: String,
+    /// `None` if the golden file doesn't have the value
+    pub expected: Option<Value>,
+    /// `None` if the parsed file doesn't have the value
+    pub actual: Option<Value>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) => {
+                write!(f, "{}: expected {}, got {}", self.path, expected, actual)
+            }
+            (Some(expected), None) => write!(f, "{}: missing (expected {})", self.path, expected),
+            (None, Some(actual)) => write!(f, "{}: unexpected {}", self.path, actual),
+            (None, None) => write!(f, "{}", self.path),
+        }
+    }
+}
+
+/// Returns all the differences between `expected` and `actual`, with the innermost values that
+/// differ reported rather than whole objects
+pub fn compare(expected: &Value, actual: &Value) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    compare_at("", expected, actual, &mut mismatches);
+    mismatches
+}
+
+fn compare_at(path: &str, expected: &Value, actual: &Value, mismatches: &mut Vec<Mismatch>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected_value) in expected {
+                let child_path = join(path, key);
+                match actual.get(key) {
+                    Some(actual_value) => {
+                        compare_at(&child_path, expected_value, actual_value, mismatches)
+                    }
+                    None => mismatches.push(Mismatch {
+                        path: child_path,
+                        expected: Some(expected_value.clone()),
+                        actual: None,
+                    }),
+                }
+            }
+            for (key, actual_value) in actual {
+                if !expected.contains_key(key) {
+                    mismatches.push(Mismatch {
+                        path: join(path, key),
+                        expected: None,
+                        actual: Some(actual_value.clone()),
+                    });
+                }
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for i in 0..expected.len().max(actual.len()) {
+                let child_path = format!("{}[{}]", path, i);
+                match (expected.get(i), actual.get(i)) {
+                    (Some(e), Some(a)) => compare_at(&child_path, e, a, mismatches),
+                    (e, a) => mismatches.push(Mismatch {
+                        path: child_path,
+                        expected: e.cloned(),
+                        actual: a.cloned(),
+                    }),
+                }
+            }
+        }
+        _ => {
+            if expected != actual {
+                mismatches.push(Mismatch {
+                    path: if path.is_empty() { "(root)" } else { path }.to_string(),
+                    expected: Some(expected.clone()),
+                    actual: Some(actual.clone()),
+                });
+            }
+        }
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
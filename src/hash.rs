@@ -0,0 +1,130 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::ParseError;
+use crate::model::{Chunk, Movie, Track};
+use crate::progress::Progress;
+use crate::rewrite::top_level_boxes;
+
+/// Content hashes that make it possible to check whether two files contain the same media,
+/// even if they are packaged differently
+#[derive(Debug, Serialize)]
+pub struct FileHashes {
+    pub boxes: Vec<BoxHash>,
+    pub tracks: Vec<TrackHash>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BoxHash {
+    pub box_type: String,
+    pub offset: u64,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackHash {
+    pub track_id: u32,
+    pub handler_type: String,
+    pub sample_count: u32,
+    /// Hash of the track's samples concatenated in decoding order
+    pub sha256: String,
+}
+
+/// Hashes each top-level box, and the sample data of each track. Samples in movie fragments
+/// are not included in the track hashes.
+pub fn hash_file(buf: &[u8]) -> Result<FileHashes, ParseError> {
+    hash_file_with_progress(buf, &mut |_, _| {})
+}
+
+/// Like `hash_file`, but reports how many bytes have been hashed. As the sample data is hashed
+/// in addition to the boxes, the total is more than the size of the file.
+pub fn hash_file_with_progress(
+    buf: &[u8],
+    progress: &mut Progress,
+) -> Result<FileHashes, ParseError> {
+    let top_level_boxes = top_level_boxes(buf);
+    let has_moov = top_level_boxes.iter().any(|b| b.box_type == "moov");
+    let tracks_with_chunks: Vec<(Track, Vec<Chunk>)> = if has_moov {
+        Movie::parse(buf)?
+            .tracks
+            .into_iter()
+            .map(|track| {
+                let chunks = track.sample_table.chunks().collect();
+                (track, chunks)
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let total = buf.len() as u64
+        + tracks_with_chunks
+            .iter()
+            .flat_map(|(_, chunks)| chunks.iter().map(|chunk| chunk.size))
+            .sum::<u64>();
+    let mut done = 0;
+
+    let mut boxes = Vec::new();
+    for header in top_level_boxes {
+        let end = (header.start_offset + header.box_size).min(buf.len() as u64);
+        boxes.push(BoxHash {
+            sha256: sha256_hex(&[&buf[header.start_offset as usize..end as usize]]),
+            box_type: header.box_type,
+            offset: header.start_offset,
+            size: header.box_size,
+        });
+        done += end - header.start_offset;
+        progress(done, total);
+    }
+
+    let mut tracks = Vec::new();
+    for (track, chunks) in &tracks_with_chunks {
+        let mut hasher = Sha256::new();
+        let mut sample_count = 0;
+        for chunk in chunks {
+            let data = buf
+                .get(chunk.offset as usize..)
+                .and_then(|data| data.get(..chunk.size as usize));
+            match data {
+                Some(data) => hasher.update(data),
+                None => {
+                    // Samples beyond the end of a truncated file are left out
+                    let mut offset = chunk.offset as usize;
+                    for &size in &track.sample_table.sample_sizes[chunk.samples.clone()] {
+                        let end = offset.saturating_add(size as usize);
+                        hasher.update(buf.get(offset..end).unwrap_or(&[]));
+                        offset = end;
+                    }
+                }
+            }
+            sample_count += chunk.samples.len();
+            done += chunk.size;
+            progress(done, total);
+        }
+        tracks.push(TrackHash {
+            track_id: track.id,
+            handler_type: track.handler_type.clone(),
+            sample_count: sample_count as u32,
+            sha256: finalize_hex(hasher),
+        });
+    }
+    progress(total, total);
+
+    Ok(FileHashes { boxes, tracks })
+}
+
+fn sha256_hex(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    finalize_hex(hasher)
+}
+
+fn finalize_hex(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -0,0 +1,481 @@
+//! Items and their properties, as used by HEIF and AVIF images instead of tracks
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use serde::Serialize;
+
+use crate::boxes::{FullBoxHeader, Mp4Box};
+use crate::reader::Reader;
+
+/// The auxiliary types of alpha planes, for AVIF and HEIC
+const ALPHA_AUX_TYPES: [&str; 2] = [
+    "urn:mpeg:mpegB:cicp:systems:auxiliary:alpha",
+    "urn:mpeg:hevc:2015:auxid:1",
+];
+
+/// An image, or other data such as Exif, stored in the file
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub id: u32,
+    /// E.g. "av01", "hvc1", "grid" or "Exif"
+    pub item_type: String,
+    pub name: String,
+    /// In the order of the item's associations in ipma
+    pub properties: Vec<ItemProperty>,
+}
+
+impl Item {
+    /// Width and height, from ispe
+    pub fn size(&self) -> Option<(u32, u32)> {
+        self.properties.iter().find_map(|p| match p {
+            ItemProperty::ImageSpatialExtents(ispe) => Some((ispe.width, ispe.height)),
+            _ => None,
+        })
+    }
+
+    /// Bits per channel, from pixi. Channels with different bit depths are rare, so the first
+    /// one is used.
+    pub fn bit_depth(&self) -> Option<u8> {
+        self.properties.iter().find_map(|p| match p {
+            ItemProperty::PixelInformation(pixi) => pixi.bits_per_channel.first().copied(),
+            _ => None,
+        })
+    }
+
+    /// Whether the item is an alpha plane for another image (auxC)
+    pub fn is_alpha(&self) -> bool {
+        self.properties.iter().any(|p| match p {
+            ItemProperty::AuxiliaryType(aux_c) => {
+                ALPHA_AUX_TYPES.contains(&aux_c.aux_type.as_str())
+            }
+            _ => false,
+        })
+    }
+
+    /// Anti-clockwise rotation in degrees, from irot
+    pub fn rotation(&self) -> u16 {
+        self.properties
+            .iter()
+            .find_map(|p| match p {
+                ItemProperty::ImageRotation(irot) => Some(irot.degrees()),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    /// From imir
+    pub fn mirror(&self) -> Option<MirrorAxis> {
+        self.properties.iter().find_map(|p| match p {
+            ItemProperty::ImageMirror(imir) => Some(imir.axis),
+            _ => None,
+        })
+    }
+
+    /// The rotations and mirrorings, in the order that they are applied (that of the item's
+    /// properties), e.g. ["rotate 90° anti-clockwise", "mirror left-right"]
+    pub fn transformations(&self) -> Vec<String> {
+        self.properties
+            .iter()
+            .filter_map(|p| match p {
+                ItemProperty::ImageRotation(irot) if irot.degrees() != 0 => {
+                    Some(format!("rotate {}° anti-clockwise", irot.degrees()))
+                }
+                ItemProperty::ImageMirror(imir) => Some(format!("mirror {}", imir.axis)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Number of layers, from a1lx. Images without it have one layer.
+    pub fn layer_count(&self) -> usize {
+        self.properties
+            .iter()
+            .find_map(|p| match p {
+                ItemProperty::LayeredImageIndexing(a1lx) => Some(a1lx.layer_count()),
+                _ => None,
+            })
+            .unwrap_or(1)
+    }
+}
+
+/// A property from ipco, e.g. the size of an image
+#[derive(Debug, Clone)]
+pub enum ItemProperty {
+    ImageSpatialExtents(ImageSpatialExtentsProperty),
+    PixelInformation(PixelInformationProperty),
+    AuxiliaryType(AuxiliaryTypeProperty),
+    LayeredImageIndexing(LayeredImageIndexingProperty),
+    ImageRotation(ImageRotationProperty),
+    ImageMirror(ImageMirrorProperty),
+    /// A property that isn't parsed, e.g. a codec configuration (av1C, hvcC)
+    Other(String),
+}
+
+impl ItemProperty {
+    pub fn from_box(box_type: &str, box_: Mp4Box) -> Self {
+        match box_ {
+            Mp4Box::Ispe(b) => ItemProperty::ImageSpatialExtents(b),
+            Mp4Box::Pixi(b) => ItemProperty::PixelInformation(b),
+            Mp4Box::AuxC(b) => ItemProperty::AuxiliaryType(b),
+            Mp4Box::A1lx(b) => ItemProperty::LayeredImageIndexing(b),
+            Mp4Box::Irot(b) => ItemProperty::ImageRotation(b),
+            Mp4Box::Imir(b) => ItemProperty::ImageMirror(b),
+            _ => ItemProperty::Other(box_type.to_string()),
+        }
+    }
+}
+
+/// Reads a NUL-terminated string that ends before `end_offset`
+pub(crate) fn read_c_string(reader: &mut Reader, end_offset: u64) -> String {
+    let mut bytes = Vec::new();
+    while reader.position() < end_offset {
+        match reader.read_u8() {
+            0 => break,
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// pitm
+#[derive(Debug)]
+pub struct PrimaryItemBox {
+    pub item_id: u32,
+}
+
+impl PrimaryItemBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let item_id = if full_box.version == 0 {
+            reader.read_u16() as u32
+        } else {
+            reader.read_u32()
+        };
+        Self { item_id }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Item ID", &self.item_id);
+    }
+}
+
+/// iinf. The entries (infe) follow as child boxes.
+pub fn parse_item_info_header(reader: &mut Reader) -> u32 {
+    let full_box = FullBoxHeader::parse(reader);
+    if full_box.version == 0 {
+        reader.read_u16() as u32
+    } else {
+        reader.read_u32()
+    }
+}
+
+/// infe
+#[derive(Debug)]
+pub struct ItemInfoEntry {
+    pub item_id: u32,
+    pub item_protection_index: u16,
+    /// Only in version 2 and later
+    pub item_type: String,
+    pub item_name: String,
+}
+
+impl ItemInfoEntry {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let end_offset = reader.position() + inner_size;
+        let full_box = FullBoxHeader::parse(reader);
+        let item_id = if full_box.version == 3 {
+            reader.read_u32()
+        } else {
+            reader.read_u16() as u32
+        };
+        let item_protection_index = reader.read_u16();
+        let item_type = if full_box.version >= 2 {
+            reader.read_string(4)
+        } else {
+            String::new()
+        };
+        let item_name = read_c_string(reader, end_offset);
+        // Followed by the content type of 'mime' items, or the URI of 'uri ' items
+        let remaining = end_offset.saturating_sub(reader.position());
+        reader.skip(remaining);
+        Self {
+            item_id,
+            item_protection_index,
+            item_type,
+            item_name,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Item ID", &self.item_id);
+        print("Protection index", &self.item_protection_index);
+        print("Item type", &self.item_type);
+        print("Item name", &self.item_name);
+    }
+}
+
+/// ipma
+#[derive(Debug)]
+pub struct ItemPropertyAssociationBox {
+    pub associations: Vec<ItemPropertyAssociation>,
+}
+
+#[derive(Debug)]
+pub struct ItemPropertyAssociation {
+    pub item_id: u32,
+    /// 1-based indices into ipco, and whether the property is essential
+    pub properties: Vec<(u16, bool)>,
+}
+
+impl ItemPropertyAssociationBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let large_indices = full_box.flags_u32() & 1 != 0;
+        let entry_count = reader.read_u32();
+        let entry_size = core::mem::size_of::<ItemPropertyAssociation>();
+        let entry_count = if reader.reserve(entry_count as u64, entry_size, "ipma") {
+            entry_count
+        } else {
+            0
+        };
+        let associations = (0..entry_count)
+            .map(|_| {
+                let item_id = if full_box.version == 0 {
+                    reader.read_u16() as u32
+                } else {
+                    reader.read_u32()
+                };
+                let association_count = reader.read_u8();
+                let properties = (0..association_count)
+                    .map(|_| {
+                        if large_indices {
+                            let value = reader.read_u16();
+                            (value & 0x7FFF, value & 0x8000 != 0)
+                        } else {
+                            let value = reader.read_u8();
+                            ((value & 0x7F) as u16, value & 0x80 != 0)
+                        }
+                    })
+                    .collect();
+                ItemPropertyAssociation {
+                    item_id,
+                    properties,
+                }
+            })
+            .collect();
+        Self { associations }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        for association in &self.associations {
+            let properties: Vec<String> = association
+                .properties
+                .iter()
+                .map(|&(index, essential)| {
+                    if essential {
+                        format!("{} (essential)", index)
+                    } else {
+                        index.to_string()
+                    }
+                })
+                .collect();
+            print(
+                &format!("Item {}", association.item_id),
+                &format!("properties {}", properties.join(", ")),
+            );
+        }
+    }
+}
+
+/// ispe
+#[derive(Debug, Clone)]
+pub struct ImageSpatialExtentsProperty {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageSpatialExtentsProperty {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        FullBoxHeader::parse(reader);
+        let width = reader.read_u32();
+        let height = reader.read_u32();
+        Self { width, height }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Width", &self.width);
+        print("Height", &self.height);
+    }
+}
+
+/// pixi
+#[derive(Debug, Clone)]
+pub struct PixelInformationProperty {
+    pub bits_per_channel: Vec<u8>,
+}
+
+impl PixelInformationProperty {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        FullBoxHeader::parse(reader);
+        let channel_count = reader.read_u8();
+        let bits_per_channel = reader.read_bytes(channel_count as usize);
+        Self { bits_per_channel }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Bits per channel", &format!("{:?}", self.bits_per_channel));
+    }
+}
+
+/// auxC
+#[derive(Debug, Clone)]
+pub struct AuxiliaryTypeProperty {
+    /// A URN, e.g. "urn:mpeg:mpegB:cicp:systems:auxiliary:alpha"
+    pub aux_type: String,
+    pub aux_subtype: Vec<u8>,
+}
+
+impl AuxiliaryTypeProperty {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let end_offset = reader.position() + inner_size;
+        FullBoxHeader::parse(reader);
+        let aux_type = read_c_string(reader, end_offset);
+        let aux_subtype = reader.read_bytes(end_offset.saturating_sub(reader.position()) as usize);
+        Self {
+            aux_type,
+            aux_subtype,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Auxiliary type", &self.aux_type);
+        if !self.aux_subtype.is_empty() {
+            print("Subtype", &format!("{} bytes", self.aux_subtype.len()));
+        }
+    }
+}
+
+/// a1lx
+#[derive(Debug, Clone)]
+pub struct LayeredImageIndexingProperty {
+    /// The sizes of all layers but the last one, which takes up the rest of the item. Unused
+    /// sizes are 0.
+    pub layer_sizes: [u32; 3],
+}
+
+impl LayeredImageIndexingProperty {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let large_size = reader.read_u8() & 1 != 0;
+        let mut layer_sizes = [0; 3];
+        for size in layer_sizes.iter_mut() {
+            *size = if large_size {
+                reader.read_u32()
+            } else {
+                reader.read_u16() as u32
+            };
+        }
+        Self { layer_sizes }
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layer_sizes.iter().filter(|&&size| size > 0).count() + 1
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Layer sizes", &format!("{:?}", self.layer_sizes));
+        print("# layers", &self.layer_count());
+    }
+}
+
+/// irot
+#[derive(Debug, Clone)]
+pub struct ImageRotationProperty {
+    /// In steps of 90 degrees, anti-clockwise
+    pub angle: u8,
+}
+
+impl ImageRotationProperty {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let angle = reader.read_u8() & 0b11;
+        Self { angle }
+    }
+
+    pub fn degrees(&self) -> u16 {
+        self.angle as u16 * 90
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Rotation", &format!("{}° anti-clockwise", self.degrees()));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MirrorAxis {
+    /// Flips left and right
+    Vertical,
+    /// Flips top and bottom
+    Horizontal,
+}
+
+impl fmt::Display for MirrorAxis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MirrorAxis::Vertical => write!(f, "left-right"),
+            MirrorAxis::Horizontal => write!(f, "top-bottom"),
+        }
+    }
+}
+
+/// imir
+#[derive(Debug, Clone)]
+pub struct ImageMirrorProperty {
+    pub axis: MirrorAxis,
+}
+
+impl ImageMirrorProperty {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let axis = if reader.read_u8() & 1 == 0 {
+            MirrorAxis::Vertical
+        } else {
+            MirrorAxis::Horizontal
+        };
+        Self { axis }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Mirror", &self.axis);
+    }
+}
@@ -1,4 +1,70 @@
+//! Without the default `std` feature, the crate is `no_std` and only needs an allocator. The box
+//! parsers (`boxes`, with `reader::Reader` over a byte slice) and `model::Movie` are then still
+//! there, but not the modules that print, read files or analyze the parsed movie.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod analysis;
+pub mod box_path;
+#[cfg(feature = "std")]
+pub mod box_stats;
 pub mod boxes;
+pub mod brand;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod diagnostics;
+#[cfg(feature = "std")]
+pub mod edit;
+pub mod encryption;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod exit_code;
+#[cfg(feature = "std")]
+pub mod extract;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+#[cfg(feature = "std")]
+pub mod golden;
+#[cfg(feature = "std")]
+pub mod hash;
+pub mod heif;
+#[cfg(feature = "std")]
 pub mod logger;
+pub mod media_time;
+pub mod model;
+pub mod piff;
+#[cfg(feature = "std")]
+pub mod probe;
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
 pub mod quicktime;
 pub mod reader;
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod repair;
+#[cfg(feature = "std")]
+pub mod rewrite;
+#[cfg(feature = "std")]
+pub mod round_trip;
+#[cfg(feature = "std")]
+pub mod seek;
+pub mod spec;
+pub mod subtitles;
+#[cfg(feature = "std")]
+pub mod summary;
+pub mod timed_metadata;
+#[cfg(feature = "std")]
+pub mod tree;
+#[cfg(feature = "std")]
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod writer;
+#[cfg(feature = "std")]
+pub mod xmp;
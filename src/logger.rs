@@ -1,4 +1,7 @@
 use std::fmt::Display;
+use std::io::{Stdout, Write};
+
+use serde::Serialize;
 
 pub type LogLevel = u32;
 pub const LOG_LEVEL_NONE: LogLevel = 0;
@@ -6,65 +9,478 @@ pub const LOG_LEVEL_INFO: LogLevel = 1;
 pub const LOG_LEVEL_DEBUG: LogLevel = 2;
 pub const LOG_LEVEL_TRACE: LogLevel = 3;
 
-pub struct Logger {
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_BOLD: &str = "\x1b[1m";
+const ANSI_DIM: &str = "\x1b[2m";
+const ANSI_CYAN: &str = "\x1b[36m";
+const ANSI_YELLOW: &str = "\x1b[33m";
+const ANSI_RED: &str = "\x1b[31m";
+
+/// The target of the records that are emitted by `FacadeLogger`
+pub const LOG_TARGET: &str = "mp4_parser";
+
+/// Receives the output of `tree::parse_mp4`. Boxes are logged one after another, and the
+/// indentation is increased while the children of a box are logged.
+pub trait Logger {
+    fn debug(&mut self, text: &str);
+
+    /// Warnings are counted even if the verbosity is too low for them to be shown
+    fn warn(&mut self, text: &str);
+
+    fn warning_count(&self) -> usize;
+
+    fn error(&mut self, text: &str);
+
+    fn log_start_of_box(&mut self, file_offset: u64);
+
+    fn debug_box_header(&mut self, box_type: &str, box_size: u64);
+
+    fn log_box_title(&mut self, text: &str);
+
+    fn debug_box(&mut self, text: &str);
+
+    fn trace_box(&mut self, text: &str);
+
+    fn debug_box_attr(&mut self, label: &str, value: &dyn Display) {
+        self.debug_box_attrs(&[(label.to_string(), value.to_string())]);
+    }
+
+    fn debug_box_attrs(&mut self, attributes: &[(String, String)]);
+
+    fn increase_indent(&mut self);
+
+    fn decrease_indent(&mut self);
+
+    /// The payload of an unknown box, or the bytes at the end of a box that weren't parsed.
+    /// Ignored by default, but applications can implement this to decode vendor boxes.
+    fn raw_data(&mut self, _box_type: &str, _offset: u64, _data: &[u8]) {}
+}
+
+/// Prints indented, and possibly colored, lines on stdout
+pub type ConsoleLogger = TextLogger<Stdout>;
+
+/// Keeps the lines that `ConsoleLogger` would have printed, e.g. for tests
+pub type CollectingLogger = TextLogger<Vec<u8>>;
+
+/// Writes the output as indented lines
+pub struct TextLogger<W: Write> {
+    out: W,
     verbosity: LogLevel,
     indent: usize,
+    color: bool,
+    warnings_on_stderr: bool,
+    warning_count: usize,
+    hex_offsets: bool,
+    relative_offsets: bool,
+    /// The offset of the last box that was started
+    box_offset: u64,
+    /// The offsets of the boxes whose children are being logged, innermost last
+    parent_offsets: Vec<u64>,
+}
+
+impl ConsoleLogger {
+    pub fn new(verbosity: LogLevel) -> Self {
+        TextLogger::with_writer(std::io::stdout(), verbosity)
+    }
 }
 
-impl Logger {
+impl CollectingLogger {
     pub fn new(verbosity: LogLevel) -> Self {
+        TextLogger::with_writer(Vec::new(), verbosity)
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        String::from_utf8_lossy(&self.out)
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
+}
+
+impl<W: Write> TextLogger<W> {
+    pub fn with_writer(out: W, verbosity: LogLevel) -> Self {
         Self {
+            out,
             verbosity,
             indent: 4,
+            color: false,
+            warnings_on_stderr: false,
+            warning_count: 0,
+            hex_offsets: false,
+            relative_offsets: false,
+            box_offset: 0,
+            parent_offsets: Vec::new(),
+        }
+    }
+
+    /// Enables ANSI colors in the output
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Also prints warnings and errors on stderr, e.g. when the output is written to a file
+    pub fn with_warnings_on_stderr(mut self, warnings_on_stderr: bool) -> Self {
+        self.warnings_on_stderr = warnings_on_stderr;
+        self
+    }
+
+    /// Prints box offsets in hex, e.g. [0x0000671C], like hex editors do
+    pub fn with_hex_offsets(mut self, hex_offsets: bool) -> Self {
+        self.hex_offsets = hex_offsets;
+        self
+    }
+
+    /// Prints box offsets relative to the box they're printed in, e.g. [+8]. Top-level boxes
+    /// still get their file offset.
+    pub fn with_relative_offsets(mut self, relative_offsets: bool) -> Self {
+        self.relative_offsets = relative_offsets;
+        self
+    }
+
+    fn format_offset(&self, file_offset: u64) -> String {
+        let (sign, offset) = match self.parent_offsets.last() {
+            Some(&parent_offset) if self.relative_offsets => ("+", file_offset - parent_offset),
+            _ => ("", file_offset),
+        };
+        if self.hex_offsets {
+            format!("[{}{:#010X}]", sign, offset)
+        } else {
+            format!("[{}{}]", sign, offset)
+        }
+    }
+
+    fn paint(&self, style: &str, text: impl Display) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, ANSI_RESET)
+        } else {
+            text.to_string()
         }
     }
 
-    pub fn debug(&self, text: impl Display) {
+    fn write_line(&mut self, line: impl Display) {
+        writeln!(self.out, "{}", line).unwrap();
+    }
+
+    /// Writes the text indented, after the vertical line that marks the current box
+    fn write_box_line(&mut self, text: impl Display) {
+        let line = format!("{:indent$}| {}", "", text, indent = self.indent);
+        self.write_line(line);
+    }
+}
+
+impl<W: Write> Logger for TextLogger<W> {
+    fn debug(&mut self, text: &str) {
         if self.verbosity >= LOG_LEVEL_DEBUG {
-            println!("{}", text);
+            self.write_line(text);
+        }
+    }
+
+    fn warn(&mut self, text: &str) {
+        self.warning_count += 1;
+        if self.warnings_on_stderr {
+            eprintln!("WARN: {}", text);
+        }
+        if self.verbosity >= LOG_LEVEL_INFO {
+            let text = self.paint(ANSI_YELLOW, format!("WARN: {}", text));
+            self.write_box_line(text);
+        }
+    }
+
+    fn warning_count(&self) -> usize {
+        self.warning_count
+    }
+
+    fn error(&mut self, text: &str) {
+        if self.warnings_on_stderr {
+            eprintln!("ERROR: {}", text);
         }
+        let text = self.paint(ANSI_RED, format!("ERROR: {}", text));
+        self.write_box_line(text);
     }
 
-    pub fn log_start_of_box(&self, file_offset: u64) {
+    fn log_start_of_box(&mut self, file_offset: u64) {
+        self.box_offset = file_offset;
         if self.verbosity >= LOG_LEVEL_DEBUG {
-            println!("[{}]", file_offset);
-            println!(
-                "{:indent$}+----------------------------",
+            let offset = self.paint(ANSI_DIM, self.format_offset(file_offset));
+            self.write_line(offset);
+            let line = format!(
+                "{:indent$}{}",
                 "",
+                self.paint(ANSI_DIM, "+----------------------------"),
                 indent = self.indent
             );
+            self.write_line(line);
+        }
+    }
+
+    fn debug_box_header(&mut self, box_type: &str, box_size: u64) {
+        if self.verbosity >= LOG_LEVEL_DEBUG {
+            let box_type = self.paint(ANSI_CYAN, format!("{:?}", box_type));
+            self.write_box_line(format!("{} ({} bytes)", box_type, box_size));
         }
     }
 
-    pub fn log_box_title(&self, text: impl AsRef<str>) {
+    fn log_box_title(&mut self, text: &str) {
         if self.verbosity >= LOG_LEVEL_INFO {
-            println!("{:indent$}| {}", "", text.as_ref(), indent = self.indent);
+            let text = self.paint(ANSI_BOLD, text);
+            self.write_box_line(text);
         }
     }
 
-    pub fn debug_box(&self, text: impl AsRef<str>) {
+    fn debug_box(&mut self, text: &str) {
         if self.verbosity >= LOG_LEVEL_DEBUG {
-            println!("{:indent$}| {}", "", text.as_ref(), indent = self.indent);
+            self.write_box_line(text);
         }
     }
 
-    pub fn trace_box(&self, text: impl AsRef<str>) {
+    fn trace_box(&mut self, text: &str) {
         if self.verbosity >= LOG_LEVEL_TRACE {
-            println!("{:indent$}| {}", "", text.as_ref(), indent = self.indent);
+            self.write_box_line(text);
         }
     }
 
-    pub fn debug_box_attr(&self, label: &str, value: &dyn Display) {
+    /// Writes the attributes of a box with their values aligned in one column
+    fn debug_box_attrs(&mut self, attributes: &[(String, String)]) {
         if self.verbosity >= LOG_LEVEL_DEBUG {
-            println!("{:indent$}| {}: {}", "", label, value, indent = self.indent);
+            let width = attributes.iter().map(|(k, _)| k.len()).max().unwrap_or(0) + 1;
+            for (label, value) in attributes {
+                let text = format!("{:width$} {}", format!("{}:", label), value, width = width);
+                self.write_box_line(text);
+            }
         }
     }
 
-    pub fn increase_indent(&mut self) {
+    fn increase_indent(&mut self) {
         self.indent += 4;
+        self.parent_offsets.push(self.box_offset);
     }
 
-    pub fn decrease_indent(&mut self) {
+    fn decrease_indent(&mut self) {
         self.indent -= 4;
+        self.parent_offsets.pop();
+    }
+}
+
+/// Discards all output, but still counts the warnings
+#[derive(Default)]
+pub struct NullLogger {
+    warning_count: usize,
+}
+
+impl Logger for NullLogger {
+    fn debug(&mut self, _text: &str) {}
+
+    fn warn(&mut self, _text: &str) {
+        self.warning_count += 1;
+    }
+
+    fn warning_count(&self) -> usize {
+        self.warning_count
+    }
+
+    fn error(&mut self, _text: &str) {}
+
+    fn log_start_of_box(&mut self, _file_offset: u64) {}
+
+    fn debug_box_header(&mut self, _box_type: &str, _box_size: u64) {}
+
+    fn log_box_title(&mut self, _text: &str) {}
+
+    fn debug_box(&mut self, _text: &str) {}
+
+    fn trace_box(&mut self, _text: &str) {}
+
+    fn debug_box_attrs(&mut self, _attributes: &[(String, String)]) {}
+
+    fn increase_indent(&mut self) {}
+
+    fn decrease_indent(&mut self) {}
+}
+
+/// Emits records through the `log` crate, so that applications that embed the parser decide
+/// what is shown and where. The verbosity is left to the application's log filter.
+#[derive(Default)]
+pub struct FacadeLogger {
+    warning_count: usize,
+}
+
+impl Logger for FacadeLogger {
+    fn debug(&mut self, text: &str) {
+        log::debug!(target: LOG_TARGET, "{}", text);
+    }
+
+    fn warn(&mut self, text: &str) {
+        self.warning_count += 1;
+        log::warn!(target: LOG_TARGET, "{}", text);
+    }
+
+    fn warning_count(&self) -> usize {
+        self.warning_count
+    }
+
+    fn error(&mut self, text: &str) {
+        log::error!(target: LOG_TARGET, "{}", text);
+    }
+
+    fn log_start_of_box(&mut self, file_offset: u64) {
+        log::trace!(target: LOG_TARGET, "Box at offset {}", file_offset);
+    }
+
+    fn debug_box_header(&mut self, box_type: &str, box_size: u64) {
+        log::debug!(target: LOG_TARGET, "Box {:?} ({} bytes)", box_type, box_size);
+    }
+
+    fn log_box_title(&mut self, text: &str) {
+        log::info!(target: LOG_TARGET, "{}", text);
+    }
+
+    fn debug_box(&mut self, text: &str) {
+        log::debug!(target: LOG_TARGET, "{}", text);
+    }
+
+    fn trace_box(&mut self, text: &str) {
+        log::trace!(target: LOG_TARGET, "{}", text);
+    }
+
+    fn debug_box_attrs(&mut self, attributes: &[(String, String)]) {
+        for (label, value) in attributes {
+            log::debug!(target: LOG_TARGET, "{}: {}", label, value);
+        }
+    }
+
+    fn increase_indent(&mut self) {}
+
+    fn decrease_indent(&mut self) {}
+}
+
+/// A box, or an entry such as a sample description, as collected by `JsonLogger`
+#[derive(Debug, Default, Serialize)]
+pub struct JsonBox {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub box_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    pub name: String,
+    /// Label and value, in the order they're printed
+    pub attributes: Vec<(String, String)>,
+    /// E.g. the fragment summary after a moof box
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<JsonBox>,
+}
+
+/// Collects the boxes as a tree that can be serialized, e.g. for web tools
+#[derive(Default)]
+pub struct JsonLogger {
+    /// The boxes at each level, down to the children of the box that is being logged
+    levels: Vec<Vec<JsonBox>>,
+    /// Warnings and errors that came before any box
+    messages: Vec<String>,
+    warning_count: usize,
+}
+
+impl JsonLogger {
+    pub fn new() -> Self {
+        Self {
+            levels: vec![Vec::new()],
+            ..Self::default()
+        }
+    }
+
+    /// The top-level boxes, and any warnings that weren't about a box
+    pub fn into_boxes(mut self) -> (Vec<JsonBox>, Vec<String>) {
+        while self.levels.len() > 1 {
+            self.decrease_indent();
+        }
+        (self.levels.pop().unwrap_or_default(), self.messages)
+    }
+
+    fn current_box(&mut self) -> Option<&mut JsonBox> {
+        self.levels.last_mut().and_then(|level| level.last_mut())
+    }
+
+    fn add_message(&mut self, text: String) {
+        match self.current_box() {
+            Some(box_) => box_.warnings.push(text),
+            None => self.messages.push(text),
+        }
+    }
+}
+
+impl Logger for JsonLogger {
+    fn debug(&mut self, _text: &str) {}
+
+    fn warn(&mut self, text: &str) {
+        self.warning_count += 1;
+        self.add_message(format!("WARN: {}", text));
+    }
+
+    fn warning_count(&self) -> usize {
+        self.warning_count
+    }
+
+    fn error(&mut self, text: &str) {
+        self.add_message(format!("ERROR: {}", text));
+    }
+
+    fn log_start_of_box(&mut self, file_offset: u64) {
+        if let Some(level) = self.levels.last_mut() {
+            level.push(JsonBox {
+                offset: Some(file_offset),
+                ..JsonBox::default()
+            });
+        }
+    }
+
+    fn debug_box_header(&mut self, box_type: &str, box_size: u64) {
+        if let Some(box_) = self.current_box() {
+            box_.box_type = Some(box_type.to_string());
+            box_.size = Some(box_size);
+        }
+    }
+
+    fn log_box_title(&mut self, text: &str) {
+        if let Some(box_) = self.current_box() {
+            if box_.name.is_empty() {
+                box_.name = text.to_string();
+            } else {
+                box_.notes.push(text.to_string());
+            }
+        }
+    }
+
+    fn debug_box(&mut self, text: &str) {
+        if let Some(level) = self.levels.last_mut() {
+            level.push(JsonBox {
+                name: text.to_string(),
+                ..JsonBox::default()
+            });
+        }
+    }
+
+    fn trace_box(&mut self, _text: &str) {}
+
+    fn debug_box_attrs(&mut self, attributes: &[(String, String)]) {
+        if let Some(box_) = self.current_box() {
+            box_.attributes.extend_from_slice(attributes);
+        }
+    }
+
+    fn increase_indent(&mut self) {
+        self.levels.push(Vec::new());
+    }
+
+    fn decrease_indent(&mut self) {
+        let children = self.levels.pop().unwrap_or_default();
+        match self.current_box() {
+            Some(parent) => parent.children = children,
+            None => self.levels.push(children),
+        }
     }
 }
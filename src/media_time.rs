@@ -0,0 +1,145 @@
+//! Times and durations in ticks of a timescale, as they're stored in the boxes. Converting them
+//! here, instead of dividing by the timescale wherever they're used, keeps the rounding the same
+//! everywhere and avoids overflows when rescaling.
+
+use core::cmp::Ordering;
+use core::convert::TryFrom;
+use core::fmt;
+use core::time::Duration;
+
+/// A time or duration of `ticks` / `timescale` seconds, e.g. a sample's decode time in its
+/// track's timescale. A timescale of 0 (which a broken file can have) is treated as 1.
+#[derive(Debug, Clone, Copy)]
+pub struct MediaTime {
+    ticks: i64,
+    timescale: u32,
+}
+
+impl MediaTime {
+    /// Ticks past `i64::MAX` are clamped to it
+    pub fn new(ticks: u64, timescale: u32) -> Self {
+        Self::signed(ticks.min(i64::MAX as u64) as i64, timescale)
+    }
+
+    /// For times that can be negative, e.g. a presentation time after a composition offset
+    pub fn signed(ticks: i64, timescale: u32) -> Self {
+        Self { ticks, timescale }
+    }
+
+    /// `secs` in ticks of `timescale`, rounded towards 0
+    pub fn from_secs_f64(secs: f64, timescale: u32) -> Self {
+        Self::signed((secs * timescale.max(1) as f64) as i64, timescale)
+    }
+
+    pub fn ticks(&self) -> i64 {
+        self.ticks
+    }
+
+    pub fn timescale(&self) -> u32 {
+        self.timescale
+    }
+
+    pub fn as_secs_f64(&self) -> f64 {
+        self.ticks as f64 / self.timescale.max(1) as f64
+    }
+
+    /// `None` if the time is negative
+    pub fn to_duration(&self) -> Option<Duration> {
+        let ticks = u64::try_from(self.ticks).ok()?;
+        let timescale = self.timescale.max(1) as u64;
+        let nanos = (ticks % timescale) as u128 * 1_000_000_000 / timescale as u128;
+        Some(Duration::new(ticks / timescale, nanos as u32))
+    }
+
+    /// The same time in another timescale, rounded towards 0, e.g. a track duration in the
+    /// movie's timescale for tkhd
+    pub fn rescale(&self, timescale: u32) -> Self {
+        let ticks = self.ticks as i128 * timescale as i128 / self.timescale.max(1) as i128;
+        Self::signed(clamp_to_i64(ticks), timescale)
+    }
+
+    /// The exact sum, in the least common multiple of the timescales. `None` if that, or the
+    /// sum, doesn't fit.
+    pub fn checked_add(&self, other: Self) -> Option<Self> {
+        let (a, b, timescale) = self.with_common_timescale(other)?;
+        Some(Self::signed(a.checked_add(b)?, timescale))
+    }
+
+    /// The exact difference, see `checked_add`
+    pub fn checked_sub(&self, other: Self) -> Option<Self> {
+        let (a, b, timescale) = self.with_common_timescale(other)?;
+        Some(Self::signed(a.checked_sub(b)?, timescale))
+    }
+
+    fn with_common_timescale(&self, other: Self) -> Option<(i64, i64, u32)> {
+        let (x, y) = (self.timescale.max(1) as u64, other.timescale.max(1) as u64);
+        let timescale = u32::try_from(x / gcd(x, y) * y).ok()?;
+        let a = self.ticks.checked_mul((timescale as u64 / x) as i64)?;
+        let b = other.ticks.checked_mul((timescale as u64 / y) as i64)?;
+        Some((a, b, timescale))
+    }
+
+    /// Both times over the same denominator, for comparing them exactly
+    fn cross_multiplied(&self, other: &Self) -> (i128, i128) {
+        (
+            self.ticks as i128 * other.timescale.max(1) as i128,
+            other.ticks as i128 * self.timescale.max(1) as i128,
+        )
+    }
+}
+
+/// Equal if they're the same time, even in different timescales
+impl PartialEq for MediaTime {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = self.cross_multiplied(other);
+        a == b
+    }
+}
+
+impl Eq for MediaTime {}
+
+impl PartialOrd for MediaTime {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MediaTime {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b) = self.cross_multiplied(other);
+        a.cmp(&b)
+    }
+}
+
+/// The ticks along with the time they correspond to, e.g. "3003 (00:00:03.003)". Only the
+/// ticks if the timescale is 0.
+impl fmt::Display for MediaTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.timescale == 0 {
+            return write!(f, "{}", self.ticks);
+        }
+        let millis = self.ticks.unsigned_abs() as u128 * 1000 / self.timescale as u128;
+        write!(
+            f,
+            "{} ({}{:02}:{:02}:{:02}.{:03})",
+            self.ticks,
+            if self.ticks < 0 { "-" } else { "" },
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000
+        )
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn clamp_to_i64(value: i128) -> i64 {
+    value.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
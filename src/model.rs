@@ -0,0 +1,1571 @@
+use alloc::collections::BTreeMap;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::convert::TryFrom;
+use core::fmt;
+use core::mem::size_of;
+use core::ops::{Range, RangeInclusive};
+
+use serde::{Serialize, Serializer};
+
+use crate::box_path;
+use crate::boxes::{
+    BoxHeader, ChunkLargeOffsetBox, ChunkOffsetBox, CompositionTimeToSampleBox,
+    CompositionTimeToSampleEntry, DecodingTimeToSampleBox, DecodingTimeToSampleEntry,
+    DegradationPriorityBox, EditListBox, EditListEntry, FileTypeBox, Mp4Box, PaddingBitsBox,
+    SampleEntry, SampleSizeBox, SampleToChunkBox, SampleToChunkEntry, SyncSampleBox,
+    TrackExtendsBox, TrackFragmentHeaderBox, TrackFragmentRandomAccessEntry, TrackRunBox,
+    UserExtensionBox,
+};
+use crate::brand::Profile;
+use crate::diagnostics::{diagnostic, Severity};
+use crate::encryption::{
+    ProtectionSchemeInfo, ProtectionSystemSpecificHeaderBox, SampleAuxiliaryInfoOffsetsBox,
+    SampleEncryptionBox, SampleEncryptionEntry,
+};
+use crate::error::ParseError;
+use crate::heif::{Item, ItemProperty, ItemPropertyAssociation};
+use crate::media_time::MediaTime;
+use crate::piff;
+use crate::progress::Progress;
+use crate::quicktime::{Annotation, CoverArt, GaplessInfo, MetadataItem};
+use crate::reader::{ParserOptions, Reader};
+use crate::timed_metadata::EventMessage;
+use crate::writer::Writer;
+
+/// A high-level view of an mp4 file, built from its box tree
+#[derive(Debug)]
+pub struct Movie {
+    pub file_type: Option<FileTypeBox>,
+    pub timescale: u32,
+    pub duration: u64,
+    pub tracks: Vec<Track>,
+    /// From the trex boxes in mvex: the defaults for the samples in movie fragments, per track.
+    /// Tracks of a fragmented file have one even if this file has no fragments, e.g. a DASH
+    /// initialization segment.
+    pub track_extends: Vec<TrackExtendsBox>,
+    /// Movie fragments (moof boxes), in file order
+    pub fragments: Vec<Fragment>,
+    /// Items from the movie-level metadata item list (moov/udta/meta/ilst)
+    pub metadata: Vec<MetadataItem>,
+    /// QuickTime annotations directly in moov/udta, e.g. ©mak and ©mod from cameras
+    pub annotations: Vec<Annotation>,
+    /// The XMP packet, from Adobe's uuid box or an xml box, if any
+    pub xmp: Option<String>,
+    /// Images and other items in the file-level meta box, as in HEIF and AVIF files
+    pub items: Vec<Item>,
+    /// The item to show, from pitm
+    pub primary_item_id: Option<u32>,
+    /// From the pssh boxes in moov and in movie fragments
+    pub protection_systems: Vec<ProtectionSystemSpecificHeaderBox>,
+    /// From the emsg boxes between movie fragments
+    pub event_messages: Vec<EventMessage>,
+}
+
+#[derive(Debug)]
+pub struct Fragment {
+    /// File offset of the moof box
+    pub offset: u64,
+    pub sequence_number: u32,
+    pub tracks: Vec<TrackFragment>,
+    /// Size of the mdat box that follows the moof box, if any
+    pub mdat_size: Option<u64>,
+    /// The styp box that starts the segment, if the fragment is the first one in a segment
+    pub segment_type: Option<FileTypeBox>,
+    /// Whether any of its track fragments has an sdtp box
+    pub has_sample_dependencies: bool,
+}
+
+#[derive(Debug)]
+pub struct TrackFragment {
+    pub track_id: u32,
+    /// From tfdt, if present. Otherwise decoding continues where the track's previous
+    /// fragment ended.
+    pub base_media_decode_time: Option<u64>,
+    pub default_base_is_moof: bool,
+    /// Samples with offsets, timing and sync flags resolved from trun, tfhd and trex
+    pub samples: Vec<Sample>,
+    /// The IVs and subsamples of encrypted samples, from senc
+    pub sample_encryption: Vec<SampleEncryptionEntry>,
+    /// File offsets of the start and end of the entries in senc
+    pub sample_encryption_range: Option<(u64, u64)>,
+    /// The size of each sample's encryption info, from saiz
+    pub auxiliary_info_sizes: Vec<u8>,
+    /// File offset of the samples' encryption info, from saio
+    pub auxiliary_info_offset: Option<u64>,
+    /// From tfxd in Smooth Streaming fragments
+    pub absolute_time: Option<u64>,
+    pub absolute_duration: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct Track {
+    pub id: u32,
+    pub handler_type: String,
+    pub timescale: u32,
+    pub duration: u64,
+    pub language: String,
+    pub edits: Vec<EditListEntry>,
+    pub sample_entries: Vec<SampleEntry>,
+    pub sample_table: SampleTable,
+    /// Sync samples in movie fragments, from tfra in mfra
+    pub random_access_points: Vec<TrackFragmentRandomAccessEntry>,
+}
+
+#[derive(Debug, Default)]
+pub struct SampleTable {
+    pub time_to_sample: Vec<DecodingTimeToSampleEntry>,
+    pub composition_offsets: Vec<CompositionTimeToSampleEntry>,
+    pub sample_to_chunk: Vec<SampleToChunkEntry>,
+    pub sample_sizes: Vec<u32>,
+    pub chunk_offsets: Vec<u64>,
+    /// Whether the chunk offsets are in co64 rather than stco
+    pub has_large_offsets: bool,
+    /// 1-based sample numbers. None means that every sample is a sync sample.
+    pub sync_samples: Option<Vec<u32>>,
+    /// From stdp, one per sample, if present
+    pub degradation_priorities: Vec<u16>,
+    /// From padb, the number of padding bits at the end of each sample, if present
+    pub padding_bits: Vec<u8>,
+}
+
+/// A sample with its location and timing resolved from the sample table
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub offset: u64,
+    pub size: u32,
+    pub decode_time: u64,
+    pub composition_offset: i32,
+    pub duration: u32,
+    pub is_sync: bool,
+    pub chunk_index: u32,
+    /// 1-based index into the track's sample entries
+    pub description_index: u32,
+}
+
+/// Samples that are stored next to each other, from the sample table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// 0-based, as in `Sample::chunk_index`
+    pub index: u32,
+    pub offset: u64,
+    /// The 0-based indices of its samples
+    pub samples: Range<usize>,
+    /// The total size of its samples, in bytes
+    pub size: u64,
+    /// 1-based index into the track's sample entries
+    pub description_index: u32,
+}
+
+/// In sample flags (trun, tfhd, trex), set for samples that aren't sync samples
+const SAMPLE_IS_NON_SYNC_SAMPLE: u32 = 0x0001_0000;
+
+impl Movie {
+    pub fn parse(buf: &[u8]) -> Result<Self, ParseError> {
+        Self::parse_with_progress(buf, &mut |_, _| {})
+    }
+
+    /// Like `parse`, but with another memory budget than `reader::DEFAULT_MAX_ALLOC_BYTES`
+    pub fn parse_with_options(buf: &[u8], options: &ParserOptions) -> Result<Self, ParseError> {
+        Self::parse_with(buf, options, &mut |_, _| {})
+    }
+
+    /// Like `parse`, but reports how far into the file the parsing has come
+    pub fn parse_with_progress(buf: &[u8], progress: &mut Progress) -> Result<Self, ParseError> {
+        Self::parse_with(buf, &ParserOptions::default(), progress)
+    }
+
+    fn parse_with(
+        buf: &[u8],
+        options: &ParserOptions,
+        progress: &mut Progress,
+    ) -> Result<Self, ParseError> {
+        let mut builder = MovieBuilder {
+            file_type: None,
+            timescale: 0,
+            duration: 0,
+            tracks: vec![],
+            current_track: None,
+            track_extends: vec![],
+            fragments: vec![],
+            current_traf: None,
+            segment_type: None,
+            fragment_data_end: 0,
+            next_decode_times: BTreeMap::new(),
+            metadata: vec![],
+            annotations: vec![],
+            xmp: None,
+            items: vec![],
+            item_properties: vec![],
+            item_associations: vec![],
+            primary_item_id: None,
+            protection_systems: vec![],
+            event_messages: vec![],
+            progress,
+            file_len: buf.len() as u64,
+        };
+        let mut reader = Reader::with_options(buf, options);
+        builder.parse(&mut reader, buf.len() as u64);
+        box_path::clear();
+        if let Some(error) = reader.take_error() {
+            return Err(error);
+        }
+        (builder.progress)(buf.len() as u64, buf.len() as u64);
+        builder.associate_item_properties();
+
+        Ok(Self {
+            file_type: builder.file_type,
+            timescale: builder.timescale,
+            duration: builder.duration,
+            tracks: builder.tracks,
+            track_extends: builder.track_extends,
+            fragments: builder.fragments,
+            metadata: builder.metadata,
+            annotations: builder.annotations,
+            xmp: builder.xmp,
+            items: builder.items,
+            primary_item_id: builder.primary_item_id,
+            protection_systems: builder.protection_systems,
+            event_messages: builder.event_messages,
+        })
+    }
+
+    /// The item that pitm points to
+    pub fn primary_item(&self) -> Option<&Item> {
+        let id = self.primary_item_id?;
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    /// Which brand-specific rules apply, from the brands in ftyp
+    pub fn profile(&self) -> Profile {
+        self.file_type
+            .as_ref()
+            .map_or(Profile::Iso, |file_type| file_type.profile())
+    }
+
+    /// The track that `selector` picks, if there is one
+    pub fn select_track(&self, selector: &TrackSelector) -> Option<&Track> {
+        let id = selector.resolve(self.tracks.iter().map(|track| (track.id, track.kind())))?;
+        self.tracks.iter().find(|track| track.id == id)
+    }
+
+    /// Whether the file has a Smooth Streaming brand, or its fragments have tfxd boxes, as
+    /// fragments fetched from a Smooth Streaming server have no ftyp
+    pub fn is_smooth_streaming(&self) -> bool {
+        self.profile() == Profile::SmoothStreaming
+            || self
+                .fragments
+                .iter()
+                .flat_map(|fragment| fragment.tracks.iter())
+                .any(|track_fragment| track_fragment.absolute_time.is_some())
+    }
+
+    /// Whether ftyp lists the brand, as the major brand or as a compatible one
+    pub fn has_brand(&self, brand: &str) -> bool {
+        self.file_type
+            .as_ref()
+            .is_some_and(|file_type| file_type.has_brand(brand))
+    }
+
+    /// The title from the metadata (©nam), or from QuickTime's annotations, if any
+    pub fn title(&self) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find_map(|item| match item {
+                MetadataItem::Text { key, value } if key == "©nam" => Some(value.as_str()),
+                _ => None,
+            })
+            .or_else(|| self.annotation("©nam"))
+    }
+
+    /// The text of the first QuickTime annotation with the key, e.g. "©mak", if any
+    pub fn annotation(&self, key: &str) -> Option<&str> {
+        self.annotations
+            .iter()
+            .find(|annotation| annotation.key == key)
+            .map(|annotation| annotation.text.as_str())
+    }
+
+    /// The first artwork from the metadata (covr), if any
+    pub fn cover_art(&self) -> Option<&CoverArt> {
+        self.metadata.iter().find_map(|item| match item {
+            MetadataItem::CoverArt(cover_art) => Some(cover_art),
+            _ => None,
+        })
+    }
+
+    /// The encoder delay and padding of the audio, from iTunes' iTunSMPB metadata, if any
+    pub fn gapless_info(&self) -> Option<GaplessInfo> {
+        self.metadata.iter().find_map(|item| match item {
+            MetadataItem::Freeform(item) => item.gapless_info(),
+            _ => None,
+        })
+    }
+
+    /// The track's duration in seconds: that of its edit list if it has one, otherwise that
+    /// of its media, including any fragments
+    pub fn presentation_duration(&self, track: &Track) -> f64 {
+        if !track.edits.is_empty() {
+            let total: u64 = track
+                .edits
+                .iter()
+                .map(|e| e.segment_duration as u64)
+                .fold(0, u64::saturating_add);
+            return MediaTime::new(total, self.timescale).as_secs_f64();
+        }
+        let media_end = self
+            .track_samples(track)
+            .last()
+            .map_or(0, |s| s.decode_time + s.duration as u64);
+        MediaTime::new(media_end, track.timescale).as_secs_f64()
+    }
+
+    /// The track's average bitrate in bits per second: the total size of its samples, including
+    /// those in movie fragments, over the time from the first sample to the end of the last one
+    /// (from stts and trun, not mdhd). None if the samples have no duration.
+    pub fn bitrate(&self, track: &Track) -> Option<f64> {
+        let samples = self.track_samples(track);
+        let start = samples.first()?.decode_time;
+        let end = samples.last()?.decode_time + samples.last()?.duration as u64;
+        let duration = MediaTime::new(end.saturating_sub(start), track.timescale).as_secs_f64();
+        let total_bytes: u64 = samples.iter().map(|s| s.size as u64).sum();
+        if duration > 0.0 {
+            Some(total_bytes as f64 * 8.0 / duration)
+        } else {
+            None
+        }
+    }
+
+    /// All samples of the track in decoding order: those in its sample table, followed by
+    /// those in movie fragments
+    pub fn track_samples(&self, track: &Track) -> Vec<Sample> {
+        let mut samples = track.sample_table.samples();
+        for fragment in &self.fragments {
+            for track_fragment in &fragment.tracks {
+                if track_fragment.track_id == track.id {
+                    samples.extend_from_slice(&track_fragment.samples);
+                }
+            }
+        }
+        samples
+    }
+}
+
+/// What a track contains, as told by its handler type (hdlr)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackKind {
+    Video,
+    Audio,
+    Subtitle,
+    Timecode,
+    Metadata,
+    Hint,
+    Picture,
+    /// The handler type
+    Other(String),
+}
+
+impl TrackKind {
+    pub fn from_handler_type(handler_type: &str) -> Self {
+        match handler_type {
+            "vide" => TrackKind::Video,
+            "soun" => TrackKind::Audio,
+            "text" | "sbtl" | "subt" | "clcp" => TrackKind::Subtitle,
+            "tmcd" => TrackKind::Timecode,
+            "meta" | "mdta" => TrackKind::Metadata,
+            "hint" => TrackKind::Hint,
+            "pict" => TrackKind::Picture,
+            other => TrackKind::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for TrackKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackKind::Video => write!(f, "video"),
+            TrackKind::Audio => write!(f, "audio"),
+            TrackKind::Subtitle => write!(f, "subtitles"),
+            TrackKind::Timecode => write!(f, "timecode"),
+            TrackKind::Metadata => write!(f, "metadata"),
+            TrackKind::Hint => write!(f, "hint"),
+            TrackKind::Picture => write!(f, "picture"),
+            TrackKind::Other(handler_type) => write!(f, "{}", handler_type),
+        }
+    }
+}
+
+/// Serialized the same way as it's displayed, e.g. "video"
+impl Serialize for TrackKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Picks one track, e.g. for the `--track` option of the binaries. A number is a track ID
+/// (from tkhd), `index:N` is the Nth track in the file counting from 0, and a kind like "video"
+/// (or a handler type like "vide") is the first track of that kind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackSelector {
+    Id(u32),
+    Index(usize),
+    Kind(TrackKind),
+}
+
+impl TrackSelector {
+    /// The ID of the selected track, given the ID and kind of each track in file order
+    pub fn resolve<I>(&self, tracks: I) -> Option<u32>
+    where
+        I: IntoIterator<Item = (u32, TrackKind)>,
+    {
+        let mut tracks = tracks.into_iter();
+        match self {
+            TrackSelector::Id(id) => tracks.find(|(track_id, _)| track_id == id),
+            TrackSelector::Index(index) => tracks.nth(*index),
+            TrackSelector::Kind(kind) => tracks.find(|(_, track_kind)| track_kind == kind),
+        }
+        .map(|(id, _)| id)
+    }
+}
+
+impl core::str::FromStr for TrackSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(id) = s.parse() {
+            return Ok(TrackSelector::Id(id));
+        }
+        if let Some(id) = s.strip_prefix("id:") {
+            return id
+                .parse()
+                .map(TrackSelector::Id)
+                .map_err(|_| format!("Invalid track ID: {}", id));
+        }
+        if let Some(index) = s.strip_prefix("index:") {
+            return index
+                .parse()
+                .map(TrackSelector::Index)
+                .map_err(|_| format!("Invalid track index: {}", index));
+        }
+        let kind = match s.to_lowercase().as_str() {
+            "video" => TrackKind::Video,
+            "audio" => TrackKind::Audio,
+            "subtitle" | "subtitles" => TrackKind::Subtitle,
+            "timecode" => TrackKind::Timecode,
+            "metadata" => TrackKind::Metadata,
+            "hint" => TrackKind::Hint,
+            "picture" => TrackKind::Picture,
+            _ if s.len() == 4 => TrackKind::from_handler_type(s),
+            _ => {
+                return Err(format!(
+                    "Expected a track ID, index:N or a kind like video, got '{}'",
+                    s
+                ))
+            }
+        };
+        Ok(TrackSelector::Kind(kind))
+    }
+}
+
+impl fmt::Display for TrackSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackSelector::Id(id) => write!(f, "{}", id),
+            TrackSelector::Index(index) => write!(f, "index:{}", index),
+            TrackSelector::Kind(kind) => write!(f, "{}", kind),
+        }
+    }
+}
+
+impl Track {
+    pub fn kind(&self) -> TrackKind {
+        TrackKind::from_handler_type(&self.handler_type)
+    }
+
+    pub fn is_video(&self) -> bool {
+        self.kind() == TrackKind::Video
+    }
+
+    pub fn is_audio(&self) -> bool {
+        self.kind() == TrackKind::Audio
+    }
+
+    /// How the samples are encrypted, if they are
+    pub fn protection(&self) -> Option<&ProtectionSchemeInfo> {
+        self.sample_entries.iter().find_map(|entry| match entry {
+            SampleEntry::Encrypted(entry) => entry.protection.as_ref(),
+            _ => None,
+        })
+    }
+
+    /// Where the track's media is shown in the movie's presentation, from its edit list.
+    /// Without one, the media is shown from composition time 0, as if by a single edit.
+    pub fn presentation_timeline(&self, movie_timescale: u32) -> PresentationTimeline {
+        let mut segments = Vec::new();
+        let mut start = 0u64;
+        for edit in &self.edits {
+            let duration = edit.segment_duration as u64;
+            segments.push(TimelineSegment {
+                start: MediaTime::new(start, movie_timescale),
+                // Fragmented files often leave the duration of their only edit at 0, since it
+                // isn't known when moov is written
+                duration: if duration == 0 && !edit.is_empty() {
+                    None
+                } else {
+                    Some(MediaTime::new(duration, movie_timescale))
+                },
+                media_time: if edit.is_empty() {
+                    None
+                } else {
+                    Some(MediaTime::signed(edit.media_time as i64, self.timescale))
+                },
+                rate: edit.media_rate_integer as i64 * 0x10000 + edit.media_rate_fraction as i64,
+            });
+            start = start.saturating_add(duration);
+        }
+        if segments.is_empty() {
+            segments.push(TimelineSegment {
+                start: MediaTime::new(0, movie_timescale),
+                duration: None,
+                media_time: Some(MediaTime::new(0, self.timescale)),
+                rate: 0x10000,
+            });
+        }
+        PresentationTimeline {
+            segments,
+            timescale: self.timescale,
+        }
+    }
+}
+
+/// Maps a track's composition times to times in the movie's presentation, see
+/// `Track::presentation_timeline`
+#[derive(Debug, Clone)]
+pub struct PresentationTimeline {
+    pub segments: Vec<TimelineSegment>,
+    /// The track's timescale
+    pub timescale: u32,
+}
+
+/// A stretch of the presentation, from one edit
+#[derive(Debug, Clone)]
+pub struct TimelineSegment {
+    /// In the movie's timescale
+    pub start: MediaTime,
+    /// In the movie's timescale. None if it lasts until the media ends.
+    pub duration: Option<MediaTime>,
+    /// The composition time that is shown first, in the track's timescale. None for an empty
+    /// edit, which shows nothing.
+    pub media_time: Option<MediaTime>,
+    /// 16.16 fixed point
+    rate: i64,
+}
+
+impl TimelineSegment {
+    /// 1 for normal speed, 0 when a single frame is held for the whole segment
+    pub fn media_rate(&self) -> f64 {
+        self.rate as f64 / 65536.0
+    }
+}
+
+impl PresentationTimeline {
+    /// When the sample is first shown, or None if no edit shows it. A sample that starts
+    /// before an edit's media time, but lasts past it, is shown when the edit starts.
+    /// Edits with a negative rate are skipped, as players don't play backwards.
+    pub fn presentation_time(&self, sample: &Sample) -> Option<MediaTime> {
+        let composition_time = sample.decode_time as i64 + sample.composition_offset as i64;
+        let end = composition_time.saturating_add((sample.duration as i64).max(1));
+        self.segments.iter().find_map(|segment| {
+            let media_time = segment.media_time?.ticks();
+            if segment.rate < 0 || end <= media_time {
+                return None;
+            }
+            let offset = (composition_time - media_time).max(0);
+            if let Some(duration) = segment.duration {
+                // Whether the offset is inside the media that the segment shows, which is its
+                // duration in the track's timescale, times the rate
+                let shown =
+                    duration.ticks() as i128 * self.timescale as i128 * segment.rate as i128;
+                let offset = offset as i128 * duration.timescale().max(1) as i128 * 0x10000;
+                if offset >= shown.max(1) {
+                    return None;
+                }
+            }
+            let offset = if segment.rate == 0 {
+                0
+            } else {
+                i64::try_from(offset as i128 * 0x10000 / segment.rate as i128).ok()?
+            };
+            let offset = MediaTime::signed(offset, self.timescale);
+            segment
+                .start
+                .checked_add(offset)
+                .or_else(|| segment.start.rescale(self.timescale).checked_add(offset))
+        })
+    }
+}
+
+impl SampleTable {
+    pub fn sample_count(&self) -> usize {
+        self.sample_sizes.len()
+    }
+
+    pub fn media_duration(&self) -> u64 {
+        self.time_to_sample
+            .iter()
+            .map(|entry| entry.sample_count as u64 * entry.sample_delta as u64)
+            .fold(0, u64::saturating_add)
+    }
+
+    /// The duration that most samples have, as it's typically constant per track
+    pub fn typical_sample_delta(&self) -> Option<u32> {
+        self.time_to_sample
+            .iter()
+            .max_by_key(|entry| entry.sample_count)
+            .map(|entry| entry.sample_delta)
+    }
+
+    pub fn samples(&self) -> Vec<Sample> {
+        let mut samples = Vec::with_capacity(self.sample_count());
+
+        for chunk in self.chunks() {
+            let mut offset = chunk.offset;
+            for &size in &self.sample_sizes[chunk.samples.clone()] {
+                samples.push(Sample {
+                    offset,
+                    size,
+                    decode_time: 0,
+                    composition_offset: 0,
+                    duration: 0,
+                    is_sync: true,
+                    chunk_index: chunk.index,
+                    description_index: chunk.description_index,
+                });
+                offset = offset.saturating_add(size as u64);
+            }
+        }
+
+        let deltas = self.time_to_sample.iter().flat_map(|entry| {
+            core::iter::repeat_n(entry.sample_delta, entry.sample_count as usize)
+        });
+        let mut decode_time = 0;
+        for (sample, delta) in samples.iter_mut().zip(deltas) {
+            sample.decode_time = decode_time;
+            sample.duration = delta;
+            decode_time += delta as u64;
+        }
+
+        let composition_offsets = self.composition_offsets.iter().flat_map(|entry| {
+            core::iter::repeat_n(entry.sample_offset, entry.sample_count as usize)
+        });
+        for (sample, composition_offset) in samples.iter_mut().zip(composition_offsets) {
+            sample.composition_offset = composition_offset;
+        }
+
+        if let Some(sync_samples) = &self.sync_samples {
+            for sample in samples.iter_mut() {
+                sample.is_sync = false;
+            }
+            for &sample_number in sync_samples {
+                // Samples are numbered from 1
+                let index = (sample_number as usize).checked_sub(1);
+                if let Some(sample) = index.and_then(|i| samples.get_mut(i)) {
+                    sample.is_sync = true;
+                }
+            }
+        }
+
+        samples
+    }
+
+    /// The chunks in the order of the chunk offsets, with the samples that stsc puts in them.
+    /// Chunks past the last sample have no samples.
+    pub fn chunks(&self) -> impl Iterator<Item = Chunk> + '_ {
+        let mut sample_index = 0;
+        self.chunk_runs()
+            .flat_map(|(numbers, entry)| numbers.map(move |number| (number, entry)))
+            .map(move |(number, entry)| {
+                let start = sample_index;
+                sample_index = (start + entry.samples_per_chunk as usize).min(self.sample_count());
+                self.chunk(number, start..sample_index, entry)
+            })
+    }
+
+    /// The chunk with the sample at the given 0-based index, found without going through
+    /// the chunks before it
+    pub fn chunk_for_sample(&self, sample_index: usize) -> Option<Chunk> {
+        if sample_index >= self.sample_count() {
+            return None;
+        }
+        let mut first_sample = 0usize;
+        for (numbers, entry) in self.chunk_runs() {
+            let samples_per_chunk = entry.samples_per_chunk as usize;
+            let chunk_count = if numbers.is_empty() {
+                0
+            } else {
+                (numbers.end() - numbers.start()) as usize + 1
+            };
+            let run_samples = chunk_count.saturating_mul(samples_per_chunk);
+            if sample_index < first_sample.saturating_add(run_samples) {
+                let n = (sample_index - first_sample) / samples_per_chunk;
+                let start = first_sample + n * samples_per_chunk;
+                let end = (start + samples_per_chunk).min(self.sample_count());
+                return Some(self.chunk(numbers.start() + n as u32, start..end, entry));
+            }
+            first_sample = first_sample.saturating_add(run_samples);
+        }
+        None
+    }
+
+    /// The 1-based numbers of the chunks that each stsc entry applies to, leaving out those
+    /// without a chunk offset
+    fn chunk_runs(&self) -> impl Iterator<Item = (RangeInclusive<u32>, &SampleToChunkEntry)> {
+        self.sample_to_chunk
+            .iter()
+            .enumerate()
+            .map(move |(i, entry)| {
+                let last_chunk = match self.sample_to_chunk.get(i + 1) {
+                    Some(next) => next.first_chunk.saturating_sub(1),
+                    None => u32::MAX,
+                };
+                let last_chunk = last_chunk.min(self.chunk_offsets.len() as u32);
+                (entry.first_chunk.max(1)..=last_chunk, entry)
+            })
+    }
+
+    /// `number` must have a chunk offset, and `samples` must have sizes
+    fn chunk(&self, number: u32, samples: Range<usize>, entry: &SampleToChunkEntry) -> Chunk {
+        Chunk {
+            index: number - 1,
+            offset: self.chunk_offsets[number as usize - 1],
+            size: self.sample_sizes[samples.clone()]
+                .iter()
+                .map(|&size| size as u64)
+                .sum(),
+            samples,
+            description_index: entry.sample_description_index,
+        }
+    }
+
+    /// Builds a sample table from samples in decoding order. Consecutive samples with the
+    /// same `chunk_index` end up in the same chunk, so they must be contiguous in the file.
+    pub fn from_samples(samples: &[Sample]) -> Self {
+        let mut table = Self::default();
+        let mut chunks: Vec<(u64, u32, u32)> = vec![];
+        let mut previous_chunk_index = None;
+        for sample in samples {
+            match table.time_to_sample.last_mut() {
+                Some(entry) if entry.sample_delta == sample.duration => entry.sample_count += 1,
+                _ => table.time_to_sample.push(DecodingTimeToSampleEntry {
+                    sample_count: 1,
+                    sample_delta: sample.duration,
+                }),
+            }
+            match table.composition_offsets.last_mut() {
+                Some(entry) if entry.sample_offset == sample.composition_offset => {
+                    entry.sample_count += 1
+                }
+                _ => table
+                    .composition_offsets
+                    .push(CompositionTimeToSampleEntry {
+                        sample_count: 1,
+                        sample_offset: sample.composition_offset,
+                    }),
+            }
+            if previous_chunk_index != Some(sample.chunk_index) {
+                chunks.push((sample.offset, 0, sample.description_index));
+                previous_chunk_index = Some(sample.chunk_index);
+            }
+            chunks.last_mut().unwrap().1 += 1;
+            table.sample_sizes.push(sample.size);
+        }
+
+        if table
+            .composition_offsets
+            .iter()
+            .all(|entry| entry.sample_offset == 0)
+        {
+            table.composition_offsets.clear();
+        }
+        if samples.iter().any(|sample| !sample.is_sync) {
+            let sync_samples = samples
+                .iter()
+                .enumerate()
+                .filter(|(_, sample)| sample.is_sync)
+                .map(|(i, _)| i as u32 + 1)
+                .collect();
+            table.sync_samples = Some(sync_samples);
+        }
+        for (i, &(offset, samples_per_chunk, description_index)) in chunks.iter().enumerate() {
+            table.chunk_offsets.push(offset);
+            match table.sample_to_chunk.last() {
+                Some(entry)
+                    if entry.samples_per_chunk == samples_per_chunk
+                        && entry.sample_description_index == description_index => {}
+                _ => table.sample_to_chunk.push(SampleToChunkEntry {
+                    first_chunk: i as u32 + 1,
+                    samples_per_chunk,
+                    sample_description_index: description_index,
+                }),
+            }
+        }
+        table
+    }
+
+    /// Writes all the boxes of the table, i.e. the contents of stbl except for stsd
+    pub fn write(&self, writer: &mut Writer) {
+        DecodingTimeToSampleBox::write(writer, &self.time_to_sample);
+        if !self.composition_offsets.is_empty() {
+            CompositionTimeToSampleBox::write(writer, &self.composition_offsets);
+        }
+        if let Some(sync_samples) = &self.sync_samples {
+            SyncSampleBox::write(writer, sync_samples);
+        }
+        SampleToChunkBox::write(writer, &self.sample_to_chunk);
+        SampleSizeBox::write(writer, &self.sample_sizes);
+        if self.has_large_offsets {
+            ChunkLargeOffsetBox::write(writer, &self.chunk_offsets);
+        } else {
+            ChunkOffsetBox::write(writer, &self.chunk_offsets);
+        }
+    }
+}
+
+struct MovieBuilder<'a, 'p> {
+    file_type: Option<FileTypeBox>,
+    timescale: u32,
+    duration: u64,
+    tracks: Vec<Track>,
+    current_track: Option<Track>,
+    track_extends: Vec<TrackExtendsBox>,
+    fragments: Vec<Fragment>,
+    current_traf: Option<(TrackFragmentHeaderBox, TrackFragment)>,
+    /// From a styp box, until the moof box that follows it
+    segment_type: Option<FileTypeBox>,
+    /// Where the data of the previous track run ended, which is the default base offset
+    /// for the next track fragment
+    fragment_data_end: u64,
+    /// Per track ID, the decode time right after the last parsed sample
+    next_decode_times: BTreeMap<u32, u64>,
+    metadata: Vec<MetadataItem>,
+    annotations: Vec<Annotation>,
+    xmp: Option<String>,
+    items: Vec<Item>,
+    /// From ipco, in order, to be associated with items by ipma
+    item_properties: Vec<ItemProperty>,
+    item_associations: Vec<ItemPropertyAssociation>,
+    primary_item_id: Option<u32>,
+    protection_systems: Vec<ProtectionSystemSpecificHeaderBox>,
+    event_messages: Vec<EventMessage>,
+    progress: &'a mut Progress<'p>,
+    file_len: u64,
+}
+
+impl MovieBuilder<'_, '_> {
+    fn parse(&mut self, reader: &mut Reader, end_offset: u64) {
+        while reader.position() < end_offset && !reader.failed() {
+            (self.progress)(reader.position(), self.file_len);
+            let header = match BoxHeader::parse_within(reader, end_offset) {
+                Some(header) => header,
+                None => return,
+            };
+
+            if &header.box_type == "moof" {
+                self.fragments.push(Fragment {
+                    offset: header.start_offset,
+                    sequence_number: 0,
+                    tracks: vec![],
+                    mdat_size: None,
+                    segment_type: self.segment_type.take(),
+                    has_sample_dependencies: false,
+                });
+                self.fragment_data_end = header.start_offset;
+            }
+            if &header.box_type == "trak" {
+                // We will build a Track from this box's children
+                self.current_track = Some(Track {
+                    id: 0,
+                    handler_type: String::new(),
+                    timescale: 0,
+                    duration: 0,
+                    language: String::new(),
+                    edits: vec![],
+                    sample_entries: vec![],
+                    sample_table: SampleTable::default(),
+                    random_access_points: vec![],
+                });
+            }
+
+            if &header.box_type == "mdat" {
+                if let Some(fragment) = self.fragments.last_mut() {
+                    fragment.mdat_size.get_or_insert(header.box_size);
+                }
+            }
+
+            let box_ = Mp4Box::parse_contents(reader, &header.box_type, header.inner_size);
+
+            let box_end_offset = header.start_offset + header.box_size;
+            match box_ {
+                Mp4Box::Container(_) if header.box_type == "ipco" => {
+                    self.parse_item_properties(reader, box_end_offset);
+                }
+                Mp4Box::Container(_) => {
+                    self.parse(reader, box_end_offset);
+                }
+                Mp4Box::Ftyp(b) => self.file_type = Some(b),
+                Mp4Box::Styp(b) => self.segment_type = Some(b),
+                Mp4Box::Sdtp(_) if self.current_traf.is_some() => {
+                    if let Some(fragment) = self.fragments.last_mut() {
+                        fragment.has_sample_dependencies = true;
+                    }
+                }
+                Mp4Box::Pitm(b) if self.current_track.is_none() => {
+                    self.primary_item_id = Some(b.item_id)
+                }
+                Mp4Box::Infe(b) if self.current_track.is_none() => self.items.push(Item {
+                    id: b.item_id,
+                    item_type: b.item_type,
+                    name: b.item_name,
+                    properties: vec![],
+                }),
+                Mp4Box::Ipma(b) if self.current_track.is_none() => {
+                    self.item_associations.extend(b.associations)
+                }
+                Mp4Box::Mvhd(b) => {
+                    self.timescale = b.timescale;
+                    self.duration = b.duration;
+                }
+                Mp4Box::Trex(b) => self.track_extends.push(b),
+                Mp4Box::Mfhd(b) => {
+                    if let Some(fragment) = self.fragments.last_mut() {
+                        fragment.sequence_number = b.sequence_number;
+                    }
+                }
+                Mp4Box::Tfhd(b) => {
+                    let track_fragment = TrackFragment {
+                        track_id: b.track_id,
+                        base_media_decode_time: None,
+                        default_base_is_moof: b.default_base_is_moof,
+                        samples: vec![],
+                        sample_encryption: vec![],
+                        sample_encryption_range: None,
+                        auxiliary_info_sizes: vec![],
+                        auxiliary_info_offset: None,
+                        absolute_time: None,
+                        absolute_duration: None,
+                    };
+                    self.current_traf = Some((b, track_fragment));
+                }
+                Mp4Box::Tfdt(b) => {
+                    if let Some((_, track_fragment)) = self.current_traf.as_mut() {
+                        track_fragment.base_media_decode_time = Some(b.base_media_decode_time);
+                    }
+                }
+                Mp4Box::Trun(b) => self.parse_track_run(reader, b),
+                Mp4Box::Senc(b) => self.parse_sample_encryption(reader, b, None),
+                Mp4Box::PiffSenc(b) => {
+                    self.parse_sample_encryption(reader, b, Some(piff::DEFAULT_IV_SIZE))
+                }
+                Mp4Box::Tfxd(b) => {
+                    if let Some((_, track_fragment)) = self.current_traf.as_mut() {
+                        track_fragment.absolute_time = Some(b.absolute_time);
+                        track_fragment.absolute_duration = Some(b.duration);
+                    }
+                }
+                Mp4Box::Pssh(b) | Mp4Box::PiffPssh(b) => self.protection_systems.push(b),
+                Mp4Box::Emsg(b) => self.event_messages.push(EventMessage {
+                    offset: header.start_offset,
+                    fragment_index: self.fragments.len(),
+                    message: b,
+                }),
+                Mp4Box::Saiz(b) if b.is_cenc() => {
+                    if let Some((_, track_fragment)) = self.current_traf.as_mut() {
+                        track_fragment.auxiliary_info_sizes = b.sizes();
+                    }
+                }
+                Mp4Box::Saio(b) if b.is_cenc() => self.set_auxiliary_info_offset(b),
+                Mp4Box::Tfra(b) => {
+                    let entry_size = size_of::<TrackFragmentRandomAccessEntry>();
+                    if !reader.reserve(b.entry_count as u64, entry_size, "tfra") {
+                        return;
+                    }
+                    let entries: Vec<_> =
+                        (0..b.entry_count).map(|_| b.parse_entry(reader)).collect();
+                    match self.tracks.iter_mut().find(|t| t.id == b.track_id) {
+                        Some(track) => track.random_access_points.extend(entries),
+                        None => diagnostic!(
+                            Severity::Warning,
+                            "tfra refers to unknown track {}",
+                            b.track_id
+                        ),
+                    }
+                }
+                Mp4Box::QuickTimeMetadataItemList(b) if self.current_track.is_none() => {
+                    while reader.position() + 8 <= box_end_offset && !reader.failed() {
+                        self.metadata.push(b.parse_entry(reader));
+                    }
+                }
+                Mp4Box::Uuid(UserExtensionBox { xmp: Some(xmp), .. }) if self.xmp.is_none() => {
+                    self.xmp = Some(xmp)
+                }
+                Mp4Box::Xml(b) if self.xmp.is_none() && b.is_xmp() => self.xmp = Some(b.xml),
+                Mp4Box::UserDataText(b) if self.current_track.is_none() => {
+                    self.annotations.extend(b.annotations)
+                }
+                box_ => {
+                    if let Some(track) = self.current_track.as_mut() {
+                        Self::parse_track_box(track, reader, box_);
+                    }
+                }
+            }
+
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            if remaining > 0 {
+                reader.skip(remaining);
+            }
+
+            // A trak inside another one has already taken the track
+            if &header.box_type == "trak" {
+                if let Some(track) = self.current_track.take() {
+                    let next_decode_time = track.sample_table.media_duration();
+                    self.next_decode_times.insert(track.id, next_decode_time);
+                    self.tracks.push(track);
+                }
+            }
+            if &header.box_type == "traf" {
+                if let Some((_, mut track_fragment)) = self.current_traf.take() {
+                    self.apply_absolute_time(&mut track_fragment);
+                    if let Some(fragment) = self.fragments.last_mut() {
+                        fragment.tracks.push(track_fragment);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The size of the IVs comes from the tenc box of the track, or else the default, if any
+    fn parse_sample_encryption(
+        &mut self,
+        reader: &mut Reader,
+        senc: SampleEncryptionBox,
+        default_iv_size: Option<u8>,
+    ) {
+        let (tfhd, track_fragment) = match self.current_traf.as_mut() {
+            Some(traf) => traf,
+            None => return,
+        };
+        let tenc = self
+            .tracks
+            .iter()
+            .find(|t| t.id == tfhd.track_id)
+            .and_then(|track| track.protection())
+            .and_then(|protection| protection.track_encryption.as_ref());
+        let iv_size = match (senc.per_sample_iv_size, tenc, default_iv_size) {
+            (Some(iv_size), _, _) => iv_size,
+            (None, Some(tenc), _) => tenc.default_per_sample_iv_size,
+            (None, None, Some(iv_size)) => iv_size,
+            (None, None, None) => {
+                diagnostic!(
+                    Severity::Warning,
+                    "senc of track {} can't be parsed without the track's tenc box",
+                    tfhd.track_id
+                );
+                return;
+            }
+        };
+        let entry_size = size_of::<SampleEncryptionEntry>();
+        if !reader.reserve(senc.sample_count as u64, entry_size, "senc") {
+            return;
+        }
+        let start = reader.position();
+        for _ in 0..senc.sample_count {
+            if reader.failed() {
+                break;
+            }
+            track_fragment
+                .sample_encryption
+                .push(senc.parse_entry(reader, iv_size));
+        }
+        track_fragment.sample_encryption_range = Some((start, reader.position()));
+    }
+
+    /// Smooth Streaming fragments have their timing in tfxd, which comes after trun. Without
+    /// tfdt, the samples are moved to its absolute time, and without default sample durations
+    /// (in tfhd or trex), the fragment's duration is spread over them.
+    fn apply_absolute_time(&mut self, track_fragment: &mut TrackFragment) {
+        let absolute_time = match track_fragment.absolute_time {
+            Some(absolute_time) => absolute_time,
+            None => return,
+        };
+        let samples = &mut track_fragment.samples;
+        if samples.is_empty() {
+            return;
+        }
+        if let Some(duration) = track_fragment.absolute_duration {
+            if samples.iter().all(|s| s.duration == 0) {
+                // In 128 bits, as the duration comes from the file and may be close to u64::MAX
+                let (duration, count) = (duration as u128, samples.len() as u128);
+                for (i, sample) in samples.iter_mut().enumerate() {
+                    let i = i as u128;
+                    sample.duration = ((i + 1) * duration / count - i * duration / count) as u32;
+                }
+            }
+        }
+        let mut decode_time = match track_fragment.base_media_decode_time {
+            Some(_) => samples[0].decode_time,
+            None => absolute_time,
+        };
+        for sample in samples.iter_mut() {
+            sample.decode_time = decode_time;
+            decode_time = decode_time.saturating_add(sample.duration as u64);
+        }
+        self.next_decode_times
+            .insert(track_fragment.track_id, decode_time);
+    }
+
+    /// Only the first offset is used, i.e. the info is assumed to be contiguous even if the
+    /// track fragment has several truns
+    fn set_auxiliary_info_offset(&mut self, saio: SampleAuxiliaryInfoOffsetsBox) {
+        let (tfhd, track_fragment) = match self.current_traf.as_mut() {
+            Some(traf) => traf,
+            None => return,
+        };
+        if saio.offsets.len() > 1 {
+            diagnostic!(
+                Severity::Warning,
+                "saio of track {} has {} offsets. Only the first one is used.",
+                tfhd.track_id,
+                saio.offsets.len()
+            );
+        }
+        let moof_offset = self.fragments.last().map_or(0, |f| f.offset);
+        let base_offset = tfhd.base_data_offset.unwrap_or(moof_offset);
+        track_fragment.auxiliary_info_offset = saio
+            .offsets
+            .first()
+            .map(|offset| base_offset.saturating_add(*offset));
+    }
+
+    fn parse_track_run(&mut self, reader: &mut Reader, trun: TrackRunBox) {
+        let (tfhd, track_fragment) = match self.current_traf.as_mut() {
+            Some(traf) => traf,
+            None => return,
+        };
+        let moof_offset = self.fragments.last().map_or(0, |f| f.offset);
+        let trex = self
+            .track_extends
+            .iter()
+            .find(|trex| trex.track_id == tfhd.track_id);
+
+        let default_duration = tfhd
+            .default_sample_duration
+            .or_else(|| trex.map(|t| t.default_sample_duration))
+            .unwrap_or(0);
+        let default_size = tfhd
+            .default_sample_size
+            .or_else(|| trex.map(|t| t.default_sample_size))
+            .unwrap_or(0);
+        let default_flags = tfhd
+            .default_sample_flags
+            .or_else(|| trex.map(|t| t.default_sample_flags))
+            .unwrap_or(0);
+        let description_index = tfhd
+            .sample_description_index
+            .or_else(|| trex.map(|t| t.default_sample_description_index))
+            .unwrap_or(1);
+
+        let base_offset = match tfhd.base_data_offset {
+            Some(base_data_offset) => base_data_offset,
+            None if tfhd.default_base_is_moof => moof_offset,
+            // The first track fragment in a moof starts at the moof, and the following ones
+            // where the data of the previous one ended
+            None => self.fragment_data_end,
+        };
+        let mut offset = match (trun.data_offset, track_fragment.samples.is_empty()) {
+            (Some(data_offset), _) => (base_offset as i64)
+                .saturating_add(data_offset as i64)
+                .max(0) as u64,
+            (None, true) => base_offset,
+            // Consecutive runs in the same track fragment follow each other
+            (None, false) => self.fragment_data_end,
+        };
+
+        let continued_decode_time = self.next_decode_times.get(&tfhd.track_id).copied();
+        let mut decode_time = match track_fragment.samples.last() {
+            Some(last) => last.decode_time.saturating_add(last.duration as u64),
+            None => track_fragment
+                .base_media_decode_time
+                .or(continued_decode_time)
+                .unwrap_or(0),
+        };
+        let chunk_index = track_fragment
+            .samples
+            .last()
+            .map_or(0, |last| last.chunk_index + 1);
+        if !reader.reserve(trun.sample_count as u64, size_of::<Sample>(), "trun") {
+            return;
+        }
+        for i in 0..trun.sample_count {
+            if reader.failed() {
+                break;
+            }
+            let entry = trun.parse_entry(reader);
+            let flags = match (i, trun.first_sample_flags) {
+                (0, Some(first_sample_flags)) => first_sample_flags,
+                _ => entry.sample_flags.unwrap_or(default_flags),
+            };
+            let sample = Sample {
+                offset,
+                size: entry.sample_size.unwrap_or(default_size),
+                decode_time,
+                composition_offset: entry.sample_composition_time_offset.unwrap_or(0),
+                duration: entry.sample_duration.unwrap_or(default_duration),
+                is_sync: flags & SAMPLE_IS_NON_SYNC_SAMPLE == 0,
+                chunk_index,
+                description_index,
+            };
+            // The values come from the file, so they may not add up
+            offset = offset.saturating_add(sample.size as u64);
+            decode_time = decode_time.saturating_add(sample.duration as u64);
+            track_fragment.samples.push(sample);
+        }
+        self.fragment_data_end = offset;
+        self.next_decode_times
+            .insert(track_fragment.track_id, decode_time);
+    }
+
+    /// Every child of ipco is a property, including those that aren't parsed, as ipma refers
+    /// to them by their index
+    fn parse_item_properties(&mut self, reader: &mut Reader, end_offset: u64) {
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_ = Mp4Box::parse_contents(reader, &header.box_type, header.inner_size);
+            self.item_properties
+                .push(ItemProperty::from_box(&header.box_type, box_));
+            let box_end_offset = header.start_offset + header.box_size;
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+    }
+
+    fn associate_item_properties(&mut self) {
+        for association in &self.item_associations {
+            let item = match self.items.iter_mut().find(|i| i.id == association.item_id) {
+                Some(item) => item,
+                None => {
+                    diagnostic!(
+                        Severity::Warning,
+                        "ipma refers to unknown item {}",
+                        association.item_id
+                    );
+                    continue;
+                }
+            };
+            for &(index, _essential) in &association.properties {
+                // 0 means that no property is associated
+                if index == 0 {
+                    continue;
+                }
+                match self.item_properties.get(index as usize - 1) {
+                    Some(property) => item.properties.push(property.clone()),
+                    None => diagnostic!(
+                        Severity::Warning,
+                        "ipma refers to property {}, but ipco only has {}",
+                        index,
+                        self.item_properties.len()
+                    ),
+                }
+            }
+        }
+    }
+
+    fn parse_track_box(track: &mut Track, reader: &mut Reader, box_: Mp4Box) {
+        let table = &mut track.sample_table;
+        match box_ {
+            Mp4Box::Tkhd(b) => track.id = b.track_id,
+            Mp4Box::Mdhd(b) => {
+                track.timescale = b.timescale;
+                track.duration = b.duration;
+                track.language = b.language;
+            }
+            Mp4Box::Hdlr(b) => track.handler_type = b.handler_type,
+            Mp4Box::Elst(b) => {
+                if !reader.reserve(b.entry_count as u64, size_of::<EditListEntry>(), "elst") {
+                    return;
+                }
+                for _ in 0..b.entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    track.edits.push(EditListBox::parse_entry(reader));
+                }
+            }
+            Mp4Box::Stsd(b) => {
+                if !reader.reserve(b.entry_count as u64, size_of::<SampleEntry>(), "stsd") {
+                    return;
+                }
+                for _ in 0..b.entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    track.sample_entries.push(b.parse_entry(reader));
+                }
+            }
+            Mp4Box::Stts(b) => {
+                let entry_size = size_of::<DecodingTimeToSampleEntry>();
+                if !reader.reserve(b.entry_count as u64, entry_size, "stts") {
+                    return;
+                }
+                for _ in 0..b.entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    table
+                        .time_to_sample
+                        .push(DecodingTimeToSampleBox::parse_entry(reader));
+                }
+            }
+            Mp4Box::Ctts(b) => {
+                let entry_size = size_of::<CompositionTimeToSampleEntry>();
+                if !reader.reserve(b.entry_count as u64, entry_size, "ctts") {
+                    return;
+                }
+                for _ in 0..b.entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    table
+                        .composition_offsets
+                        .push(CompositionTimeToSampleBox::parse_entry(reader));
+                }
+            }
+            Mp4Box::Stsc(b) => {
+                if !reader.reserve(
+                    b.entry_count as u64,
+                    size_of::<SampleToChunkEntry>(),
+                    "stsc",
+                ) {
+                    return;
+                }
+                for _ in 0..b.entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    table
+                        .sample_to_chunk
+                        .push(SampleToChunkBox::parse_entry(reader));
+                }
+            }
+            Mp4Box::Stsz(b) => {
+                // A fixed sample size needs no table in the file, so this also accounts for
+                // the samples that `SampleTable::samples` creates
+                let entry_size = size_of::<u32>() + size_of::<Sample>();
+                if !reader.reserve(b.sample_count as u64, entry_size, "stsz") {
+                    return;
+                }
+                if b.sample_size == 0 {
+                    for _ in 0..b.sample_count {
+                        if reader.failed() {
+                            break;
+                        }
+                        table.sample_sizes.push(SampleSizeBox::parse_entry(reader));
+                    }
+                } else {
+                    table.sample_sizes = vec![b.sample_size; b.sample_count as usize];
+                }
+            }
+            Mp4Box::Stco(b) => {
+                if !reader.reserve(b.entry_count as u64, size_of::<u64>(), "stco") {
+                    return;
+                }
+                for _ in 0..b.entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    let offset = ChunkOffsetBox::parse_entry(reader);
+                    table.chunk_offsets.push(offset as u64);
+                }
+            }
+            Mp4Box::Co64(b) => {
+                table.has_large_offsets = true;
+                if !reader.reserve(b.entry_count as u64, size_of::<u64>(), "co64") {
+                    return;
+                }
+                for _ in 0..b.entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    table
+                        .chunk_offsets
+                        .push(ChunkLargeOffsetBox::parse_entry(reader));
+                }
+            }
+            Mp4Box::Stss(b) => {
+                if !reader.reserve(b.entry_count as u64, size_of::<u32>(), "stss") {
+                    return;
+                }
+                let mut sync_samples = Vec::new();
+                for _ in 0..b.entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    sync_samples.push(SyncSampleBox::parse_entry(reader));
+                }
+                table.sync_samples = Some(sync_samples);
+            }
+            Mp4Box::Stdp(b) => {
+                // stsz usually comes first. If it doesn't, fill what fits in the box.
+                let sample_count = match table.sample_count() {
+                    0 => b.max_entry_count,
+                    n => n as u32,
+                };
+                if sample_count > b.max_entry_count {
+                    diagnostic!(
+                        Severity::Warning,
+                        "Track {}: stdp has room for {} entries, but there are {} samples",
+                        track.id,
+                        b.max_entry_count,
+                        sample_count
+                    );
+                }
+                let entry_count = sample_count.min(b.max_entry_count);
+                if !reader.reserve(entry_count as u64, size_of::<u16>(), "stdp") {
+                    return;
+                }
+                for _ in 0..entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    table
+                        .degradation_priorities
+                        .push(DegradationPriorityBox::parse_entry(reader));
+                }
+            }
+            Mp4Box::Padb(b) => {
+                if !table.sample_sizes.is_empty() && b.sample_count as usize != table.sample_count()
+                {
+                    diagnostic!(
+                        Severity::Warning,
+                        "Track {}: padb has {} samples, but stsz has {}",
+                        track.id,
+                        b.sample_count,
+                        table.sample_count()
+                    );
+                }
+                if !reader.reserve(b.sample_count as u64, size_of::<u8>(), "padb") {
+                    return;
+                }
+                for _ in 0..b.sample_count.div_ceil(2) {
+                    if reader.failed() {
+                        break;
+                    }
+                    let (first, second) = PaddingBitsBox::parse_entry(reader);
+                    table.padding_bits.push(first);
+                    table.padding_bits.push(second);
+                }
+                table.padding_bits.truncate(b.sample_count as usize);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fixtures"))]
+mod tests {
+    use super::*;
+    use crate::error::ParseErrorKind;
+    use crate::fixtures::{self, TrackFixture};
+    use core::convert::TryInto;
+
+    /// The offset of the first box of type `box_type`
+    fn find_box(buf: &[u8], box_type: &[u8; 4]) -> usize {
+        buf.windows(4).position(|w| w == box_type).unwrap() - 4
+    }
+
+    fn box_size(buf: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(buf[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Replaces `range` of `buf` with `bytes`, resizing the first box of each of `parents`
+    fn splice_box(buf: &mut Vec<u8>, range: Range<usize>, bytes: &[u8], parents: &[&[u8; 4]]) {
+        let growth = bytes.len() as i64 - range.len() as i64;
+        buf.splice(range, bytes.iter().copied());
+        for box_type in parents {
+            let offset = find_box(buf, box_type);
+            let size = (box_size(buf, offset) as i64 + growth) as u32;
+            buf[offset..offset + 4].copy_from_slice(&size.to_be_bytes());
+        }
+    }
+
+    /// Adds `property` at the end of ipco
+    fn with_item_property(mut buf: Vec<u8>, property: &[u8]) -> Vec<u8> {
+        let ipco_end = find_box(&buf, b"ipco") + box_size(&buf, find_box(&buf, b"ipco")) as usize;
+        splice_box(
+            &mut buf,
+            ipco_end..ipco_end,
+            property,
+            &[b"meta", b"iprp", b"ipco"],
+        );
+        buf
+    }
+
+    /// A '©nam' box with a 64-bit size of `size`, but only 8 bytes of text
+    fn text_box(size: u64) -> Vec<u8> {
+        let mut property = Vec::new();
+        property.extend_from_slice(&1u32.to_be_bytes());
+        property.extend_from_slice(b"\xA9nam");
+        property.extend_from_slice(&size.to_be_bytes());
+        property.extend_from_slice(b"\0\x04\0\0text");
+        property
+    }
+
+    #[test]
+    fn text_box_larger_than_the_file_is_not_allocated() {
+        let buf = with_item_property(fixtures::heif_image(64, 48), &text_box(1 << 40));
+        let options = ParserOptions {
+            max_alloc_bytes: 16 << 20,
+        };
+        let error = Movie::parse_with_options(&buf, &options).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::QuotaExceeded);
+        assert!(error.message.starts_with("QuotaExceeded: bytes would need"));
+    }
+
+    #[test]
+    fn text_box_larger_than_the_file_within_the_budget() {
+        let buf = with_item_property(fixtures::heif_image(64, 48), &text_box(1 << 20));
+        let error = Movie::parse(&buf).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::Malformed);
+    }
+
+    #[test]
+    fn chunk_offset_near_the_end_of_the_address_space() {
+        let track = TrackFixture {
+            sample_count: 2,
+            ..TrackFixture::audio()
+        };
+        let mut buf = fixtures::movie(&[track]);
+        let stco_offset = find_box(&buf, b"stco");
+        let mut co64 = Writer::new();
+        let start = co64.begin_box("co64");
+        co64.write_u32(0);
+        co64.write_u32(1);
+        co64.write_u64(u64::MAX - 10);
+        co64.end_box(start);
+        let stco_range = stco_offset..stco_offset + box_size(&buf, stco_offset) as usize;
+        splice_box(
+            &mut buf,
+            stco_range,
+            &co64.into_inner(),
+            &[b"moov", b"trak", b"mdia", b"minf", b"stbl"],
+        );
+
+        let movie = Movie::parse(&buf).unwrap();
+        let samples = movie.track_samples(&movie.tracks[0]);
+        let offsets: Vec<_> = samples.iter().map(|sample| sample.offset).collect();
+        assert_eq!(offsets, [u64::MAX - 10, u64::MAX]);
+    }
+}
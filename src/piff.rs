@@ -0,0 +1,104 @@
+//! PIFF, the format of Microsoft's Smooth Streaming, which stores encryption info and fragment
+//! timing in uuid boxes
+
+use crate::boxes::FullBoxHeader;
+use crate::reader::Reader;
+use alloc::{format, vec::Vec};
+
+/// Same layout as senc
+pub const SAMPLE_ENCRYPTION_UUID: [u8; 16] = [
+    0xA2, 0x39, 0x4F, 0x52, 0x5A, 0x9B, 0x4F, 0x14, 0xA2, 0x44, 0x6C, 0x42, 0x7C, 0x64, 0x8D, 0xF4,
+];
+
+/// Same layout as version 0 of tenc, in which the algorithm ID takes the place of the reserved
+/// bytes and the protection flag
+pub const TRACK_ENCRYPTION_UUID: [u8; 16] = [
+    0x89, 0x74, 0xDB, 0xCE, 0x7B, 0xE7, 0x4C, 0x51, 0x84, 0xF9, 0x71, 0x48, 0xF9, 0x88, 0x25, 0x54,
+];
+
+/// Same layout as version 0 of pssh
+pub const PROTECTION_SYSTEM_HEADER_UUID: [u8; 16] = [
+    0xD0, 0x8A, 0x4F, 0x18, 0x10, 0xF3, 0x4A, 0x82, 0xB6, 0xC8, 0x32, 0xD8, 0xAB, 0xA1, 0x83, 0xD3,
+];
+
+pub const TFXD_UUID: [u8; 16] = [
+    0x6D, 0x1D, 0x9B, 0x05, 0x42, 0xD5, 0x44, 0xE6, 0x80, 0xE2, 0x14, 0x1D, 0xAF, 0xF7, 0x57, 0xB2,
+];
+
+pub const TFRF_UUID: [u8; 16] = [
+    0xD4, 0x80, 0x7E, 0xF2, 0xCA, 0x39, 0x46, 0x95, 0x8E, 0x54, 0x26, 0xCB, 0x9E, 0x46, 0xA7, 0x9F,
+];
+
+/// The timescale of Smooth Streaming fragments, unless the manifest says otherwise
+pub const DEFAULT_TIMESCALE: u32 = 10_000_000;
+
+/// The size of the IVs in PIFF's sample encryption box, unless the track encryption box says
+/// otherwise
+pub const DEFAULT_IV_SIZE: u8 = 8;
+
+/// tfxd, the absolute time and duration of a fragment, in the track's timescale
+#[derive(Debug)]
+pub struct TfxdBox {
+    pub absolute_time: u64,
+    pub duration: u64,
+}
+
+impl TfxdBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let (absolute_time, duration) = read_time_and_duration(reader, full_box.version);
+        Self {
+            absolute_time,
+            duration,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Absolute time", &self.absolute_time);
+        print("Duration", &self.duration);
+    }
+}
+
+/// tfrf, the times of the fragments that follow, so that live clients can request them before
+/// the manifest is updated
+#[derive(Debug)]
+pub struct TfrfBox {
+    /// Absolute time and duration of each fragment
+    pub fragments: Vec<(u64, u64)>,
+}
+
+impl TfrfBox {
+    pub fn parse(reader: &mut Reader, _inner_size: u64) -> Self {
+        let full_box = FullBoxHeader::parse(reader);
+        let fragment_count = reader.read_u8();
+        let fragments = (0..fragment_count)
+            .map(|_| read_time_and_duration(reader, full_box.version))
+            .collect();
+        Self { fragments }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("# fragments", &self.fragments.len());
+        for (absolute_time, duration) in &self.fragments {
+            print(
+                "Fragment",
+                &format!("time {}, duration {}", absolute_time, duration),
+            );
+        }
+    }
+}
+
+/// 64-bit values in version 1, 32-bit ones in version 0
+fn read_time_and_duration(reader: &mut Reader, version: u8) -> (u64, u64) {
+    if version == 1 {
+        (reader.read_u64(), reader.read_u64())
+    } else {
+        (reader.read_u32() as u64, reader.read_u32() as u64)
+    }
+}
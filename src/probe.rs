@@ -0,0 +1,161 @@
+//! Reading only the boxes that describe a file, and seeking past the media data instead of
+//! reading it, to quickly check large files or many of them
+
+use std::convert::TryInto;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::boxes::BoxHeader;
+use crate::model::Movie;
+use crate::rewrite::top_level_boxes;
+
+#[derive(Debug)]
+pub struct Probe {
+    pub movie: Movie,
+    /// The top-level boxes up to moov or the first mdat, whichever comes last
+    pub top_level_boxes: Vec<BoxHeader>,
+    /// Whether moov has an mvex box, i.e. the file has movie fragments. These aren't read.
+    pub fragmented: bool,
+    /// How many bytes of the file were read
+    pub bytes_read: u64,
+}
+
+/// Reads the top-level box headers until both moov and the first mdat have been found, but only
+/// the contents of ftyp and moov. Other boxes, such as mdat, are skipped by seeking, so files
+/// with moov at the end are also probed quickly.
+pub fn probe<R: Read + Seek>(reader: &mut R) -> io::Result<Probe> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let mut offset = reader.seek(SeekFrom::Start(0))?;
+    let mut boxes: Vec<BoxHeader> = Vec::new();
+    // ftyp and moov, to be parsed like a file of their own
+    let mut header_boxes = Vec::new();
+    let mut bytes_read = 0;
+    while offset.saturating_add(8) <= file_len {
+        let header = read_box_header(reader, offset, file_len)?;
+        bytes_read += header.box_size - header.inner_size;
+        if header.box_type == "ftyp" || header.box_type == "moov" {
+            bytes_read += read_box(reader, &header, file_len, &mut header_boxes)?;
+        }
+        offset = offset.saturating_add(header.box_size);
+        boxes.push(header);
+        // Once moov has been read, the first mdat is only needed to tell whether it comes first
+        let has = |box_type: &str| boxes.iter().any(|b| b.box_type == box_type);
+        if has("moov") && has("mdat") {
+            break;
+        }
+    }
+
+    let movie = Movie::parse(&header_boxes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let fragmented = top_level_boxes(&header_boxes)
+        .iter()
+        .find(|b| b.box_type == "moov")
+        .is_some_and(|moov| {
+            let children_offset = (moov.start_offset + moov.box_size - moov.inner_size) as usize;
+            let moov_end = (moov.start_offset + moov.box_size) as usize;
+            top_level_boxes(&header_boxes[children_offset..moov_end])
+                .iter()
+                .any(|b| b.box_type == "mvex")
+        });
+    Ok(Probe {
+        movie,
+        top_level_boxes: boxes,
+        fragmented,
+        bytes_read,
+    })
+}
+
+/// Reads all top-level boxes except mdat, which is skipped by seeking. The boxes are returned
+/// one after the other, like a file without media data. Chunk and data offsets then no longer
+/// point at the samples, but the file can be parsed for everything that's in moov, including
+/// when it comes after mdat.
+pub fn read_without_media_data<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let mut offset = 0u64;
+    let mut buf = Vec::new();
+    while offset.saturating_add(8) <= file_len {
+        let header = read_box_header(reader, offset, file_len)?;
+        if header.box_type != "mdat" {
+            read_box(reader, &header, file_len, &mut buf)?;
+        }
+        offset = offset.saturating_add(header.box_size);
+    }
+    Ok(buf)
+}
+
+/// Like `read_without_media_data`, but the boxes stay where they are in the file, so that
+/// offsets still point at the right boxes: the buffer is as long as the file, with the payloads
+/// of the mdat boxes left as zeros. These aren't read, and as the buffer is allocated zeroed,
+/// operating systems typically don't back them with memory either.
+pub fn read_skipping_media_data<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let mut buf = vec![0; file_len as usize];
+    let mut offset = 0u64;
+    while offset.saturating_add(8) <= file_len {
+        let header = read_box_header(reader, offset, file_len)?;
+        let len = if header.box_type == "mdat" {
+            header.box_size - header.inner_size
+        } else {
+            header.box_size
+        };
+        let end = offset.saturating_add(len).min(file_len);
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut buf[offset as usize..end as usize])?;
+        offset = offset.saturating_add(header.box_size);
+    }
+    // Trailing bytes that are too few for a box header
+    if offset < file_len {
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut buf[offset as usize..])?;
+    }
+    Ok(buf)
+}
+
+/// Reads the header of the top-level box at `offset`, leaving the reader at its contents
+fn read_box_header<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    file_len: u64,
+) -> io::Result<BoxHeader> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut header = [0; 16];
+    let header_len = if offset + 16 <= file_len { 16 } else { 8 };
+    reader.read_exact(&mut header[..header_len])?;
+    let box_type = String::from_utf8_lossy(&header[4..8]).to_string();
+    let (box_size, header_size) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+        // The box extends to the end of the file
+        0 => (file_len - offset, 8),
+        // largesize
+        1 => (u64::from_be_bytes(header[8..16].try_into().unwrap()), 16),
+        size => (size as u64, 8),
+    };
+    if box_size < header_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Box {} (at {}) has invalid size: {}",
+                box_type, offset, box_size
+            ),
+        ));
+    }
+    Ok(BoxHeader {
+        start_offset: offset,
+        box_size,
+        box_type,
+        inner_size: box_size - header_size,
+    })
+}
+
+/// Appends the whole box, including its header, to `buf`. Returns the number of bytes read
+/// after the header.
+fn read_box<R: Read + Seek>(
+    reader: &mut R,
+    header: &BoxHeader,
+    file_len: u64,
+    buf: &mut Vec<u8>,
+) -> io::Result<u64> {
+    let mut contents = vec![0; header.box_size.min(file_len - header.start_offset) as usize];
+    reader.seek(SeekFrom::Start(header.start_offset))?;
+    reader.read_exact(&mut contents)?;
+    buf.extend_from_slice(&contents);
+    Ok((contents.len() as u64).saturating_sub(header.box_size - header.inner_size))
+}
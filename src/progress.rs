@@ -0,0 +1,65 @@
+//! Progress reporting for operations that can take a while on multi-GB files
+
+#[cfg(feature = "std")]
+use std::io::{IsTerminal, Write};
+
+/// Called with the number of bytes processed so far and the total number of bytes
+pub type Progress<'a> = dyn FnMut(u64, u64) + 'a;
+
+#[cfg(feature = "std")]
+const BAR_WIDTH: usize = 30;
+
+/// A progress bar on stderr. It is only drawn if stderr is a terminal, so that it doesn't end up
+/// in log files.
+#[cfg(feature = "std")]
+pub struct ProgressBar {
+    label: String,
+    enabled: bool,
+    percent: Option<u64>,
+}
+
+#[cfg(feature = "std")]
+impl ProgressBar {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            enabled: std::io::stderr().is_terminal(),
+            percent: None,
+        }
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = self.enabled && enabled;
+        self
+    }
+
+    /// Redraws the bar, if the percentage has changed since it was last drawn
+    pub fn update(&mut self, done: u64, total: u64) {
+        if !self.enabled {
+            return;
+        }
+        let percent = (done * 100).checked_div(total).unwrap_or(100).min(100);
+        if self.percent == Some(percent) {
+            return;
+        }
+        self.percent = Some(percent);
+        let filled = percent as usize * BAR_WIDTH / 100;
+        eprint!(
+            "\r{} [{}{}] {:>3}%",
+            self.label,
+            "=".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            percent
+        );
+        std::io::stderr().flush().unwrap();
+    }
+
+    /// Erases the bar, so that it doesn't mix with the output that follows
+    pub fn finish(&mut self) {
+        if self.enabled && self.percent.is_some() {
+            eprint!("\r\x1b[K");
+            std::io::stderr().flush().unwrap();
+        }
+        self.percent = None;
+    }
+}
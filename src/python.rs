@@ -0,0 +1,165 @@
+//! A Python module, built with `maturin build --features python`:
+//!
+//! ```python
+//! import mp4_parser
+//! movie = mp4_parser.parse("clip.mp4")
+//! for track in movie.tracks:
+//!     print(track.id, track.handler_type, track.codec, track.duration)
+//! ```
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::logger::{JsonBox, JsonLogger};
+use crate::model::Movie;
+use crate::summary::{Summary, TrackSummary};
+use crate::tree::{parse_mp4, BoxFilter};
+
+#[pyclass(name = "Movie", get_all, frozen, skip_from_py_object)]
+#[derive(Clone)]
+pub struct PyMovie {
+    major_brand: Option<String>,
+    compatible_brands: Vec<String>,
+    /// In seconds
+    duration: f64,
+    fragmented: bool,
+    faststart: bool,
+    title: Option<String>,
+    tracks: Vec<PyTrack>,
+    /// The top-level boxes
+    boxes: Vec<PyBox>,
+}
+
+#[pyclass(name = "Track", get_all, frozen, skip_from_py_object)]
+#[derive(Clone)]
+pub struct PyTrack {
+    id: u32,
+    handler_type: String,
+    codec: Option<String>,
+    language: String,
+    /// In seconds, after edit lists
+    duration: f64,
+    sample_count: usize,
+    width: Option<u16>,
+    height: Option<u16>,
+    sample_rate: Option<f32>,
+    channel_count: Option<u16>,
+    /// Average, in bits per second
+    bitrate: Option<f64>,
+}
+
+/// A box, or an entry such as a sample description, which has no type, offset or size
+#[pyclass(name = "Box", get_all, frozen, skip_from_py_object)]
+#[derive(Clone)]
+pub struct PyBox {
+    box_type: Option<String>,
+    offset: Option<u64>,
+    size: Option<u64>,
+    name: String,
+    /// Label and value, as printed by `parse`
+    attributes: Vec<(String, String)>,
+    warnings: Vec<String>,
+    children: Vec<PyBox>,
+}
+
+#[pymethods]
+impl PyMovie {
+    fn __repr__(&self) -> String {
+        format!(
+            "Movie(major_brand={:?}, duration={:.3}, tracks={})",
+            self.major_brand.as_deref().unwrap_or(""),
+            self.duration,
+            self.tracks.len()
+        )
+    }
+}
+
+#[pymethods]
+impl PyTrack {
+    fn __repr__(&self) -> String {
+        format!(
+            "Track(id={}, handler_type={:?}, codec={:?}, duration={:.3})",
+            self.id,
+            self.handler_type,
+            self.codec.as_deref().unwrap_or(""),
+            self.duration
+        )
+    }
+}
+
+#[pymethods]
+impl PyBox {
+    fn __repr__(&self) -> String {
+        format!(
+            "Box(type={:?}, name={:?}, children={})",
+            self.box_type.as_deref().unwrap_or(""),
+            self.name,
+            self.children.len()
+        )
+    }
+}
+
+/// Parses the file at `path`. Raises ValueError if it isn't an MP4 file that can be parsed.
+#[pyfunction]
+fn parse(path: &str) -> PyResult<PyMovie> {
+    let buf = std::fs::read(path).map_err(|e| PyOSError::new_err(format!("{}: {}", path, e)))?;
+    let movie =
+        Movie::parse(&buf).map_err(|e| PyValueError::new_err(format!("{}: {}", path, e)))?;
+    let summary = Summary::new(&buf, &movie);
+    let mut logger = JsonLogger::new();
+    // The boxes before a box that can't be parsed are still listed
+    let _ = parse_mp4(&buf, &mut logger, &BoxFilter::default());
+    let (boxes, _) = logger.into_boxes();
+
+    Ok(PyMovie {
+        major_brand: summary.major_brand,
+        compatible_brands: summary.compatible_brands,
+        duration: summary.duration,
+        fragmented: summary.fragmented,
+        faststart: summary.faststart,
+        title: summary.title,
+        tracks: summary.tracks.into_iter().map(PyTrack::from).collect(),
+        boxes: boxes.into_iter().map(PyBox::from).collect(),
+    })
+}
+
+impl From<TrackSummary> for PyTrack {
+    fn from(track: TrackSummary) -> Self {
+        Self {
+            id: track.id,
+            handler_type: track.handler_type,
+            codec: track.codec,
+            language: track.language,
+            duration: track.duration,
+            sample_count: track.sample_count,
+            width: track.width,
+            height: track.height,
+            sample_rate: track.sample_rate,
+            channel_count: track.channel_count,
+            bitrate: track.bitrate,
+        }
+    }
+}
+
+impl From<JsonBox> for PyBox {
+    fn from(json_box: JsonBox) -> Self {
+        Self {
+            box_type: json_box.box_type,
+            offset: json_box.offset,
+            size: json_box.size,
+            name: json_box.name,
+            attributes: json_box.attributes,
+            warnings: json_box.warnings,
+            children: json_box.children.into_iter().map(PyBox::from).collect(),
+        }
+    }
+}
+
+#[pymodule]
+fn mp4_parser(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(parse, module)?)?;
+    module.add_class::<PyMovie>()?;
+    module.add_class::<PyTrack>()?;
+    module.add_class::<PyBox>()?;
+    Ok(())
+}
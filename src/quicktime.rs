@@ -1,25 +1,488 @@
-use crate::boxes::BoxHeader;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use crate::boxes::{decode_language, BoxHeader};
 use crate::reader::Reader;
 
 #[derive(Debug)]
 pub struct MetadataItemList;
 
 impl MetadataItemList {
-    pub fn parse_entry(&self, reader: &mut Reader) -> EncoderTag {
+    pub fn parse_entry(&self, reader: &mut Reader) -> MetadataItem {
+        let header = BoxHeader::parse(reader);
+        if header.box_type == "----" {
+            return MetadataItem::Freeform(parse_freeform(reader, header.inner_size));
+        }
+        let (type_indicator, data) = parse_data(reader, header.inner_size);
+        match (header.box_type.as_ref(), type_indicator) {
+            ("©too", _) => {
+                MetadataItem::Encoder(EncoderTag(String::from_utf8_lossy(&data).into()))
+            }
+            ("covr", _) => match ImageFormat::detect(type_indicator, &data) {
+                Some(format) => MetadataItem::CoverArt(CoverArt { format, data }),
+                None => MetadataItem::Other {
+                    key: header.box_type,
+                    type_indicator,
+                    data,
+                },
+            },
+            (_, DATA_TYPE_UTF8) => MetadataItem::Text {
+                key: header.box_type,
+                value: String::from_utf8_lossy(&data).into(),
+            },
+            _ => MetadataItem::Other {
+                key: header.box_type,
+                type_indicator,
+                data,
+            },
+        }
+    }
+}
+
+/// Well-known types of the value in a 'data' box
+const DATA_TYPE_UTF8: u32 = 1;
+const DATA_TYPE_JPEG: u32 = 13;
+const DATA_TYPE_PNG: u32 = 14;
+const DATA_TYPE_BMP: u32 = 27;
+
+/// Reads the value of a metadata item, which is normally wrapped in a 'data' box that
+/// starts with a type indicator and a locale. Returns the type indicator and the value.
+fn parse_data(reader: &mut Reader, inner_size: u64) -> (u32, Vec<u8>) {
+    let end_offset = reader.position() + inner_size;
+    let mut result = None;
+    while reader.position() + 16 <= end_offset && !reader.failed() {
+        let header = BoxHeader::parse(reader);
+        let box_end_offset = header.start_offset + header.box_size;
+        if header.box_type == "data" && result.is_none() && header.inner_size >= 8 {
+            let type_indicator = reader.read_u32() & 0x00FF_FFFF;
+            let _locale = reader.read_u32();
+            let data = reader.read_bytes((header.inner_size as usize).saturating_sub(8));
+            result = Some((type_indicator, data));
+        }
+        let remaining = box_end_offset.saturating_sub(reader.position());
+        reader.skip(remaining);
+    }
+    let remaining = end_offset.saturating_sub(reader.position());
+    reader.skip(remaining);
+    result.unwrap_or((0, Vec::new()))
+}
+
+/// Reads a freeform ('----') item, whose key is given by its 'mean' and 'name' boxes, and whose
+/// value is in a 'data' box
+fn parse_freeform(reader: &mut Reader, inner_size: u64) -> FreeformItem {
+    let end_offset = reader.position() + inner_size;
+    let mut item = FreeformItem {
+        domain: String::new(),
+        key: String::new(),
+        value: String::new(),
+    };
+    while reader.position() + 8 <= end_offset && !reader.failed() {
         let header = BoxHeader::parse(reader);
+        let box_end_offset = header.start_offset + header.box_size;
         match header.box_type.as_ref() {
-            "©too" => EncoderTag::parse(reader, header.inner_size),
-            _ => todo!("Handle quicktime metadata item entry: {}", header.box_type),
+            "mean" | "name" if header.inner_size >= 4 => {
+                let _version_and_flags = reader.read_u32();
+                let bytes = reader.read_bytes((header.inner_size as usize).saturating_sub(4));
+                let text = String::from_utf8_lossy(&bytes).into();
+                if header.box_type == "mean" {
+                    item.domain = text;
+                } else {
+                    item.key = text;
+                }
+            }
+            "data" if header.inner_size >= 8 => {
+                let _type_indicator = reader.read_u32();
+                let _locale = reader.read_u32();
+                let bytes = reader.read_bytes((header.inner_size as usize).saturating_sub(8));
+                item.value = String::from_utf8_lossy(&bytes).into();
+            }
+            _ => {}
+        }
+        let remaining = box_end_offset.saturating_sub(reader.position());
+        reader.skip(remaining);
+    }
+    let remaining = end_offset.saturating_sub(reader.position());
+    reader.skip(remaining);
+    item
+}
+
+#[derive(Debug, Clone)]
+pub enum MetadataItem {
+    Encoder(EncoderTag),
+    /// Artwork (covr)
+    CoverArt(CoverArt),
+    /// An item with a reverse-DNS domain, e.g. iTunes' gapless info or encoder settings
+    Freeform(FreeformItem),
+    /// A text item, e.g. the title (©nam) or the artist (©ART)
+    Text {
+        key: String,
+        value: String,
+    },
+    Other {
+        key: String,
+        type_indicator: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl MetadataItem {
+    pub fn key(&self) -> &str {
+        match self {
+            MetadataItem::Encoder(_) => "©too",
+            MetadataItem::CoverArt(_) => "covr",
+            MetadataItem::Freeform(_) => "----",
+            MetadataItem::Text { key, .. } => key,
+            MetadataItem::Other { key, .. } => key,
+        }
+    }
+}
+
+impl fmt::Display for MetadataItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataItem::Encoder(EncoderTag(encoder)) => write!(f, "Encoder: {}", encoder),
+            MetadataItem::CoverArt(cover_art) => write!(
+                f,
+                "Cover art: {}, {} bytes",
+                cover_art.format,
+                cover_art.data.len()
+            ),
+            MetadataItem::Freeform(item) => write!(f, "{}", item),
+            MetadataItem::Text { key, value } => write!(f, "{}: {}", key, value),
+            MetadataItem::Other {
+                key,
+                type_indicator,
+                data,
+            } => write!(f, "{}: {} bytes (type {})", key, data.len(), type_indicator),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EncoderTag(pub String);
+
+/// An annotation stored directly in udta, as QuickTime does, rather than in a metadata item
+/// list, e.g. the recording date (©day) or the camera's make (©mak) and model (©mod)
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    /// E.g. "©day"
+    pub key: String,
+    /// ISO-639-2/T, e.g. "eng"
+    pub language: String,
+    pub text: String,
+}
+
+/// A ©-prefixed box in udta. It holds the same text in one or more languages, each as a run of
+/// a 16-bit size, a 16-bit language code and the text.
+#[derive(Debug)]
+pub struct UserDataTextBox {
+    /// Empty if the contents aren't text runs, as in some camera makers' boxes
+    pub annotations: Vec<Annotation>,
+    pub data_len: u64,
+}
+
+/// The languages of Macintosh language codes 0 to 14, which QuickTime uses below 0x400
+const MAC_LANGUAGES: [&str; 15] = [
+    "eng", "fra", "deu", "ita", "nld", "swe", "spa", "dan", "por", "nor", "heb", "jpn", "ara",
+    "fin", "ell",
+];
+
+/// Bytes 0x80 to 0xFF of Mac OS Roman, the text encoding of annotations with a Macintosh
+/// language code
+const MAC_ROMAN: &str = "ÄÅÇÉÑÖÜáàâäãåçéèêëíìîïñóòôöõúùûü†°¢£§•¶ß®©™´¨≠ÆØ∞±≤≥¥µ∂∑∏π∫ªºΩæø¿¡¬√ƒ≈∆«»…\u{A0}ÀÃÕŒœ–—“”‘’÷◊ÿŸ⁄€‹›ﬁﬂ‡·‚„‰ÂÊÁËÈÍÎÏÌÓÔ\u{F8FF}ÒÚÛÙıˆ˜¯˘˙˚¸˝˛ˇ";
+
+impl UserDataTextBox {
+    pub fn parse(reader: &mut Reader, key: &str, inner_size: u64) -> Self {
+        let data = reader.read_bytes(inner_size as usize);
+        let mut annotations = Vec::new();
+        let mut rest = data.as_slice();
+        while rest.len() >= 4 {
+            let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+            let code = u16::from_be_bytes([rest[2], rest[3]]);
+            let text = match rest.get(4..4 + len) {
+                Some(text) => text,
+                None => break,
+            };
+            let (language, text) = if code < 0x400 {
+                let language = MAC_LANGUAGES.get(code as usize).unwrap_or(&"und");
+                (language.to_string(), mac_text(text))
+            } else {
+                (
+                    decode_language(code),
+                    String::from_utf8_lossy(text).to_string(),
+                )
+            };
+            annotations.push(Annotation {
+                key: key.to_string(),
+                language,
+                text: text.trim_end_matches('\0').to_string(),
+            });
+            rest = &rest[4 + len..];
+        }
+        // Anything but padding after the runs means that they weren't text runs after all
+        if rest.iter().any(|&b| b != 0) {
+            annotations.clear();
+        }
+        Self {
+            annotations,
+            data_len: inner_size,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        for annotation in &self.annotations {
+            print(&format!("Text ({})", annotation.language), &annotation.text);
+        }
+        if self.annotations.is_empty() {
+            print("Data", &format!("{} bytes, not text", self.data_len));
+        }
+    }
+}
+
+/// UTF-8 if it's valid, and Mac OS Roman otherwise
+fn mac_text(bytes: &[u8]) -> String {
+    match core::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes
+            .iter()
+            .map(|&b| match b {
+                0..=0x7F => b as char,
+                _ => MAC_ROMAN.chars().nth(b as usize - 0x80).unwrap(),
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CoverArt {
+    pub format: ImageFormat,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Bmp,
+}
+
+impl ImageFormat {
+    /// From the type of the 'data' box, or from the image's signature if the type is implicit
+    fn detect(type_indicator: u32, data: &[u8]) -> Option<Self> {
+        match type_indicator {
+            DATA_TYPE_JPEG => Some(ImageFormat::Jpeg),
+            DATA_TYPE_PNG => Some(ImageFormat::Png),
+            DATA_TYPE_BMP => Some(ImageFormat::Bmp),
+            _ if data.starts_with(&[0xFF, 0xD8, 0xFF]) => Some(ImageFormat::Jpeg),
+            _ if data.starts_with(b"\x89PNG") => Some(ImageFormat::Png),
+            _ if data.starts_with(b"BM") => Some(ImageFormat::Bmp),
+            _ => None,
+        }
+    }
+
+    /// Whether a file with this extension is expected to be in this format, e.g. "jpeg" or "JPG"
+    pub fn matches_extension(&self, extension: &str) -> bool {
+        let extension = extension.to_ascii_lowercase();
+        extension == self.extension() || (*self == ImageFormat::Jpeg && extension == "jpeg")
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::Bmp => "bmp",
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageFormat::Jpeg => write!(f, "JPEG"),
+            ImageFormat::Png => write!(f, "PNG"),
+            ImageFormat::Bmp => write!(f, "BMP"),
         }
     }
 }
 
+/// A '----' item, e.g. "com.apple.iTunes" / "iTunSMPB"
+#[derive(Debug, Clone)]
+pub struct FreeformItem {
+    /// From the 'mean' box
+    pub domain: String,
+    /// From the 'name' box
+    pub key: String,
+    pub value: String,
+}
+
+impl FreeformItem {
+    /// Decodes the value if this is iTunes' gapless playback info
+    pub fn gapless_info(&self) -> Option<GaplessInfo> {
+        if self.domain == "com.apple.iTunes" && self.key == "iTunSMPB" {
+            GaplessInfo::parse(&self.value)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for FreeformItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.domain, self.key, self.value.trim())?;
+        if let Some(gapless_info) = self.gapless_info() {
+            write!(f, " ({})", gapless_info)?;
+        }
+        Ok(())
+    }
+}
+
+/// How many samples the encoder added before and after the audio, from iTunSMPB
+#[derive(Debug, Clone, PartialEq)]
+pub struct GaplessInfo {
+    /// Encoder delay, to be skipped at the start
+    pub priming_samples: u32,
+    /// To be skipped at the end
+    pub padding_samples: u32,
+    /// Number of samples without the priming and padding
+    pub original_sample_count: u64,
+}
+
+impl GaplessInfo {
+    /// Parses the hex fields of iTunSMPB, e.g. " 00000000 00000840 000001CA 00000000003F31F6 ...".
+    /// The first field is reserved.
+    pub fn parse(value: &str) -> Option<Self> {
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        Some(Self {
+            priming_samples: u32::from_str_radix(fields.get(1)?, 16).ok()?,
+            padding_samples: u32::from_str_radix(fields.get(2)?, 16).ok()?,
+            original_sample_count: u64::from_str_radix(fields.get(3)?, 16).ok()?,
+        })
+    }
+}
+
+impl fmt::Display for GaplessInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "priming: {} samples, padding: {} samples, original length: {} samples",
+            self.priming_samples, self.padding_samples, self.original_sample_count
+        )
+    }
+}
+
+/// mebx, the sample entry of QuickTime timed metadata tracks (handler 'mdta'), e.g. the
+/// still image times and slow-motion ranges in iPhone videos. Each sample holds boxes whose
+/// types are local key ids from the key table.
 #[derive(Debug)]
-pub struct EncoderTag(String);
+pub struct MetadataSampleEntry {
+    pub data_reference_index: u16,
+    pub keys: Vec<MetadataKey>,
+}
 
-impl EncoderTag {
+/// An entry of the local key table ('keys') of a mebx sample entry
+#[derive(Debug, Clone)]
+pub struct MetadataKey {
+    /// The box type of the entry, which is also the box type of the values in the samples
+    pub local_key_id: u32,
+    /// From 'keyd', e.g. "mdta"
+    pub namespace: String,
+    /// From 'keyd', e.g. "com.apple.quicktime.still-image-time"
+    pub value: String,
+    /// Well-known type of the values, from 'dtyp', if it's in the well-known namespace
+    pub data_type: Option<u32>,
+}
+
+impl MetadataSampleEntry {
     pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
-        let content = reader.read_string(inner_size as usize);
-        Self(content)
+        let end_offset = reader.position() + inner_size;
+        let _reserved = reader.read_bytes(6);
+        let data_reference_index = reader.read_u16();
+
+        let mut keys = Vec::new();
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            if header.box_type == "keys" {
+                while reader.position() + 8 <= box_end_offset && !reader.failed() {
+                    keys.push(MetadataKey::parse(reader));
+                }
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+        let remaining = end_offset.saturating_sub(reader.position());
+        reader.skip(remaining);
+
+        Self {
+            data_reference_index,
+            keys,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        for key in &self.keys {
+            print(&format!("Key {}", key.local_key_id), key);
+        }
+    }
+}
+
+impl MetadataKey {
+    /// Reads a key box, whose type is the local key id, and its 'keyd' and 'dtyp' boxes
+    fn parse(reader: &mut Reader) -> Self {
+        let start_offset = reader.position();
+        let box_size = reader.read_u32() as u64;
+        let local_key_id = reader.read_u32();
+        let end_offset = start_offset + box_size.max(8);
+
+        let mut key = Self {
+            local_key_id,
+            namespace: String::new(),
+            value: String::new(),
+            data_type: None,
+        };
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            match header.box_type.as_ref() {
+                "keyd" if header.inner_size >= 4 => {
+                    key.namespace = String::from_utf8_lossy(&reader.read_bytes(4)).into();
+                    let value = reader.read_bytes((header.inner_size as usize).saturating_sub(4));
+                    key.value = String::from_utf8_lossy(&value).into();
+                }
+                "dtyp" if header.inner_size >= 8 => {
+                    let namespace = reader.read_u32();
+                    if namespace == 0 {
+                        key.data_type = Some(reader.read_u32());
+                    }
+                }
+                _ => {}
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+        let remaining = end_offset.saturating_sub(reader.position());
+        reader.skip(remaining);
+        key
+    }
+}
+
+impl fmt::Display for MetadataKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.value)?;
+        if let Some(data_type) = self.data_type {
+            write!(f, " (type {})", data_type)?;
+        }
+        Ok(())
     }
 }
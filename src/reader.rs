@@ -1,105 +1,449 @@
-use std::convert::TryInto;
-use std::io::{Cursor, Read, Seek, SeekFrom};
-use std::string::FromUtf8Error;
+use alloc::rc::Rc;
+use alloc::string::FromUtf8Error;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cell::{Cell, RefCell};
+use core::convert::TryInto;
+use core::fmt;
 
+use crate::brand::Profile;
+use crate::error::{ParseError, ParseErrorKind};
+
+/// Enough for the sample tables of many hours of video, but not for a crafted table that
+/// claims billions of entries
+pub const DEFAULT_MAX_ALLOC_BYTES: u64 = 1 << 30;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParserOptions {
+    /// How much memory the parsed tables and lists may take up in total. Parsing fails with
+    /// `ParseErrorKind::QuotaExceeded` before allocating more.
+    pub max_alloc_bytes: u64,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            max_alloc_bytes: DEFAULT_MAX_ALLOC_BYTES,
+        }
+    }
+}
+
+/// A box declared more entries, or more bytes, than the memory budget allows, see
+/// `ParserOptions`
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    pub what: String,
+    pub requested_bytes: u64,
+    pub remaining_bytes: u64,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "QuotaExceeded: {} would need {} bytes, but only {} bytes are left of the budget",
+            self.what, self.requested_bytes, self.remaining_bytes
+        )
+    }
+}
+
+/// A value that a `Reader` read, see `Reader::record_fields`
+#[derive(Debug, Clone)]
+pub struct FieldRead {
+    pub offset: u64,
+    pub len: u64,
+    /// The field's name if the parser gave it one (see `Reader::name_field`), otherwise what
+    /// was read, e.g. "u32"
+    pub name: &'static str,
+    pub value: String,
+}
+
+/// Reads big-endian values straight from the borrowed buffer. Reading past its end fails the
+/// reader (see `fail`) and gives zeroes.
 pub struct Reader<'a> {
-    cursor: Cursor<&'a [u8]>,
+    buf: &'a [u8],
+    position: u64,
+    /// Set for readers of a single box, so that its parser can't read into the boxes after it
+    limit: Option<u64>,
+    /// How many bytes were requested past the limit
+    overrun: u64,
+    /// From the ftyp box, once it has been parsed
+    profile: Profile,
+    /// What's left of `ParserOptions::max_alloc_bytes`, shared with `limited` readers
+    alloc_budget: Rc<Cell<u64>>,
+    /// Every value read so far, if `record_fields` was called. Shared with `limited` readers.
+    fields: Option<Rc<RefCell<Vec<FieldRead>>>>,
+    /// The first parse error, see `fail`. Shared with `limited` readers.
+    error: Rc<RefCell<Option<ParseError>>>,
 }
 
 impl<'a> Reader<'a> {
     pub fn new(buf: &'a [u8]) -> Self {
+        Self::with_options(buf, &ParserOptions::default())
+    }
+
+    pub fn with_options(buf: &'a [u8], options: &ParserOptions) -> Self {
         Self {
-            cursor: Cursor::new(buf),
+            buf,
+            position: 0,
+            limit: None,
+            overrun: 0,
+            profile: Profile::default(),
+            alloc_budget: Rc::new(Cell::new(options.max_alloc_bytes)),
+            fields: None,
+            error: Rc::default(),
+        }
+    }
+
+    /// A reader for the next `len` bytes, e.g. the contents of a box. Reading past them gives
+    /// zeroes, and is counted by `overrun`, instead of reading into whatever comes after.
+    pub fn limited(&self, len: u64) -> Reader<'a> {
+        Reader {
+            buf: self.buf,
+            position: self.position,
+            limit: Some(self.position() + len),
+            overrun: 0,
+            profile: self.profile,
+            alloc_budget: Rc::clone(&self.alloc_budget),
+            fields: self.fields.clone(),
+            error: Rc::clone(&self.error),
+        }
+    }
+
+    /// Takes the memory for `count` entries of `entry_size` bytes from the budget. Call this
+    /// before parsing a table whose length comes from the file, so that a huge count fails
+    /// instead of running out of memory. If it returns false, the reader has failed and the
+    /// entries should be left out.
+    #[must_use]
+    pub fn reserve(&self, count: u64, entry_size: usize, what: &str) -> bool {
+        let requested_bytes = count.saturating_mul(entry_size as u64);
+        let remaining_bytes = self.alloc_budget.get();
+        if requested_bytes > remaining_bytes {
+            let error = QuotaExceeded {
+                what: what.to_string(),
+                requested_bytes,
+                remaining_bytes,
+            };
+            self.record_error(ParseError {
+                kind: ParseErrorKind::QuotaExceeded,
+                ..ParseError::in_current_box(error.to_string())
+            });
+            return false;
+        }
+        self.alloc_budget.set(remaining_bytes - requested_bytes);
+        true
+    }
+
+    /// Records that the file can't be parsed, in the box that is being parsed (see
+    /// `box_path`), unless an earlier error was recorded. The parsers go on with zeroes, but
+    /// the loops over the boxes stop, and the error is returned by e.g. `Movie::parse`.
+    pub fn fail(&self, message: String) {
+        self.record_error(ParseError::in_current_box(message));
+    }
+
+    fn record_error(&self, error: ParseError) {
+        let mut first_error = self.error.borrow_mut();
+        if first_error.is_none() {
+            *first_error = Some(error);
+        }
+    }
+
+    /// Whether `fail` has been called
+    pub fn failed(&self) -> bool {
+        self.error.borrow().is_some()
+    }
+
+    /// The error recorded by `fail`, if any
+    pub fn take_error(&self) -> Option<ParseError> {
+        self.error.borrow_mut().take()
+    }
+
+    /// Makes the reader, and the `limited` readers made from it, remember the offset and value
+    /// of everything they read, e.g. for an annotated hexdump
+    pub fn record_fields(&mut self) {
+        self.fields = Some(Rc::default());
+    }
+
+    /// The values read since the last call, in the order they were read
+    pub fn take_fields(&self) -> Vec<FieldRead> {
+        match &self.fields {
+            Some(fields) => fields.take(),
+            None => Vec::new(),
         }
     }
 
+    /// Names the value that was read last, e.g. "version"
+    pub fn name_field(&self, name: &'static str) {
+        if let Some(fields) = &self.fields {
+            if let Some(field) = fields.borrow_mut().last_mut() {
+                field.name = name;
+            }
+        }
+    }
+
+    /// Records the value read from `offset` up to the current position. It replaces the
+    /// values read after `offset`, which were the parts of it, e.g. the bytes of a u24 in an i24.
+    fn record(&self, offset: u64, name: &'static str, value: impl FnOnce() -> String) {
+        if let Some(fields) = self.fields.as_ref().filter(|_| self.position > offset) {
+            let mut fields = fields.borrow_mut();
+            while fields.last().is_some_and(|field| field.offset >= offset) {
+                fields.pop();
+            }
+            fields.push(FieldRead {
+                offset,
+                len: self.position - offset,
+                name,
+                value: value(),
+            });
+        }
+    }
+
+    /// Which brand-specific rules the boxes are parsed by
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = profile;
+    }
+
+    /// How many bytes were read or skipped past the limit of a `limited` reader
+    pub fn overrun(&self) -> u64 {
+        self.overrun
+    }
+
     pub fn position(&self) -> u64 {
-        self.cursor.position()
+        self.position
+    }
+
+    /// The length of the whole buffer, regardless of any limit
+    pub fn file_len(&self) -> u64 {
+        self.buf.len() as u64
+    }
+
+    /// Where the reader's limit is, or the end of the file if that comes first
+    pub fn end_offset(&self) -> u64 {
+        self.limit.unwrap_or(u64::MAX).min(self.file_len())
     }
 
     pub fn read_u8(&mut self) -> u8 {
-        let mut buf = [0; 1];
-        self.cursor.read_exact(&mut buf).unwrap();
-        u8::from_be_bytes((&buf[..]).try_into().unwrap())
+        let offset = self.position;
+        let value = u8::from_be_bytes(self.read_array());
+        self.record(offset, "u8", || value.to_string());
+        value
     }
 
     pub fn read_u16(&mut self) -> u16 {
-        let mut buf = [0; 2];
-        self.cursor.read_exact(&mut buf).unwrap();
-        u16::from_be_bytes((&buf[..]).try_into().unwrap())
+        let offset = self.position;
+        let value = u16::from_be_bytes(self.read_array());
+        self.record(offset, "u16", || value.to_string());
+        value
     }
 
     pub fn read_i16(&mut self) -> i16 {
-        let mut buf = [0; 2];
-        self.cursor.read_exact(&mut buf).unwrap();
-        i16::from_be_bytes((&buf[..]).try_into().unwrap())
+        let offset = self.position;
+        let value = i16::from_be_bytes(self.read_array());
+        self.record(offset, "i16", || value.to_string());
+        value
+    }
+
+    pub fn read_u24(&mut self) -> u32 {
+        let offset = self.position;
+        let [a, b, c] = self.read_array();
+        let value = u32::from_be_bytes([0, a, b, c]);
+        self.record(offset, "u24", || value.to_string());
+        value
+    }
+
+    pub fn read_i24(&mut self) -> i32 {
+        let offset = self.position;
+        // Shifted to the top and back, which extends the sign
+        let value = ((self.read_u24() << 8) as i32) >> 8;
+        self.record(offset, "i24", || value.to_string());
+        value
     }
 
     pub fn read_u32(&mut self) -> u32 {
-        let mut buf = [0; 4];
-        self.cursor.read_exact(&mut buf).unwrap();
-        u32::from_be_bytes((&buf[..]).try_into().unwrap())
+        let offset = self.position;
+        let value = u32::from_be_bytes(self.read_array());
+        self.record(offset, "u32", || value.to_string());
+        value
     }
 
     pub fn read_i32(&mut self) -> i32 {
-        let mut buf = [0; 4];
-        self.cursor.read_exact(&mut buf).unwrap();
-        i32::from_be_bytes((&buf[..]).try_into().unwrap())
+        let offset = self.position;
+        let value = i32::from_be_bytes(self.read_array());
+        self.record(offset, "i32", || value.to_string());
+        value
     }
 
     pub fn read_u64(&mut self) -> u64 {
-        let mut buf = [0; 8];
-        self.cursor.read_exact(&mut buf).unwrap();
-        u64::from_be_bytes((&buf[..]).try_into().unwrap())
+        let offset = self.position;
+        let value = u64::from_be_bytes(self.read_array());
+        self.record(offset, "u64", || value.to_string());
+        value
+    }
+
+    /// The size of an MPEG-4 descriptor, as in esds: 7 bits per byte, with the top bit set on
+    /// all bytes but the last, in at most 4 bytes
+    pub fn read_descriptor_size(&mut self) -> u32 {
+        let offset = self.position;
+        let mut size = 0;
+        for _ in 0..4 {
+            let byte = self.read_u8();
+            size = size << 7 | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        self.record(offset, "descriptor size", || size.to_string());
+        size
     }
 
     pub fn read_fixed_point_16_16(&mut self) -> f32 {
-        let mut buf = [0; 4];
-        self.cursor.read_exact(&mut buf).unwrap();
-        let n = u32::from_be_bytes((&buf[..]).try_into().unwrap());
-        n as f32 / 2_u32.pow(16) as f32
+        let offset = self.position;
+        let n = self.read_u32();
+        let value = n as f32 / 2_u32.pow(16) as f32;
+        self.record(offset, "16.16", || value.to_string());
+        value
     }
 
     pub fn read_fixed_point_8_8(&mut self) -> f32 {
-        let mut buf = [0; 2];
-        self.cursor.read_exact(&mut buf).unwrap();
-        let n = u16::from_be_bytes((&buf[..]).try_into().unwrap());
-        n as f32 / 2_u32.pow(8) as f32
+        let offset = self.position;
+        let n = self.read_u16();
+        let value = n as f32 / 2_u32.pow(8) as f32;
+        self.record(offset, "8.8", || value.to_string());
+        value
     }
 
     pub fn read_string(&mut self, len: usize) -> String {
-        self.try_read_string(len).unwrap()
+        let position = self.position;
+        self.try_read_string(len).unwrap_or_else(|e| {
+            self.fail(format!(
+                "Invalid UTF-8 string at {}: {:?}",
+                position,
+                e.as_bytes()
+            ));
+            String::from_utf8_lossy(e.as_bytes()).to_string()
+        })
     }
 
     pub fn try_read_string(&mut self, len: usize) -> Result<String, FromUtf8Error> {
-        let mut buf = Vec::new();
-        buf.resize(len, 0);
-        self.cursor.read_exact(&mut buf).unwrap();
-        String::from_utf8(buf)
+        let offset = self.position;
+        let value = String::from_utf8(self.read_bytes(len));
+        if let Ok(value) = &value {
+            self.record(offset, "string", || format!("{:?}", value));
+        }
+        value
     }
 
     pub fn read_string_inexact(&mut self, max_len: usize) -> String {
-        let mut buf = Vec::new();
-        buf.resize(max_len, 0);
-        let n_read = self.cursor.read(&mut buf).unwrap();
-        String::from_utf8_lossy(&buf[..n_read]).to_string()
+        let offset = self.position;
+        let start = (self.position as usize).min(self.buf.len());
+        let end = (start + self.available(max_len as u64) as usize).min(self.buf.len());
+        self.position = end as u64;
+        let value = String::from_utf8_lossy(&self.buf[start..end]).to_string();
+        self.record(offset, "string", || format!("{:?}", value));
+        value
     }
 
     pub fn read_bytes(&mut self, n_bytes: usize) -> Vec<u8> {
-        let mut buf = Vec::new();
-        buf.resize(n_bytes, 0);
-        self.cursor.read_exact(&mut buf).unwrap();
+        // The length comes from the file, so the bytes are taken from the budget before they
+        // are allocated, and a corrupt one fails instead of running out of memory
+        let offset = self.position;
+        let buf = if self.reserve(n_bytes as u64, 1, "bytes") {
+            let available = self.available(n_bytes as u64) as usize;
+            let mut buf = self.take(available).to_vec();
+            buf.resize(n_bytes, 0);
+            self.overrun += (n_bytes - available) as u64;
+            buf
+        } else {
+            self.skip(n_bytes as u64);
+            Vec::new()
+        };
+        self.record(offset, "bytes", || describe_bytes(&buf));
+        buf
+    }
+
+    /// Reads bytes without moving past them
+    pub fn peek_bytes(&mut self, n_bytes: usize) -> Vec<u8> {
+        let pos = self.position;
+        let overrun = self.overrun;
+        let buf = self.read_bytes(n_bytes);
+        self.position = pos;
+        self.overrun = overrun;
+        if let Some(fields) = &self.fields {
+            fields.borrow_mut().pop();
+        }
         buf
     }
 
     pub fn read_exact(&mut self, buf: &mut [u8]) {
-        self.cursor.read_exact(buf).unwrap();
+        let offset = self.position;
+        self.fill(buf);
+        self.record(offset, "bytes", || describe_bytes(buf));
     }
 
-    pub fn skip_bytes(&mut self, n_bytes: u32) -> Result<(), String> {
-        let pos = self.cursor.position();
-        let target = pos + n_bytes as u64;
-        let file_len = self.cursor.get_ref().len() as u64;
+    fn read_array<const N: usize>(&mut self) -> [u8; N] {
+        let available = self.available(N as u64) as usize;
+        if available == N {
+            // The common case, without the zero-filling
+            return self.take(N).try_into().unwrap();
+        }
+        let mut array = [0; N];
+        self.fill(&mut array);
+        array
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        let available = self.available(buf.len() as u64) as usize;
+        buf[..available].copy_from_slice(self.take(available));
+        for b in &mut buf[available..] {
+            *b = 0;
+        }
+        self.overrun += (buf.len() - available) as u64;
+    }
+
+    /// The next `n_bytes` of the buffer, which must all be there (see `available`)
+    fn take(&mut self, n_bytes: usize) -> &'a [u8] {
+        let start = self.position as usize;
+        let bytes = &self.buf[start..start + n_bytes];
+        self.position += n_bytes as u64;
+        bytes
+    }
+
+    /// How many of the next `n_bytes` are before the limit and the end of the file. Reading
+    /// past the end of the file fails the reader.
+    fn available(&self, n_bytes: u64) -> u64 {
+        let file_len = self.file_len();
+        let available = n_bytes.min(self.end_offset().saturating_sub(self.position));
+        // Reading past a limit within the file is an overrun, not an error
+        let limited_within_file = self.limit.is_some_and(|limit| limit <= file_len);
+        if !limited_within_file && self.position.saturating_add(n_bytes) > file_len {
+            self.fail(format!(
+                "Reading {} bytes at {} goes past the end of the file ({} bytes)",
+                n_bytes, self.position, file_len
+            ));
+        }
+        available
+    }
+
+    /// Like `skip_bytes`, for parsers that can't go on past the end of the file. If the file
+    /// ends first, the reader fails and moves to the end.
+    pub fn skip(&mut self, n_bytes: u64) {
+        if let Err(e) = self.skip_bytes(n_bytes) {
+            self.fail(e);
+            self.position = self.file_len();
+        }
+    }
+
+    pub fn skip_bytes(&mut self, n_bytes: u64) -> Result<(), String> {
+        let pos = self.position;
+        let target = pos.saturating_add(n_bytes);
+        let file_len = self.buf.len() as u64;
         if target > file_len {
             let err = format!(
                 "Seeking {} from {} would land on {}, but the file is only {} bytes long",
@@ -107,7 +451,215 @@ impl<'a> Reader<'a> {
             );
             return Err(err);
         }
-        self.cursor.seek(SeekFrom::Current(n_bytes as i64)).unwrap();
+        let available = self.available(n_bytes);
+        self.position += available;
+        self.overrun += n_bytes - available;
         Ok(())
     }
 }
+
+/// The bytes as text if they're all printable ASCII, e.g. a box type, otherwise their count
+fn describe_bytes(bytes: &[u8]) -> String {
+    if !bytes.is_empty() && bytes.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        format!("{:?}", String::from_utf8_lossy(bytes))
+    } else {
+        format!("{} bytes", bytes.len())
+    }
+}
+
+/// Reads fields of any number of bits, most significant bit first, e.g. from an
+/// AudioSpecificConfig or a NAL unit header. Like a `limited` reader, reading past the end gives
+/// zeroes and is counted by `overrun`.
+///
+/// ```
+/// use mp4_parser::reader::BitReader;
+///
+/// // 5, 6, then 0b10 and the exp-Golomb codes 010 (1), 00101 (-2) and 1 (0)
+/// let mut bits = BitReader::new(&[0x56, 0b1001_0001, 0b0110_0000]);
+/// assert_eq!(bits.read_bits(4), 5);
+/// assert_eq!(bits.read_bits(4), 6);
+/// assert!(bits.read_bit());
+/// assert!(!bits.read_bit());
+/// assert_eq!(bits.read_ue(), 1);
+/// assert_eq!(bits.read_se(), -2);
+/// assert_eq!(bits.read_ue(), 0);
+/// assert_eq!(bits.bits_left(), 5);
+/// assert_eq!(bits.read_bits(8), 0);
+/// assert_eq!(bits.overrun(), 3);
+/// ```
+pub struct BitReader<'a> {
+    buf: &'a [u8],
+    /// In bits
+    position: u64,
+    /// How many bits were requested past the end
+    overrun: u64,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self {
+            buf,
+            position: 0,
+            overrun: 0,
+        }
+    }
+
+    /// In bits from the start
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn bits_left(&self) -> u64 {
+        (self.buf.len() as u64 * 8).saturating_sub(self.position)
+    }
+
+    /// How many bits were read or skipped past the end
+    pub fn overrun(&self) -> u64 {
+        self.overrun
+    }
+
+    pub fn read_bit(&mut self) -> bool {
+        self.read_bits(1) == 1
+    }
+
+    /// The next `n` bits, at most 64, as an unsigned number
+    pub fn read_bits(&mut self, n: u32) -> u64 {
+        assert!(n <= 64, "Can't read {} bits at once", n);
+        let mut value = 0;
+        for _ in 0..n {
+            let bit = match self.buf.get((self.position / 8) as usize) {
+                Some(byte) => (byte >> (7 - self.position % 8)) & 1,
+                None => {
+                    self.overrun += 1;
+                    0
+                }
+            };
+            value = value << 1 | bit as u64;
+            self.position += 1;
+        }
+        value
+    }
+
+    pub fn skip_bits(&mut self, n: u64) {
+        let available = n.min(self.bits_left());
+        self.position += n;
+        self.overrun += n - available;
+    }
+
+    /// Skips to the start of the next byte, unless already there
+    pub fn byte_align(&mut self) {
+        let misalignment = self.position % 8;
+        if misalignment != 0 {
+            self.skip_bits(8 - misalignment);
+        }
+    }
+
+    /// An unsigned exponential-Golomb code, ue(v) in H.264 and HEVC. A code of more than 32
+    /// leading zeroes, which only a broken stream has, gives `u32::MAX`.
+    pub fn read_ue(&mut self) -> u32 {
+        let mut leading_zeros = 0;
+        while !self.read_bit() {
+            leading_zeros += 1;
+            if leading_zeros > 32 || self.bits_left() == 0 {
+                return u32::MAX;
+            }
+        }
+        let value = (1u64 << leading_zeros) - 1 + self.read_bits(leading_zeros);
+        value.min(u32::MAX as u64) as u32
+    }
+
+    /// A signed exponential-Golomb code, se(v): 1, -1, 2, -2 and so on for the codes after 0
+    pub fn read_se(&mut self) -> i32 {
+        let code = self.read_ue() as i64;
+        let value = if code % 2 == 1 {
+            (code + 1) / 2
+        } else {
+            -(code / 2)
+        };
+        value as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_ue_stops_at_the_end_of_the_buffer() {
+        let mut bits = BitReader::new(&[0x00]);
+        assert_eq!(bits.read_ue(), u32::MAX);
+        assert_eq!(bits.position(), 8);
+        assert_eq!(bits.overrun(), 0);
+
+        let mut bits = BitReader::new(&[]);
+        assert_eq!(bits.read_ue(), u32::MAX);
+        assert_eq!(bits.overrun(), 1);
+    }
+
+    #[test]
+    fn read_ue_with_more_than_32_leading_zeros() {
+        let mut bits = BitReader::new(&[0, 0, 0, 0, 0, 0xFF]);
+        assert_eq!(bits.read_ue(), u32::MAX);
+        assert_eq!(bits.position(), 33);
+    }
+
+    #[test]
+    fn read_ue_with_31_leading_zeros() {
+        // The largest code that fits: 31 zeroes, a one and 31 bits of zeroes
+        let mut bits = BitReader::new(&[0, 0, 0, 0x01, 0, 0, 0, 0]);
+        assert_eq!(bits.read_ue(), (1 << 31) - 1);
+        assert_eq!(bits.position(), 63);
+        assert_eq!(bits.overrun(), 0);
+    }
+
+    #[test]
+    fn read_se_maps_codes_to_alternating_signs() {
+        // The codes 1, 010, 011, 00100 and 00101
+        let mut bits = BitReader::new(&[0b1010_0110, 0b0100_0010, 0b1000_0000]);
+        let values: Vec<i32> = (0..5).map(|_| bits.read_se()).collect();
+        assert_eq!(values, [0, 1, -1, 2, -2]);
+        assert_eq!(bits.position(), 17);
+    }
+
+    #[test]
+    fn read_64_bits() {
+        let buf = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        let mut bits = BitReader::new(&buf);
+        assert_eq!(bits.read_bits(64), 0x0102_0304_0506_0708);
+        assert_eq!(bits.overrun(), 0);
+
+        let mut bits = BitReader::new(&buf);
+        bits.skip_bits(4);
+        assert_eq!(bits.read_bits(64), 0x1020_3040_5060_7080);
+        assert_eq!(bits.overrun(), 4);
+    }
+
+    #[test]
+    fn skip_bits_past_the_end() {
+        let mut bits = BitReader::new(&[0xFF]);
+        bits.skip_bits(5);
+        bits.skip_bits(10);
+        assert_eq!(bits.position(), 15);
+        assert_eq!(bits.bits_left(), 0);
+        assert_eq!(bits.overrun(), 7);
+        assert!(!bits.read_bit());
+        assert_eq!(bits.overrun(), 8);
+    }
+
+    #[test]
+    fn byte_align() {
+        let mut bits = BitReader::new(&[0xFF, 0x0F]);
+        bits.byte_align();
+        assert_eq!(bits.position(), 0);
+        bits.read_bits(3);
+        bits.byte_align();
+        assert_eq!(bits.position(), 8);
+        assert_eq!(bits.read_bits(8), 0x0F);
+
+        // Aligning past the end counts as an overrun
+        bits.read_bit();
+        bits.byte_align();
+        assert_eq!(bits.position(), 24);
+        assert_eq!(bits.overrun(), 8);
+    }
+}
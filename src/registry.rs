@@ -0,0 +1,89 @@
+//! Parsers for boxes that the crate doesn't know, e.g. a vendor's private boxes, registered by
+//! the application that uses it
+
+use alloc::boxed::Box;
+use core::any::Any;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::convert::TryInto;
+#[cfg(feature = "std")]
+use std::sync::{OnceLock, RwLock};
+
+use crate::reader::Reader;
+
+/// A box parsed by a registered parser
+pub trait CustomBox: fmt::Debug + Send {
+    /// Shown as the title of the box in the tree
+    fn name(&self) -> &'static str;
+
+    fn print_attributes(&self, print: &dyn Fn(&str, &dyn fmt::Display));
+
+    /// For getting the concrete type back, with `downcast_ref`
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Parses the contents of a box. The reader can't read past the end of the box, which is
+/// `inner_size` bytes long.
+pub type ParseFn = fn(reader: &mut Reader, inner_size: u64) -> Box<dyn CustomBox>;
+
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Registry {
+    by_type: HashMap<String, ParseFn>,
+    by_uuid: HashMap<[u8; 16], ParseFn>,
+}
+
+#[cfg(feature = "std")]
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Registers a parser for boxes of the given type, e.g. "gpmd". It's only used for types that
+/// the crate doesn't parse itself.
+#[cfg(feature = "std")]
+pub fn register(box_type: &str, parse: ParseFn) {
+    registry()
+        .write()
+        .unwrap()
+        .by_type
+        .insert(box_type.to_string(), parse);
+}
+
+/// Registers a parser for uuid boxes with the given extended type. The parser gets the contents
+/// that follow the extended type.
+#[cfg(feature = "std")]
+pub fn register_uuid(extended_type: [u8; 16], parse: ParseFn) {
+    registry()
+        .write()
+        .unwrap()
+        .by_uuid
+        .insert(extended_type, parse);
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn parser(box_type: &str) -> Option<ParseFn> {
+    registry().read().unwrap().by_type.get(box_type).copied()
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn uuid_parser(extended_type: &[u8]) -> Option<ParseFn> {
+    let registry = registry().read().unwrap();
+    extended_type
+        .try_into()
+        .ok()
+        .and_then(|extended_type: [u8; 16]| registry.by_uuid.get(&extended_type).copied())
+}
+
+/// Parsers can only be registered with the `std` feature, which the global registry needs
+#[cfg(not(feature = "std"))]
+pub(crate) fn parser(_box_type: &str) -> Option<ParseFn> {
+    None
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn uuid_parser(_extended_type: &[u8]) -> Option<ParseFn> {
+    None
+}
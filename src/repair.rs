@@ -0,0 +1,403 @@
+use crate::boxes::SampleEntry;
+use crate::model::{Movie, Sample, SampleTable, Track};
+use crate::reader::Reader;
+use crate::rewrite::{rewrite_moov, top_level_boxes, TrackRewrite};
+use crate::writer::Writer;
+
+/// The outcome of a repair, to be presented to the user
+#[derive(Debug)]
+pub struct RepairReport {
+    pub tracks: Vec<RecoveredTrack>,
+    /// Bytes in the orphaned mdat that couldn't be attributed to any track
+    pub unreferenced_bytes: u64,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct RecoveredTrack {
+    pub track_id: u32,
+    pub handler_type: String,
+    pub sample_count: usize,
+    pub sync_sample_count: usize,
+}
+
+/// How sample boundaries are found in the orphaned mdat for a given track
+enum Framing {
+    /// Length-prefixed H.264 NAL units, grouped into access units
+    AvcNalUnits { length_size: u8 },
+    /// ADTS frames, i.e. AAC with a sync word and length in each frame header
+    Adts,
+    /// Every sample has the same size (e.g. PCM)
+    FixedSize(u32),
+}
+
+struct TrackRecovery {
+    framing: Option<Framing>,
+    sample_delta: u32,
+    samples: Vec<Sample>,
+}
+
+struct AccessUnit {
+    offset: u64,
+    size: u64,
+    has_vcl: bool,
+    is_idr: bool,
+}
+
+/// Reconstructs a playable file from `broken`, whose moov box is missing or corrupt,
+/// using `reference` (a healthy file from the same device) for the track layout and
+/// codec configuration. The orphaned mdat is scanned for sample boundaries and new sample
+/// tables are built from them.
+pub fn repair(broken: &[u8], reference: &[u8]) -> Result<(Vec<u8>, RepairReport), String> {
+    let (mdat_start, mdat_end) =
+        find_mdat_payload(broken).ok_or("Found no mdat box in the broken file")?;
+    let top_level_boxes = top_level_boxes(reference);
+    let ftyp = top_level_boxes.iter().find(|b| b.box_type == "ftyp");
+    let moov = top_level_boxes
+        .iter()
+        .find(|b| b.box_type == "moov")
+        .ok_or("Found no moov box in the reference file")?;
+
+    let movie =
+        Movie::parse(reference).map_err(|e| format!("Can't parse the reference file: {}", e))?;
+    let mut warnings = Vec::new();
+    let mut recoveries: Vec<TrackRecovery> = movie
+        .tracks
+        .iter()
+        .map(|track| {
+            let framing = detect_framing(track, reference);
+            if framing.is_none() {
+                warnings.push(format!(
+                    "Can't detect sample boundaries for track {} ('{}'). It will be left out.",
+                    track.id, track.handler_type
+                ));
+            }
+            TrackRecovery {
+                framing,
+                sample_delta: track.sample_table.typical_sample_delta().unwrap_or(1),
+                samples: vec![],
+            }
+        })
+        .collect();
+
+    let unreferenced_bytes = scan_mdat(broken, mdat_start, mdat_end, &mut recoveries);
+
+    if recoveries.iter().all(|r| r.samples.is_empty()) {
+        return Err("Couldn't recover any samples from the broken file".to_string());
+    }
+    for (track, recovery) in movie.tracks.iter().zip(&recoveries) {
+        if recovery.framing.is_some() && recovery.samples.is_empty() {
+            warnings.push(format!("Found no samples for track {}", track.id));
+        }
+    }
+    if movie
+        .tracks
+        .iter()
+        .any(|track| !track.sample_table.composition_offsets.is_empty())
+    {
+        warnings.push(
+            "The reference file uses composition offsets (B-frames), which can't be recovered. \
+             Frames may be presented out of order."
+                .to_string(),
+        );
+    }
+
+    let mut writer = Writer::new();
+    if let Some(ftyp) = ftyp {
+        let end = ftyp.start_offset + ftyp.box_size;
+        writer.write_bytes(&reference[ftyp.start_offset as usize..end as usize]);
+    }
+    writer.write_box_header("mdat", mdat_end - mdat_start);
+    let new_mdat_start = writer.position();
+    writer.write_bytes(&broken[mdat_start as usize..mdat_end as usize]);
+    for recovery in recoveries.iter_mut() {
+        for sample in recovery.samples.iter_mut() {
+            sample.offset = sample.offset - mdat_start + new_mdat_start;
+        }
+    }
+
+    // The reference's edit lists don't apply to the recovered media, so they are dropped
+    let track_rewrites: Vec<Option<TrackRewrite>> = recoveries
+        .iter()
+        .map(|recovery| {
+            if recovery.samples.is_empty() {
+                return None;
+            }
+            Some(TrackRewrite {
+                sample_table: SampleTable::from_samples(&recovery.samples),
+                edits: None,
+            })
+        })
+        .collect();
+    rewrite_moov(reference, moov, &movie, &track_rewrites, &mut writer);
+
+    let tracks = movie
+        .tracks
+        .iter()
+        .zip(&recoveries)
+        .filter(|(_, recovery)| !recovery.samples.is_empty())
+        .map(|(track, recovery)| RecoveredTrack {
+            track_id: track.id,
+            handler_type: track.handler_type.clone(),
+            sample_count: recovery.samples.len(),
+            sync_sample_count: recovery.samples.iter().filter(|s| s.is_sync).count(),
+        })
+        .collect();
+    let report = RepairReport {
+        tracks,
+        unreferenced_bytes,
+        warnings,
+    };
+    Ok((writer.into_inner(), report))
+}
+
+/// Finds the payload of the first mdat box. Unlike BoxHeader::parse this tolerates an mdat
+/// that has size 0 or claims to be bigger than the file, as is common for interrupted
+/// recordings.
+fn find_mdat_payload(buf: &[u8]) -> Option<(u64, u64)> {
+    let file_len = buf.len() as u64;
+    let mut reader = Reader::new(buf);
+    while reader.position() + 8 <= file_len {
+        let start_offset = reader.position();
+        let mut size = reader.read_u32() as u64;
+        let box_type = reader.read_bytes(4);
+        if size == 1 {
+            if reader.position() + 8 > file_len {
+                return None;
+            }
+            size = reader.read_u64();
+        }
+        let payload_start = reader.position();
+        if &box_type == b"mdat" {
+            let end = start_offset.saturating_add(size);
+            let payload_end = if size == 0 || end > file_len {
+                file_len
+            } else {
+                end
+            };
+            return Some((payload_start, payload_end));
+        }
+        if size < payload_start - start_offset || start_offset + size > file_len {
+            return None;
+        }
+        reader
+            .skip_bytes(start_offset + size - payload_start)
+            .ok()?;
+    }
+    None
+}
+
+fn detect_framing(track: &Track, reference: &[u8]) -> Option<Framing> {
+    match track.sample_entries.first()? {
+        SampleEntry::Avc1(avc1) => {
+            let length_size = avc1
+                .avc_config
+                .as_ref()
+                .map(|config| config.nal_length_size)
+                .unwrap_or(4);
+            Some(Framing::AvcNalUnits { length_size })
+        }
+        SampleEntry::Mp4a(_) => {
+            let sizes = &track.sample_table.sample_sizes;
+            if !sizes.is_empty() && sizes.iter().all(|&size| size == sizes[0]) {
+                return Some(Framing::FixedSize(sizes[0]));
+            }
+            let first_sample = track.sample_table.samples().into_iter().next()?;
+            let start = first_sample.offset as usize;
+            match reference.get(start..start + 2) {
+                Some(&[0xFF, b]) if b & 0xF0 == 0xF0 => Some(Framing::Adts),
+                _ => None,
+            }
+        }
+        // Only H.264 NAL unit headers are recognized when scanning the mdat
+        SampleEntry::Hevc(_)
+        | SampleEntry::Mp4v(_)
+        | SampleEntry::H263(_)
+        | SampleEntry::Image(_)
+        | SampleEntry::Mebx(_)
+        | SampleEntry::TimedMetadata(_)
+        | SampleEntry::Text(_)
+        | SampleEntry::WebVtt(_) => None,
+        SampleEntry::Ac4(_)
+        | SampleEntry::Mlpa(_)
+        | SampleEntry::Pcm(_)
+        | SampleEntry::Encrypted(_)
+        | SampleEntry::Unknown(_) => None,
+    }
+}
+
+/// Walks through the orphaned mdat, attributing data to the tracks whose framing matches.
+/// Returns the number of bytes that couldn't be attributed to any track.
+fn scan_mdat(buf: &[u8], start: u64, end: u64, recoveries: &mut [TrackRecovery]) -> u64 {
+    let video = recoveries
+        .iter()
+        .position(|r| matches!(r.framing, Some(Framing::AvcNalUnits { .. })));
+    let audio = recoveries
+        .iter()
+        .position(|r| matches!(r.framing, Some(Framing::Adts | Framing::FixedSize(_))));
+
+    let mut unreferenced_bytes = 0;
+    let mut access_unit: Option<AccessUnit> = None;
+    let mut pos = start;
+    while pos < end {
+        if let Some(video) = video {
+            let length_size = match recoveries[video].framing {
+                Some(Framing::AvcNalUnits { length_size }) => length_size,
+                _ => unreachable!(),
+            };
+            let continuing = access_unit.is_some();
+            if let Some((nal_size, nal_type, first_slice)) =
+                nal_unit_at(buf, pos, end, length_size, continuing)
+            {
+                let is_vcl = nal_type == 1 || nal_type == 5;
+                let starts_new_unit = match &access_unit {
+                    Some(au) if au.has_vcl => {
+                        if is_vcl {
+                            first_slice
+                        } else {
+                            matches!(nal_type, 6..=9 | 13..=18)
+                        }
+                    }
+                    _ => false,
+                };
+                if starts_new_unit {
+                    let au = access_unit.take().unwrap();
+                    recoveries[video].add_sample(au.offset, au.size as u32, au.is_idr);
+                }
+                let au = access_unit.get_or_insert(AccessUnit {
+                    offset: pos,
+                    size: 0,
+                    has_vcl: false,
+                    is_idr: false,
+                });
+                au.size += nal_size;
+                au.has_vcl |= is_vcl;
+                au.is_idr |= nal_type == 5;
+                pos += nal_size;
+                continue;
+            }
+        }
+
+        // Video samples never span across other data
+        if let Some(au) = access_unit.take() {
+            recoveries[video.unwrap()].add_sample(au.offset, au.size as u32, au.is_idr);
+        }
+
+        let audio_frame_size = audio.and_then(|audio| {
+            let size = match recoveries[audio].framing {
+                Some(Framing::Adts) => adts_frame_size_at(buf, pos, end)?,
+                Some(Framing::FixedSize(size)) => size as u64,
+                _ => unreachable!(),
+            };
+            if size > 0 && pos + size <= end {
+                Some((audio, size))
+            } else {
+                None
+            }
+        });
+        match audio_frame_size {
+            Some((audio, size)) => {
+                recoveries[audio].add_sample(pos, size as u32, true);
+                pos += size;
+            }
+            None => {
+                unreferenced_bytes += 1;
+                pos += 1;
+            }
+        }
+    }
+    if let Some(au) = access_unit.take() {
+        recoveries[video.unwrap()].add_sample(au.offset, au.size as u32, au.is_idr);
+    }
+
+    unreferenced_bytes
+}
+
+/// Checks if there's a plausible length-prefixed NAL unit at `pos`, and if so returns its
+/// total size (including the length prefix), its type, and whether it's the first slice
+/// of a picture. When not `continuing` a run of NAL units the check is stricter, since
+/// we're then looking for the start of a video chunk among unrelated data.
+fn nal_unit_at(
+    buf: &[u8],
+    pos: u64,
+    end: u64,
+    length_size: u8,
+    continuing: bool,
+) -> Option<(u64, u8, bool)> {
+    let header_pos = pos + length_size as u64;
+    if header_pos + 2 > end {
+        return None;
+    }
+    let mut nal_length = 0_u64;
+    for &b in &buf[pos as usize..header_pos as usize] {
+        nal_length = (nal_length << 8) | b as u64;
+    }
+    if nal_length < 2 || header_pos + nal_length > end {
+        return None;
+    }
+
+    let nal_header = buf[header_pos as usize];
+    let forbidden_zero_bit = nal_header & 0b1000_0000;
+    let nal_ref_idc = (nal_header & 0b0110_0000) >> 5;
+    let nal_type = nal_header & 0b0001_1111;
+    if forbidden_zero_bit != 0 {
+        return None;
+    }
+    let plausible = if continuing {
+        (1..=12).contains(&nal_type)
+    } else {
+        match nal_type {
+            1 => true,
+            5 | 7 | 8 => nal_ref_idc != 0,
+            6 | 9 => nal_ref_idc == 0,
+            _ => false,
+        }
+    };
+    if !plausible {
+        return None;
+    }
+
+    // first_mb_in_slice is the first field of the slice header, stored as ue(v). It's 0
+    // (i.e. the first slice of a picture) exactly when the first bit is set.
+    let first_slice = buf[header_pos as usize + 1] & 0b1000_0000 != 0;
+    Some((length_size as u64 + nal_length, nal_type, first_slice))
+}
+
+fn adts_frame_size_at(buf: &[u8], pos: u64, end: u64) -> Option<u64> {
+    if pos + 7 > end {
+        return None;
+    }
+    let h = &buf[pos as usize..pos as usize + 7];
+    if h[0] != 0xFF || h[1] & 0xF6 != 0xF0 {
+        return None;
+    }
+    let frame_length = ((h[3] as u64 & 0b11) << 11) | ((h[4] as u64) << 3) | (h[5] as u64 >> 5);
+    if frame_length < 7 {
+        return None;
+    }
+    Some(frame_length)
+}
+
+impl TrackRecovery {
+    fn add_sample(&mut self, offset: u64, size: u32, is_sync: bool) {
+        let chunk_index = match self.samples.last() {
+            Some(last) if last.offset + last.size as u64 == offset => last.chunk_index,
+            Some(last) => last.chunk_index + 1,
+            None => 0,
+        };
+        self.samples.push(Sample {
+            offset,
+            size,
+            decode_time: self.media_duration(),
+            composition_offset: 0,
+            duration: self.sample_delta,
+            is_sync,
+            chunk_index,
+            description_index: 1,
+        });
+    }
+
+    fn media_duration(&self) -> u64 {
+        self.samples.len() as u64 * self.sample_delta as u64
+    }
+}
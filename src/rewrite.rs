@@ -0,0 +1,348 @@
+use crate::boxes::{
+    BoxHeader, ChunkLargeOffsetBox, ChunkOffsetBox, EditListBox, EditListEntry, MediaHeaderBox,
+    MovieHeaderBox, TrackHeaderBox,
+};
+use crate::media_time::MediaTime;
+use crate::model::{Movie, SampleTable};
+use crate::reader::Reader;
+use crate::writer::Writer;
+
+/// What to do with a box while copying a box tree
+pub enum Rewrite {
+    /// Copy the box as-is
+    Keep,
+    /// Leave the box out
+    Drop,
+    /// Write the given bytes (typically one or more complete boxes) in place of the box
+    Replace(Vec<u8>),
+    /// Re-emit the box header and visit each of its children. Only valid for containers.
+    Descend,
+    /// Like `Descend`, but the given bytes are added after the last child
+    DescendAppending(Vec<u8>),
+}
+
+/// Containers whose payload starts with a FullBox version and flags before the child boxes
+const FULL_BOX_CONTAINERS: [&str; 1] = ["meta"];
+
+/// Copies the boxes in `buf[start_offset..end_offset]` into `writer`, letting `visit`
+/// decide per box whether it's kept, dropped, replaced or descended into. `visit` is given
+/// the box types of all ancestors, the box header and the complete raw box.
+pub fn rewrite_boxes<F>(
+    buf: &[u8],
+    start_offset: u64,
+    end_offset: u64,
+    writer: &mut Writer,
+    visit: &mut F,
+) where
+    F: FnMut(&[String], &BoxHeader, &[u8]) -> Rewrite,
+{
+    let mut path = Vec::new();
+    _rewrite_boxes(buf, start_offset, end_offset, writer, &mut path, visit);
+}
+
+fn _rewrite_boxes<F>(
+    buf: &[u8],
+    start_offset: u64,
+    end_offset: u64,
+    writer: &mut Writer,
+    path: &mut Vec<String>,
+    visit: &mut F,
+) where
+    F: FnMut(&[String], &BoxHeader, &[u8]) -> Rewrite,
+{
+    let mut reader = Reader::new(buf);
+    reader.skip_bytes(start_offset).unwrap();
+    while reader.position() < end_offset {
+        let header = BoxHeader::try_parse(&mut reader).unwrap_or_else(|e| panic!("{}", e));
+        let header_size = reader.position() - header.start_offset;
+        let box_end_offset = header.start_offset + header.box_size;
+        let raw = buf
+            .get(header.start_offset as usize..box_end_offset as usize)
+            .unwrap_or_else(|| panic!("Truncated '{}' box", header.box_type));
+
+        let mut append = None;
+        match visit(path, &header, raw) {
+            Rewrite::Keep => writer.write_bytes(raw),
+            Rewrite::Drop => {}
+            Rewrite::Replace(bytes) => writer.write_bytes(&bytes),
+            Rewrite::Descend => append = Some(vec![]),
+            Rewrite::DescendAppending(bytes) => append = Some(bytes),
+        }
+
+        if let Some(append) = append {
+            let mut children_offset = header.start_offset + header_size;
+            let start = writer.begin_box(&header.box_type);
+            // QuickTime's meta box is a plain box, whose first child (hdlr) follows directly
+            let is_plain_box =
+                raw.get(header_size as usize + 4..header_size as usize + 8) == Some(b"hdlr");
+            if FULL_BOX_CONTAINERS.contains(&header.box_type.as_str()) && !is_plain_box {
+                writer.write_bytes(&buf[children_offset as usize..children_offset as usize + 4]);
+                children_offset += 4;
+            }
+            path.push(header.box_type.clone());
+            _rewrite_boxes(buf, children_offset, box_end_offset, writer, path, visit);
+            path.pop();
+            writer.write_bytes(&append);
+            writer.end_box(start);
+        }
+
+        reader
+            .skip_bytes(box_end_offset - reader.position())
+            .unwrap_or_else(|e| panic!("Truncated '{}' box: {}", header.box_type, e));
+    }
+}
+
+/// Rewrites a complete file, see `rewrite_boxes`. If that moves any of the top-level mdat
+/// boxes (e.g. because a moov in front of them grew), the chunk offsets are updated to
+/// point into the media data's new location.
+pub fn rewrite_file<F>(buf: &[u8], visit: &mut F) -> Vec<u8>
+where
+    F: FnMut(&[String], &BoxHeader, &[u8]) -> Rewrite,
+{
+    let mut writer = Writer::new();
+    rewrite_boxes(buf, 0, buf.len() as u64, &mut writer, visit);
+    let output = writer.into_inner();
+
+    let mdats = |buf: &[u8]| -> Vec<BoxHeader> {
+        top_level_boxes(buf)
+            .into_iter()
+            .filter(|b| b.box_type == "mdat")
+            .collect()
+    };
+    let old_mdats = mdats(buf);
+    let new_mdats = mdats(&output);
+    let moved = old_mdats
+        .iter()
+        .zip(&new_mdats)
+        .any(|(old, new)| old.start_offset != new.start_offset);
+    if !moved || old_mdats.len() != new_mdats.len() {
+        return output;
+    }
+
+    // Upgrading stco to co64 when offsets pass 4 GiB makes moov grow, and moves the mdat
+    // boxes again, so this is repeated until they stay in place
+    let mut new_mdats = new_mdats;
+    loop {
+        let relocated = relocate_chunk_offsets(&output, &old_mdats, &new_mdats);
+        let relocated_mdats = mdats(&relocated);
+        let stable = relocated_mdats
+            .iter()
+            .zip(&new_mdats)
+            .all(|(relocated, new)| relocated.start_offset == new.start_offset);
+        if stable {
+            return relocated;
+        }
+        new_mdats = relocated_mdats;
+    }
+}
+
+/// Moves the chunk offsets that point into each of `old_mdats` to the same place in the
+/// corresponding one of `new_mdats`
+fn relocate_chunk_offsets(buf: &[u8], old_mdats: &[BoxHeader], new_mdats: &[BoxHeader]) -> Vec<u8> {
+    let relocate = |offset: u64| -> u64 {
+        old_mdats
+            .iter()
+            .zip(new_mdats)
+            .find(|(old, _)| offset >= old.start_offset && offset < old.start_offset + old.box_size)
+            .map_or(offset, |(old, new)| {
+                offset - old.start_offset + new.start_offset
+            })
+    };
+    let mut writer = Writer::new();
+    rewrite_boxes(
+        buf,
+        0,
+        buf.len() as u64,
+        &mut writer,
+        &mut |_path: &[String], header: &BoxHeader, raw: &[u8]| {
+            let mut reader = Reader::new(raw);
+            reader
+                .skip_bytes(header.box_size - header.inner_size)
+                .unwrap();
+            let mut box_writer = Writer::new();
+            match header.box_type.as_ref() {
+                "moov" | "trak" | "mdia" | "minf" | "stbl" => Rewrite::Descend,
+                "stco" => {
+                    let stco = ChunkOffsetBox::parse_header(&mut reader);
+                    let offsets: Vec<u64> = (0..stco.entry_count)
+                        .map(|_| relocate(ChunkOffsetBox::parse_entry(&mut reader) as u64))
+                        .collect();
+                    ChunkOffsetBox::write(&mut box_writer, &offsets);
+                    Rewrite::Replace(box_writer.into_inner())
+                }
+                "co64" => {
+                    let co64 = ChunkLargeOffsetBox::parse_header(&mut reader);
+                    let offsets: Vec<u64> = (0..co64.entry_count)
+                        .map(|_| relocate(ChunkLargeOffsetBox::parse_entry(&mut reader)))
+                        .collect();
+                    ChunkLargeOffsetBox::write(&mut box_writer, &offsets);
+                    Rewrite::Replace(box_writer.into_inner())
+                }
+                _ => Rewrite::Keep,
+            }
+        },
+    );
+    writer.into_inner()
+}
+
+/// New contents for a track whose samples have been moved, added or removed
+pub struct TrackRewrite {
+    pub sample_table: SampleTable,
+    /// Replaces the track's edit list. If None, any existing edit list is removed.
+    pub edits: Option<Vec<EditListEntry>>,
+}
+
+impl TrackRewrite {
+    /// The track's duration in the movie's timescale
+    fn presentation_duration(&self, track_timescale: u32, movie_timescale: u32) -> u64 {
+        match &self.edits {
+            Some(edits) => edits.iter().map(|e| e.segment_duration as u64).sum(),
+            None => MediaTime::new(self.sample_table.media_duration(), track_timescale)
+                .rescale(movie_timescale)
+                .ticks() as u64,
+        }
+    }
+}
+
+/// Copies the moov box, replacing the sample tables, edit lists and durations of each track
+/// with those of the corresponding entry in `tracks`, which are in the order of
+/// `movie.tracks`. Tracks without an entry are left out. The boxes in stbl that describe
+/// individual samples, other than those of the new sample table, are dropped, so
+/// encrypted tracks can't be rewritten (see `check_rewritable`).
+pub fn rewrite_moov(
+    buf: &[u8],
+    moov: &BoxHeader,
+    movie: &Movie,
+    tracks: &[Option<TrackRewrite>],
+    writer: &mut Writer,
+) {
+    let movie_duration = movie
+        .tracks
+        .iter()
+        .zip(tracks)
+        .filter_map(|(track, rewrite)| {
+            let rewrite = rewrite.as_ref()?;
+            Some(rewrite.presentation_duration(track.timescale, movie.timescale))
+        })
+        .max()
+        .unwrap_or(0);
+
+    let mut track_index = None;
+    rewrite_boxes(
+        buf,
+        moov.start_offset,
+        moov.start_offset + moov.box_size,
+        writer,
+        &mut |path: &[String], header: &BoxHeader, raw: &[u8]| {
+            let parent = path.last().map(|p| p.as_str());
+            let mut reader = Reader::new(raw);
+            reader
+                .skip_bytes(header.box_size - header.inner_size)
+                .unwrap();
+            let rewrite = track_index.and_then(|i: usize| tracks[i].as_ref());
+            let mut box_writer = Writer::new();
+            match (parent, header.box_type.as_ref()) {
+                (None, "moov") => Rewrite::Descend,
+                (Some("moov"), "mvhd") => {
+                    let mut mvhd = MovieHeaderBox::parse(&mut reader, header.inner_size);
+                    mvhd.duration = movie_duration;
+                    mvhd.write(&mut box_writer);
+                    Rewrite::Replace(box_writer.into_inner())
+                }
+                (Some("moov"), "trak") => {
+                    // Not every trak box is necessarily one of the movie's tracks
+                    track_index = track_id(buf, header)
+                        .and_then(|id| movie.tracks.iter().position(|t| t.id == id));
+                    match track_index.and_then(|i| tracks[i].as_ref()) {
+                        Some(_) => Rewrite::Descend,
+                        None => Rewrite::Drop,
+                    }
+                }
+                // Any fragments are not carried over
+                (Some("moov"), "mvex") => Rewrite::Drop,
+                (Some("trak"), "tkhd") => {
+                    let track = &movie.tracks[track_index.unwrap()];
+                    let mut tkhd = TrackHeaderBox::parse(&mut reader, header.inner_size);
+                    tkhd.duration = rewrite
+                        .unwrap()
+                        .presentation_duration(track.timescale, movie.timescale);
+                    tkhd.write(&mut box_writer);
+                    // The edit list conventionally comes right after tkhd
+                    if let Some(edits) = &rewrite.unwrap().edits {
+                        EditListBox::write(&mut box_writer, edits);
+                    }
+                    Rewrite::Replace(box_writer.into_inner())
+                }
+                (Some("trak"), "edts") => Rewrite::Drop,
+                (Some("trak"), "mdia") | (Some("mdia"), "minf") => Rewrite::Descend,
+                (Some("mdia"), "mdhd") => {
+                    let mut mdhd = MediaHeaderBox::parse(&mut reader, header.inner_size);
+                    mdhd.duration = rewrite.unwrap().sample_table.media_duration();
+                    mdhd.write(&mut box_writer);
+                    Rewrite::Replace(box_writer.into_inner())
+                }
+                (Some("minf"), "stbl") => {
+                    rewrite.unwrap().sample_table.write(&mut box_writer);
+                    Rewrite::DescendAppending(box_writer.into_inner())
+                }
+                // Sample group descriptions don't refer to individual samples, unlike the
+                // rest of the sample table
+                (Some("stbl"), "stsd") | (Some("stbl"), "sgpd") => Rewrite::Keep,
+                (Some("stbl"), _) => Rewrite::Drop,
+                _ => Rewrite::Keep,
+            }
+        },
+    );
+}
+
+/// Fails if the sample tables of any of the movie's tracks can't be rewritten by
+/// `rewrite_moov`
+pub fn check_rewritable(movie: &Movie) -> Result<(), String> {
+    match movie
+        .tracks
+        .iter()
+        .find(|track| track.protection().is_some())
+    {
+        Some(track) => Err(format!(
+            "Track {} is encrypted. Rewriting encrypted tracks is not supported.",
+            track.id
+        )),
+        None => Ok(()),
+    }
+}
+
+/// The track ID in the tkhd box of the given trak box
+fn track_id(buf: &[u8], trak: &BoxHeader) -> Option<u32> {
+    let end_offset = trak.start_offset + trak.box_size;
+    let mut reader = Reader::new(buf);
+    reader.skip_bytes(end_offset - trak.inner_size).ok()?;
+    while reader.position() + 8 <= end_offset {
+        let child = BoxHeader::try_parse(&mut reader).ok()?;
+        if child.box_type == "tkhd" {
+            return Some(TrackHeaderBox::parse(&mut reader, child.inner_size).track_id);
+        }
+        reader
+            .skip_bytes(child.start_offset + child.box_size - reader.position())
+            .ok()?;
+    }
+    None
+}
+
+/// Lists the boxes at the top level of the file
+pub fn top_level_boxes(buf: &[u8]) -> Vec<BoxHeader> {
+    let mut headers = Vec::new();
+    let mut reader = Reader::new(buf);
+    while reader.position() < buf.len() as u64 {
+        let header = match BoxHeader::try_parse(&mut reader) {
+            Ok(header) => header,
+            Err(_) => break,
+        };
+        let remaining = header.start_offset + header.box_size - reader.position();
+        let truncated = reader.skip_bytes(remaining).is_err();
+        headers.push(header);
+        if truncated {
+            break;
+        }
+    }
+    headers
+}
@@ -0,0 +1,306 @@
+//! Checks that the writers behind `edit` don't corrupt files: each of them is run with an edit
+//! that changes nothing, and the output must parse to the same box tree as the input. The
+//! copying rewrites, which claim to be lossless, must also give back the same bytes.
+//!
+//! ```
+//! # #[cfg(feature = "fixtures")]
+//! for (name, buf) in mp4_parser::fixtures::corpus() {
+//!     let failures = mp4_parser::round_trip::check(&buf)
+//!         .unwrap_or_else(|reason| panic!("{} was skipped: {}", name, reason));
+//!     assert!(failures.is_empty(), "{}: {:#?}", name, failures);
+//! }
+//! ```
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::boxes::BoxHeader;
+use crate::diagnostics::{Diagnostics, Severity};
+use crate::edit::{edit_timestamps, edit_track, TimestampEdit, TrackEdit};
+use crate::exit_code;
+use crate::golden;
+use crate::logger::JsonLogger;
+use crate::model::{Movie, Sample, SampleTable};
+use crate::rewrite::{rewrite_file, rewrite_moov, Rewrite, TrackRewrite};
+use crate::tree::{parse_mp4, BoxFilter};
+use crate::writer::Writer;
+
+/// Boxes whose payload is only child boxes, after the version and flags for meta
+const CONTAINERS: [&str; 15] = [
+    "moov", "trak", "edts", "mdia", "minf", "dinf", "stbl", "mvex", "moof", "traf", "mfra", "udta",
+    "meta", "iprp", "ipco",
+];
+
+/// What a writer promises about its output, when the edit changes nothing
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Claim {
+    /// The same bytes
+    Lossless,
+    /// The same box tree, as printed by `parse`
+    SameTree,
+    /// The same samples, with the same timing and data. The boxes that describe them may be
+    /// written differently, e.g. with the durations recomputed.
+    SameSamples,
+}
+
+/// A writer whose output differs from its input
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// E.g. "edit_timestamps"
+    pub writer: String,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.writer, self.message)
+    }
+}
+
+type Rewriter<'a> = Box<dyn Fn() -> Vec<u8> + 'a>;
+
+/// Runs every writer over `buf` with an edit that changes nothing, and returns the ones whose
+/// output doesn't round-trip. `buf` should be a valid file: fields that are cut short or in a
+/// box version that isn't supported aren't kept by the writers. Files that fail to parse, or
+/// that parse with warnings, aren't checked, and the reason is returned as an error.
+pub fn check(buf: &[u8]) -> Result<Vec<Failure>, String> {
+    let expected = box_tree(buf)?;
+    let movie = Movie::parse(buf).map_err(|e| e.to_string())?;
+
+    let mut rewrites: Vec<(String, Claim, Rewriter)> = vec![
+        (
+            "copy".to_string(),
+            Claim::Lossless,
+            Box::new(|| copy(buf, false)),
+        ),
+        (
+            "descend".to_string(),
+            Claim::Lossless,
+            Box::new(|| copy(buf, true)),
+        ),
+        (
+            "edit_timestamps".to_string(),
+            Claim::SameTree,
+            Box::new(|| edit_timestamps(buf, &TimestampEdit::Shift(chrono::Duration::zero()))),
+        ),
+    ];
+    for track in &movie.tracks {
+        let track_id = track.id;
+        rewrites.push((
+            format!("edit_track {}", track_id),
+            Claim::SameTree,
+            Box::new(move || {
+                edit_track(
+                    buf,
+                    &TrackEdit {
+                        track_id,
+                        ..TrackEdit::default()
+                    },
+                )
+                .unwrap_or_else(|e| panic!("{}", e))
+            }),
+        ));
+    }
+    // Fragments are dropped when the sample tables are rewritten
+    if !movie.tracks.is_empty() && movie.fragments.is_empty() {
+        rewrites.push((
+            "rewrite_moov".to_string(),
+            Claim::SameSamples,
+            Box::new(|| rewrite_sample_tables(buf, &movie)),
+        ));
+    }
+
+    let mut failures = Vec::new();
+    for (writer, claim, rewrite) in rewrites {
+        let result = exit_code::catch(rewrite)
+            .map_err(|e| format!("Failed to write: {}", e))
+            .and_then(|output| match claim {
+                Claim::Lossless => same_bytes(buf, &output),
+                Claim::SameTree => same_tree(&expected, &output),
+                Claim::SameSamples => same_samples(buf, &movie, &output),
+            });
+        if let Err(message) = result {
+            failures.push(Failure { writer, message });
+        }
+    }
+    Ok(failures)
+}
+
+fn same_bytes(buf: &[u8], output: &[u8]) -> Result<(), String> {
+    match buf.iter().zip(output).position(|(a, b)| a != b) {
+        Some(offset) => Err(format!(
+            "The output differs from the input at offset {}",
+            offset
+        )),
+        None if buf.len() != output.len() => Err(format!(
+            "The output is {} bytes, but the input is {} bytes",
+            output.len(),
+            buf.len()
+        )),
+        None => Ok(()),
+    }
+}
+
+fn same_tree(expected: &Value, output: &[u8]) -> Result<(), String> {
+    let actual = box_tree(output).map_err(|e| format!("The output can't be parsed: {}", e))?;
+    match golden::compare(expected, &actual).first() {
+        Some(mismatch) => Err(format!("The output parses differently: {}", mismatch)),
+        None => Ok(()),
+    }
+}
+
+fn same_samples(buf: &[u8], movie: &Movie, output: &[u8]) -> Result<(), String> {
+    let output_movie =
+        Movie::parse(output).map_err(|e| format!("The output can't be parsed: {}", e))?;
+    if output_movie.tracks.len() != movie.tracks.len() {
+        return Err(format!(
+            "The output has {} tracks, but the input has {}",
+            output_movie.tracks.len(),
+            movie.tracks.len()
+        ));
+    }
+    for (track, output_track) in movie.tracks.iter().zip(&output_movie.tracks) {
+        let samples = track.sample_table.samples();
+        let output_samples = output_track.sample_table.samples();
+        if samples.len() != output_samples.len() {
+            return Err(format!(
+                "Track {} has {} samples in the output, but {} in the input",
+                track.id,
+                output_samples.len(),
+                samples.len()
+            ));
+        }
+        for (i, (a, b)) in samples.iter().zip(&output_samples).enumerate() {
+            let timing = |s: &Sample| (s.decode_time, s.duration, s.composition_offset, s.is_sync);
+            if timing(a) != timing(b) || a.description_index != b.description_index {
+                return Err(format!(
+                    "Sample {} of track {} differs: {:?} in the input, {:?} in the output",
+                    i + 1,
+                    track.id,
+                    a,
+                    b
+                ));
+            }
+            if sample_data(buf, a) != sample_data(output, b) {
+                return Err(format!(
+                    "The data of sample {} of track {} differs",
+                    i + 1,
+                    track.id
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `None` if the sample is outside the file
+fn sample_data<'a>(buf: &'a [u8], sample: &Sample) -> Option<&'a [u8]> {
+    buf.get(sample.offset as usize..)?
+        .get(..sample.size as usize)
+}
+
+/// The parsed boxes, as the JSON printed by `parse --json`. Warnings count as parse errors,
+/// but unknown boxes don't, as the writers copy them as they are.
+fn box_tree(buf: &[u8]) -> Result<Value, String> {
+    let mut logger = JsonLogger::new();
+    let (result, diagnostics) =
+        Diagnostics::collect(|| parse_mp4(buf, &mut logger, &BoxFilter::default()));
+    result.map_err(|e| e.to_string())?;
+    let (boxes, messages) = logger.into_boxes();
+    if let Some(message) = messages.first() {
+        return Err(message.clone());
+    }
+    if let Some(warning) = diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.severity >= Severity::Warning)
+    {
+        return Err(warning.to_string());
+    }
+    serde_json::to_value(&boxes).map_err(|e| e.to_string())
+}
+
+/// Copies every box as-is, or re-emits the header of every container and copies its children
+fn copy(buf: &[u8], descend: bool) -> Vec<u8> {
+    rewrite_file(buf, &mut |_: &[String], header: &BoxHeader, _: &[u8]| {
+        if descend && CONTAINERS.contains(&header.box_type.as_str()) {
+            Rewrite::Descend
+        } else {
+            Rewrite::Keep
+        }
+    })
+}
+
+/// Rebuilds each track's sample table from its samples, and writes it back with the same edits
+fn rewrite_sample_tables(buf: &[u8], movie: &Movie) -> Vec<u8> {
+    let tracks: Vec<Option<TrackRewrite>> = movie
+        .tracks
+        .iter()
+        .map(|track| {
+            Some(TrackRewrite {
+                sample_table: SampleTable {
+                    has_large_offsets: track.sample_table.has_large_offsets,
+                    ..SampleTable::from_samples(&track.sample_table.samples())
+                },
+                edits: if track.edits.is_empty() {
+                    None
+                } else {
+                    Some(track.edits.clone())
+                },
+            })
+        })
+        .collect();
+    rewrite_file(buf, &mut |path: &[String], header: &BoxHeader, _: &[u8]| {
+        if path.is_empty() && header.box_type == "moov" {
+            let mut writer = Writer::new();
+            rewrite_moov(buf, header, movie, &tracks, &mut writer);
+            Rewrite::Replace(writer.into_inner())
+        } else {
+            Rewrite::Keep
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The files in the root of the repository
+    const FILES: [&str; 5] = [
+        "aud_120ms.mp4",
+        "aud_500ms.mp4",
+        "av_120ms.mp4",
+        "frag_aud_120ms.mp4",
+        "vid_120ms.mp4",
+    ];
+
+    fn assert_round_trips(name: &str, buf: &[u8]) {
+        let failures =
+            check(buf).unwrap_or_else(|reason| panic!("{} was skipped: {}", name, reason));
+        assert!(failures.is_empty(), "{}: {:#?}", name, failures);
+    }
+
+    #[test]
+    fn files_round_trip() {
+        for name in FILES {
+            let path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), name);
+            let buf = std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+            assert_round_trips(name, &buf);
+        }
+    }
+
+    #[cfg(feature = "fixtures")]
+    #[test]
+    fn corpus_round_trips() {
+        for (name, buf) in crate::fixtures::corpus() {
+            assert_round_trips(name, &buf);
+        }
+    }
+
+    #[test]
+    fn broken_files_are_skipped() {
+        let buf = std::fs::read(format!("{}/aud_120ms.mp4", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let reason = check(&buf[..buf.len() - 1]).unwrap_err();
+        assert!(reason.contains("bytes are left of the file"), "{}", reason);
+    }
+}
@@ -0,0 +1,76 @@
+//! Finding where in the file playback should start to reach a given time
+
+use crate::media_time::MediaTime;
+use crate::model::{Movie, Track};
+
+/// A sync sample to start decoding from
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeekPoint {
+    /// Presentation time of the sample in seconds, in the track's media timeline (edit lists
+    /// aren't applied)
+    pub time: f64,
+    /// Where to start reading: the sample itself in a progressive file, or the moof box of
+    /// the fragment that has it in a fragmented file
+    pub offset: u64,
+}
+
+/// Finds the last sync sample of the track that is presented at or before `time` (in seconds),
+/// or the first one if `time` comes before all of them. Fragmented files are looked up in their
+/// random access boxes (tfra) if they have them, as those list the sync samples of every
+/// fragment without having to parse the fragments.
+pub fn seek(movie: &Movie, track_id: u32, time: f64) -> Option<SeekPoint> {
+    let track = movie.tracks.iter().find(|t| t.id == track_id)?;
+    let target = MediaTime::from_secs_f64(time.max(0.0), track.timescale);
+    let candidates = if track.random_access_points.is_empty() {
+        sync_samples(movie, track)
+    } else {
+        track
+            .random_access_points
+            .iter()
+            .map(|entry| (entry.time as i64, entry.moof_offset))
+            .collect()
+    };
+    let (time, offset) = candidates
+        .iter()
+        .filter(|&&(time, _)| time <= target.ticks())
+        .max_by_key(|&&(time, _)| time)
+        .or_else(|| candidates.iter().min_by_key(|&&(time, _)| time))
+        .copied()?;
+    Some(SeekPoint {
+        time: MediaTime::signed(time, track.timescale).as_secs_f64(),
+        offset,
+    })
+}
+
+/// The presentation times of the track's sync samples, and where to start reading for each
+fn sync_samples(movie: &Movie, track: &Track) -> Vec<(i64, u64)> {
+    let presentation_time =
+        |decode_time: u64, composition_offset: i32| decode_time as i64 + composition_offset as i64;
+    let mut candidates: Vec<(i64, u64)> = track
+        .sample_table
+        .samples()
+        .iter()
+        .filter(|s| s.is_sync)
+        .map(|s| {
+            (
+                presentation_time(s.decode_time, s.composition_offset),
+                s.offset,
+            )
+        })
+        .collect();
+    for fragment in &movie.fragments {
+        for track_fragment in fragment.tracks.iter().filter(|t| t.track_id == track.id) {
+            candidates.extend(
+                track_fragment
+                    .samples
+                    .iter()
+                    .filter(|s| s.is_sync)
+                    .map(|s| {
+                        let time = presentation_time(s.decode_time, s.composition_offset);
+                        (time, fragment.offset)
+                    }),
+            );
+        }
+    }
+    candidates
+}
@@ -0,0 +1,115 @@
+//! Where the boxes and sample entries are specified, for pointing users of the box tree to the
+//! spec text (see `--spec-refs`)
+
+/// The spec and section that define the box or sample entry type, e.g.
+/// "ISO/IEC 14496-12 §8.3.2" for tkhd. Section numbers of ISO/IEC 14496-12 are those of the
+/// 2015 edition. None for types that aren't known.
+pub fn reference(fourcc: &str) -> Option<&'static str> {
+    let reference = match fourcc {
+        // ISO/IEC 14496-12, the ISO base media file format
+        "uuid" => "ISO/IEC 14496-12 §4.2",
+        "ftyp" => "ISO/IEC 14496-12 §4.3",
+        "mdat" => "ISO/IEC 14496-12 §8.1.1",
+        "free" | "skip" => "ISO/IEC 14496-12 §8.1.2",
+        "moov" => "ISO/IEC 14496-12 §8.2.1",
+        "mvhd" => "ISO/IEC 14496-12 §8.2.2",
+        "trak" => "ISO/IEC 14496-12 §8.3.1",
+        "tkhd" => "ISO/IEC 14496-12 §8.3.2",
+        "tref" => "ISO/IEC 14496-12 §8.3.3",
+        "mdia" => "ISO/IEC 14496-12 §8.4.1",
+        "mdhd" => "ISO/IEC 14496-12 §8.4.2",
+        "hdlr" => "ISO/IEC 14496-12 §8.4.3",
+        "minf" => "ISO/IEC 14496-12 §8.4.4",
+        "nmhd" => "ISO/IEC 14496-12 §8.4.5.2",
+        "stbl" => "ISO/IEC 14496-12 §8.5.1",
+        "stsd" => "ISO/IEC 14496-12 §8.5.2",
+        "stdp" => "ISO/IEC 14496-12 §8.5.3",
+        "stts" => "ISO/IEC 14496-12 §8.6.1.2",
+        "ctts" => "ISO/IEC 14496-12 §8.6.1.3",
+        "cslg" => "ISO/IEC 14496-12 §8.6.1.4",
+        "stss" => "ISO/IEC 14496-12 §8.6.2",
+        "stsh" => "ISO/IEC 14496-12 §8.6.3",
+        "sdtp" => "ISO/IEC 14496-12 §8.6.4",
+        "edts" => "ISO/IEC 14496-12 §8.6.5",
+        "elst" => "ISO/IEC 14496-12 §8.6.6",
+        "dinf" => "ISO/IEC 14496-12 §8.7.1",
+        "dref" | "url " | "urn " => "ISO/IEC 14496-12 §8.7.2",
+        "stsz" | "stz2" => "ISO/IEC 14496-12 §8.7.3",
+        "stsc" => "ISO/IEC 14496-12 §8.7.4",
+        "stco" | "co64" => "ISO/IEC 14496-12 §8.7.5",
+        "padb" => "ISO/IEC 14496-12 §8.7.6",
+        "subs" => "ISO/IEC 14496-12 §8.7.7",
+        "saiz" => "ISO/IEC 14496-12 §8.7.8",
+        "saio" => "ISO/IEC 14496-12 §8.7.9",
+        "mvex" => "ISO/IEC 14496-12 §8.8.1",
+        "mehd" => "ISO/IEC 14496-12 §8.8.2",
+        "trex" => "ISO/IEC 14496-12 §8.8.3",
+        "moof" => "ISO/IEC 14496-12 §8.8.4",
+        "mfhd" => "ISO/IEC 14496-12 §8.8.5",
+        "traf" => "ISO/IEC 14496-12 §8.8.6",
+        "tfhd" => "ISO/IEC 14496-12 §8.8.7",
+        "trun" => "ISO/IEC 14496-12 §8.8.8",
+        "mfra" => "ISO/IEC 14496-12 §8.8.9",
+        "tfra" => "ISO/IEC 14496-12 §8.8.10",
+        "mfro" => "ISO/IEC 14496-12 §8.8.11",
+        "tfdt" => "ISO/IEC 14496-12 §8.8.12",
+        "sbgp" => "ISO/IEC 14496-12 §8.9.2",
+        "sgpd" => "ISO/IEC 14496-12 §8.9.3",
+        "udta" => "ISO/IEC 14496-12 §8.10.1",
+        "cprt" => "ISO/IEC 14496-12 §8.10.2",
+        "meta" => "ISO/IEC 14496-12 §8.11.1",
+        "xml " | "bxml" => "ISO/IEC 14496-12 §8.11.2",
+        "iloc" => "ISO/IEC 14496-12 §8.11.3",
+        "pitm" => "ISO/IEC 14496-12 §8.11.4",
+        "iinf" | "infe" => "ISO/IEC 14496-12 §8.11.6",
+        "meco" => "ISO/IEC 14496-12 §8.11.7",
+        "mere" => "ISO/IEC 14496-12 §8.11.8",
+        "iref" => "ISO/IEC 14496-12 §8.11.12",
+        "sinf" => "ISO/IEC 14496-12 §8.12.1",
+        "frma" => "ISO/IEC 14496-12 §8.12.2",
+        "schm" => "ISO/IEC 14496-12 §8.12.5",
+        "schi" => "ISO/IEC 14496-12 §8.12.6",
+        "encv" | "enca" => "ISO/IEC 14496-12 §8.12",
+        "styp" => "ISO/IEC 14496-12 §8.16.2",
+        "sidx" => "ISO/IEC 14496-12 §8.16.3",
+        "ssix" => "ISO/IEC 14496-12 §8.16.4",
+        "prft" => "ISO/IEC 14496-12 §8.16.5",
+        "vmhd" => "ISO/IEC 14496-12 §12.1.2",
+        "smhd" => "ISO/IEC 14496-12 §12.2.2",
+        "mett" | "metx" => "ISO/IEC 14496-12 §12.3.3",
+        "hmhd" => "ISO/IEC 14496-12 §12.4.2",
+        "sthd" => "ISO/IEC 14496-12 §12.6.2",
+        // Common encryption
+        "senc" => "ISO/IEC 23001-7 §7.2",
+        "pssh" => "ISO/IEC 23001-7 §8.1",
+        "tenc" => "ISO/IEC 23001-7 §8.2",
+        // MPEG-4 and its codecs
+        "mp4a" | "mp4v" => "ISO/IEC 14496-14 §5.6",
+        "esds" => "ISO/IEC 14496-14 §5.6",
+        "avc1" | "avc3" | "avcC" => "ISO/IEC 14496-15 §5.4",
+        "hvc1" | "hev1" | "hvcC" => "ISO/IEC 14496-15 §8.4",
+        "wvtt" | "vttC" | "vlab" => "ISO/IEC 14496-30 §7.5",
+        // HEIF and AVIF
+        "iprp" | "ipco" | "ipma" => "ISO/IEC 23008-12 §9.3",
+        "ispe" => "ISO/IEC 23008-12 §6.5.3",
+        "pixi" => "ISO/IEC 23008-12 §6.5.6",
+        "auxC" => "ISO/IEC 23008-12 §6.5.8",
+        "irot" => "ISO/IEC 23008-12 §6.5.10",
+        "imir" => "ISO/IEC 23008-12 §6.5.12",
+        "a1lx" => "AV1 Image File Format (AVIF) §4.3",
+        // DASH
+        "emsg" => "ISO/IEC 23009-1 §5.10.3.3",
+        // 3GPP
+        "s263" | "d263" => "3GPP TS 26.244 §6.8",
+        "tx3g" | "ftab" => "3GPP TS 26.245 §5.16",
+        // Vendor specs
+        "ac-4" | "dac4" => "ETSI TS 103 190-2 Annex E",
+        "mlpa" | "dmlp" => "Dolby TrueHD (MLP) in ISOBMFF",
+        "dvh1" | "dvhe" | "dvcC" | "dvvC" | "dvwC" => "Dolby Vision streams within ISOBMFF",
+        "chpl" => "Nero chapter list (vendor)",
+        "wide" | "ilst" | "keys" | "mebx" | "twos" | "sowt" | "in24" | "lpcm" | "ulaw" | "alaw"
+        | "jpeg" | "mjpa" | "mjpb" | "png " | "wave" | "srat" => "QuickTime File Format",
+        _ => return None,
+    };
+    Some(reference)
+}
@@ -0,0 +1,350 @@
+//! Timed text tracks: 3GPP timed text (tx3g) and WebVTT in ISOBMFF (wvtt), and their export
+//! to .srt and .vtt files
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use crate::boxes::{BoxHeader, SampleEntry};
+use crate::media_time::MediaTime;
+use crate::model::{Movie, Track};
+use crate::reader::Reader;
+
+/// tx3g
+#[derive(Debug)]
+pub struct TextSampleEntry {
+    pub data_reference_index: u16,
+    pub display_flags: u32,
+    pub horizontal_justification: i8,
+    pub vertical_justification: i8,
+    /// RGBA
+    pub background_color: [u8; 4],
+    /// Of the default style
+    pub font_size: u8,
+    /// RGBA, of the default style
+    pub text_color: [u8; 4],
+    /// From ftab, by font ID
+    pub fonts: Vec<(u16, String)>,
+}
+
+impl TextSampleEntry {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let end_offset = reader.position() + inner_size;
+        let _reserved = reader.read_bytes(6);
+        let data_reference_index = reader.read_u16();
+        let display_flags = reader.read_u32();
+        let horizontal_justification = reader.read_u8() as i8;
+        let vertical_justification = reader.read_u8() as i8;
+        let mut background_color = [0; 4];
+        reader.read_exact(&mut background_color);
+        let _default_text_box = reader.read_bytes(4 * 2);
+        // The default StyleRecord: start and end char, font ID and face style flags
+        let _style = reader.read_bytes(2 + 2 + 2 + 1);
+        let font_size = reader.read_u8();
+        let mut text_color = [0; 4];
+        reader.read_exact(&mut text_color);
+
+        let mut fonts = Vec::new();
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            if header.box_type == "ftab" {
+                let entry_count = reader.read_u16();
+                for _ in 0..entry_count {
+                    let font_id = reader.read_u16();
+                    let name_len = reader.read_u8();
+                    fonts.push((font_id, reader.read_string_inexact(name_len as usize)));
+                }
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+        let remaining = end_offset.saturating_sub(reader.position());
+        reader.skip(remaining);
+
+        Self {
+            data_reference_index,
+            display_flags,
+            horizontal_justification,
+            vertical_justification,
+            background_color,
+            font_size,
+            text_color,
+            fonts,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        print("Display flags", &format!("{:#010x}", self.display_flags));
+        print(
+            "Justification",
+            &format!(
+                "{} horizontal, {} vertical",
+                self.horizontal_justification, self.vertical_justification
+            ),
+        );
+        print("Font size", &self.font_size);
+        print("Text color", &rgba(self.text_color));
+        print("Background color", &rgba(self.background_color));
+        for (font_id, name) in &self.fonts {
+            print(&format!("Font {}", font_id), name);
+        }
+    }
+}
+
+fn rgba(color: [u8; 4]) -> String {
+    format!(
+        "#{:02X}{:02X}{:02X}{:02X}",
+        color[0], color[1], color[2], color[3]
+    )
+}
+
+/// wvtt
+#[derive(Debug)]
+pub struct WebVttSampleEntry {
+    pub data_reference_index: u16,
+    /// From vttC: the header of the WebVTT file, e.g. "WEBVTT" followed by style blocks
+    pub config: Option<String>,
+    /// From vlab: identifies the source of the cues
+    pub source_label: Option<String>,
+}
+
+impl WebVttSampleEntry {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let end_offset = reader.position() + inner_size;
+        let _reserved = reader.read_bytes(6);
+        let data_reference_index = reader.read_u16();
+
+        let mut config = None;
+        let mut source_label = None;
+        while reader.position() + 8 <= end_offset && !reader.failed() {
+            let header = BoxHeader::parse(reader);
+            let box_end_offset = header.start_offset + header.box_size;
+            match header.box_type.as_ref() {
+                "vttC" => config = Some(reader.read_string_inexact(header.inner_size as usize)),
+                "vlab" => {
+                    source_label = Some(reader.read_string_inexact(header.inner_size as usize))
+                }
+                _ => {}
+            }
+            let remaining = box_end_offset.saturating_sub(reader.position());
+            reader.skip(remaining);
+        }
+        let remaining = end_offset.saturating_sub(reader.position());
+        reader.skip(remaining);
+
+        Self {
+            data_reference_index,
+            config,
+            source_label,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        if let Some(config) = &self.config {
+            print("Config", &config.trim_end().replace('\n', "\\n"));
+        }
+        if let Some(source_label) = &self.source_label {
+            print("Source label", source_label);
+        }
+    }
+}
+
+/// A subtitle shown from `start` to `end` in the movie's presentation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: MediaTime,
+    pub end: MediaTime,
+    /// The cue identifier, from iden in WebVTT
+    pub id: Option<String>,
+    /// WebVTT cue settings, from sttg, e.g. "line:90% align:center"
+    pub settings: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    WebVtt,
+}
+
+/// The cues of a tx3g or wvtt track, in presentation order, with the edit list applied. A cue
+/// that was split over several samples (WebVTT does that where cues overlap) is joined again.
+/// None if the track isn't a timed text track.
+pub fn cues(buf: &[u8], movie: &Movie, track: &Track) -> Option<Vec<Cue>> {
+    let is_webvtt = match track.sample_entries.first()? {
+        SampleEntry::Text(_) => false,
+        SampleEntry::WebVtt(_) => true,
+        _ => return None,
+    };
+    let timeline = track.presentation_timeline(movie.timescale);
+    let mut cues: Vec<Cue> = Vec::new();
+    for sample in movie.track_samples(track) {
+        let start = match timeline.presentation_time(&sample) {
+            Some(start) => start,
+            None => continue,
+        };
+        let end = match start.checked_add(MediaTime::new(sample.duration as u64, track.timescale)) {
+            Some(end) => end,
+            None => continue,
+        };
+        let data = buf
+            .get(sample.offset as usize..)
+            .and_then(|data| data.get(..sample.size as usize))
+            .unwrap_or(&[]);
+        let sample_cues = if is_webvtt {
+            webvtt_cues(data)
+        } else {
+            text_sample(data).into_iter().collect()
+        };
+        for (id, settings, text) in sample_cues {
+            let continued = cues.iter_mut().rev().find(|cue| {
+                cue.end == start && cue.id == id && cue.settings == settings && cue.text == text
+            });
+            match continued {
+                Some(cue) => cue.end = end,
+                None => cues.push(Cue {
+                    start,
+                    end,
+                    id,
+                    settings,
+                    text,
+                }),
+            }
+        }
+    }
+    cues.sort_by_key(|cue| cue.start);
+    Some(cues)
+}
+
+/// The text of a tx3g sample, which is None for the empty samples between subtitles. The
+/// modifier boxes after the text, e.g. for styles, are left out.
+fn text_sample(data: &[u8]) -> Option<(Option<String>, Option<String>, String)> {
+    let len = u16::from_be_bytes([*data.first()?, *data.get(1)?]) as usize;
+    let text = data.get(2..2 + len).unwrap_or(&data[2..]);
+    let text = match text {
+        [0xFE, 0xFF, utf16 @ ..] => {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        utf8 => String::from_utf8_lossy(utf8).to_string(),
+    };
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some((None, None, text))
+    }
+}
+
+/// The cues in a wvtt sample: a vttc box for each, with the text in payl. A sample with only
+/// vtte is a gap between cues.
+fn webvtt_cues(data: &[u8]) -> Vec<(Option<String>, Option<String>, String)> {
+    let mut cues = Vec::new();
+    for (box_type, payload) in child_boxes(data) {
+        if box_type != *b"vttc" {
+            continue;
+        }
+        let (mut id, mut settings, mut text) = (None, None, String::new());
+        for (box_type, payload) in child_boxes(payload) {
+            let value = String::from_utf8_lossy(payload).to_string();
+            match &box_type {
+                b"iden" => id = Some(value),
+                b"sttg" => settings = Some(value),
+                b"payl" => text = value,
+                _ => {}
+            }
+        }
+        cues.push((id, settings, text));
+    }
+    cues
+}
+
+/// The type and payload of each box in `data`, up to the first one that doesn't fit
+fn child_boxes(mut data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = Vec::new();
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        if size < 8 || size > data.len() {
+            break;
+        }
+        boxes.push(([data[4], data[5], data[6], data[7]], &data[8..size]));
+        data = &data[size..];
+    }
+    boxes
+}
+
+/// The track's subtitles as the contents of an .srt or .vtt file. None if the track isn't a
+/// timed text track.
+pub fn export(buf: &[u8], movie: &Movie, track: &Track, format: SubtitleFormat) -> Option<String> {
+    let cues = cues(buf, movie, track)?;
+    let mut out = String::new();
+    match format {
+        SubtitleFormat::Srt => {
+            for (i, cue) in cues.iter().enumerate() {
+                let _ = write!(
+                    out,
+                    "{}\n{} --> {}\n{}\n\n",
+                    i + 1,
+                    timestamp(cue.start, ','),
+                    timestamp(cue.end, ','),
+                    cue.text.trim_end()
+                );
+            }
+        }
+        SubtitleFormat::WebVtt => {
+            let config = match track.sample_entries.first() {
+                Some(SampleEntry::WebVtt(entry)) => entry.config.as_deref(),
+                _ => None,
+            };
+            match config {
+                Some(config) if config.starts_with("WEBVTT") => out.push_str(config.trim_end()),
+                _ => out.push_str("WEBVTT"),
+            }
+            out.push_str("\n\n");
+            for cue in &cues {
+                if let Some(id) = &cue.id {
+                    let _ = writeln!(out, "{}", id);
+                }
+                let _ = write!(
+                    out,
+                    "{} --> {}",
+                    timestamp(cue.start, '.'),
+                    timestamp(cue.end, '.')
+                );
+                if let Some(settings) = cue.settings.as_deref().filter(|s| !s.is_empty()) {
+                    let _ = write!(out, " {}", settings);
+                }
+                let _ = write!(out, "\n{}\n\n", cue.text.trim_end());
+            }
+        }
+    }
+    Some(out)
+}
+
+/// E.g. "00:01:02,345" for SRT, with '.' before the milliseconds for WebVTT
+fn timestamp(time: MediaTime, separator: char) -> String {
+    let millis = time.to_duration().unwrap_or_default().as_millis();
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
@@ -0,0 +1,348 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::boxes::{BoxHeader, DolbyVisionConfigurationBox, SampleEntry};
+use crate::brand;
+use crate::media_time::MediaTime;
+use crate::model::{Fragment, Movie, Track, TrackKind};
+use crate::piff;
+use crate::rewrite::top_level_boxes;
+
+/// The essentials of a file, at a glance
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub major_brand: Option<String>,
+    pub compatible_brands: Vec<String>,
+    /// In seconds
+    pub duration: f64,
+    pub fragmented: bool,
+    /// Whether moov comes before the media data, so that playback can start while downloading
+    pub faststart: bool,
+    pub title: Option<String>,
+    pub tracks: Vec<TrackSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackSummary {
+    pub id: u32,
+    pub handler_type: String,
+    pub codec: Option<String>,
+    pub language: String,
+    /// In seconds
+    pub duration: f64,
+    pub sample_count: usize,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    pub sample_rate: Option<f32>,
+    pub channel_count: Option<u16>,
+    /// Average bitrate in bits per second
+    pub bitrate: Option<f64>,
+}
+
+/// What a movie fragment (moof box) contains, so that mfhd, tfhd and trun don't have to be
+/// correlated by hand
+#[derive(Debug, Serialize)]
+pub struct FragmentSummary {
+    pub sequence_number: u32,
+    pub tracks: Vec<TrackFragmentSummary>,
+    /// Size of the mdat box that follows the moof box
+    pub mdat_size: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackFragmentSummary {
+    pub track_id: u32,
+    pub sample_count: usize,
+    /// Decode time of the first sample, in seconds
+    pub start_time: f64,
+    /// Decode time right after the last sample, in seconds
+    pub end_time: f64,
+    /// From tfxd in Smooth Streaming fragments, in the track's timescale
+    pub absolute_time: Option<u64>,
+}
+
+impl Summary {
+    pub fn new(buf: &[u8], movie: &Movie) -> Self {
+        Self::from_layout(movie, &top_level_boxes(buf), !movie.fragments.is_empty())
+    }
+
+    /// For when only the boxes that describe the file have been read, see `probe::probe`
+    pub fn from_layout(movie: &Movie, top_level_boxes: &[BoxHeader], fragmented: bool) -> Self {
+        let position = |box_type: &str| top_level_boxes.iter().position(|b| b.box_type == box_type);
+        let faststart = match (position("moov"), position("mdat")) {
+            (Some(moov), Some(mdat)) => moov < mdat,
+            _ => false,
+        };
+
+        let tracks: Vec<TrackSummary> = movie
+            .tracks
+            .iter()
+            .map(|track| TrackSummary::new(movie, track))
+            .collect();
+        let mut duration = MediaTime::new(movie.duration, movie.timescale).as_secs_f64();
+        if fragmented {
+            // The movie header's duration doesn't include fragments
+            duration = tracks.iter().map(|t| t.duration).fold(duration, f64::max);
+        }
+
+        Self {
+            major_brand: movie.file_type.as_ref().map(|f| f.major_brand.clone()),
+            compatible_brands: movie
+                .file_type
+                .as_ref()
+                .map(|f| f.compatible_brands.clone())
+                .unwrap_or_default(),
+            duration,
+            fragmented,
+            faststart,
+            title: movie.title().map(|t| t.to_string()),
+            tracks,
+        }
+    }
+}
+
+impl TrackSummary {
+    fn new(movie: &Movie, track: &Track) -> Self {
+        let samples = movie.track_samples(track);
+        let bitrate = movie.bitrate(track);
+
+        let mut summary = Self {
+            id: track.id,
+            handler_type: track.handler_type.clone(),
+            codec: None,
+            language: track.language.clone(),
+            duration: movie.presentation_duration(track),
+            sample_count: samples.len(),
+            width: None,
+            height: None,
+            sample_rate: None,
+            channel_count: None,
+            bitrate,
+        };
+        match track.sample_entries.first() {
+            Some(SampleEntry::Avc1(avc1)) => {
+                let profile = avc1.avc_config.as_ref().map(|config| {
+                    format!(
+                        " ({} profile, level {})",
+                        config.profile_name(),
+                        config.level()
+                    )
+                });
+                summary.codec = Some(format!(
+                    "H.264/avc1{}{}",
+                    profile.unwrap_or_default(),
+                    dolby_vision(&avc1.dolby_vision_config)
+                ));
+                summary.width = Some(avc1.width);
+                summary.height = Some(avc1.height);
+            }
+            Some(SampleEntry::Hevc(hevc)) => {
+                summary.codec = Some(format!(
+                    "H.265/{}{}",
+                    hevc.fourcc,
+                    dolby_vision(&hevc.dolby_vision_config)
+                ));
+                summary.width = Some(hevc.width);
+                summary.height = Some(hevc.height);
+            }
+            Some(SampleEntry::Mp4v(mp4v)) => {
+                summary.codec = Some(format!("MPEG-4 Visual/{}", mp4v.codec_string()));
+                summary.width = Some(mp4v.width);
+                summary.height = Some(mp4v.height);
+            }
+            Some(SampleEntry::H263(h263)) => {
+                let profile = h263
+                    .h263_config
+                    .as_ref()
+                    .map(|config| format!(" (profile {}, level {})", config.profile, config.level));
+                summary.codec = Some(format!("H.263/s263{}", profile.unwrap_or_default()));
+                summary.width = Some(h263.width);
+                summary.height = Some(h263.height);
+            }
+            Some(SampleEntry::Image(image)) => {
+                summary.codec = Some(format!(
+                    "{}/{}",
+                    image.format_name(),
+                    image.fourcc.trim_end()
+                ));
+                summary.width = Some(image.width);
+                summary.height = Some(image.height);
+            }
+            Some(SampleEntry::Mp4a(mp4a)) => {
+                summary.codec = Some("mp4a".to_string());
+                summary.sample_rate = Some(mp4a.sample_rate);
+                summary.channel_count = Some(mp4a.channel_count);
+            }
+            Some(SampleEntry::Ac4(ac4)) => {
+                summary.codec = Some("AC-4/ac-4".to_string());
+                summary.sample_rate = Some(ac4.sample_rate);
+                summary.channel_count = Some(ac4.channel_count);
+            }
+            Some(SampleEntry::Mlpa(mlpa)) => {
+                summary.codec = Some("TrueHD/mlpa".to_string());
+                summary.sample_rate = Some(mlpa.sampling_rate() as f32);
+                summary.channel_count = Some(mlpa.channels());
+            }
+            Some(SampleEntry::Pcm(pcm)) => {
+                summary.codec = Some(format!("PCM/{}", pcm.fourcc));
+                summary.sample_rate = Some(pcm.sample_rate as f32);
+                summary.channel_count = Some(pcm.channel_count as u16);
+            }
+            Some(SampleEntry::Encrypted(entry)) => {
+                let original_format = entry
+                    .protection
+                    .as_ref()
+                    .map_or("unknown", |protection| &protection.original_format);
+                summary.codec = Some(format!("encrypted {}/{}", original_format, entry.fourcc));
+            }
+            Some(SampleEntry::Mebx(_)) => summary.codec = Some("mebx".to_string()),
+            Some(SampleEntry::TimedMetadata(entry)) => {
+                summary.codec = Some(format!("{}/{}", entry.format, entry.fourcc))
+            }
+            Some(SampleEntry::Text(_)) => summary.codec = Some("3GPP timed text/tx3g".to_string()),
+            Some(SampleEntry::WebVtt(_)) => summary.codec = Some("WebVTT/wvtt".to_string()),
+            Some(SampleEntry::Unknown(entry)) => {
+                summary.codec = Some(format!("unknown/{}", entry.fourcc))
+            }
+            None => {}
+        }
+        summary
+    }
+
+    fn kind(&self) -> TrackKind {
+        TrackKind::from_handler_type(&self.handler_type)
+    }
+}
+
+impl FragmentSummary {
+    pub fn new(movie: &Movie, fragment: &Fragment) -> Self {
+        let tracks = fragment
+            .tracks
+            .iter()
+            .map(|track_fragment| {
+                // Smooth Streaming fragments may come without moov
+                let default_timescale = if movie.is_smooth_streaming() {
+                    piff::DEFAULT_TIMESCALE
+                } else {
+                    1
+                };
+                let timescale = movie
+                    .tracks
+                    .iter()
+                    .find(|t| t.id == track_fragment.track_id)
+                    .map_or(default_timescale, |t| t.timescale.max(1));
+                let samples = &track_fragment.samples;
+                let start = samples.first().map_or(0, |s| s.decode_time);
+                let end = samples
+                    .last()
+                    .map_or(start, |s| s.decode_time + s.duration as u64);
+                TrackFragmentSummary {
+                    track_id: track_fragment.track_id,
+                    sample_count: samples.len(),
+                    start_time: MediaTime::new(start, timescale).as_secs_f64(),
+                    end_time: MediaTime::new(end, timescale).as_secs_f64(),
+                    absolute_time: track_fragment.absolute_time,
+                }
+            })
+            .collect();
+        Self {
+            sequence_number: fragment.sequence_number,
+            tracks,
+            mdat_size: fragment.mdat_size,
+        }
+    }
+}
+
+/// E.g. " + Dolby Vision dvhe.08.06"
+fn dolby_vision(config: &Option<DolbyVisionConfigurationBox>) -> String {
+    config.as_ref().map_or(String::new(), |config| {
+        format!(" + Dolby Vision {}", config.codec_string())
+    })
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(major_brand) = &self.major_brand {
+            write!(f, "Brand:      {}", major_brand)?;
+            if let Some(description) = brand::describe(major_brand) {
+                write!(f, ": {}", description)?;
+            }
+            writeln!(f, " (compatible: {})", self.compatible_brands.join(", "))?;
+        }
+        if let Some(title) = &self.title {
+            writeln!(f, "Title:      {}", title)?;
+        }
+        writeln!(f, "Duration:   {:.3}s", self.duration)?;
+        writeln!(
+            f,
+            "Layout:     {}, {}",
+            if self.fragmented {
+                "fragmented"
+            } else {
+                "not fragmented"
+            },
+            if self.faststart {
+                "faststart (moov before mdat)"
+            } else {
+                "not faststart"
+            }
+        )?;
+        for track in &self.tracks {
+            let mut details = vec![track
+                .codec
+                .clone()
+                .unwrap_or_else(|| "unknown codec".into())];
+            if let (Some(width), Some(height)) = (track.width, track.height) {
+                details.push(format!("{}x{}", width, height));
+            }
+            if let Some(sample_rate) = track.sample_rate {
+                details.push(format!("{} Hz", sample_rate));
+            }
+            if let Some(channel_count) = track.channel_count {
+                details.push(format!("{} channels", channel_count));
+            }
+            if let Some(bitrate) = track.bitrate {
+                details.push(format!("{:.1} kbit/s", bitrate / 1000.0));
+            }
+            details.push(format!("{:.3}s", track.duration));
+            details.push(format!("{} samples", track.sample_count));
+            details.push(format!("language: {}", track.language));
+            writeln!(
+                f,
+                "Track {}:    {}: {}",
+                track.id,
+                track.kind(),
+                details.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FragmentSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fragment {}:", self.sequence_number)?;
+        for (i, track) in self.tracks.iter().enumerate() {
+            write!(
+                f,
+                "{} track {}: {} samples, {:.3}s-{:.3}s",
+                if i > 0 { ";" } else { "" },
+                track.track_id,
+                track.sample_count,
+                track.start_time,
+                track.end_time
+            )?;
+            if let Some(absolute_time) = track.absolute_time {
+                write!(f, " (absolute time {})", absolute_time)?;
+            }
+        }
+        if self.tracks.is_empty() {
+            write!(f, " no tracks")?;
+        }
+        match self.mdat_size {
+            Some(mdat_size) => write!(f, "; mdat: {} bytes", mdat_size),
+            None => write!(f, "; no mdat"),
+        }
+    }
+}
@@ -0,0 +1,517 @@
+//! Timed metadata: events in emsg boxes, as in DASH and CMAF segments (e.g. SCTE-35 ad markers),
+//! and the samples of metadata tracks (mett, metx and mebx). `collect` lists them by
+//! presentation time, with ID3 payloads decoded.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use serde::Serialize;
+
+use crate::boxes::{FullBoxHeader, SampleEntry};
+use crate::heif::read_c_string;
+use crate::media_time::MediaTime;
+use crate::model::{Movie, Sample};
+use crate::reader::Reader;
+
+/// emsg
+#[derive(Debug)]
+pub struct EventMessageBox {
+    pub version: u8,
+    /// E.g. "urn:scte:scte35:2013:bin" or "https://aomedia.org/emsg/ID3"
+    pub scheme_id_uri: String,
+    pub value: String,
+    pub timescale: u32,
+    /// In version 0, relative to the start of the segment. In version 1, the presentation time.
+    pub presentation_time: u64,
+    /// 0xFFFFFFFF if unknown
+    pub event_duration: u32,
+    pub id: u32,
+    pub message_data: Vec<u8>,
+}
+
+impl EventMessageBox {
+    pub fn parse(reader: &mut Reader, inner_size: u64) -> Self {
+        let end_offset = reader.position() + inner_size;
+        let full_box = FullBoxHeader::parse(reader);
+        let (scheme_id_uri, value, timescale, presentation_time, event_duration, id) =
+            if full_box.version == 0 {
+                let scheme_id_uri = read_c_string(reader, end_offset);
+                let value = read_c_string(reader, end_offset);
+                let timescale = reader.read_u32();
+                let presentation_time_delta = reader.read_u32() as u64;
+                let event_duration = reader.read_u32();
+                let id = reader.read_u32();
+                (
+                    scheme_id_uri,
+                    value,
+                    timescale,
+                    presentation_time_delta,
+                    event_duration,
+                    id,
+                )
+            } else {
+                let timescale = reader.read_u32();
+                let presentation_time = reader.read_u64();
+                let event_duration = reader.read_u32();
+                let id = reader.read_u32();
+                let scheme_id_uri = read_c_string(reader, end_offset);
+                let value = read_c_string(reader, end_offset);
+                (
+                    scheme_id_uri,
+                    value,
+                    timescale,
+                    presentation_time,
+                    event_duration,
+                    id,
+                )
+            };
+        let remaining = end_offset.saturating_sub(reader.position());
+        let message_data = reader.read_bytes(remaining as usize);
+        Self {
+            version: full_box.version,
+            scheme_id_uri,
+            value,
+            timescale,
+            presentation_time,
+            event_duration,
+            id,
+            message_data,
+        }
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Scheme", &self.scheme_id_uri);
+        print("Value", &self.value);
+        print("Timescale", &self.timescale);
+        let time = MediaTime::new(self.presentation_time, self.timescale);
+        if self.version == 0 {
+            print("Presentation time delta", &time);
+        } else {
+            print("Presentation time", &time);
+        }
+        if self.event_duration == u32::MAX {
+            print("Event duration", &"unknown");
+        } else {
+            print(
+                "Event duration",
+                &MediaTime::new(self.event_duration as u64, self.timescale),
+            );
+        }
+        print("ID", &self.id);
+        print(
+            "Message data",
+            &format!("{} bytes", self.message_data.len()),
+        );
+    }
+}
+
+/// An emsg box from the top level of the file
+#[derive(Debug)]
+pub struct EventMessage {
+    /// File offset of the box
+    pub offset: u64,
+    /// The index of the movie fragment that follows the box. A version 0 box's time is relative
+    /// to the start of the segment that it begins.
+    pub fragment_index: usize,
+    pub message: EventMessageBox,
+}
+
+/// mett (TextMetaDataSampleEntry) or metx (XMLMetaDataSampleEntry)
+#[derive(Debug)]
+pub struct TimedMetadataSampleEntry {
+    pub fourcc: String,
+    pub data_reference_index: u16,
+    /// Empty if the samples aren't e.g. compressed
+    pub content_encoding: String,
+    /// The MIME type of the samples for mett, e.g. "application/id3", or the XML namespace
+    /// for metx
+    pub format: String,
+    /// For metx only
+    pub schema_location: Option<String>,
+}
+
+impl TimedMetadataSampleEntry {
+    pub fn parse(reader: &mut Reader, fourcc: &str, inner_size: u64) -> Self {
+        let end_offset = reader.position() + inner_size;
+        let _reserved = reader.read_bytes(6);
+        let data_reference_index = reader.read_u16();
+        let content_encoding = read_c_string(reader, end_offset);
+        let format = read_c_string(reader, end_offset);
+        let schema_location = if fourcc == "metx" {
+            Some(read_c_string(reader, end_offset))
+        } else {
+            None
+        };
+        // btrt or txtC may follow
+        let remaining = end_offset.saturating_sub(reader.position());
+        reader.skip(remaining);
+
+        Self {
+            fourcc: fourcc.to_string(),
+            data_reference_index,
+            content_encoding,
+            format,
+            schema_location,
+        }
+    }
+
+    /// Whether the samples are text that can be shown as it is
+    pub fn is_text(&self) -> bool {
+        self.content_encoding.is_empty()
+            && (self.fourcc == "metx" || self.format.starts_with("text/"))
+    }
+
+    pub fn print_attributes<F>(&self, print: F)
+    where
+        F: Fn(&str, &dyn core::fmt::Display),
+    {
+        print("Data reference index", &self.data_reference_index);
+        if !self.content_encoding.is_empty() {
+            print("Content encoding", &self.content_encoding);
+        }
+        if self.fourcc == "metx" {
+            print("Namespace", &self.format);
+        } else {
+            print("MIME format", &self.format);
+        }
+        if let Some(schema_location) = &self.schema_location {
+            print("Schema location", schema_location);
+        }
+    }
+}
+
+/// An event or a metadata sample, at the time it's presented
+#[derive(Debug, Clone, Serialize)]
+pub struct TimedMetadata {
+    /// In seconds
+    pub time: f64,
+    /// In seconds. None if unknown.
+    pub duration: Option<f64>,
+    /// "emsg", or the sample entry type of the metadata track
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track_id: Option<u32>,
+    /// The scheme and value of an emsg event
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme_id_uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// The ID of an emsg event
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    /// File offset of the emsg box or the sample
+    pub offset: u64,
+    pub payload: Payload,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Payload {
+    /// Binary data, e.g. a SCTE-35 splice_info_section, base64-encoded
+    Base64(String),
+    /// The frames of an ID3v2 tag
+    Id3(Vec<Id3Frame>),
+    Text(String),
+}
+
+/// A frame of an ID3v2.3 or ID3v2.4 tag
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Id3Frame {
+    /// E.g. "TIT2" or "PRIV"
+    pub id: String,
+    /// The description of a TXXX frame, or the owner of a PRIV frame
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// For text frames
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// For other frames, base64-encoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+/// The emsg events and the samples of metadata tracks, in presentation order. Samples that the
+/// edit list leaves out aren't included.
+pub fn collect(buf: &[u8], movie: &Movie) -> Vec<TimedMetadata> {
+    let mut entries = Vec::new();
+    for event in &movie.event_messages {
+        let message = &event.message;
+        let time = MediaTime::new(message.presentation_time, message.timescale);
+        let time = if message.version == 0 {
+            segment_start(movie, event.fragment_index)
+                .and_then(|start| start.checked_add(time))
+                .unwrap_or(time)
+        } else {
+            time
+        };
+        let duration = Some(message.event_duration)
+            .filter(|&duration| duration != u32::MAX)
+            .map(|duration| MediaTime::new(duration as u64, message.timescale).as_secs_f64());
+        entries.push((
+            time,
+            TimedMetadata {
+                time: time.as_secs_f64(),
+                duration,
+                source: "emsg".to_string(),
+                track_id: None,
+                scheme_id_uri: Some(message.scheme_id_uri.clone()),
+                value: Some(message.value.clone()),
+                id: Some(message.id),
+                offset: event.offset,
+                payload: binary_payload(&message.message_data),
+            },
+        ));
+    }
+
+    for track in &movie.tracks {
+        let is_metadata = track
+            .sample_entries
+            .iter()
+            .any(|entry| matches!(entry, SampleEntry::TimedMetadata(_) | SampleEntry::Mebx(_)));
+        if !is_metadata {
+            continue;
+        }
+        let timeline = track.presentation_timeline(movie.timescale);
+        for sample in movie.track_samples(track) {
+            let time = match timeline.presentation_time(&sample) {
+                Some(time) => time,
+                None => continue,
+            };
+            let entry = track
+                .sample_entries
+                .get(sample.description_index.saturating_sub(1) as usize);
+            let data = sample_data(buf, &sample);
+            let (source, payload) = match entry {
+                Some(SampleEntry::TimedMetadata(entry)) if entry.is_text() => (
+                    entry.fourcc.clone(),
+                    Payload::Text(String::from_utf8_lossy(data).to_string()),
+                ),
+                Some(SampleEntry::TimedMetadata(entry)) => {
+                    (entry.fourcc.clone(), binary_payload(data))
+                }
+                _ => ("mebx".to_string(), binary_payload(data)),
+            };
+            entries.push((
+                time,
+                TimedMetadata {
+                    time: time.as_secs_f64(),
+                    duration: Some(
+                        MediaTime::new(sample.duration as u64, track.timescale).as_secs_f64(),
+                    ),
+                    source,
+                    track_id: Some(track.id),
+                    scheme_id_uri: None,
+                    value: None,
+                    id: None,
+                    offset: sample.offset,
+                    payload,
+                },
+            ));
+        }
+    }
+    entries.sort_by_key(|(time, _)| *time);
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// The earliest presentation time of the fragment's first track, which starts the segment
+fn segment_start(movie: &Movie, fragment_index: usize) -> Option<MediaTime> {
+    let fragment = movie.fragments.get(fragment_index)?;
+    let track_fragment = fragment.tracks.first()?;
+    let track = movie
+        .tracks
+        .iter()
+        .find(|track| track.id == track_fragment.track_id)?;
+    let earliest = track_fragment
+        .samples
+        .iter()
+        .map(|s| s.decode_time as i64 + s.composition_offset as i64)
+        .min()?;
+    Some(MediaTime::signed(earliest, track.timescale))
+}
+
+fn sample_data<'a>(buf: &'a [u8], sample: &Sample) -> &'a [u8] {
+    buf.get(sample.offset as usize..)
+        .and_then(|data| data.get(..sample.size as usize))
+        .unwrap_or(&[])
+}
+
+/// The frames of the data if it's an ID3 tag, and the data in base64 otherwise
+fn binary_payload(data: &[u8]) -> Payload {
+    match id3_frames(data) {
+        Some(frames) => Payload::Id3(frames),
+        None => Payload::Base64(base64(data)),
+    }
+}
+
+/// The frames of an ID3v2.3 or ID3v2.4 tag. None if the data isn't one.
+pub fn id3_frames(data: &[u8]) -> Option<Vec<Id3Frame>> {
+    if data.len() < 10 || &data[..3] != b"ID3" {
+        return None;
+    }
+    let major_version = data[3];
+    if major_version != 3 && major_version != 4 {
+        return None;
+    }
+    let flags = data[5];
+    let tag_end = (10 + synchsafe(&data[6..10]) as usize).min(data.len());
+    let mut pos = 10;
+    if flags & 0x40 != 0 {
+        // An extended header, whose size includes itself in version 4 only
+        let size = data.get(10..14)?;
+        pos += if major_version == 4 {
+            synchsafe(size) as usize
+        } else {
+            4 + u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize
+        };
+    }
+
+    let mut frames = Vec::new();
+    while pos + 10 <= tag_end && data[pos] != 0 {
+        let header = &data[pos..pos + 10];
+        let id = String::from_utf8_lossy(&header[..4]).to_string();
+        let size = if major_version == 4 {
+            synchsafe(&header[4..8])
+        } else {
+            u32::from_be_bytes([header[4], header[5], header[6], header[7]])
+        } as usize;
+        let body = data.get(pos + 10..pos + 10 + size)?;
+        pos += 10 + size;
+        frames.push(id3_frame(id, body));
+    }
+    Some(frames)
+}
+
+fn id3_frame(id: String, body: &[u8]) -> Id3Frame {
+    let mut frame = Id3Frame {
+        id,
+        description: None,
+        text: None,
+        data: None,
+    };
+    match (frame.id.as_str(), body.split_first()) {
+        ("TXXX", Some((&encoding, text))) => {
+            let text = id3_text(encoding, text);
+            let (description, value) = text.split_once('\0').unwrap_or(("", &text));
+            frame.description = Some(description.to_string());
+            frame.text = Some(value.trim_end_matches('\0').to_string());
+        }
+        (id, Some((&encoding, text))) if id.starts_with('T') => {
+            let text = id3_text(encoding, text);
+            frame.text = Some(text.trim_end_matches('\0').replace('\0', " / "));
+        }
+        ("PRIV", _) => {
+            let owner_len = body.iter().position(|&b| b == 0).unwrap_or(body.len());
+            frame.description = Some(latin1(&body[..owner_len]));
+            frame.data = Some(base64(body.get(owner_len + 1..).unwrap_or_default()));
+        }
+        _ => frame.data = Some(base64(body)),
+    }
+    frame
+}
+
+/// Text in one of ID3's encodings: ISO-8859-1, UTF-16 with a byte order mark, UTF-16BE or
+/// UTF-8. Strings are separated by '\0'.
+fn id3_text(encoding: u8, text: &[u8]) -> String {
+    match encoding {
+        0 => latin1(text),
+        1 | 2 => {
+            let mut little_endian = false;
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .filter_map(|pair| match (pair, encoding) {
+                    ([0xFF, 0xFE], 1) => {
+                        little_endian = true;
+                        None
+                    }
+                    ([0xFE, 0xFF], 1) => {
+                        little_endian = false;
+                        None
+                    }
+                    ([a, b], _) if little_endian => Some(u16::from_le_bytes([*a, *b])),
+                    ([a, b], _) => Some(u16::from_be_bytes([*a, *b])),
+                    _ => None,
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).to_string(),
+    }
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// 7 bits per byte, as ID3 stores sizes
+fn synchsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |size, &byte| size << 7 | (byte & 0x7F) as u32)
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// One line, e.g. "12.000s emsg urn:scte:scte35:2013:bin id=1 (10.000s): base64 /DAl..."
+impl fmt::Display for TimedMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.3}s {}", self.time, self.source)?;
+        if let Some(track_id) = self.track_id {
+            write!(f, " track {}", track_id)?;
+        }
+        if let Some(scheme_id_uri) = &self.scheme_id_uri {
+            write!(f, " {}", scheme_id_uri)?;
+        }
+        if let Some(value) = self.value.as_deref().filter(|value| !value.is_empty()) {
+            write!(f, " value={}", value)?;
+        }
+        if let Some(id) = self.id {
+            write!(f, " id={}", id)?;
+        }
+        if let Some(duration) = self.duration {
+            write!(f, " ({:.3}s)", duration)?;
+        }
+        match &self.payload {
+            Payload::Base64(data) => write!(f, ": base64 {}", data),
+            Payload::Text(text) => write!(f, ": {}", text.trim_end().replace('\n', "\\n")),
+            Payload::Id3(frames) => {
+                write!(f, ": ID3")?;
+                for frame in frames {
+                    write!(f, " {}", frame.id)?;
+                    if let Some(description) = &frame.description {
+                        write!(f, "[{}]", description)?;
+                    }
+                    if let Some(text) = &frame.text {
+                        write!(f, "={:?}", text)?;
+                    } else if let Some(data) = &frame.data {
+                        write!(f, "={}", data)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,679 @@
+//! Printing the box tree of a file, as done by the parse tool
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::box_path;
+use crate::boxes::{format_duration, BoxHeader, EditListBox, Mp4Box, Timestamp};
+use crate::diagnostics::{self, Severity};
+use crate::error::ParseError;
+use crate::logger::Logger;
+use crate::media_time::MediaTime;
+use crate::model::Movie;
+use crate::quicktime::MetadataItem;
+use crate::reader::{ParserOptions, Reader};
+use crate::rewrite::top_level_boxes;
+use crate::spec;
+use crate::summary::FragmentSummary;
+
+/// Decides which boxes are printed, and how
+#[derive(Debug, Default)]
+pub struct BoxFilter {
+    pub only: Option<Vec<String>>,
+    pub skip: Vec<String>,
+    /// Top-level boxes have depth 1. Boxes below this depth aren't parsed.
+    pub max_depth: Option<usize>,
+    /// Only the trak and traf boxes of this track are printed
+    pub track_id: Option<u32>,
+    /// Print each box's fields next to their raw bytes
+    pub hexdump_fields: bool,
+    /// Print where each box is specified, see `spec::reference`
+    pub spec_refs: bool,
+    /// Show creation and modification times in the local time zone instead of UTC
+    pub local_time: bool,
+}
+
+impl BoxFilter {
+    /// Skipped boxes are jumped over, along with all of their children
+    fn skips(&self, box_type: &str) -> bool {
+        self.skip.iter().any(|t| t == box_type)
+    }
+
+    /// Boxes that aren't shown are still parsed, as their children may be shown
+    fn shows(&self, box_type: &str) -> bool {
+        match &self.only {
+            Some(only) => only.iter().any(|t| t == box_type),
+            None => true,
+        }
+    }
+
+    /// Whether the box is a trak or traf of another track than `track_id`. The track ID is
+    /// read ahead from the tkhd or tfhd box in it.
+    fn skips_track(&self, buf: &[u8], header: &BoxHeader) -> bool {
+        let track_id = match self.track_id {
+            Some(track_id) => track_id,
+            None => return false,
+        };
+        let child_type: &[u8] = match header.box_type.as_str() {
+            "trak" => b"tkhd",
+            "traf" => b"tfhd",
+            _ => return false,
+        };
+        let end = header.start_offset + header.box_size;
+        let start = end - header.inner_size;
+        let mut children = buf
+            .get(start as usize..(end as usize).min(buf.len()))
+            .unwrap_or(&[]);
+        while children.len() >= 8 {
+            let size = u32::from_be_bytes([children[0], children[1], children[2], children[3]]);
+            if &children[4..8] == child_type {
+                // After the version and flags, and in tkhd, the creation and modification times
+                let id_offset = match (child_type, children.get(8)) {
+                    (b"tkhd", Some(1)) => 28,
+                    (b"tkhd", _) => 20,
+                    _ => 12,
+                };
+                let id = children
+                    .get(id_offset..id_offset + 4)
+                    .map(|id| u32::from_be_bytes([id[0], id[1], id[2], id[3]]));
+                return id != Some(track_id);
+            }
+            if size < 8 || size as usize > children.len() {
+                break;
+            }
+            children = &children[size as usize..];
+        }
+        false
+    }
+
+    fn descends_below(&self, depth: usize) -> bool {
+        match self.max_depth {
+            Some(max_depth) => depth < max_depth,
+            None => true,
+        }
+    }
+}
+
+/// What is remembered from earlier boxes while printing later ones
+#[derive(Default)]
+struct Context<'a> {
+    /// The whole file, for the raw data of unknown boxes
+    buf: &'a [u8],
+    /// Only needed for the fragment summaries
+    movie: Option<&'a Movie>,
+    /// From mvhd, for the durations in tkhd, mehd and elst
+    movie_timescale: u32,
+    /// From tkhd or tfhd
+    current_track_id: u32,
+    /// Per track ID, from mdhd, for the durations in trun
+    track_timescales: HashMap<u32, u32>,
+    /// Per track ID, from trex
+    default_sample_durations: HashMap<u32, u32>,
+    /// From the current tfhd
+    tfhd_default_sample_duration: Option<u32>,
+}
+
+impl Context<'_> {
+    fn remember(&mut self, box_: &Mp4Box) {
+        match box_ {
+            Mp4Box::Mvhd(b) => self.movie_timescale = b.timescale,
+            Mp4Box::Tkhd(b) => self.current_track_id = b.track_id,
+            Mp4Box::Mdhd(b) => {
+                self.track_timescales
+                    .insert(self.current_track_id, b.timescale);
+            }
+            Mp4Box::Trex(b) => {
+                self.default_sample_durations
+                    .insert(b.track_id, b.default_sample_duration);
+            }
+            Mp4Box::Tfhd(b) => {
+                self.current_track_id = b.track_id;
+                self.tfhd_default_sample_duration = b.default_sample_duration;
+            }
+            _ => {}
+        }
+    }
+
+    fn track_timescale(&self) -> u32 {
+        self.track_timescales
+            .get(&self.current_track_id)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// The "Created" and "Modified" attributes in the local time zone, for `BoxFilter::local_time`
+fn show_local_times(
+    attributes: &mut [(String, String)],
+    filter: &BoxFilter,
+    created: &Timestamp,
+    modified: &Timestamp,
+) {
+    if !filter.local_time {
+        return;
+    }
+    for (key, value) in attributes.iter_mut() {
+        match key.as_str() {
+            "Created" => *value = created.to_local_string(),
+            "Modified" => *value = modified.to_local_string(),
+            _ => {}
+        }
+    }
+}
+
+/// Replaces a duration in ticks with one that also shows the time, for boxes whose timescale
+/// is in another box
+fn show_duration(attributes: &mut [(String, String)], label: &str, ticks: u64, timescale: u32) {
+    for (key, value) in attributes.iter_mut() {
+        if key == label {
+            *value = format_duration(ticks, timescale);
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum HandleUnknown {
+    Skip,
+    Fail,
+}
+
+/// Prints the boxes of the file, and their attributes, to the logger. Each moof box is followed
+/// by a summary of the fragment. The boxes before the first one that can't be parsed are
+/// printed, even when an error is returned.
+pub fn parse_mp4(
+    buf: &[u8],
+    logger: &mut dyn Logger,
+    filter: &BoxFilter,
+) -> Result<(), ParseError> {
+    parse_mp4_with_options(buf, logger, filter, &ParserOptions::default())
+}
+
+/// Like `parse_mp4`, but with another memory budget than `reader::DEFAULT_MAX_ALLOC_BYTES`
+pub fn parse_mp4_with_options(
+    buf: &[u8],
+    logger: &mut dyn Logger,
+    filter: &BoxFilter,
+    options: &ParserOptions,
+) -> Result<(), ParseError> {
+    let mut reader = Reader::with_options(buf, options);
+    if filter.hexdump_fields {
+        reader.record_fields();
+    }
+    let fragmented = top_level_boxes(buf).iter().any(|b| b.box_type == "moof");
+    let movie = if fragmented {
+        Some(Movie::parse_with_options(buf, options)?)
+    } else {
+        None
+    };
+
+    let mut context = Context {
+        buf,
+        movie: movie.as_ref(),
+        ..Context::default()
+    };
+    _parse(
+        &mut reader,
+        logger,
+        filter,
+        &mut context,
+        HandleUnknown::Fail,
+        buf.len() as u64,
+        1,
+    );
+
+    box_path::clear();
+    if let Some(error) = reader.take_error() {
+        return Err(error);
+    }
+    logger.debug(&format!("[{}]", reader.position()));
+    logger.debug("Reached end of file");
+    Ok(())
+}
+
+fn _parse(
+    reader: &mut Reader,
+    logger: &mut dyn Logger,
+    filter: &BoxFilter,
+    context: &mut Context,
+    handle_unknown: HandleUnknown,
+    end_offset: u64,
+    depth: usize,
+) {
+    while reader.position() < end_offset && !reader.failed() {
+        let box_start_offset = reader.position();
+        // Only what is read of this box is dumped
+        reader.take_fields();
+
+        let header = match BoxHeader::parse_within(reader, end_offset) {
+            Some(header) => header,
+            None => return,
+        };
+
+        if filter.skips(&header.box_type) || filter.skips_track(context.buf, &header) {
+            reader.skip(header.inner_size);
+            continue;
+        }
+        let shown = filter.shows(&header.box_type);
+
+        if shown {
+            logger.log_start_of_box(header.start_offset);
+            logger.debug_box_header(&header.box_type, header.box_size);
+        }
+
+        let (box_, trailing_data) =
+            Mp4Box::parse_with_trailing_data(reader, &header.box_type, header.inner_size);
+        // println!("DEBUG: Parsed box: {:?}", box_);
+
+        if let Mp4Box::Unknown(unknown) = &box_ {
+            match handle_unknown {
+                HandleUnknown::Skip => {
+                    let text = format!(
+                        "Skipping unknown: '{}' ({} bytes)",
+                        header.box_type, header.box_size
+                    );
+                    if shown {
+                        logger.warn(&text);
+                    }
+                    diagnostics::report(Severity::Info, text);
+                    logger.raw_data(
+                        &unknown.box_type,
+                        unknown.data.offset,
+                        unknown.data.bytes(context.buf),
+                    );
+                    continue;
+                }
+                HandleUnknown::Fail => {
+                    reader.fail(format!(
+                        "Unhandled box: {:?} (inner size: {})",
+                        header.box_type, header.inner_size
+                    ));
+                    return;
+                }
+            }
+        }
+        if let Some(trailing_data) = trailing_data {
+            logger.raw_data(
+                &header.box_type,
+                trailing_data.offset,
+                trailing_data.bytes(context.buf),
+            );
+        }
+
+        context.remember(&box_);
+
+        let box_end_offset = box_start_offset + header.box_size;
+        if !shown {
+            if let Mp4Box::Container(_) = box_ {
+                if filter.descends_below(depth) {
+                    let handle_unknown = HandleUnknown::Skip;
+                    _parse(
+                        reader,
+                        logger,
+                        filter,
+                        context,
+                        handle_unknown,
+                        box_end_offset,
+                        depth + 1,
+                    );
+                }
+            }
+            skip_to_end(reader, &header.box_type, box_end_offset);
+            continue;
+        }
+
+        logger.log_box_title(box_.name());
+        let attributes = RefCell::new(Vec::new());
+        box_.print_attributes(|k, v| attributes.borrow_mut().push((k.to_string(), v.to_string())));
+        let mut attributes = attributes.into_inner();
+        match &box_ {
+            Mp4Box::Mvhd(b) => show_local_times(
+                &mut attributes,
+                filter,
+                &b.creation_time,
+                &b.modification_time,
+            ),
+            Mp4Box::Tkhd(b) => {
+                show_local_times(
+                    &mut attributes,
+                    filter,
+                    &b.creation_time,
+                    &b.modification_time,
+                );
+                show_duration(
+                    &mut attributes,
+                    "Duration",
+                    b.duration,
+                    context.movie_timescale,
+                )
+            }
+            Mp4Box::Mdhd(b) => show_local_times(
+                &mut attributes,
+                filter,
+                &b.creation_time,
+                &b.modification_time,
+            ),
+            Mp4Box::Mehd(b) => show_duration(
+                &mut attributes,
+                "Fragment duration",
+                b.fragment_duration,
+                context.movie_timescale,
+            ),
+            Mp4Box::Trun(b) => {
+                let default_duration = context.tfhd_default_sample_duration.or_else(|| {
+                    context
+                        .default_sample_durations
+                        .get(&context.current_track_id)
+                        .copied()
+                });
+                let duration: u64 = if b.has_sample_durations() {
+                    let mut duration = 0;
+                    for _ in 0..b.sample_count {
+                        if reader.failed() {
+                            break;
+                        }
+                        duration += b.parse_entry(reader).sample_duration.unwrap_or(0) as u64;
+                    }
+                    duration
+                } else {
+                    // The entries may be empty, so they aren't read one by one
+                    b.sample_count as u64 * default_duration.unwrap_or(0) as u64
+                };
+                attributes.push((
+                    "Duration".to_string(),
+                    format_duration(duration, context.track_timescale()),
+                ));
+            }
+            _ => {}
+        }
+        if let Some(trailing_data) = trailing_data {
+            attributes.push((
+                "Unparsed data".to_string(),
+                format!("{} bytes", trailing_data.len),
+            ));
+        }
+        push_spec_ref(&mut attributes, filter, &header.box_type);
+        logger.debug_box_attrs(&attributes);
+
+        let is_container = matches!(box_, Mp4Box::Container(_));
+        if is_container {
+            // The header, before the children
+            hexdump_fields(logger, filter, context.buf, reader, reader.position());
+        }
+        match box_ {
+            Mp4Box::Container(_) if filter.descends_below(depth) => {
+                logger.increase_indent();
+                //println!("DEBUG: It's a container. Will jump into it");
+                let handle_unknown = HandleUnknown::Skip;
+                _parse(
+                    reader,
+                    logger,
+                    filter,
+                    context,
+                    handle_unknown,
+                    box_end_offset,
+                    depth + 1,
+                );
+                logger.decrease_indent();
+                if header.box_type == "moof" {
+                    let fragment = context.movie.and_then(|movie| {
+                        movie
+                            .fragments
+                            .iter()
+                            .find(|f| f.offset == header.start_offset)
+                            .map(|f| FragmentSummary::new(movie, f))
+                    });
+                    if let Some(fragment) = fragment {
+                        logger.log_box_title(&fragment.to_string());
+                    }
+                }
+            }
+            Mp4Box::Elst(edit_list_box) => {
+                logger.increase_indent();
+                let mut entries = Vec::new();
+                for i in 0..edit_list_box.entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    let entry = EditListBox::parse_entry(reader);
+                    logger.debug_box(&format!("Edit {}", i + 1));
+                    let attributes = RefCell::new(Vec::new());
+                    entry.print_attributes(|k, v| {
+                        attributes.borrow_mut().push((k.to_string(), v.to_string()))
+                    });
+                    let mut attributes = attributes.into_inner();
+                    show_duration(
+                        &mut attributes,
+                        "Segment duration",
+                        entry.segment_duration as u64,
+                        context.movie_timescale,
+                    );
+                    logger.debug_box_attrs(&attributes);
+                    if entry.has_unusual_media_rate() {
+                        let text = format!(
+                            "Edit {} has media rate {}, but players usually only support 1 (or 0 to hold a frame)",
+                            i + 1,
+                            entry.media_rate()
+                        );
+                        logger.warn(&text);
+                        diagnostics::report(Severity::Warning, text);
+                    }
+                    entries.push(entry);
+                }
+                let start_delay = EditListBox::start_delay(&entries);
+                if start_delay > 0 {
+                    logger.debug_box_attr(
+                        "Start delay",
+                        &format!(
+                            "{:.3}s (empty edits)",
+                            MediaTime::new(start_delay, context.movie_timescale).as_secs_f64()
+                        ),
+                    );
+                }
+                logger.decrease_indent();
+            }
+            Mp4Box::QuickTimeMetadataItemList(metadata_item_list) => {
+                logger.increase_indent();
+                while reader.position() < box_end_offset && !reader.failed() {
+                    let item: MetadataItem = metadata_item_list.parse_entry(reader);
+                    logger.debug_box(&item.to_string());
+                }
+                logger.decrease_indent();
+            }
+            Mp4Box::Stsd(sample_description_box) => {
+                logger.increase_indent();
+                for _ in 0..sample_description_box.entry_count {
+                    if reader.failed() {
+                        break;
+                    }
+                    let entry_offset = reader.position() as usize;
+                    let entry = sample_description_box.parse_entry(reader);
+                    logger.debug_box(entry.name());
+                    let attributes = RefCell::new(Vec::new());
+                    entry.print_attributes(|k, v| {
+                        attributes.borrow_mut().push((k.to_string(), v.to_string()))
+                    });
+                    let mut attributes = attributes.into_inner();
+                    if let Some(fourcc) = context.buf.get(entry_offset + 4..entry_offset + 8) {
+                        push_spec_ref(&mut attributes, filter, &String::from_utf8_lossy(fourcc));
+                    }
+                    logger.debug_box_attrs(&attributes);
+                }
+                logger.decrease_indent();
+            }
+            _ => {}
+        }
+        if !is_container {
+            hexdump_fields(logger, filter, context.buf, reader, box_end_offset);
+        }
+
+        skip_to_end(reader, &header.box_type, box_end_offset);
+    }
+}
+
+/// Adds where the box or sample entry is specified, if `--spec-refs` is on
+fn push_spec_ref(attributes: &mut Vec<(String, String)>, filter: &BoxFilter, fourcc: &str) {
+    if filter.spec_refs {
+        if let Some(reference) = spec::reference(fourcc) {
+            attributes.push(("Spec".to_string(), reference.to_string()));
+        }
+    }
+}
+
+/// How many fields of a box are dumped, so that large tables don't drown out everything else
+const MAX_HEXDUMP_FIELDS: usize = 64;
+
+/// How many bytes of a field are shown
+const MAX_HEXDUMP_BYTES: usize = 8;
+
+/// Prints the fields read since the start of the box, one per line with its offset, raw bytes,
+/// name and value, if `--hexdump-fields` is on. Bytes up to `end_offset` that no field covers,
+/// like reserved or unparsed ones, are shown as skipped.
+fn hexdump_fields(
+    logger: &mut dyn Logger,
+    filter: &BoxFilter,
+    buf: &[u8],
+    reader: &Reader,
+    end_offset: u64,
+) {
+    if !filter.hexdump_fields {
+        return;
+    }
+    let fields = reader.take_fields();
+    let mut lines = Vec::new();
+    let mut position = match fields.first() {
+        Some(field) => field.offset,
+        None => return,
+    };
+    for field in &fields {
+        if field.offset > position {
+            let len = field.offset - position;
+            lines.push((position, len, "(skipped)", format!("{} bytes", len)));
+        }
+        lines.push((field.offset, field.len, field.name, field.value.clone()));
+        position = position.max(field.offset + field.len);
+    }
+    if end_offset > position {
+        let len = end_offset - position;
+        lines.push((position, len, "(skipped)", format!("{} bytes", len)));
+    }
+
+    logger.debug_box("Fields:");
+    let name_width = lines
+        .iter()
+        .map(|(_, _, name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (offset, len, name, value) in lines.iter().take(MAX_HEXDUMP_FIELDS) {
+        let start = (*offset as usize).min(buf.len());
+        let end = (start + (*len as usize).min(MAX_HEXDUMP_BYTES)).min(buf.len());
+        let mut hex: Vec<String> = buf[start..end]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        if *len as usize > MAX_HEXDUMP_BYTES {
+            hex.push("...".to_string());
+        }
+        logger.debug_box(&format!(
+            "  {:#010X}  {:<width$}  {:name_width$}  {}",
+            offset,
+            hex.join(" "),
+            name,
+            value,
+            width = MAX_HEXDUMP_BYTES * 3 + 3,
+            name_width = name_width
+        ));
+    }
+    if lines.len() > MAX_HEXDUMP_FIELDS {
+        logger.debug_box(&format!(
+            "  ... and {} more fields",
+            lines.len() - MAX_HEXDUMP_FIELDS
+        ));
+    }
+}
+
+/// Moves past what's left of the box, after its fields and children
+fn skip_to_end(reader: &mut Reader, box_type: &str, box_end_offset: u64) {
+    if reader.position() > box_end_offset {
+        reader.fail(format!(
+            "The contents of the '{}' box go {} bytes past its end",
+            box_type,
+            reader.position() - box_end_offset
+        ));
+        return;
+    }
+    reader.skip(box_end_offset - reader.position());
+}
+
+#[cfg(all(test, feature = "fixtures"))]
+mod tests {
+    use super::*;
+    use crate::box_stats::box_stats;
+    use crate::fixtures::{self, TrackFixture};
+    use crate::logger::{JsonBox, JsonLogger};
+
+    fn top_level_boxes(buf: &[u8]) -> Result<Vec<JsonBox>, ParseError> {
+        let mut logger = JsonLogger::new();
+        parse_mp4(buf, &mut logger, &BoxFilter::default())?;
+        let (boxes, messages) = logger.into_boxes();
+        assert_eq!(messages, Vec::<String>::new());
+        Ok(boxes)
+    }
+
+    fn media_data_len(tracks: &[TrackFixture]) -> u64 {
+        tracks
+            .iter()
+            .map(|t| t.sample_count as u64 * t.sample_size as u64)
+            .sum()
+    }
+
+    #[test]
+    fn largesize_mdat() {
+        let tracks = [TrackFixture::video(), TrackFixture::audio()];
+        let buf = fixtures::movie_with_largesize_mdat(&tracks);
+        let boxes = top_level_boxes(&buf).unwrap();
+
+        let types: Vec<_> = boxes.iter().map(|b| b.box_type.as_deref()).collect();
+        assert_eq!(types, [Some("ftyp"), Some("moov"), Some("mdat")]);
+        // The size includes the 16 byte header, with largesize
+        let mdat = &boxes[2];
+        let (mdat_offset, mdat_size) = (mdat.offset.unwrap(), mdat.size.unwrap());
+        assert_eq!(mdat_size, 16 + media_data_len(&tracks));
+        assert_eq!(mdat_offset + mdat_size, buf.len() as u64);
+        let total: u64 = boxes.iter().map(|b| b.size.unwrap()).sum();
+        assert_eq!(total, buf.len() as u64);
+
+        // The chunk offsets point right after the header
+        let movie = Movie::parse(&buf).unwrap();
+        let samples = movie.track_samples(&movie.tracks[0]);
+        assert_eq!(samples[0].offset, mdat_offset + 16);
+
+        let stats = box_stats(&buf);
+        let mdat_stats = stats.iter().find(|s| s.box_type == "mdat").unwrap();
+        assert_eq!((mdat_stats.count, mdat_stats.total_size), (1, mdat_size));
+    }
+
+    #[test]
+    fn mdat_with_size_zero_extends_to_the_end_of_the_file() {
+        let tracks = [TrackFixture::audio()];
+        let mut buf = fixtures::movie(&tracks);
+        let mdat_offset = buf.len() - media_data_len(&tracks) as usize - 8;
+        assert_eq!(&buf[mdat_offset + 4..mdat_offset + 8], b"mdat");
+        buf[mdat_offset..mdat_offset + 4].copy_from_slice(&[0; 4]);
+
+        let boxes = top_level_boxes(&buf).unwrap();
+        let mdat = boxes.last().unwrap();
+        assert_eq!(mdat.offset, Some(mdat_offset as u64));
+        assert_eq!(mdat.size, Some(8 + media_data_len(&tracks)));
+    }
+
+    #[test]
+    fn child_box_larger_than_its_parent() {
+        let mut buf = fixtures::movie(&[TrackFixture::audio()]);
+        let trak_offset = buf.windows(4).position(|w| w == b"trak").unwrap() - 4;
+        buf[trak_offset..trak_offset + 4].copy_from_slice(&1_000_000u32.to_be_bytes());
+        let error = top_level_boxes(&buf).unwrap_err();
+        assert!(error.message.contains("bytes are left of its parent"));
+        assert_eq!(error.box_path.as_deref(), Some("moov/trak[0]"));
+        assert_eq!(error.offset, Some(trak_offset as u64));
+    }
+}
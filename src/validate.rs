@@ -0,0 +1,720 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::analysis::Interleaving;
+use crate::box_path;
+use crate::box_stats::walk_boxes;
+use crate::boxes::{BoxHeader, FileTypeBox};
+use crate::brand::Profile;
+use crate::media_time::MediaTime;
+use crate::model::{Movie, Sample, Track};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found while validating a file
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Identifies the rule that was broken, for the rules of a specification, e.g.
+    /// "cmaf-tfdt"
+    pub rule: Option<&'static str>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "WARN: ")?,
+            Severity::Error => write!(f, "ERROR: ")?,
+        }
+        if let Some(rule) = self.rule {
+            write!(f, "[{}] ", rule)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidationOptions {
+    /// How much the durations of the audio and video tracks may differ, in seconds
+    pub max_av_duration_mismatch: f64,
+    /// How far apart in time the audio and video data next to each other may be, in seconds
+    pub max_interleave_depth: f64,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        Self {
+            max_av_duration_mismatch: 0.2,
+            max_interleave_depth: 1.0,
+        }
+    }
+}
+
+pub fn validate(movie: &Movie, options: &ValidationOptions) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for track in &movie.tracks {
+        let samples = movie.track_samples(track);
+        check_empty_track(movie, track, &samples, &mut issues);
+        check_sample_timing(track, &samples, &mut issues);
+        check_edit_list(track, &samples, movie.timescale, &mut issues);
+        check_fragment_continuity(movie, track, &mut issues);
+    }
+    check_fragment_order(movie, &mut issues);
+    check_brand_rules(movie, &mut issues);
+    if is_cmaf(movie) {
+        check_cmaf_fragments(movie, &mut issues);
+    }
+    check_sample_auxiliary_info(movie, &mut issues);
+    check_av_durations(movie, options, &mut issues);
+    check_interleaving(movie, options, &mut issues);
+    check_chunk_offsets(movie, &mut issues);
+    issues
+}
+
+fn warn(issues: &mut Vec<Issue>, message: String) {
+    issues.push(Issue {
+        severity: Severity::Warning,
+        rule: None,
+        message,
+    });
+}
+
+/// A broken rule of a specification
+fn violation(issues: &mut Vec<Issue>, severity: Severity, rule: &'static str, message: String) {
+    issues.push(Issue {
+        severity,
+        rule: Some(rule),
+        message,
+    });
+}
+
+fn secs(ticks: i64, timescale: u32) -> f64 {
+    MediaTime::signed(ticks, timescale).as_secs_f64()
+}
+
+/// Tracks without samples or with a duration of zero, and sample entries that no sample uses,
+/// which buggy editors tend to leave behind and which some players choke on
+fn check_empty_track(movie: &Movie, track: &Track, samples: &[Sample], issues: &mut Vec<Issue>) {
+    let trex = movie
+        .track_extends
+        .iter()
+        .find(|trex| trex.track_id == track.id);
+    if samples.is_empty() {
+        // An initialization segment has the samples in other files
+        if trex.is_none() || !movie.fragments.is_empty() {
+            warn(issues, format!("Track {} has no samples", track.id));
+        }
+        return;
+    }
+
+    let media_duration: u64 = samples.iter().map(|s| s.duration as u64).sum();
+    if media_duration == 0 {
+        warn(
+            issues,
+            format!(
+                "Track {} has {} samples, but all of them have zero duration",
+                track.id,
+                samples.len()
+            ),
+        );
+    } else if track.duration == 0 && trex.is_none() {
+        warn(
+            issues,
+            format!(
+                "Track {} has a duration of zero in mdhd, but its samples last {:.3}s",
+                track.id,
+                secs(media_duration as i64, track.timescale)
+            ),
+        );
+    }
+
+    for (i, entry) in track.sample_entries.iter().enumerate() {
+        let index = i as u32 + 1;
+        if !samples.iter().any(|s| s.description_index == index) {
+            warn(
+                issues,
+                format!(
+                    "Track {}: sample entry {} ({}) isn't used by any sample",
+                    track.id,
+                    index,
+                    entry.name()
+                ),
+            );
+        }
+    }
+}
+
+/// Zero-duration samples (which make decode times repeat) and samples that are presented
+/// before time zero
+fn check_sample_timing(track: &Track, samples: &[Sample], issues: &mut Vec<Issue>) {
+    // The last sample may legitimately have a zero duration in some muxers' output
+    let zero_durations = samples
+        .iter()
+        .take(samples.len().saturating_sub(1))
+        .filter(|s| s.duration == 0)
+        .count();
+    if zero_durations > 0 {
+        warn(
+            issues,
+            format!(
+                "Track {}: {} samples have zero duration, so their decode times coincide",
+                track.id, zero_durations
+            ),
+        );
+    }
+
+    let negative: Vec<(usize, i64)> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (i, s.decode_time as i64 + s.composition_offset as i64))
+        .filter(|&(_, cts)| cts < 0)
+        .collect();
+    if let Some(&(first, cts)) = negative.first() {
+        warn(
+            issues,
+            format!(
+                "Track {}: {} samples have a negative composition time (first: sample {} at {:.3}s)",
+                track.id,
+                negative.len(),
+                first + 1,
+                secs(cts, track.timescale)
+            ),
+        );
+    }
+}
+
+/// Gaps in the presentation caused by empty edits, and edits that refer to media beyond the
+/// end of the track
+fn check_edit_list(
+    track: &Track,
+    samples: &[Sample],
+    movie_timescale: u32,
+    issues: &mut Vec<Issue>,
+) {
+    let media_end = samples
+        .iter()
+        .map(|s| s.decode_time as i64 + s.composition_offset as i64 + s.duration as i64)
+        .max()
+        .unwrap_or(0);
+    let mut presentation_time = 0;
+    for (i, edit) in track.edits.iter().enumerate() {
+        if edit.is_empty() {
+            if i > 0 {
+                warn(
+                    issues,
+                    format!(
+                        "Track {}: empty edit leaves a {:.3}s gap at {:.3}s",
+                        track.id,
+                        secs(edit.segment_duration as i64, movie_timescale),
+                        secs(presentation_time, movie_timescale)
+                    ),
+                );
+            }
+        } else {
+            let media_duration = MediaTime::new(edit.segment_duration as u64, movie_timescale)
+                .rescale(track.timescale)
+                .ticks();
+            let edit_end = edit.media_time as i64 + media_duration;
+            // Allow for rounding when converting between timescales
+            if edit_end > media_end + track.timescale as i64 / 100 {
+                warn(
+                    issues,
+                    format!(
+                        "Track {}: edit {} refers to media up to {:.3}s, but the media ends at {:.3}s",
+                        track.id,
+                        i + 1,
+                        secs(edit_end, track.timescale),
+                        secs(media_end, track.timescale)
+                    ),
+                );
+            }
+        }
+        presentation_time += edit.segment_duration as i64;
+    }
+}
+
+/// Each fragment's tfdt should equal the end of the track's previous fragment
+fn check_fragment_continuity(movie: &Movie, track: &Track, issues: &mut Vec<Issue>) {
+    let mut expected = track.sample_table.media_duration();
+    for fragment in &movie.fragments {
+        for track_fragment in fragment.tracks.iter().filter(|t| t.track_id == track.id) {
+            if let Some(actual) = track_fragment.base_media_decode_time {
+                let difference = actual as i64 - expected as i64;
+                if difference != 0 {
+                    let kind = if difference > 0 { "gap" } else { "overlap" };
+                    warn(
+                        issues,
+                        format!(
+                            "Track {}: {:.3}s {} before fragment {} (moof at {}): tfdt is {:.3}s, expected {:.3}s",
+                            track.id,
+                            secs(difference.abs(), track.timescale),
+                            kind,
+                            fragment.sequence_number,
+                            fragment.offset,
+                            secs(actual as i64, track.timescale),
+                            secs(expected as i64, track.timescale)
+                        ),
+                    );
+                }
+            }
+            if let Some(last) = track_fragment.samples.last() {
+                expected = last.decode_time + last.duration as u64;
+            }
+        }
+    }
+}
+
+/// Sequence numbers (mfhd) should increase from one fragment to the next, and each track
+/// fragment should belong to a track in moov
+fn check_fragment_order(movie: &Movie, issues: &mut Vec<Issue>) {
+    for pair in movie.fragments.windows(2) {
+        let (previous, fragment) = (&pair[0], &pair[1]);
+        if fragment.sequence_number <= previous.sequence_number {
+            warn(
+                issues,
+                format!(
+                    "Fragment sequence number {} (moof at {}) doesn't increase from the previous fragment's {}",
+                    fragment.sequence_number, fragment.offset, previous.sequence_number
+                ),
+            );
+        }
+    }
+    // The tracks of Smooth Streaming fragments are described by the manifest instead of moov
+    if movie.tracks.is_empty() && movie.is_smooth_streaming() {
+        return;
+    }
+    for fragment in &movie.fragments {
+        for track_fragment in &fragment.tracks {
+            if !movie.tracks.iter().any(|t| t.id == track_fragment.track_id) {
+                issues.push(Issue {
+                    severity: Severity::Error,
+                    rule: None,
+                    message: format!(
+                        "Fragment {} (moof at {}) has samples for track {}, which isn't in moov",
+                        fragment.sequence_number, fragment.offset, track_fragment.track_id
+                    ),
+                });
+            }
+        }
+    }
+}
+
+/// Rules that only apply to some brands
+fn check_brand_rules(movie: &Movie, issues: &mut Vec<Issue>) {
+    match movie.profile() {
+        Profile::Segmented if movie.fragments.is_empty() => warn(
+            issues,
+            "The brands are for streaming segments (DASH/CMAF), but there are no movie fragments"
+                .to_string(),
+        ),
+        Profile::Segmented if movie.has_brand("cmfc") && movie.tracks.len() > 1 => warn(
+            issues,
+            format!(
+                "CMAF track files (cmfc) have a single track, but there are {}",
+                movie.tracks.len()
+            ),
+        ),
+        Profile::SmoothStreaming if movie.fragments.is_empty() => warn(
+            issues,
+            "The brands are for Smooth Streaming (isml/piff), but there are no movie fragments"
+                .to_string(),
+        ),
+        Profile::Heif
+            if (movie.has_brand("msf1") || movie.has_brand("avis")) && movie.tracks.is_empty() =>
+        {
+            warn(
+                issues,
+                "The brands are for an image sequence (msf1/avis), but there are no tracks"
+                    .to_string(),
+            )
+        }
+        _ => {}
+    }
+}
+
+/// Whether the file claims to be CMAF, with a CMAF brand in ftyp or in a styp box
+fn is_cmaf(movie: &Movie) -> bool {
+    movie.has_brand("cmfc")
+        || movie.has_brand("cmf2")
+        || movie
+            .fragments
+            .iter()
+            .filter_map(|fragment| fragment.segment_type.as_ref())
+            .any(|styp| ["cmfs", "cmff", "cmfl"].iter().any(|b| styp.has_brand(b)))
+}
+
+/// The constraints that CMAF puts on movie fragments and segments
+fn check_cmaf_fragments(movie: &Movie, issues: &mut Vec<Issue>) {
+    for fragment in &movie.fragments {
+        let name = format!(
+            "Fragment {} (moof at {})",
+            fragment.sequence_number, fragment.offset
+        );
+        if fragment.tracks.len() != 1 {
+            violation(
+                issues,
+                Severity::Error,
+                "cmaf-single-track",
+                format!(
+                    "{} has {} track fragments, but CMAF fragments have exactly one",
+                    name,
+                    fragment.tracks.len()
+                ),
+            );
+        }
+        for track_fragment in &fragment.tracks {
+            if track_fragment.base_media_decode_time.is_none() {
+                violation(
+                    issues,
+                    Severity::Error,
+                    "cmaf-tfdt",
+                    format!(
+                        "{} has no tfdt box for track {}",
+                        name, track_fragment.track_id
+                    ),
+                );
+            }
+            if !track_fragment.default_base_is_moof {
+                violation(
+                    issues,
+                    Severity::Error,
+                    "cmaf-default-base-is-moof",
+                    format!(
+                        "{}: tfhd of track {} doesn't have the default-base-is-moof flag",
+                        name, track_fragment.track_id
+                    ),
+                );
+            }
+        }
+        if fragment.has_sample_dependencies {
+            violation(
+                issues,
+                Severity::Warning,
+                "cmaf-no-sdtp",
+                format!(
+                    "{} has an sdtp box, which CMAF fragments shouldn't have",
+                    name
+                ),
+            );
+        }
+        if let Some(styp) = &fragment.segment_type {
+            if !["cmfs", "cmff", "cmfl"].iter().any(|b| styp.has_brand(b)) {
+                violation(
+                    issues,
+                    Severity::Warning,
+                    "cmaf-styp-brand",
+                    format!(
+                        "{}: the styp box before it has none of the CMAF brands cmfs, cmff or cmfl (brands: {})",
+                        name,
+                        styp_brands(styp)
+                    ),
+                );
+            }
+        }
+    }
+
+    for track in &movie.tracks {
+        check_cmaf_fragment_durations(movie, track, issues);
+    }
+}
+
+fn styp_brands(styp: &FileTypeBox) -> String {
+    let mut brands = vec![styp.major_brand.as_str()];
+    for brand in &styp.compatible_brands {
+        if !brands.contains(&brand.as_str()) {
+            brands.push(brand);
+        }
+    }
+    brands.join(", ")
+}
+
+/// All fragments of a track but the last should have about the same duration. They may differ
+/// by one sample, since audio frames don't line up with the fragment boundaries.
+fn check_cmaf_fragment_durations(movie: &Movie, track: &Track, issues: &mut Vec<Issue>) {
+    let track_fragments: Vec<(u32, u64, u32)> = movie
+        .fragments
+        .iter()
+        .flat_map(|fragment| {
+            fragment
+                .tracks
+                .iter()
+                .filter(|t| t.track_id == track.id)
+                .map(move |t| {
+                    let duration = t.samples.iter().map(|s| s.duration as u64).sum();
+                    let max_sample_duration =
+                        t.samples.iter().map(|s| s.duration).max().unwrap_or(0);
+                    (fragment.sequence_number, duration, max_sample_duration)
+                })
+        })
+        .collect();
+    let (_, expected, _) = match track_fragments.first() {
+        Some(&first) => first,
+        None => return,
+    };
+    let inconsistent: Vec<&(u32, u64, u32)> = track_fragments
+        .iter()
+        .take(track_fragments.len() - 1)
+        .filter(|&&(_, duration, max_sample_duration)| {
+            (duration as i64 - expected as i64).unsigned_abs() > max_sample_duration as u64
+        })
+        .collect();
+    if let Some(&&(sequence_number, duration, _)) = inconsistent.first() {
+        violation(
+            issues,
+            Severity::Warning,
+            "cmaf-fragment-duration",
+            format!(
+                "Track {}: {} fragments differ in duration from the first one ({:.3}s), e.g. fragment {} ({:.3}s)",
+                track.id,
+                inconsistent.len(),
+                secs(expected as i64, track.timescale),
+                sequence_number,
+                secs(duration as i64, track.timescale)
+            ),
+        );
+    }
+}
+
+/// Players read the IVs and subsamples of encrypted samples from where saio and saiz point, so
+/// they must describe the senc box
+fn check_sample_auxiliary_info(movie: &Movie, issues: &mut Vec<Issue>) {
+    for fragment in &movie.fragments {
+        for track_fragment in &fragment.tracks {
+            let (start, end) = match track_fragment.sample_encryption_range {
+                Some(range) => range,
+                None => continue,
+            };
+            let name = format!(
+                "Fragment {} (moof at {}), track {}",
+                fragment.sequence_number, fragment.offset, track_fragment.track_id
+            );
+            // PIFF files from before saio and saiz were added to the standard don't have them
+            let is_piff = movie.is_smooth_streaming()
+                || movie
+                    .tracks
+                    .iter()
+                    .find(|track| track.id == track_fragment.track_id)
+                    .and_then(|track| track.protection())
+                    .is_some_and(|protection| protection.scheme_type == "piff");
+            let offset = match track_fragment.auxiliary_info_offset {
+                Some(offset) => offset,
+                None if is_piff => continue,
+                None => {
+                    violation(
+                        issues,
+                        Severity::Warning,
+                        "cenc-saio",
+                        format!("{} has a senc box, but no saio box pointing to it", name),
+                    );
+                    continue;
+                }
+            };
+            if offset != start {
+                violation(
+                    issues,
+                    Severity::Error,
+                    "cenc-saio-offset",
+                    format!(
+                        "{}: saio points to {}, but the samples in senc start at {}",
+                        name, offset, start
+                    ),
+                );
+            }
+            let sizes = &track_fragment.auxiliary_info_sizes;
+            if sizes.len() != track_fragment.sample_encryption.len() {
+                violation(
+                    issues,
+                    Severity::Error,
+                    "cenc-saiz-count",
+                    format!(
+                        "{}: saiz has {} samples, but senc has {}",
+                        name,
+                        sizes.len(),
+                        track_fragment.sample_encryption.len()
+                    ),
+                );
+            }
+            let total_size: u64 = sizes.iter().map(|&size| size as u64).sum();
+            if total_size != end - start {
+                violation(
+                    issues,
+                    Severity::Error,
+                    "cenc-saiz-size",
+                    format!(
+                        "{}: the sizes in saiz add up to {} bytes, but the samples in senc are {} bytes",
+                        name,
+                        total_size,
+                        end - start
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn check_av_durations(movie: &Movie, options: &ValidationOptions, issues: &mut Vec<Issue>) {
+    let video = movie.tracks.iter().find(|t| t.is_video());
+    let audio = movie.tracks.iter().find(|t| t.is_audio());
+    if let (Some(video), Some(audio)) = (video, audio) {
+        let video_duration = movie.presentation_duration(video);
+        let audio_duration = movie.presentation_duration(audio);
+        if (video_duration - audio_duration).abs() > options.max_av_duration_mismatch {
+            warn(
+                issues,
+                format!(
+                    "Audio track {} lasts {:.3}s but video track {} lasts {:.3}s",
+                    audio.id, audio_duration, video.id, video_duration
+                ),
+            );
+        }
+    }
+}
+
+/// Poorly interleaved files need a large buffer to be played while they're downloaded
+fn check_interleaving(movie: &Movie, options: &ValidationOptions, issues: &mut Vec<Issue>) {
+    if let Some(interleaving) = Interleaving::from_movie(movie) {
+        if interleaving.depth > options.max_interleave_depth {
+            warn(
+                issues,
+                format!(
+                    "Audio and video are poorly interleaved: up to {:.3}s apart (at offset {}). Progressive playback needs a buffer of {} bytes",
+                    interleaving.depth, interleaving.depth_offset, interleaving.max_buffered_bytes
+                ),
+            );
+        }
+    }
+}
+
+/// How close to 4 GiB the chunk offsets in stco may get before there's a risk of overflow
+const STCO_MARGIN: u64 = 256 * 1024 * 1024;
+
+/// stco can't point past 4 GiB, so files that come close to it can't grow before their media
+/// data, e.g. by adding metadata to moov, without switching to co64
+fn check_chunk_offsets(movie: &Movie, issues: &mut Vec<Issue>) {
+    for track in &movie.tracks {
+        let table = &track.sample_table;
+        if table.has_large_offsets {
+            continue;
+        }
+        if let Some(&max_offset) = table.chunk_offsets.iter().max() {
+            if max_offset > u32::MAX as u64 - STCO_MARGIN {
+                warn(
+                    issues,
+                    format!(
+                        "Track {} has chunk offsets up to {} in stco, close to the 4 GiB limit. Edits that grow the file before mdat need co64.",
+                        track.id, max_offset
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Boxes that a parent may only have one of
+const SINGLETONS: [&str; 38] = [
+    "ftyp", "moov", "mvhd", "mvex", "mehd", "tkhd", "tref", "edts", "elst", "mdia", "mdhd", "hdlr",
+    "minf", "vmhd", "smhd", "hmhd", "nmhd", "sthd", "dinf", "dref", "stbl", "stsd", "stts", "ctts",
+    "cslg", "stss", "stsz", "stz2", "stsc", "stco", "co64", "sdtp", "mfhd", "tfhd", "tfdt", "mfro",
+    "iloc", "pitm",
+];
+
+/// The parents that a box may be in, "" for the top level. Boxes that aren't listed may be
+/// anywhere.
+fn allowed_parents(box_type: &str) -> Option<&'static [&'static str]> {
+    let parents: &[&str] = match box_type {
+        "ftyp" | "styp" | "moov" | "moof" | "mfra" | "mdat" | "sidx" | "ssix" | "prft" => &[""],
+        "mvhd" | "trak" | "mvex" => &["moov"],
+        "tkhd" | "tref" | "edts" | "mdia" => &["trak"],
+        "elst" => &["edts"],
+        "mdhd" | "minf" => &["mdia"],
+        // QuickTime files have a data handler in minf, next to dinf
+        "hdlr" => &["mdia", "minf", "meta"],
+        "vmhd" | "smhd" | "hmhd" | "nmhd" | "sthd" | "stbl" => &["minf"],
+        "dinf" => &["minf", "meta"],
+        "dref" => &["dinf"],
+        "stsd" | "stts" | "ctts" | "cslg" | "stss" | "stsz" | "stz2" | "stsc" | "stco" | "co64"
+        | "stsh" | "stdp" | "padb" => &["stbl"],
+        "sdtp" | "sbgp" | "sgpd" | "subs" | "saiz" | "saio" => &["stbl", "traf"],
+        "mehd" | "trex" => &["mvex"],
+        "mfhd" | "traf" => &["moof"],
+        "tfhd" | "tfdt" | "trun" | "senc" => &["traf"],
+        "tfra" | "mfro" => &["mfra"],
+        "udta" => &["moov", "trak", "moof", "traf"],
+        _ => return None,
+    };
+    Some(parents)
+}
+
+/// Singleton boxes that a parent has more than one of, e.g. two stsd boxes in a stbl, and boxes
+/// in a parent that they don't belong in, e.g. a tkhd box at the top level. Unlike the other
+/// checks, this works on the box tree, as `Movie` keeps only one of each box.
+pub fn check_box_structure(buf: &[u8]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    // By the path of the parent and the box type
+    let mut counts: HashMap<(String, String), usize> = HashMap::new();
+    let result = walk_boxes(buf, &mut |parents: &[String], header: &BoxHeader| {
+        let (path, offset) = box_path::current().unwrap_or_default();
+        let parent = parents.last().map_or("", String::as_str);
+        let box_type = header.box_type.as_str();
+
+        let parent_path = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let count = counts
+            .entry((parent_path.to_string(), box_type.to_string()))
+            .or_insert(0);
+        *count += 1;
+        if *count == 2 && SINGLETONS.contains(&box_type) {
+            let parent_name = match parent {
+                "" => "the top level".to_string(),
+                parent => format!("'{}'", parent),
+            };
+            warn(
+                &mut issues,
+                format!(
+                    "Duplicate '{}' box ({} @ {:#X}): {} may only have one",
+                    box_type, path, offset, parent_name
+                ),
+            );
+        }
+
+        if let Some(allowed) = allowed_parents(box_type) {
+            if !allowed.contains(&parent) {
+                let location = match parent {
+                    "" => "at the top level".to_string(),
+                    parent => format!("in '{}'", parent),
+                };
+                let expected: Vec<String> = allowed
+                    .iter()
+                    .map(|p| match *p {
+                        "" => "the top level".to_string(),
+                        p => format!("'{}'", p),
+                    })
+                    .collect();
+                warn(
+                    &mut issues,
+                    format!(
+                        "'{}' box {} ({} @ {:#X}) belongs in {}",
+                        box_type,
+                        location,
+                        path,
+                        offset,
+                        expected.join(" or ")
+                    ),
+                );
+            }
+        }
+    });
+    // The boxes after a broken header can't be found
+    if let Err(message) = result {
+        issues.push(Issue {
+            severity: Severity::Error,
+            rule: None,
+            message,
+        });
+    }
+    issues
+}
@@ -0,0 +1,68 @@
+//! The parser for web tools, built to WebAssembly with `wasm-pack build --features wasm`
+
+use serde::Serialize;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::logger::{JsonBox, JsonLogger};
+use crate::model::Movie;
+use crate::rewrite::top_level_boxes;
+use crate::summary::Summary;
+use crate::tree::{parse_mp4, BoxFilter};
+
+/// What `parse_to_json` returns
+#[derive(Debug, Serialize)]
+pub struct Inspection {
+    pub boxes: Vec<JsonBox>,
+    /// Warnings that aren't about a particular box, e.g. that the file was truncated
+    pub warnings: Vec<String>,
+    pub summary: Option<Summary>,
+    /// Set if the file couldn't be parsed. The boxes that came before the problem are still
+    /// listed.
+    pub error: Option<String>,
+}
+
+/// Parses the file and returns the box tree, with the attributes of each box, and the summary
+/// as JSON, see `Inspection`
+#[wasm_bindgen]
+pub fn parse_to_json(bytes: &[u8]) -> String {
+    serde_json::to_string(&inspect(bytes)).unwrap()
+}
+
+/// Like `parse_to_json`, without serializing the result
+pub fn inspect(bytes: &[u8]) -> Inspection {
+    let mut warnings = Vec::new();
+    // Downloads that were cut off are common, and would fail to parse on the box that doesn't
+    // fit. Only the complete boxes are parsed instead, so that there is still a summary.
+    let mut complete_len = bytes.len();
+    if let Some(truncated) = top_level_boxes(bytes)
+        .into_iter()
+        .find(|b| b.start_offset + b.box_size > bytes.len() as u64)
+    {
+        warnings.push(format!(
+            "The file is truncated: '{}' at {} is {} bytes, but only {} are left",
+            truncated.box_type,
+            truncated.start_offset,
+            truncated.box_size,
+            bytes.len() as u64 - truncated.start_offset
+        ));
+        complete_len = truncated.start_offset as usize;
+    }
+    let bytes = &bytes[..complete_len];
+
+    let mut logger = JsonLogger::new();
+    // Neither panics on a broken file, as panics can't be caught in WebAssembly
+    let tree = parse_mp4(bytes, &mut logger, &BoxFilter::default());
+    let summary = Movie::parse(bytes).map(|movie| Summary::new(bytes, &movie));
+    let error = tree
+        .err()
+        .or_else(|| summary.as_ref().err().cloned())
+        .map(|e| e.to_string());
+    let (boxes, messages) = logger.into_boxes();
+    warnings.extend(messages);
+    Inspection {
+        boxes,
+        warnings,
+        summary: summary.ok(),
+        error,
+    }
+}
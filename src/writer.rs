@@ -0,0 +1,120 @@
+use alloc::vec::Vec;
+
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Self { buf: Vec::new() }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.buf.len() as u64
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn write_u8(&mut self, n: u8) {
+        self.buf.push(n);
+    }
+
+    pub fn write_u16(&mut self, n: u16) {
+        self.buf.extend_from_slice(&n.to_be_bytes());
+    }
+
+    pub fn write_i16(&mut self, n: i16) {
+        self.buf.extend_from_slice(&n.to_be_bytes());
+    }
+
+    pub fn write_u32(&mut self, n: u32) {
+        self.buf.extend_from_slice(&n.to_be_bytes());
+    }
+
+    pub fn write_i32(&mut self, n: i32) {
+        self.buf.extend_from_slice(&n.to_be_bytes());
+    }
+
+    pub fn write_u64(&mut self, n: u64) {
+        self.buf.extend_from_slice(&n.to_be_bytes());
+    }
+
+    pub fn write_fixed_point_16_16(&mut self, n: f32) {
+        self.write_u32((n * 2_u32.pow(16) as f32) as u32);
+    }
+
+    pub fn write_fixed_point_8_8(&mut self, n: f32) {
+        self.write_u16((n * 2_u32.pow(8) as f32) as u16);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn write_string(&mut self, s: &str) {
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    /// Writes a box header with a placeholder size. The returned offset must be handed
+    /// to `end_box` once the box contents have been written.
+    pub fn begin_box(&mut self, box_type: &str) -> u64 {
+        let start_offset = self.position();
+        self.write_u32(0);
+        self.write_box_type(box_type);
+        start_offset
+    }
+
+    pub fn begin_full_box(&mut self, box_type: &str, version: u8, flags: [u8; 3]) -> u64 {
+        let start_offset = self.begin_box(box_type);
+        self.write_u8(version);
+        self.write_bytes(&flags);
+        start_offset
+    }
+
+    /// Panics if the box is 4 GiB or larger. Those need a largesize, see `write_box_header`.
+    pub fn end_box(&mut self, start_offset: u64) {
+        let box_size = self.position() - start_offset;
+        assert!(
+            box_size <= u32::MAX as u64,
+            "Box at {} is {} bytes, which needs a largesize",
+            start_offset,
+            box_size
+        );
+        let start = start_offset as usize;
+        self.buf[start..start + 4].copy_from_slice(&(box_size as u32).to_be_bytes());
+    }
+
+    /// Writes a complete box header for a payload of known size, using 'largesize' if the
+    /// box wouldn't fit in 32 bits (which is typically only the case for mdat).
+    pub fn write_box_header(&mut self, box_type: &str, inner_size: u64) {
+        if inner_size + 8 > u32::MAX as u64 {
+            self.write_u32(1);
+            self.write_box_type(box_type);
+            self.write_u64(inner_size + 16);
+        } else {
+            self.write_u32((inner_size + 8) as u32);
+            self.write_box_type(box_type);
+        }
+    }
+
+    /// Patches a previously written u32, e.g. a chunk offset that wasn't known at the time.
+    pub fn patch_u32(&mut self, offset: u64, n: u32) {
+        let offset = offset as usize;
+        self.buf[offset..offset + 4].copy_from_slice(&n.to_be_bytes());
+    }
+
+    fn write_box_type(&mut self, box_type: &str) {
+        // Box types like '©too' are stored as a single byte per char (see BoxHeader::parse)
+        let bytes: Vec<u8> = box_type.chars().map(|c| c as u32 as u8).collect();
+        assert_eq!(bytes.len(), 4, "Invalid box type: {:?}", box_type);
+        self.buf.extend_from_slice(&bytes);
+    }
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
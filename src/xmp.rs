@@ -0,0 +1,62 @@
+//! XMP metadata, as stored by Adobe's tools and exiftool in a uuid box or an xml box
+
+use std::path::{Path, PathBuf};
+
+/// Where the XMP of the media file is stored as a sidecar, e.g. "clip.xmp" for "clip.mp4"
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    path.with_extension("xmp")
+}
+
+/// Puts each element of the XML on its own line, indented by its depth. Elements that only
+/// contain text are kept on one line.
+pub fn pretty_print(xml: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut depth: usize = 0;
+    let mut rest = xml.trim();
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = rest[..end].trim();
+            if !text.is_empty() {
+                lines.push(format!("{}{}", "  ".repeat(depth), text));
+            }
+            rest = &rest[end..];
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(end) => end + 1,
+            // Not well-formed, so keep the rest as it is
+            None => rest.len(),
+        };
+        let tag = &rest[..end];
+        rest = &rest[end..];
+
+        if tag.starts_with("</") {
+            depth = depth.saturating_sub(1);
+            lines.push(format!("{}{}", "  ".repeat(depth), tag));
+        } else if tag.starts_with("<?") || tag.starts_with("<!") || tag.ends_with("/>") {
+            lines.push(format!("{}{}", "  ".repeat(depth), tag));
+        } else {
+            // Keep `<tag>text</tag>` together
+            let text_end = rest.find('<').unwrap_or(rest.len());
+            let text = &rest[..text_end];
+            if !text.trim().is_empty() && rest[text_end..].starts_with("</") {
+                let close_end = rest[text_end..]
+                    .find('>')
+                    .map_or(rest.len(), |i| text_end + i + 1);
+                lines.push(format!(
+                    "{}{}{}{}",
+                    "  ".repeat(depth),
+                    tag,
+                    text.trim(),
+                    &rest[text_end..close_end]
+                ));
+                rest = &rest[close_end..];
+            } else {
+                lines.push(format!("{}{}", "  ".repeat(depth), tag));
+                depth += 1;
+            }
+        }
+    }
+    lines.join("\n")
+}